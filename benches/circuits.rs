@@ -8,7 +8,7 @@
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use fancy_garbling::circuit::Circuit;
-use fancy_garbling::garble;
+use fancy_garbling::{garble, garble_batch, garble_parallel};
 use std::time::Duration;
 
 fn circuit(fname: &str) -> Circuit {
@@ -35,6 +35,34 @@ fn bench_eval_aes(c: &mut Criterion) {
     });
 }
 
+fn bench_garble_parallel_aes(c: &mut Criterion) {
+    let circ = circuit("circuits/AES-non-expanded.txt");
+    c.bench_function("garble_parallel::aes", move |bench| {
+        bench.iter(|| garble_parallel(&circ));
+    });
+}
+
+fn bench_eval_parallel_aes(c: &mut Criterion) {
+    let circ = circuit("circuits/AES-non-expanded.txt");
+    let (en, gc) = garble_parallel(&circ).unwrap();
+    let gb = en.encode_garbler_inputs(&vec![0u16; 128]);
+    let ev = en.encode_evaluator_inputs(&vec![0u16; 128]);
+    c.bench_function("eval_parallel::aes", move |bench| {
+        bench.iter(|| gc.eval_parallel(&circ, &gb, &ev));
+    });
+}
+
+fn bench_garble_batch_aes(c: &mut Criterion) {
+    let mut circ = circuit("circuits/AES-non-expanded.txt");
+    c.bench_function("garble_batch::aes-16", move |bench| {
+        bench.iter(|| garble_batch(&mut circ, 16));
+    });
+    let mut circ = circuit("circuits/AES-non-expanded.txt");
+    c.bench_function("garble::aes-16", move |bench| {
+        bench.iter(|| (0..16).map(|_| garble(&mut circ)).collect::<Vec<_>>());
+    });
+}
+
 fn bench_garble_sha_1(c: &mut Criterion) {
     let mut circ = circuit("circuits/sha-1.txt");
     c.bench_function("garble::sha-1", move |bench| {
@@ -52,6 +80,23 @@ fn bench_eval_sha_1(c: &mut Criterion) {
     });
 }
 
+fn bench_garble_parallel_sha_1(c: &mut Criterion) {
+    let circ = circuit("circuits/sha-1.txt");
+    c.bench_function("garble_parallel::sha-1", move |bench| {
+        bench.iter(|| garble_parallel(&circ));
+    });
+}
+
+fn bench_eval_parallel_sha_1(c: &mut Criterion) {
+    let circ = circuit("circuits/sha-1.txt");
+    let (en, gc) = garble_parallel(&circ).unwrap();
+    let gb = en.encode_garbler_inputs(&vec![0u16; 512]);
+    let ev = en.encode_evaluator_inputs(&[]);
+    c.bench_function("eval_parallel::sha-1", move |bench| {
+        bench.iter(|| gc.eval_parallel(&circ, &gb, &ev));
+    });
+}
+
 fn bench_garble_sha_256(c: &mut Criterion) {
     let mut circ = circuit("circuits/sha-256.txt");
     c.bench_function("garble::sha-256", move |bench| {
@@ -72,7 +117,7 @@ fn bench_eval_sha_256(c: &mut Criterion) {
 criterion_group! {
     name = parsing;
     config = Criterion::default().warm_up_time(Duration::from_millis(100));
-    targets = bench_garble_aes, bench_eval_aes, bench_garble_sha_1, bench_eval_sha_1, bench_garble_sha_256, bench_eval_sha_256
+    targets = bench_garble_aes, bench_eval_aes, bench_garble_parallel_aes, bench_eval_parallel_aes, bench_garble_batch_aes, bench_garble_sha_1, bench_eval_sha_1, bench_garble_parallel_sha_1, bench_eval_parallel_sha_1, bench_garble_sha_256, bench_eval_sha_256
 }
 
 criterion_main!(parsing);
@@ -3,13 +3,23 @@
 
 use crate::dummy::DummyVal;
 use crate::error::{CircuitBuilderError, DummyError, FancyError, InformerError};
-use crate::fancy::{BinaryBundle, CrtBundle, Fancy, FancyInput, HasModulus};
-use crate::informer::InformerVal;
+use crate::fancy::{
+    build_branches, multiplex, BinaryBundle, Branch, CrtBundle, Fancy, FancyBranch, FancyInput,
+    HasModulus,
+};
+use crate::garble::ProjScheme;
+use crate::informer::{Informer, InformerReport, InformerVal};
 use itertools::Itertools;
 use std::collections::HashMap;
 
+mod dot;
+mod optimize;
+pub mod text;
+pub use optimize::OptimizationReport;
+
 /// The index and modulus of a gate in a circuit.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct CircuitRef {
     pub(crate) ix: usize,
     pub(crate) modulus: u16,
@@ -29,6 +39,7 @@ impl HasModulus for CircuitRef {
 
 /// Static representation of the type of computation supported by fancy garbling.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Circuit {
     pub(crate) gates: Vec<Gate>,
     pub(crate) gate_moduli: Vec<u16>,
@@ -37,10 +48,70 @@ pub struct Circuit {
     pub(crate) const_refs: Vec<CircuitRef>,
     pub(crate) output_refs: Vec<CircuitRef>,
     pub(crate) num_nonfree_gates: usize,
+    pub(crate) scopes: Vec<Scope>,
+    // The innermost scope of each gate. Gates past the end are not in any scope.
+    pub(crate) gate_scopes: Vec<Option<usize>>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) conditionals: Vec<Conditional>,
+}
+
+/// A conditional recorded by `CircuitBuilder::branch`. The gates from `start` to
+/// `end` multiplex its branches into `outputs`, so any `Fancy` object can evaluate
+/// the circuit, but static garbling stacks the branches instead.
+///
+/// Optimizing a circuit or writing it in the text format drops its conditionals,
+/// leaving the multiplexers.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Conditional {
+    pub(crate) cond: CircuitRef,
+    pub(crate) inputs: Vec<CircuitRef>,
+    pub(crate) branches: Vec<Circuit>,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) outputs: Vec<CircuitRef>,
+}
+
+impl Conditional {
+    /// The number of bytes in a stacked garbling of the conditional with the default
+    /// schemes: the seed, demultiplexer and multiplexer tables, the length of the
+    /// stack and the largest branch.
+    fn garbled_size(&self) -> Result<usize, FancyError> {
+        let b = self.branches.len();
+        let mut stack = 0;
+        for c in self.branches.iter() {
+            let (operands, _) = c.operand_gates()?;
+            let blocks = (0..c.gates.len())
+                .map(|i| c.gate_blocks(i, &operands[i]))
+                .sum::<usize>();
+            stack = stack.max(blocks);
+        }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|r| b * b * r.modulus() as usize)
+            .sum::<usize>();
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|r| 2 * b * (r.modulus() as usize + b - 1))
+            .sum::<usize>();
+        Ok(16 * (b * b + inputs + stack + outputs) + 8)
+    }
+}
+
+/// A named group of gates, such as those built by one call to a gadget. Scopes are
+/// numbered in the order they were opened, so parents come before their children.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Scope {
+    pub(crate) name: String,
+    pub(crate) parent: Option<usize>,
 }
 
 /// The most basic types of computation supported by fancy garbling.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum Gate {
     GarblerInput {
         id: usize,
@@ -114,18 +185,154 @@ impl Circuit {
             output_refs: Vec::new(),
             gate_moduli: Vec::new(),
             num_nonfree_gates: 0,
+            scopes: Vec::new(),
+            gate_scopes: Vec::new(),
+            conditionals: Vec::new(),
         }
     }
 
+    /// Return the innermost scope of gate `i`, if any.
+    pub(crate) fn gate_scope(&self, i: usize) -> Option<usize> {
+        self.gate_scopes.get(i).cloned().unwrap_or(None)
+    }
+
+    /// Check that the scopes of the circuit refer to existing gates and scopes, and
+    /// that every parent comes before its children.
+    pub(crate) fn check_scopes(&self) -> Result<(), String> {
+        if self.gate_scopes.len() > self.gates.len() {
+            return Err(format!(
+                "{} gate scopes for {} gates",
+                self.gate_scopes.len(),
+                self.gates.len()
+            ));
+        }
+        for (i, scope) in self.scopes.iter().enumerate() {
+            match scope.parent {
+                Some(p) if p >= i => {
+                    return Err(format!("scope {} has parent {} after it", i, p));
+                }
+                _ => (),
+            }
+        }
+        match self
+            .gate_scopes
+            .iter()
+            .flatten()
+            .find(|&&s| s >= self.scopes.len())
+        {
+            Some(s) => Err(format!("scope {} out of range", s)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the conditionals of the circuit are in order and do not overlap,
+    /// that they only read values computed before them, and that their branches
+    /// match their condition, inputs and outputs.
+    pub(crate) fn check_conditionals(&self) -> Result<(), String> {
+        let moduli = |rs: &[CircuitRef]| rs.iter().map(|r| r.modulus()).collect::<Vec<u16>>();
+        let mut end = 0;
+        for c in self.conditionals.iter() {
+            if c.start < end || c.end <= c.start || c.end > self.gates.len() {
+                return Err(format!("conditional on gates {}..{}", c.start, c.end));
+            }
+            end = c.end;
+            let refs = std::iter::once(&c.cond).chain(c.inputs.iter());
+            if let Some(r) = refs.clone().find(|r| r.ix >= c.start) {
+                return Err(format!("conditional reads {} computed after it", r));
+            }
+            if let Some(r) = c.outputs.iter().find(|r| r.ix < c.start || r.ix >= c.end) {
+                return Err(format!("conditional writes {} outside its gates", r));
+            }
+            if let Some(r) = refs
+                .chain(c.outputs.iter())
+                .find(|r| self.gate_moduli[r.ix] != r.modulus())
+            {
+                return Err(format!("conditional reference {} has the wrong modulus", r));
+            }
+            if c.branches.len() != c.cond.modulus() as usize {
+                return Err(format!(
+                    "{} branches for a condition of modulus {}",
+                    c.branches.len(),
+                    c.cond.modulus()
+                ));
+            }
+            if c.branches.iter().any(|b| {
+                b.num_evaluator_inputs() > 0
+                    || moduli(&b.garbler_input_refs) != moduli(&c.inputs)
+                    || moduli(&b.output_refs) != moduli(&c.outputs)
+            }) {
+                return Err("branch does not match its conditional".to_string());
+            }
+        }
+        Ok(())
+    }
+
     /// Evaluate the circuit using fancy object `f`.
     pub fn eval<F: Fancy>(
         &mut self,
         f: &mut F,
         garbler_inputs: &[F::Item],
         evaluator_inputs: &[F::Item],
+    ) -> Result<Vec<F::Item>, F::Error> {
+        self.eval_with(f, garbler_inputs, evaluator_inputs, None)
+    }
+
+    /// Evaluate the circuit using fancy object `f`, computing the conditionals
+    /// recorded by `CircuitBuilder::branch` with `f.branch` instead of their
+    /// multiplexers.
+    pub(crate) fn eval_branching<F: FancyBranch>(
+        &mut self,
+        f: &mut F,
+        garbler_inputs: &[F::Item],
+        evaluator_inputs: &[F::Item],
+    ) -> Result<Vec<F::Item>, F::Error> {
+        self.eval_with(
+            f,
+            garbler_inputs,
+            evaluator_inputs,
+            Some(eval_conditional::<F>),
+        )
+    }
+
+    /// Evaluate the circuit using fancy object `f`, computing each conditional with
+    /// `branch` if it is given.
+    fn eval_with<F: Fancy>(
+        &mut self,
+        f: &mut F,
+        garbler_inputs: &[F::Item],
+        evaluator_inputs: &[F::Item],
+        branch: Option<BranchFn<F>>,
     ) -> Result<Vec<F::Item>, F::Error> {
         let mut cache: Vec<Option<F::Item>> = vec![None; self.gates.len()];
+        let mut open_scopes = Vec::new();
+        // the next conditional, and the end of the gates of the last one computed
+        let mut next = 0;
+        let mut skip_to = 0;
         for (i, gate) in self.gates.iter().enumerate() {
+            if i < skip_to {
+                continue;
+            }
+            if !self.scopes.is_empty() {
+                self.enter_scope(f, &mut open_scopes, self.gate_scope(i));
+            }
+            if let (Some(branch), Some(c)) = (branch, self.conditionals.get(next)) {
+                if c.start == i {
+                    let get = |r: &CircuitRef| {
+                        cache[r.ix]
+                            .clone()
+                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))
+                    };
+                    let cond = get(&c.cond)?;
+                    let inputs = c.inputs.iter().map(get).collect::<Result<Vec<_>, _>>()?;
+                    let zs = branch(f, c, &cond, &inputs)?;
+                    for (r, z) in c.outputs.iter().zip(zs) {
+                        cache[r.ix] = Some(z);
+                    }
+                    next += 1;
+                    skip_to = c.end;
+                    continue;
+                }
+            }
             let q = self.modulus(i);
             let (zref_, val) = match *gate {
                 Gate::GarblerInput { id } => (None, garbler_inputs[id].clone()),
@@ -198,6 +405,7 @@ impl Circuit {
             };
             cache[zref_.unwrap_or(i)] = Some(val);
         }
+        self.enter_scope(f, &mut open_scopes, None);
         let mut outputs = Vec::with_capacity(self.output_refs.len());
         for r in self.output_refs.iter() {
             let out = cache[r.ix]
@@ -208,6 +416,117 @@ impl Circuit {
         Ok(outputs)
     }
 
+    /// Close and open scopes of `f` so that the scopes in `open`, innermost last, are
+    /// `scope` and its ancestors.
+    fn enter_scope<F: Fancy>(&self, f: &mut F, open: &mut Vec<usize>, scope: Option<usize>) {
+        if open.last().cloned() == scope {
+            return;
+        }
+        let mut path = Vec::new();
+        let mut s = scope;
+        while let Some(x) = s {
+            path.push(x);
+            s = self.scopes[x].parent;
+        }
+        path.reverse();
+        let common = open
+            .iter()
+            .zip(path.iter())
+            .take_while(|(x, y)| x == y)
+            .count();
+        for _ in common..open.len() {
+            f.pop_scope();
+        }
+        open.truncate(common);
+        for &x in path[common..].iter() {
+            f.push_scope(&self.scopes[x].name);
+            open.push(x);
+        }
+    }
+
+    /// Find the gates defining the operands of each gate, and the gates defining the
+    /// outputs. Gates may write their output to the slot of another gate, so the index
+    /// of a `CircuitRef` is not necessarily the gate which computed its value.
+    pub(crate) fn operand_gates(&self) -> Result<(Vec<Vec<usize>>, Vec<usize>), FancyError> {
+        let mut defs = vec![None; self.gates.len()];
+        let mut operands = Vec::with_capacity(self.gates.len());
+        let resolve = |defs: &[Option<usize>], r: &CircuitRef| {
+            defs.get(r.ix)
+                .cloned()
+                .unwrap_or(None)
+                .ok_or(FancyError::UninitializedValue)
+        };
+        for (i, gate) in self.gates.iter().enumerate() {
+            let (xs, out) = match gate {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } | Gate::Constant { .. } => {
+                    (vec![], None)
+                }
+                Gate::Add { xref, yref, out }
+                | Gate::Sub { xref, yref, out }
+                | Gate::Mul {
+                    xref, yref, out, ..
+                } => (vec![resolve(&defs, xref)?, resolve(&defs, yref)?], *out),
+                Gate::Cmul { xref, out, .. } | Gate::Proj { xref, out, .. } => {
+                    (vec![resolve(&defs, xref)?], *out)
+                }
+            };
+            operands.push(xs);
+            defs[out.unwrap_or(i)] = Some(i);
+        }
+        let outputs = self
+            .output_refs
+            .iter()
+            .map(|r| resolve(&defs, r))
+            .collect::<Result<Vec<usize>, FancyError>>()?;
+        Ok((operands, outputs))
+    }
+
+    /// The number of ciphertexts the default garbling schemes produce for a gate with
+    /// operands `xs`: `qx + qy - 2` for a half-gate multiplication, plus one if the
+    /// moduli differ, and `q_in - 1` for a projection.
+    pub(crate) fn gate_ciphertexts(&self, gate: &Gate, xs: &[usize]) -> usize {
+        match gate {
+            Gate::Mul { .. } => {
+                let (qx, qy) = (self.gate_moduli[xs[0]], self.gate_moduli[xs[1]]);
+                qx as usize + qy as usize - 2 + (qx != qy) as usize
+            }
+            Gate::Proj { .. } => self.gate_moduli[xs[0]] as usize - 1,
+            _ => 0,
+        }
+    }
+
+    /// The number of blocks the default garbling schemes produce for gate `i` with
+    /// operands `xs`: its ciphertexts, or a wirelabel for a constant.
+    fn gate_blocks(&self, i: usize, xs: &[usize]) -> usize {
+        match self.gates[i] {
+            Gate::Constant { .. } => 1,
+            ref gate => self.gate_ciphertexts(gate, xs),
+        }
+    }
+
+    /// The number of blocks in a garbling of the circuit with the default schemes: the
+    /// ciphertexts of its gates, a wirelabel for each constant, the stacked garbling
+    /// of each conditional and a decoding ciphertext for each value of each output.
+    pub(crate) fn garbled_size(&self) -> Result<usize, FancyError> {
+        let (operands, outputs) = self.operand_gates()?;
+        let mut stacked = vec![false; self.gates.len()];
+        let mut bytes = 0;
+        for c in self.conditionals.iter() {
+            stacked[c.start..c.end].iter_mut().for_each(|x| *x = true);
+            bytes += c.garbled_size()?;
+        }
+        let gates = (0..self.gates.len())
+            .filter(|&i| !stacked[i])
+            .map(|i| self.gate_blocks(i, &operands[i]))
+            .sum::<usize>();
+        let outputs = outputs
+            .iter()
+            .map(|&x| self.gate_moduli[x] as usize)
+            .sum::<usize>();
+        // the lengths of the stacks leave the last block partly filled
+        Ok((bytes + 16 * (gates + outputs)).div_ceil(16))
+    }
+
     /// Process the outputs provided by `outputs` using fancy object `f`.
     pub fn process_outputs<F: Fancy>(
         &mut self,
@@ -255,13 +574,37 @@ impl Circuit {
 
     /// Print circuit info.
     pub fn print_info(&mut self) -> Result<(), InformerError> {
-        let mut informer = crate::informer::Informer::new();
+        self.informer()?.print_info();
+        Ok(())
+    }
+
+    /// Run the circuit through an `Informer` and collect the information it gathers.
+    pub fn report(&mut self) -> Result<InformerReport, InformerError> {
+        Ok(self.informer()?.report())
+    }
+
+    /// Like `report`, counting the ciphertexts of projections garbled with `scheme`.
+    pub fn report_with_proj_scheme(
+        &mut self,
+        scheme: ProjScheme,
+    ) -> Result<InformerReport, InformerError> {
+        Ok(self
+            .run_informer(Informer::new().with_proj_scheme(scheme))?
+            .report())
+    }
 
+    /// Run the circuit through an `Informer`.
+    pub(crate) fn informer(&mut self) -> Result<Informer, InformerError> {
+        self.run_informer(Informer::new())
+    }
+
+    /// Run the circuit through `informer`.
+    fn run_informer(&mut self, mut informer: Informer) -> Result<Informer, InformerError> {
         // encode inputs as InformerVals
         let gb = self
             .garbler_input_refs
             .iter()
-            .map(|r| informer.receive(r.modulus()))
+            .map(|r| informer.encode(0, r.modulus()))
             .collect::<Result<Vec<InformerVal>, InformerError>>()?;
         let ev = self
             .evaluator_input_refs
@@ -271,8 +614,7 @@ impl Circuit {
 
         let outputs = self.eval(&mut informer, &gb, &ev)?;
         self.process_outputs(&outputs, &mut informer)?;
-        informer.print_info();
-        Ok(())
+        Ok(informer)
     }
 
     /// Return the number of garbler inputs.
@@ -314,15 +656,84 @@ impl Circuit {
     }
 }
 
+/// Computes a conditional on the values of its condition and inputs, for
+/// `Circuit::eval_with`.
+type BranchFn<F> = fn(
+    &mut F,
+    &Conditional,
+    &<F as Fancy>::Item,
+    &[<F as Fancy>::Item],
+) -> Result<Vec<<F as Fancy>::Item>, <F as Fancy>::Error>;
+
+/// Compute the conditional `c` with `f.branch`, rebuilding its branches from their
+/// circuits.
+fn eval_conditional<F: FancyBranch>(
+    f: &mut F,
+    c: &Conditional,
+    cond: &F::Item,
+    inputs: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let branches = c
+        .branches
+        .iter()
+        .map(|b| {
+            move |builder: &mut CircuitBuilder,
+                  xs: &[CircuitRef]|
+                  -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+                b.clone().eval(builder, xs, &[])
+            }
+        })
+        .collect::<Vec<_>>();
+    let branches = branches
+        .iter()
+        .map(|b| b as Branch)
+        .collect::<Vec<Branch>>();
+    f.branch(cond, inputs, &branches)
+}
+
 /// CircuitBuilder is used to build circuits.
 pub struct CircuitBuilder {
     next_ref_ix: usize,
     next_garbler_input_id: usize,
     next_evaluator_input_id: usize,
     const_map: HashMap<(u16, u16), CircuitRef>,
+    scope_stack: Vec<usize>,
     circ: Circuit,
 }
 
+impl FancyBranch for CircuitBuilder {
+    /// Multiplex the branches, and record them so that `garble` and
+    /// `GarbledCircuit::eval` stack them instead.
+    fn branch(
+        &mut self,
+        cond: &CircuitRef,
+        inputs: &[CircuitRef],
+        branches: &[Branch],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        let mut circuits = build_branches(cond, inputs, branches)?;
+        let start = self.circ.gates.len();
+        let outputs = multiplex(self, cond, inputs, &mut circuits)?;
+        let end = self.circ.gates.len();
+        // constants built by the multiplexer are not computed when it is stacked
+        self.const_map.retain(|_, r| r.ix < start);
+        if !outputs.is_empty() && outputs.iter().all(|r| r.ix >= start) {
+            // only the conditionals of the circuit itself are stacked
+            for c in circuits.iter_mut() {
+                c.conditionals.clear();
+            }
+            self.circ.conditionals.push(Conditional {
+                cond: *cond,
+                inputs: inputs.to_vec(),
+                branches: circuits,
+                start,
+                end,
+                outputs: outputs.clone(),
+            });
+        }
+        Ok(outputs)
+    }
+}
+
 impl Fancy for CircuitBuilder {
     type Item = CircuitRef;
     type Error = CircuitBuilderError;
@@ -413,6 +824,21 @@ impl Fancy for CircuitBuilder {
         self.circ.output_refs.push(xref.clone());
         Ok(())
     }
+
+    /// Open a scope called `name`, which tags every gate built until the matching
+    /// `pop_scope`. The scopes are shown as subgraphs by `Circuit::to_dot`.
+    fn push_scope(&mut self, name: &str) {
+        let parent = self.scope_stack.last().cloned();
+        self.scope_stack.push(self.circ.scopes.len());
+        self.circ.scopes.push(Scope {
+            name: name.to_string(),
+            parent,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scope_stack.pop();
+    }
 }
 
 impl CircuitBuilder {
@@ -423,6 +849,7 @@ impl CircuitBuilder {
             next_garbler_input_id: 0,
             next_evaluator_input_id: 0,
             const_map: HashMap::new(),
+            scope_stack: Vec::new(),
             circ: Circuit::new(None),
         }
     }
@@ -460,6 +887,10 @@ impl CircuitBuilder {
         self.circ.gates.push(gate);
         self.circ.gate_moduli.push(modulus);
         let ix = self.get_next_ref_ix();
+        if let Some(&s) = self.scope_stack.last() {
+            self.circ.gate_scopes.resize(ix, None);
+            self.circ.gate_scopes.push(Some(s));
+        }
         CircuitRef { ix, modulus }
     }
 
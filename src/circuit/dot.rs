@@ -0,0 +1,311 @@
+//! Export of `Circuit`s to Graphviz DOT, for inspecting what gadgets build.
+
+use super::{Circuit, Gate};
+use crate::error::FancyError;
+use itertools::Itertools;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Escape `s` for use inside a quoted DOT string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A short description of a gate, without its operands.
+fn gate_label(gate: &Gate) -> String {
+    match gate {
+        Gate::GarblerInput { id } => format!("garbler input {}", id),
+        Gate::EvaluatorInput { id } => format!("evaluator input {}", id),
+        Gate::Constant { val } => format!("const {}", val),
+        Gate::Add { .. } => "add".to_string(),
+        Gate::Sub { .. } => "sub".to_string(),
+        Gate::Cmul { c, .. } => format!("cmul {}", c),
+        Gate::Mul { .. } => "mul".to_string(),
+        Gate::Proj { tt, .. } => format!("proj [{}]", tt.iter().join(",")),
+    }
+}
+
+/// The DOT attributes of the node for a gate. Inputs and constants get their own
+/// shapes, and the non-free gates are filled.
+fn gate_style(gate: &Gate) -> &'static str {
+    match gate {
+        Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } => {
+            "shape=invhouse, style=filled, fillcolor=lightblue"
+        }
+        Gate::Constant { .. } => "shape=ellipse",
+        Gate::Add { .. } | Gate::Sub { .. } | Gate::Cmul { .. } => "shape=box",
+        Gate::Mul { .. } | Gate::Proj { .. } => "shape=box, style=filled, fillcolor=salmon",
+    }
+}
+
+impl Circuit {
+    /// Render the circuit as a Graphviz graph in the DOT language, for example to be
+    /// drawn with `dot -Tsvg`.
+    ///
+    /// Each gate is a node labelled with its index, type and modulus. Inputs are
+    /// blue, and the non-free gates, which need ciphertexts when garbled, are red.
+    /// Outputs are separate nodes. Gates built inside a `CircuitBuilder::push_scope`
+    /// are drawn inside a box labelled with the name of the scope, except that every
+    /// scope whose name is in `collapse` is drawn as a single node counting its gates
+    /// and the ciphertexts they need with the default garbling schemes.
+    pub fn to_dot(&self, collapse: &[&str]) -> Result<String, FancyError> {
+        let (operands, outputs) = self.operand_gates()?;
+        let nscopes = self.scopes.len();
+
+        // The outermost collapsed scope containing each scope, if any.
+        let mut collapsed = vec![None; nscopes];
+        for (s, scope) in self.scopes.iter().enumerate() {
+            collapsed[s] = scope
+                .parent
+                .and_then(|p| collapsed[p])
+                .or_else(|| Some(s).filter(|_| collapse.contains(&scope.name.as_str())));
+        }
+        let node = |i: usize| match self.gate_scope(i).and_then(|s| collapsed[s]) {
+            Some(s) => format!("s{}", s),
+            None => format!("g{}", i),
+        };
+
+        // The gates drawn directly inside each scope, with the top level last, and the
+        // gates and ciphertexts of each collapsed scope.
+        let mut members = vec![Vec::new(); nscopes + 1];
+        let mut sizes = vec![(0, 0); nscopes];
+        for (i, gate) in self.gates.iter().enumerate() {
+            let scope = self.gate_scope(i);
+            match scope.and_then(|s| collapsed[s]) {
+                Some(s) => {
+                    sizes[s].0 += 1;
+                    sizes[s].1 += self.gate_ciphertexts(gate, &operands[i]);
+                }
+                None => members[scope.unwrap_or(nscopes)].push(i),
+            }
+        }
+        // Collapsed scopes are drawn in their parent, and empty scopes are skipped.
+        let mut children = vec![Vec::new(); nscopes + 1];
+        let mut nonempty = vec![false; nscopes + 1];
+        for (s, scope) in self.scopes.iter().enumerate().rev() {
+            let parent = scope.parent.unwrap_or(nscopes);
+            if collapsed[s] == Some(s) {
+                nonempty[s] = sizes[s].0 > 0;
+            } else if collapsed[s].is_some() {
+                continue;
+            } else {
+                nonempty[s] |= !members[s].is_empty();
+            }
+            if nonempty[s] {
+                nonempty[parent] = true;
+                children[parent].push(s);
+            }
+        }
+
+        let mut dot = String::new();
+        let mut stack = vec![(nscopes, 1, false)];
+        writeln!(dot, "digraph circuit {{").unwrap();
+        while let Some((s, depth, close)) = stack.pop() {
+            let indent = "    ".repeat(depth);
+            if close {
+                writeln!(dot, "{}}}", "    ".repeat(depth - 1)).unwrap();
+                continue;
+            }
+            if s < nscopes && collapsed[s] == Some(s) {
+                write!(
+                    dot,
+                    "{}s{} [label=\"{}\\n{} gates, {} ciphertexts\", ",
+                    indent,
+                    s,
+                    escape(&self.scopes[s].name),
+                    sizes[s].0,
+                    sizes[s].1
+                )
+                .unwrap();
+                writeln!(dot, "shape=box3d, style=filled, fillcolor=khaki];").unwrap();
+                continue;
+            }
+            let depth = if s < nscopes {
+                writeln!(dot, "{}subgraph cluster_s{} {{", indent, s).unwrap();
+                writeln!(
+                    dot,
+                    "{}    label=\"{}\";",
+                    indent,
+                    escape(&self.scopes[s].name)
+                )
+                .unwrap();
+                stack.push((s, depth + 1, true));
+                depth + 1
+            } else {
+                depth
+            };
+            let indent = "    ".repeat(depth);
+            for &i in members[s].iter() {
+                writeln!(
+                    dot,
+                    "{}g{} [label=\"{}: {}\\nmod {}\", {}];",
+                    indent,
+                    i,
+                    i,
+                    gate_label(&self.gates[i]),
+                    self.gate_moduli[i],
+                    gate_style(&self.gates[i])
+                )
+                .unwrap();
+            }
+            stack.extend(children[s].iter().map(|&c| (c, depth, false)));
+        }
+        for i in 0..outputs.len() {
+            writeln!(
+                dot,
+                "    o{} [label=\"output {}\", shape=house, style=filled, fillcolor=palegreen];",
+                i, i
+            )
+            .unwrap();
+        }
+
+        let mut edges = HashSet::new();
+        for (i, xs) in operands.iter().enumerate() {
+            let to = node(i);
+            for &x in xs.iter() {
+                let from = node(x);
+                if from != to && edges.insert((from.clone(), to.clone())) {
+                    writeln!(dot, "    {} -> {};", from, to).unwrap();
+                }
+            }
+        }
+        for (i, &x) in outputs.iter().enumerate() {
+            writeln!(dot, "    {} -> o{};", node(x), i).unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        Ok(dot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circuit::{Circuit, CircuitBuilder, Gate};
+    use crate::{BundleGadgets, CrtGadgets, Fancy};
+
+    #[test]
+    fn gates_and_edges() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let y = b.evaluator_input(3);
+        let z = b.mul(&x, &y).unwrap();
+        let z = b.cmul(&z, 2).unwrap();
+        let z = b.proj(&z, 2, Some(vec![0, 1, 1])).unwrap();
+        b.output(&z).unwrap();
+        let dot = b.finish().to_dot(&[]).unwrap();
+
+        assert!(dot.starts_with("digraph circuit {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("g0 [label=\"0: garbler input 0\\nmod 3\", shape=invhouse"));
+        assert!(dot.contains("g2 [label=\"2: mul\\nmod 3\", shape=box, style=filled"));
+        assert!(dot.contains("g3 [label=\"3: cmul 2\\nmod 3\", shape=box];"));
+        assert!(dot.contains("g4 [label=\"4: proj [0,1,1]\\nmod 2\""));
+        for edge in &[
+            "g0 -> g2;",
+            "g1 -> g2;",
+            "g2 -> g3;",
+            "g3 -> g4;",
+            "g4 -> o0;",
+        ] {
+            assert!(dot.contains(edge), "missing edge {}", edge);
+        }
+        assert_eq!(dot.matches("->").count(), 5);
+        assert!(!dot.contains("subgraph"));
+    }
+
+    #[test]
+    fn bristol_outputs() {
+        // gates of parsed circuits write their outputs to the slots of their wires
+        let c = Circuit::parse("circuits/adder_32bit.txt").unwrap();
+        let dot = c.to_dot(&[]).unwrap();
+        assert_eq!(dot.matches("shape=house").count(), c.noutputs());
+        assert_eq!(dot.matches("shape=invhouse").count(), 64);
+    }
+
+    #[test]
+    fn scopes() {
+        let q = crate::util::modulus_with_nprimes(3);
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        b.push_scope("layer");
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.push_scope("unused");
+        b.pop_scope();
+        b.pop_scope();
+        b.output_bundle(&z).unwrap();
+        let c = b.finish();
+        let relu = c.scopes.iter().position(|s| s.name == "crt_relu").unwrap();
+        assert_eq!(c.scopes[relu].parent, Some(0));
+
+        let dot = c.to_dot(&[]).unwrap();
+        assert!(dot.contains("subgraph cluster_s0 {\n        label=\"layer\";"));
+        assert!(dot.contains(&format!(
+            "subgraph cluster_s{} {{\n            label=\"crt_relu\";",
+            relu
+        )));
+        assert!(!dot.contains("unused"));
+
+        // the gates built by crt_relu, including those of the gadgets it uses
+        let in_relu = |i: usize| {
+            let mut s = c.gate_scope(i);
+            while let Some(x) = s {
+                if x == relu {
+                    return true;
+                }
+                s = c.scopes[x].parent;
+            }
+            false
+        };
+        let gates = (0..c.gates.len())
+            .filter(|&i| in_relu(i))
+            .collect::<Vec<usize>>();
+        let (operands, _) = c.operand_gates().unwrap();
+        let ciphertexts = gates
+            .iter()
+            .map(|&i| c.gate_ciphertexts(&c.gates[i], &operands[i]))
+            .sum::<usize>();
+        let nonfree = gates
+            .iter()
+            .filter(|&&i| matches!(c.gates[i], Gate::Mul { .. } | Gate::Proj { .. }))
+            .count();
+        assert!(ciphertexts > nonfree);
+        let dot = c.to_dot(&["crt_relu"]).unwrap();
+        assert!(dot.contains(&format!(
+            "s{} [label=\"crt_relu\\n{} gates, {} ciphertexts\"",
+            relu,
+            gates.len(),
+            ciphertexts
+        )));
+        assert!(!dot.contains(&format!("cluster_s{} ", relu)));
+        assert_eq!(dot.matches("-> o").count(), 3);
+        assert_eq!(dot.matches(&format!("s{} -> o", relu)).count(), 3);
+
+        // collapsing the outer scope hides the inner one
+        let dot = c.to_dot(&["layer", "crt_relu"]).unwrap();
+        assert!(dot.contains("s0 [label=\"layer\\n"));
+        assert!(!dot.contains(&format!("s{} ", relu)));
+        assert!(!dot.contains("subgraph"));
+    }
+
+    #[test]
+    fn ciphertexts() {
+        // the ciphertexts of the gates, with the wirelabels of the constants and the
+        // decoding ciphertexts of the outputs, make up the whole garbling
+        let mut b = CircuitBuilder::new();
+        let q = crate::util::modulus_with_nprimes(3);
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        let x = b.garbler_input(2);
+        let y = b.evaluator_input(5);
+        let z = b.mul(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+
+        let (_, gc) = crate::garble(&mut c).unwrap();
+        assert_eq!(c.garbled_size().unwrap(), gc.size());
+    }
+}
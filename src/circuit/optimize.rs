@@ -0,0 +1,584 @@
+//! Optimization passes over the `Circuit`s produced by `CircuitBuilder` and the parser.
+
+use super::{Circuit, CircuitRef, Gate};
+use crate::error::{FancyError, InformerError};
+use crate::informer::Informer;
+use std::collections::HashMap;
+
+/// The savings from running `Circuit::optimize`, measured by running an `Informer`
+/// over the circuit before and after optimizing it.
+pub struct OptimizationReport {
+    /// Number of gates before optimizing.
+    pub gates_before: usize,
+    /// Number of gates after optimizing.
+    pub gates_after: usize,
+    /// Information about the circuit before optimizing.
+    pub before: Informer,
+    /// Information about the optimized circuit.
+    pub after: Informer,
+}
+
+impl OptimizationReport {
+    /// Number of gates removed by the optimizer.
+    pub fn gates_removed(&self) -> usize {
+        self.gates_before.saturating_sub(self.gates_after)
+    }
+
+    /// Number of ciphertexts saved by the optimizer.
+    pub fn ciphertexts_saved(&self) -> usize {
+        self.before
+            .num_ciphertexts()
+            .saturating_sub(self.after.num_ciphertexts())
+    }
+
+    /// Print a summary of the optimization.
+    pub fn print_info(&self) {
+        let cs = self.ciphertexts_saved();
+        println!("optimization info:");
+        println!(
+            "  gates:              {:16} -> {} ({} removed)",
+            self.gates_before,
+            self.gates_after,
+            self.gates_removed()
+        );
+        println!(
+            "  ciphertexts:        {:16} -> {} ({} saved)",
+            self.before.num_ciphertexts(),
+            self.after.num_ciphertexts(),
+            cs
+        );
+        println!(
+            "  communication saved:  {:11.2} Kb",
+            cs as f64 * 128.0 / 1000.0
+        );
+    }
+}
+
+impl Circuit {
+    /// Optimize the circuit without changing what it computes.
+    ///
+    /// The optimizer folds constants through every gate type, simplifies gates with
+    /// constant operands (for example, multiplication by a constant becomes a free
+    /// `cmul`), fuses chains of projections into a single projection, eliminates
+    /// common subexpressions, and removes gates which do not contribute to the
+    /// outputs. Inputs are never removed, so the optimized circuit takes the same
+    /// inputs as the original. Scopes are dropped, since the optimized gates no longer
+    /// correspond to the gates built inside them.
+    pub fn optimize(&mut self) -> Result<OptimizationReport, InformerError> {
+        let gates_before = self.gates.len();
+        let before = self.informer()?;
+        loop {
+            let ngates = self.gates.len();
+            let nciphertexts = self.informer()?.num_ciphertexts();
+            *self = simplify(self)?;
+            if self.gates.len() >= ngates && self.informer()?.num_ciphertexts() >= nciphertexts {
+                break;
+            }
+        }
+        let after = self.informer()?;
+        Ok(OptimizationReport {
+            gates_before,
+            gates_after: self.gates.len(),
+            before,
+            after,
+        })
+    }
+}
+
+/// Key identifying a gate for common subexpression elimination.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Constant(u16, u16),
+    Add(usize, usize),
+    Sub(usize, usize),
+    Cmul(usize, u16),
+    Mul(usize, usize),
+    Proj(usize, u16, Vec<u16>),
+}
+
+/// Rebuilds a circuit gate by gate, simplifying gates as they are added.
+struct Optimizer {
+    gates: Vec<Gate>,
+    moduli: Vec<u16>,
+    // The value of each gate, if it is a constant.
+    constants: Vec<Option<u16>>,
+    // Whether each gate is used at most once, so that fusing it into a projection
+    // will not leave it behind.
+    single_use: Vec<bool>,
+    table: HashMap<Key, usize>,
+    // Whether the old gate currently being added is used at most once.
+    current_single_use: bool,
+}
+
+impl Optimizer {
+    fn new() -> Self {
+        Optimizer {
+            gates: Vec::new(),
+            moduli: Vec::new(),
+            constants: Vec::new(),
+            single_use: Vec::new(),
+            table: HashMap::new(),
+            current_single_use: false,
+        }
+    }
+
+    fn r(&self, ix: usize) -> CircuitRef {
+        CircuitRef {
+            ix,
+            modulus: self.moduli[ix],
+        }
+    }
+
+    fn push(&mut self, gate: Gate, q: u16) -> usize {
+        let constant = match gate {
+            Gate::Constant { val } => Some(val % q),
+            _ => None,
+        };
+        self.gates.push(gate);
+        self.moduli.push(q);
+        self.constants.push(constant);
+        self.single_use.push(self.current_single_use);
+        self.gates.len() - 1
+    }
+
+    fn emit(&mut self, key: Key, gate: Gate, q: u16) -> usize {
+        if let Some(&ix) = self.table.get(&key) {
+            self.single_use[ix] = false;
+            return ix;
+        }
+        let ix = self.push(gate, q);
+        self.table.insert(key, ix);
+        ix
+    }
+
+    fn constant(&mut self, val: u16, q: u16) -> usize {
+        let val = val % q;
+        self.emit(Key::Constant(val, q), Gate::Constant { val }, q)
+    }
+
+    fn add(&mut self, x: usize, y: usize) -> usize {
+        let q = self.moduli[x];
+        match (self.constants[x], self.constants[y]) {
+            (Some(a), Some(b)) => self.constant(((a as u32 + b as u32) % q as u32) as u16, q),
+            (Some(0), None) => y,
+            (None, Some(0)) => x,
+            _ => {
+                let (x, y) = if x <= y { (x, y) } else { (y, x) };
+                let gate = Gate::Add {
+                    xref: self.r(x),
+                    yref: self.r(y),
+                    out: None,
+                };
+                self.emit(Key::Add(x, y), gate, q)
+            }
+        }
+    }
+
+    fn sub(&mut self, x: usize, y: usize) -> usize {
+        let q = self.moduli[x];
+        match (self.constants[x], self.constants[y]) {
+            (Some(a), Some(b)) => {
+                self.constant(((q as u32 + a as u32 - b as u32) % q as u32) as u16, q)
+            }
+            (_, Some(0)) => x,
+            _ if x == y => self.constant(0, q),
+            _ => {
+                let gate = Gate::Sub {
+                    xref: self.r(x),
+                    yref: self.r(y),
+                    out: None,
+                };
+                self.emit(Key::Sub(x, y), gate, q)
+            }
+        }
+    }
+
+    fn cmul(&mut self, x: usize, c: u16) -> usize {
+        let q = self.moduli[x];
+        let c = c % q;
+        match self.constants[x] {
+            _ if c == 0 => self.constant(0, q),
+            _ if c == 1 => x,
+            Some(a) => self.constant((a as u32 * c as u32 % q as u32) as u16, q),
+            None => {
+                let gate = Gate::Cmul {
+                    xref: self.r(x),
+                    c,
+                    out: None,
+                };
+                self.emit(Key::Cmul(x, c), gate, q)
+            }
+        }
+    }
+
+    // `x` has the larger modulus, which is the modulus of the output.
+    fn mul(&mut self, x: usize, y: usize) -> usize {
+        let q = self.moduli[x];
+        let qy = self.moduli[y];
+        match (self.constants[x], self.constants[y]) {
+            (Some(a), Some(b)) => self.constant((a as u32 * b as u32 % q as u32) as u16, q),
+            (_, Some(b)) => self.cmul(x, b),
+            (Some(a), _) if qy == q => self.cmul(y, a),
+            (Some(a), _) => {
+                let tt = (0..qy)
+                    .map(|i| (a as u32 * i as u32 % q as u32) as u16)
+                    .collect();
+                self.proj(y, q, tt)
+            }
+            _ => {
+                let (x, y) = if qy == q && y < x { (y, x) } else { (x, y) };
+                let gate = Gate::Mul {
+                    xref: self.r(x),
+                    yref: self.r(y),
+                    id: 0,
+                    out: None,
+                };
+                self.emit(Key::Mul(x, y), gate, q)
+            }
+        }
+    }
+
+    fn proj(&mut self, x: usize, q: u16, tt: Vec<u16>) -> usize {
+        let mut x = x;
+        let mut tt = tt[..self.moduli[x] as usize].to_vec();
+        if let Some(a) = self.constants[x] {
+            return self.constant(tt[a as usize], q);
+        }
+        // Fuse with the projection computing `x`, as long as this cannot cost more
+        // ciphertexts than projecting twice.
+        if let Gate::Proj {
+            xref,
+            tt: ref inner,
+            ..
+        } = self.gates[x]
+        {
+            if xref.modulus <= self.moduli[x] || self.single_use[x] {
+                tt = (0..xref.modulus as usize)
+                    .map(|i| tt[inner[i] as usize])
+                    .collect();
+                x = xref.ix;
+            }
+        }
+        let qx = self.moduli[x];
+        if tt.iter().all(|y| *y == tt[0]) {
+            return self.constant(tt[0], q);
+        }
+        if qx == q && tt.iter().enumerate().all(|(i, y)| i == *y as usize) {
+            return x;
+        }
+        let gate = Gate::Proj {
+            xref: self.r(x),
+            tt: tt.clone(),
+            id: 0,
+            out: None,
+        };
+        self.emit(Key::Proj(x, q, tt), gate, q)
+    }
+
+    /// Remove gates not needed for `outputs`, and build the final circuit.
+    fn finish(self, outputs: &[usize]) -> Circuit {
+        let mut live = vec![false; self.gates.len()];
+        for ix in outputs.iter() {
+            live[*ix] = true;
+        }
+        for i in (0..self.gates.len()).rev() {
+            match self.gates[i] {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } => live[i] = true,
+                _ if !live[i] => (),
+                Gate::Constant { .. } => (),
+                Gate::Add { xref, yref, .. }
+                | Gate::Sub { xref, yref, .. }
+                | Gate::Mul { xref, yref, .. } => {
+                    live[xref.ix] = true;
+                    live[yref.ix] = true;
+                }
+                Gate::Cmul { xref, .. } | Gate::Proj { xref, .. } => live[xref.ix] = true,
+            }
+        }
+
+        let mut c = Circuit::new(Some(live.iter().filter(|x| **x).count()));
+        let mut ixs = vec![0; self.gates.len()];
+        for (i, gate) in self.gates.into_iter().enumerate() {
+            if !live[i] {
+                continue;
+            }
+            let ix = c.gates.len();
+            let q = self.moduli[i];
+            let r = CircuitRef { ix, modulus: q };
+            let remap = |r: CircuitRef| CircuitRef {
+                ix: ixs[r.ix],
+                modulus: r.modulus,
+            };
+            let gate = match gate {
+                Gate::GarblerInput { id } => {
+                    c.garbler_input_refs.push(r);
+                    Gate::GarblerInput { id }
+                }
+                Gate::EvaluatorInput { id } => {
+                    c.evaluator_input_refs.push(r);
+                    Gate::EvaluatorInput { id }
+                }
+                Gate::Constant { val } => {
+                    c.const_refs.push(r);
+                    Gate::Constant { val }
+                }
+                Gate::Add { xref, yref, .. } => Gate::Add {
+                    xref: remap(xref),
+                    yref: remap(yref),
+                    out: None,
+                },
+                Gate::Sub { xref, yref, .. } => Gate::Sub {
+                    xref: remap(xref),
+                    yref: remap(yref),
+                    out: None,
+                },
+                Gate::Cmul { xref, c: k, .. } => Gate::Cmul {
+                    xref: remap(xref),
+                    c: k,
+                    out: None,
+                },
+                Gate::Mul { xref, yref, .. } => {
+                    c.num_nonfree_gates += 1;
+                    Gate::Mul {
+                        xref: remap(xref),
+                        yref: remap(yref),
+                        id: c.num_nonfree_gates - 1,
+                        out: None,
+                    }
+                }
+                Gate::Proj { xref, tt, .. } => {
+                    c.num_nonfree_gates += 1;
+                    Gate::Proj {
+                        xref: remap(xref),
+                        tt,
+                        id: c.num_nonfree_gates - 1,
+                        out: None,
+                    }
+                }
+            };
+            c.gates.push(gate);
+            c.gate_moduli.push(q);
+            ixs[i] = ix;
+        }
+        let gates = &c.gates;
+        c.garbler_input_refs.sort_by_key(|r| match gates[r.ix] {
+            Gate::GarblerInput { id } => id,
+            _ => unreachable!(),
+        });
+        c.evaluator_input_refs.sort_by_key(|r| match gates[r.ix] {
+            Gate::EvaluatorInput { id } => id,
+            _ => unreachable!(),
+        });
+        c.output_refs = outputs
+            .iter()
+            .map(|ix| CircuitRef {
+                ix: ixs[*ix],
+                modulus: c.gate_moduli[ixs[*ix]],
+            })
+            .collect();
+        c
+    }
+}
+
+/// Run a single pass of the optimizer over `c`.
+fn simplify(c: &Circuit) -> Result<Circuit, FancyError> {
+    // Gates may write their output to an arbitrary slot, so first find the gate
+    // defining each operand, and how many times each gate is used.
+    let (operands, outputs) = c.operand_gates()?;
+    let mut nuses = vec![0; c.gates.len()];
+    for x in operands.iter().flatten() {
+        nuses[*x] += 1;
+    }
+    for x in outputs.iter() {
+        nuses[*x] += 1;
+    }
+
+    let mut opt = Optimizer::new();
+    let mut ixs = Vec::with_capacity(c.gates.len());
+    for (i, gate) in c.gates.iter().enumerate() {
+        opt.current_single_use = nuses[i] <= 1;
+        let q = c.gate_moduli[i];
+        let xs = operands[i].iter().map(|x| ixs[*x]).collect::<Vec<usize>>();
+        let ix = match gate {
+            Gate::GarblerInput { id } => opt.push(Gate::GarblerInput { id: *id }, q),
+            Gate::EvaluatorInput { id } => opt.push(Gate::EvaluatorInput { id: *id }, q),
+            Gate::Constant { val } => opt.constant(*val, q),
+            Gate::Add { .. } => opt.add(xs[0], xs[1]),
+            Gate::Sub { .. } => opt.sub(xs[0], xs[1]),
+            Gate::Cmul { c, .. } => opt.cmul(xs[0], *c),
+            Gate::Mul { .. } => {
+                if opt.moduli[xs[0]] < opt.moduli[xs[1]] {
+                    opt.mul(xs[1], xs[0])
+                } else {
+                    opt.mul(xs[0], xs[1])
+                }
+            }
+            Gate::Proj { tt, .. } => {
+                if tt.len() < opt.moduli[xs[0]] as usize {
+                    return Err(FancyError::InvalidTruthTable);
+                }
+                opt.proj(xs[0], q, tt.clone())
+            }
+        };
+        ixs.push(ix);
+    }
+    let outputs = outputs.iter().map(|x| ixs[*x]).collect::<Vec<usize>>();
+    Ok(opt.finish(&outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::util::RngExt;
+    use crate::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy};
+    use rand::thread_rng;
+
+    fn check_equivalent(c: &mut Circuit, opt: &mut Circuit) {
+        let mut rng = thread_rng();
+        assert_eq!(c.num_garbler_inputs(), opt.num_garbler_inputs());
+        assert_eq!(c.num_evaluator_inputs(), opt.num_evaluator_inputs());
+        for _ in 0..16 {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect::<Vec<u16>>();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect::<Vec<u16>>();
+            assert_eq!(
+                c.eval_plain(&gb, &ev).unwrap(),
+                opt.eval_plain(&gb, &ev).unwrap()
+            );
+        }
+    }
+
+    fn check_garbled(c: &mut Circuit) {
+        let mut rng = thread_rng();
+        let (en, gc) = crate::garble(c).unwrap();
+        for _ in 0..4 {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect::<Vec<u16>>();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect::<Vec<u16>>();
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            let xs = en.encode_garbler_inputs(&gb);
+            let ys = en.encode_evaluator_inputs(&ev);
+            assert_eq!(gc.eval(c, &xs, &ys).unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn constant_folding() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(7);
+        let y = b.evaluator_input(5);
+        let one = b.constant(1, 7).unwrap();
+        let three = b.constant(3, 7).unwrap();
+        let four = b.add(&one, &three).unwrap();
+        let z = b.cmul(&x, 1).unwrap();
+        let z = b.mul(&z, &four).unwrap(); // becomes a cmul
+        let w = b.proj(&three, 5, Some(vec![0, 1, 2, 3, 4, 0, 1])).unwrap();
+        let w = b.mul(&w, &y).unwrap(); // becomes a cmul
+        let v = b.mul(&three, &y).unwrap(); // becomes a projection
+        let u = b.sub(&x, &x).unwrap();
+        b.outputs(&[z, w, v, u]).unwrap();
+        let mut c = b.finish();
+        let mut opt = c.clone();
+        let report = opt.optimize().unwrap();
+        check_equivalent(&mut c, &mut opt);
+        assert_eq!(report.after.num_muls(), 0);
+        assert_eq!(report.after.num_projs(), 1);
+        assert_eq!(report.after.num_ciphertexts(), 4);
+        assert!(report.ciphertexts_saved() > 0);
+        check_garbled(&mut opt);
+    }
+
+    #[test]
+    fn proj_fusion() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let p = 17;
+        let q = 5;
+        let x = b.garbler_input(p);
+        let y = b.mod_change(&x, q).unwrap();
+        let z = b.mod_change(&y, p).unwrap();
+        let w = b.mod_change(&z, 3).unwrap();
+        b.output(&w).unwrap();
+        let mut c = b.finish();
+        let mut opt = c.clone();
+        let report = opt.optimize().unwrap();
+        assert_eq!(report.after.num_projs(), 1);
+        assert_eq!(report.after.num_ciphertexts(), p as usize - 1);
+        check_equivalent(&mut c, &mut opt);
+        for _ in 0..16 {
+            let x = rng.gen_u16() % p;
+            assert_eq!(opt.eval_plain(&[x], &[]).unwrap()[0], x % q % 3);
+        }
+        check_garbled(&mut opt);
+    }
+
+    #[test]
+    fn cse_and_dce() {
+        let mut b = CircuitBuilder::new();
+        let xs = b.garbler_inputs(&[3, 3]);
+        let ys = b.evaluator_inputs(&[3, 3]);
+        let a = b.mul(&xs[0], &ys[0]).unwrap();
+        let a_ = b.mul(&ys[0], &xs[0]).unwrap();
+        let s = b.add(&a, &xs[1]).unwrap();
+        let s_ = b.add(&xs[1], &a_).unwrap();
+        let _unused = b.mul(&xs[1], &ys[1]).unwrap();
+        let z = b.sub(&s, &s_).unwrap();
+        let z = b.add(&z, &s).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+        let mut opt = c.clone();
+        let report = opt.optimize().unwrap();
+        assert_eq!(report.before.num_muls(), 3);
+        assert_eq!(report.after.num_muls(), 1);
+        // the inputs are all kept, alongside one multiplication and addition
+        assert_eq!(opt.gates.len(), 6);
+        check_equivalent(&mut c, &mut opt);
+        check_garbled(&mut opt);
+    }
+
+    #[test]
+    fn gadgets() {
+        let mut b = CircuitBuilder::new();
+        let q = crate::util::modulus_with_width(10);
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        let x = b.bin_garbler_input(16);
+        let y = b.bin_evaluator_input(16);
+        let k = b.bin_constant_bundle(12345, 16).unwrap();
+        let z = b.bin_addition_no_carry(&x, &k).unwrap();
+        let z = b.bin_cmul(&z, 2, 16).unwrap();
+        let z = b.bin_max(&[z, y]).unwrap();
+        b.output_bundle(&z).unwrap();
+        let mut c = b.finish();
+        let mut opt = c.clone();
+        let report = opt.optimize().unwrap();
+        assert!(report.after.num_ciphertexts() <= report.before.num_ciphertexts());
+        check_equivalent(&mut c, &mut opt);
+        check_garbled(&mut opt);
+
+        // optimizing is idempotent
+        let mut again = opt.clone();
+        let report = again.optimize().unwrap();
+        assert_eq!(report.gates_removed(), 0);
+        assert_eq!(again, opt);
+    }
+
+    #[test]
+    fn parsed_circuit() {
+        let mut c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let mut opt = c.clone();
+        let report = opt.optimize().unwrap();
+        assert!(report.after.num_ciphertexts() <= report.before.num_ciphertexts());
+        check_equivalent(&mut c, &mut opt);
+    }
+}
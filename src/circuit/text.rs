@@ -0,0 +1,497 @@
+//! A native text format for `Circuit`s, which unlike Bristol represents every gate
+//! type, non-binary moduli, projection truth tables and constants.
+//!
+//! Blank lines and anything after a `#` are ignored. A file starts with a header
+//! giving the version, the input, constant and output wires, the number of
+//! ciphertext-producing gates and the number of gates, in that order:
+//!
+//! ```text
+//! fancy 1
+//! garbler_inputs 0:3
+//! evaluator_inputs 1:3
+//! constants 2:3
+//! outputs 5:2
+//! nonfree 2
+//! gates 6
+//! ```
+//!
+//! Wires are written `ix:q`, where `ix` is the index of the gate defining the wire
+//! and `q` is its modulus. The header is followed by one line per gate of the form
+//! `ix q op args`, where `ix` counts up from 0 and `q` is the modulus of the gate's
+//! output. The gates are
+//!
+//! * `gin id` and `ein id`, the `id`th garbler or evaluator input,
+//! * `const val`, a constant,
+//! * `add x y`, `sub x y` and `cmul x c`, the free operations,
+//! * `mul x y id`, a multiplication with gate number `id`,
+//! * `proj x id tt_0 .. tt_{p-1}`, a projection with gate number `id` of a wire `x`
+//!   mod `p` using the truth table `tt`.
+//!
+//! Free operations, multiplications and projections may end with `-> ix`, which
+//! stores their output at index `ix` instead, as in circuits parsed from Bristol. For
+//! example, these are the gates of the header above:
+//!
+//! ```text
+//! 0 3 gin 0
+//! 1 3 ein 0
+//! 2 3 const 2
+//! 3 3 mul 0:3 1:3 0
+//! 4 3 add 3:3 2:3
+//! 5 2 proj 4:3 1 0 1 1
+//! ```
+//!
+//! Circuits built with `CircuitBuilder::push_scope` also list their scopes between
+//! the header and the gates, one `scope name` or `scope name parent` line per scope,
+//! numbered from 0 in order, where `parent` is the number of an earlier scope. A gate
+//! line ending with `@ s` belongs to scope `s`:
+//!
+//! ```text
+//! scope relu
+//! scope sign 0
+//! 5 2 proj 4:3 1 0 1 1 @ 1
+//! ```
+
+use super::{Circuit, CircuitRef, Gate, Scope};
+use crate::error::CircuitParserError as Error;
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+
+/// The version of the native text format written by `write_native`.
+const VERSION: usize = 1;
+
+/// Parse a token of a line, or return a `ParseLineError` for the line.
+fn parse<T: FromStr>(tok: &str, line: &str) -> Result<T, Error> {
+    tok.parse()
+        .map_err(|_| Error::ParseLineError(line.to_string()))
+}
+
+/// Parse a wire of the form `ix:q`, checking that `ix` is less than `ngates`.
+fn parse_ref(tok: &str, ngates: usize, line: &str) -> Result<CircuitRef, Error> {
+    let mut parts = tok.splitn(2, ':');
+    let ix: usize = parse(parts.next().unwrap(), line)?;
+    let modulus = parse(parts.next().unwrap_or(""), line)?;
+    if ix >= ngates {
+        return Err(Error::InvalidCircuit(format!(
+            "wire {} out of range for a circuit with {} gates",
+            ix, ngates
+        )));
+    }
+    Ok(CircuitRef { ix, modulus })
+}
+
+/// Parse a header line `keyword args..`, returning the line and its arguments.
+fn parse_header<'a>(
+    line: Option<&'a String>,
+    keyword: &str,
+) -> Result<(&'a str, Vec<&'a str>), Error> {
+    let line = line.ok_or_else(|| Error::InvalidCircuit(format!("missing {}", keyword)))?;
+    let mut toks = line.split_whitespace();
+    if toks.next() != Some(keyword) {
+        return Err(Error::ParseLineError(line.to_string()));
+    }
+    Ok((line, toks.collect()))
+}
+
+/// Parse a header line `keyword n`, returning `n`.
+fn parse_count(line: Option<&String>, keyword: &str) -> Result<usize, Error> {
+    match parse_header(line, keyword)? {
+        (line, toks) if toks.len() == 1 => parse(toks[0], line),
+        (line, _) => Err(Error::ParseLineError(line.to_string())),
+    }
+}
+
+/// Parse a scope line `scope name [parent]`.
+fn parse_scope(line: &str) -> Result<Scope, Error> {
+    match line.split_whitespace().collect_vec()[..] {
+        ["scope", name] => Ok(Scope {
+            name: name.to_string(),
+            parent: None,
+        }),
+        ["scope", name, parent] => Ok(Scope {
+            name: name.to_string(),
+            parent: Some(parse(parent, line)?),
+        }),
+        _ => Err(Error::ParseLineError(line.to_string())),
+    }
+}
+
+/// Parse a gate line with index `i`, returning the gate, its modulus and its scope.
+fn parse_gate(line: &str, i: usize, ngates: usize) -> Result<(Gate, u16, Option<usize>), Error> {
+    let err = || Error::ParseLineError(line.to_string());
+    let toks = line.split_whitespace().collect_vec();
+    let (toks, scope) = match toks.iter().position(|&t| t == "@") {
+        Some(p) if p + 2 == toks.len() => (&toks[..p], Some(parse(toks[p + 1], line)?)),
+        Some(_) => return Err(err()),
+        None => (&toks[..], None),
+    };
+    let (toks, out) = match toks.iter().position(|&t| t == "->") {
+        Some(p) if p + 2 == toks.len() => {
+            let out = parse(toks[p + 1], line)?;
+            if out >= ngates {
+                return Err(err());
+            }
+            (&toks[..p], Some(out))
+        }
+        Some(_) => return Err(err()),
+        None => (toks, None),
+    };
+    if toks.len() < 3 || parse::<usize>(toks[0], line)? != i {
+        return Err(err());
+    }
+    let q: u16 = parse(toks[1], line)?;
+    let args = &toks[3..];
+    let wire = |k: usize| parse_ref(args[k], ngates, line);
+    let gate = match (toks[2], args.len(), out) {
+        ("gin", 1, None) => Gate::GarblerInput {
+            id: parse(args[0], line)?,
+        },
+        ("ein", 1, None) => Gate::EvaluatorInput {
+            id: parse(args[0], line)?,
+        },
+        ("const", 1, None) => Gate::Constant {
+            val: parse(args[0], line)?,
+        },
+        ("add", 2, _) => Gate::Add {
+            xref: wire(0)?,
+            yref: wire(1)?,
+            out,
+        },
+        ("sub", 2, _) => Gate::Sub {
+            xref: wire(0)?,
+            yref: wire(1)?,
+            out,
+        },
+        ("cmul", 2, _) => Gate::Cmul {
+            xref: wire(0)?,
+            c: parse(args[1], line)?,
+            out,
+        },
+        ("mul", 3, _) => Gate::Mul {
+            xref: wire(0)?,
+            yref: wire(1)?,
+            id: parse(args[2], line)?,
+            out,
+        },
+        ("proj", n, _) if n >= 2 => {
+            let xref = wire(0)?;
+            let tt = args[2..]
+                .iter()
+                .map(|t| parse(t, line))
+                .collect::<Result<Vec<u16>, Error>>()?;
+            if tt.len() != xref.modulus as usize || tt.iter().any(|&v| v >= q) {
+                return Err(Error::ParseGateError(line.to_string()));
+            }
+            Gate::Proj {
+                xref,
+                tt,
+                id: parse(args[1], line)?,
+                out,
+            }
+        }
+        _ => return Err(Error::ParseGateError(line.to_string())),
+    };
+    Ok((gate, q, scope))
+}
+
+/// Write ` ix:q` for each of `refs`.
+fn format_refs(refs: &[CircuitRef]) -> String {
+    refs.iter()
+        .map(|r| format!(" {}:{}", r.ix, r.modulus))
+        .collect()
+}
+
+impl Circuit {
+    /// Generates a new `Circuit` from the file `filename` in the native text format
+    /// described in `circuit::text`.
+    pub fn parse_native(filename: &str) -> Result<Self, Error> {
+        let f = File::open(filename)?;
+        Self::read_native(BufReader::new(f))
+    }
+
+    /// Reads a circuit in the native text format from `reader`. See `parse_native`.
+    pub fn read_native<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut lines = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.split('#').next().unwrap().trim();
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        let mut lines = lines.iter().peekable();
+
+        let (_, version) = parse_header(lines.next(), "fancy")?;
+        if version != [VERSION.to_string()] {
+            return Err(Error::InvalidCircuit(format!(
+                "unsupported version {}",
+                version.join(" ")
+            )));
+        }
+        let garbler_inputs = parse_header(lines.next(), "garbler_inputs")?;
+        let evaluator_inputs = parse_header(lines.next(), "evaluator_inputs")?;
+        let consts = parse_header(lines.next(), "constants")?;
+        let outputs = parse_header(lines.next(), "outputs")?;
+        let nonfree = parse_count(lines.next(), "nonfree")?;
+        let ngates = parse_count(lines.next(), "gates")?;
+
+        let refs = |(line, toks): (&str, Vec<&str>)| {
+            toks.iter()
+                .map(|t| parse_ref(t, ngates, line))
+                .collect::<Result<Vec<CircuitRef>, Error>>()
+        };
+        let mut circ = Circuit::new(Some(ngates));
+        circ.garbler_input_refs = refs(garbler_inputs)?;
+        circ.evaluator_input_refs = refs(evaluator_inputs)?;
+        circ.const_refs = refs(consts)?;
+        circ.output_refs = refs(outputs)?;
+        circ.num_nonfree_gates = nonfree;
+        while let Some(line) = lines.next_if(|l| l.starts_with("scope")) {
+            circ.scopes.push(parse_scope(line)?);
+        }
+
+        for (i, line) in lines.enumerate() {
+            if i >= ngates {
+                return Err(Error::InvalidCircuit(format!("more than {} gates", ngates)));
+            }
+            let (gate, q, scope) = parse_gate(line, i, ngates)?;
+            circ.gates.push(gate);
+            circ.gate_moduli.push(q);
+            if scope.is_some() {
+                circ.gate_scopes.resize(i, None);
+                circ.gate_scopes.push(scope);
+            }
+        }
+        if circ.gates.len() != ngates {
+            return Err(Error::InvalidCircuit(format!(
+                "expected {} gates but found {}",
+                ngates,
+                circ.gates.len()
+            )));
+        }
+        circ.check_scopes().map_err(Error::InvalidCircuit)?;
+        Ok(circ)
+    }
+
+    /// Writes this circuit to `writer` in the native text format described in
+    /// `circuit::text`. Reading the result back with `read_native` gives an equal
+    /// circuit.
+    pub fn write_native<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writeln!(writer, "fancy {}", VERSION)?;
+        writeln!(
+            writer,
+            "garbler_inputs{}",
+            format_refs(&self.garbler_input_refs)
+        )?;
+        writeln!(
+            writer,
+            "evaluator_inputs{}",
+            format_refs(&self.evaluator_input_refs)
+        )?;
+        writeln!(writer, "constants{}", format_refs(&self.const_refs))?;
+        writeln!(writer, "outputs{}", format_refs(&self.output_refs))?;
+        writeln!(writer, "nonfree {}", self.num_nonfree_gates)?;
+        writeln!(writer, "gates {}", self.gates.len())?;
+        for scope in self.scopes.iter() {
+            if scope.name.is_empty() || scope.name.contains(|c: char| c.is_whitespace() || c == '#')
+            {
+                return Err(Error::InvalidCircuit(format!(
+                    "scope name {:?} cannot be written",
+                    scope.name
+                )));
+            }
+            match scope.parent {
+                Some(p) => writeln!(writer, "scope {} {}", scope.name, p)?,
+                None => writeln!(writer, "scope {}", scope.name)?,
+            }
+        }
+        for (i, gate) in self.gates.iter().enumerate() {
+            write!(writer, "{} {} ", i, self.modulus(i))?;
+            let out = match gate {
+                Gate::GarblerInput { id } => {
+                    write!(writer, "gin {}", id)?;
+                    None
+                }
+                Gate::EvaluatorInput { id } => {
+                    write!(writer, "ein {}", id)?;
+                    None
+                }
+                Gate::Constant { val } => {
+                    write!(writer, "const {}", val)?;
+                    None
+                }
+                Gate::Add { xref, yref, out } => {
+                    write!(writer, "add{}", format_refs(&[*xref, *yref]))?;
+                    *out
+                }
+                Gate::Sub { xref, yref, out } => {
+                    write!(writer, "sub{}", format_refs(&[*xref, *yref]))?;
+                    *out
+                }
+                Gate::Cmul { xref, c, out } => {
+                    write!(writer, "cmul{} {}", format_refs(&[*xref]), c)?;
+                    *out
+                }
+                Gate::Mul {
+                    xref,
+                    yref,
+                    id,
+                    out,
+                } => {
+                    write!(writer, "mul{} {}", format_refs(&[*xref, *yref]), id)?;
+                    *out
+                }
+                Gate::Proj { xref, tt, id, out } => {
+                    write!(
+                        writer,
+                        "proj{} {} {}",
+                        format_refs(&[*xref]),
+                        id,
+                        tt.iter().join(" ")
+                    )?;
+                    *out
+                }
+            };
+            if let Some(ix) = out {
+                write!(writer, " -> {}", ix)?;
+            }
+            match self.gate_scope(i) {
+                Some(s) => writeln!(writer, " @ {}", s)?,
+                None => writeln!(writer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circuit::{Circuit, CircuitBuilder};
+    use crate::error::CircuitParserError;
+    use crate::util::{self, RngExt};
+    use crate::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy};
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    fn round_trip(circ: &Circuit) -> Circuit {
+        let mut buf = Vec::new();
+        circ.write_native(&mut buf).unwrap();
+        let parsed = Circuit::read_native(&buf[..]).unwrap();
+        assert_eq!(&parsed, circ);
+        parsed
+    }
+
+    #[test]
+    fn crt_gadgets() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(5);
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_cmul(&z, 17).unwrap();
+        let z = b.crt_sub(&z, &y).unwrap();
+        let c = b.crt_constant_bundle(1234, q).unwrap();
+        let z = b.crt_add(&z, &c).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        let w = b.crt_to_bin(&x, 8).unwrap();
+        let w = b.bin_cmul(&w, 3, 8).unwrap();
+        b.output_bundle(&w).unwrap();
+        let mut circ = b.finish();
+        let mut parsed = round_trip(&circ);
+
+        for _ in 0..16 {
+            let x = util::crt_factor(rng.gen_u128() % q, q);
+            let y = util::crt_factor(rng.gen_u128() % q, q);
+            assert_eq!(
+                parsed.eval_plain(&x, &y).unwrap(),
+                circ.eval_plain(&x, &y).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn bristol() {
+        round_trip(&Circuit::parse("circuits/AES-non-expanded.txt").unwrap());
+        round_trip(&Circuit::parse("circuits/adder_32bit.txt").unwrap());
+
+        // Bristol Fashion circuits store gate outputs in the slots of their wires
+        let mut buf = Vec::new();
+        let circ = Circuit::parse("circuits/adder_32bit.txt").unwrap();
+        circ.write_bristol_fashion(&mut buf).unwrap();
+        round_trip(&Circuit::read_bristol_fashion(&buf[..], 1).unwrap());
+    }
+
+    #[test]
+    fn scopes() {
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_garbler_input(8);
+        let y = b.bin_evaluator_input(8);
+        b.push_scope("outer");
+        let z = b.bin_addition_no_carry(&x, &y).unwrap();
+        b.push_scope("inner");
+        let z = b.bin_cmul(&z, 5, 8).unwrap();
+        b.pop_scope();
+        b.pop_scope();
+        let z = b.bin_xor(&z, &x).unwrap();
+        b.output_bundle(&z).unwrap();
+        let circ = b.finish();
+        let names = circ.scopes.iter().map(|s| s.name.as_str()).collect_vec();
+        assert!(names.contains(&"outer") && names.contains(&"inner"));
+        round_trip(&circ);
+
+        let mut bad = circ.clone();
+        bad.scopes[1].name = "two words".to_string();
+        assert!(bad.write_native(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn documented_example() {
+        let text = "
+            fancy 1
+            garbler_inputs 0:3
+            evaluator_inputs 1:3
+            constants 2:3
+            outputs 5:2
+            nonfree 2
+            gates 6
+            0 3 gin 0
+            1 3 ein 0
+            2 3 const 2
+            3 3 mul 0:3 1:3 0 # x * y
+            4 3 add 3:3 2:3
+            5 2 proj 4:3 1 0 1 1
+        ";
+        let mut circ = Circuit::read_native(text.as_bytes()).unwrap();
+        for (x, y) in (0..3).cartesian_product(0..3) {
+            let z = (x * y + 2) % 3;
+            assert_eq!(circ.eval_plain(&[x], &[y]).unwrap(), [(z != 0) as u16]);
+        }
+        round_trip(&circ);
+    }
+
+    #[test]
+    fn malformed() {
+        let header = "fancy 1\ngarbler_inputs 0:3\nevaluator_inputs\nconstants\noutputs 1:2\n";
+        for gates in &[
+            "nonfree 1\ngates 2\n0 3 gin 0\n1 2 proj 0:3 0 0 1\n", // short truth table
+            "nonfree 1\ngates 2\n0 3 gin 0\n1 2 proj 0:3 0 0 1 2\n", // truth table out of range
+            "nonfree 0\ngates 2\n0 3 gin 0\n1 3 add 0:3 2:3\n",    // wire out of range
+            "nonfree 0\ngates 2\n0 3 gin 0\n2 3 cmul 0:3 2\n",     // wrong index
+            "nonfree 0\ngates 2\n0 3 gin 0\n1 3 xor 0:3 0:3\n",    // unknown gate
+            "nonfree 0\ngates 3\n0 3 gin 0\n1 3 cmul 0:3 2\n",     // missing gate
+            "nonfree 0\ngates 2\n0 3 gin 0\n1 3 cmul 0:3 2 @ 0\n", // scope out of range
+            "nonfree 0\ngates 2\nscope a 0\n0 3 gin 0\n1 3 cmul 0:3 2\n", // parent after scope
+            "nonfree 0\ngates 2\nscope a\n0 3 gin 0\n1 3 cmul 0:3 2 @\n", // missing scope
+        ] {
+            let text = format!("{}{}", header, gates);
+            assert!(Circuit::read_native(text.as_bytes()).is_err(), "{}", gates);
+        }
+        match Circuit::read_native("fancy 2\n".as_bytes()) {
+            Err(CircuitParserError::InvalidCircuit(_)) => (),
+            _ => panic!("accepted unsupported version"),
+        }
+    }
+}
@@ -4,7 +4,7 @@
 //! creating any circuits.
 
 use crate::error::{DummyError, FancyError};
-use crate::fancy::{Fancy, FancyInput, HasModulus};
+use crate::fancy::{Fancy, FancyBranch, FancyInput, HasModulus, OutputMode};
 
 /// Simple struct that performs the fancy computation over `u16`.
 pub struct Dummy {
@@ -69,6 +69,8 @@ impl FancyInput for Dummy {
     }
 }
 
+impl FancyBranch for Dummy {}
+
 impl Fancy for Dummy {
     type Item = DummyVal;
     type Error = DummyError;
@@ -105,6 +107,9 @@ impl Fancy for Dummy {
     }
 
     fn mul(&mut self, x: &DummyVal, y: &DummyVal) -> Result<DummyVal, Self::Error> {
+        if x.modulus < y.modulus {
+            return self.mul(y, x);
+        }
         Ok(DummyVal {
             val: x.val * y.val % x.modulus,
             modulus: x.modulus,
@@ -129,12 +134,20 @@ impl Fancy for Dummy {
         self.outputs.push(x.val);
         Ok(())
     }
+
+    /// The dummy plays both parties, so every output mode reveals the plaintext.
+    fn output_with_mode(&mut self, x: &DummyVal, _: OutputMode) -> Result<(), Self::Error> {
+        self.output(x)
+    }
 }
 
 #[cfg(test)]
 mod bundle {
     use super::*;
-    use crate::fancy::{BinaryGadgets, Bundle, BundleGadgets, CrtGadgets};
+    use crate::fancy::{
+        BinaryGadgets, Bundle, BundleGadgets, CrtGadgets, FixedPointGadgets, NeuralGadgets, Tensor,
+    };
+    use crate::informer::Informer;
     use crate::util::{self, crt_inv_factor, RngExt};
     use itertools::Itertools;
     use rand::thread_rng;
@@ -218,6 +231,371 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn binary_full_multiplication() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let nbits = 1 + rng.gen_usize() % 64;
+            let q = 1 << nbits;
+            let x = rng.gen_u128() % q;
+            let y = rng.gen_u128() % q;
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let y = d.bin_encode(y, nbits).unwrap();
+                let z = d.bin_mul_full(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.bin_mul_karatsuba(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let outs = d.get_output();
+            assert_eq!(util::u128_from_bits(&outs[..2 * nbits]), x * y);
+            assert_eq!(util::u128_from_bits(&outs[2 * nbits..]), x * y);
+        }
+    }
+
+    #[test]
+    fn binary_signed_multiplication() {
+        let mut rng = thread_rng();
+        let mut cases = vec![(i32::MIN, i32::MIN), (i32::MIN, -1), (i32::MAX, i32::MIN)];
+        cases.extend((0..NITERS).map(|_| (rng.gen_u32() as i32, rng.gen_u32() as i32)));
+        for (x, y) in cases {
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x as u32 as u128, 32).unwrap();
+                let y = d.bin_encode(y as u32 as u128, 32).unwrap();
+                let z = d.bin_mul_full_signed(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let z = util::u128_from_bits(&d.get_output()) as u64 as i64;
+            assert_eq!(z, x as i64 * y as i64, "x={} y={}", x, y);
+        }
+    }
+
+    #[test] // AND gates used by full-width multiplication
+    fn bin_mul_cost() {
+        for &nbits in &[8, 16, 32, 64] {
+            let mut informer = Informer::new();
+            let x = informer.bin_receive(nbits).unwrap();
+            let y = informer.bin_receive(nbits).unwrap();
+            informer.bin_mul_full(&x, &y).unwrap();
+            let schoolbook = 2 * nbits * nbits - nbits;
+            assert_eq!(informer.num_muls(), schoolbook);
+
+            let mut informer = Informer::new();
+            let x = informer.bin_receive(nbits).unwrap();
+            let y = informer.bin_receive(nbits).unwrap();
+            informer.bin_mul_full_signed(&x, &y).unwrap();
+            assert_eq!(informer.num_muls(), schoolbook + 10 * nbits - 3);
+
+            let mut informer = Informer::new();
+            let x = informer.bin_receive(nbits).unwrap();
+            let y = informer.bin_receive(nbits).unwrap();
+            informer.bin_mul_karatsuba(&x, &y).unwrap();
+            if nbits < 16 {
+                assert_eq!(informer.num_muls(), schoolbook);
+            } else {
+                assert!(informer.num_muls() < schoolbook);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_shifts() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let nbits = 1 + rng.gen_usize() % 64;
+            let sbits = 1 + rng.gen_usize() % 8;
+            let mask = (1 << nbits) - 1;
+            let x = rng.gen_u128() & mask;
+            let s = rng.gen_u128() % (1 << sbits);
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let s = d.bin_encode(s, sbits).unwrap();
+                let z = d.bin_shl(&x, &s).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.bin_shr(&x, &s).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.bin_shr_arithmetic(&x, &s).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.bin_rotl(&x, &s).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.bin_rotr(&x, &s).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let outs = d
+                .get_output()
+                .chunks(nbits)
+                .map(util::u128_from_bits)
+                .collect_vec();
+            let k = s as usize;
+            let sign = x >> (nbits - 1);
+            let r = k % nbits;
+            let (shl, shr, sar) = if k >= nbits {
+                (0, 0, sign * mask)
+            } else {
+                let fill = sign * (mask ^ (mask >> k));
+                ((x << k) & mask, x >> k, x >> k | fill)
+            };
+            let rotl = (x << r | x >> (nbits - r)) & mask;
+            let rotr = (x >> r | x << (nbits - r)) & mask;
+            assert_eq!(outs, vec![shl, shr, sar, rotl, rotr], "x={} s={}", x, s);
+        }
+    }
+
+    #[test]
+    fn binary_sort() {
+        let mut rng = thread_rng();
+        let nbits = 8;
+        let q = util::modulus_with_width(8);
+        for _ in 0..64 {
+            let n = rng.gen_usize() % 12;
+            let xs = (0..n).map(|_| rng.gen_u128() % (1 << nbits)).collect_vec();
+            let mut d = Dummy::new();
+            {
+                let keys = d.bin_encode_many(&xs, nbits).unwrap();
+                let ids = (0..n as u128).collect_vec();
+                let ids = d.crt_encode_many(&ids, q).unwrap();
+                let ids = ids.into_iter().map(|b| b.extract()).collect_vec();
+                let (keys, ids) = d.bin_sort_with_payloads(&keys, &ids).unwrap();
+                for key in keys.iter() {
+                    d.output_bundle(key).unwrap();
+                }
+                d.output_bundles(&ids).unwrap();
+                for key in d.bin_sort(&keys).unwrap().iter() {
+                    d.output_bundle(key).unwrap();
+                }
+            }
+            let outs = d.get_output();
+            let (keys, rest) = outs.split_at(n * nbits);
+            let (ids, again) = rest.split_at(n * util::factor(q).len());
+            let keys = keys.chunks(nbits).map(util::u128_from_bits).collect_vec();
+            let ids = ids
+                .chunks(util::factor(q).len())
+                .map(|x| crt_inv_factor(x, q) as usize)
+                .collect_vec();
+            let mut sorted = xs.clone();
+            sorted.sort();
+            assert_eq!(keys, sorted);
+            assert_eq!(ids.iter().map(|&i| xs[i]).collect_vec(), sorted);
+            assert_eq!(
+                ids.iter().sorted().cloned().collect_vec(),
+                (0..n).collect_vec()
+            );
+            let again = again.chunks(nbits).map(util::u128_from_bits).collect_vec();
+            assert_eq!(again, sorted);
+        }
+    }
+
+    #[test]
+    fn crt_sort() {
+        let mut rng = thread_rng();
+        for _ in 0..64 {
+            let q = util::modulus_with_nprimes(4 + rng.gen_usize() % 7); // exact comparisons support up to 11 primes
+            let n = rng.gen_usize() % 12;
+            let xs = (0..n).map(|_| rng.gen_u128() % (q / 4)).collect_vec();
+            let mut d = Dummy::new();
+            {
+                let keys = d.crt_encode_many(&xs, q).unwrap();
+                let ids = (0..n as u128).collect_vec();
+                let ids = d.bin_encode_many(&ids, 4).unwrap();
+                let ids = ids.into_iter().map(|b| b.extract()).collect_vec();
+                let (keys, ids) = d.crt_sort_with_payloads(&keys, &ids, "100%").unwrap();
+                d.crt_outputs(&keys).unwrap();
+                d.output_bundles(&ids).unwrap();
+            }
+            let outs = d.get_output();
+            let nprimes = util::factor(q).len();
+            let (keys, ids) = outs.split_at(n * nprimes);
+            let keys = keys
+                .chunks(nprimes)
+                .map(|x| crt_inv_factor(x, q))
+                .collect_vec();
+            let ids = ids.chunks(4).map(util::u128_from_bits).collect_vec();
+            let mut sorted = xs.clone();
+            sorted.sort();
+            assert_eq!(keys, sorted);
+            assert_eq!(ids.iter().map(|&i| xs[i as usize]).collect_vec(), sorted);
+        }
+    }
+
+    #[test]
+    fn neural_layers() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let nprimes = util::factor(q).len();
+        let enc = |v: i128| (v.rem_euclid(q as i128)) as u128;
+        let dec = |x: u128| {
+            if x >= q / 2 {
+                x as i128 - q as i128
+            } else {
+                x as i128
+            }
+        };
+        let mut small = |n: usize, b: i128| {
+            (0..n)
+                .map(|_| (rng.gen_u128() % (2 * b as u128 + 1)) as i128 - b)
+                .collect_vec()
+        };
+        for _ in 0..16 {
+            let x = small(2 * 5 * 5, 8);
+            let k = small(3 * 2 * 2 * 2, 3);
+            let kb = small(3, 3);
+            let w = small(4 * 12, 3);
+            let wb = small(4, 3);
+            let v = small(2 * 4, 3);
+            let vb = small(2, 3);
+
+            let mut d = Dummy::new();
+            {
+                let enc_all = |xs: &[i128]| xs.iter().map(|&v| enc(v)).collect_vec();
+                let x = d.crt_encode_many(&enc_all(&x), q).unwrap();
+                let x = Tensor::new(x, &[2, 5, 5]);
+                let k = Tensor::new(enc_all(&k), &[3, 2, 2, 2]);
+                let kb = Tensor::new(enc_all(&kb), &[3]);
+                let z = d.nn_conv2d(&x, &k, &kb, (1, 1)).unwrap();
+                let z = d.nn_relu(&z, "100%").unwrap();
+                let m = d.nn_max_pool2d(&z, (2, 2), (2, 2), "100%").unwrap();
+                let a = d.nn_avg_pool2d(&z, (2, 2), (2, 2)).unwrap();
+                let w = Tensor::new(enc_all(&w), &[4, 12]);
+                let wb = Tensor::new(enc_all(&wb), &[4]);
+                let y = d.nn_dense(&m.clone().reshape(&[12]), &w, &wb).unwrap();
+                let v = d.crt_encode_many(&enc_all(&v), q).unwrap();
+                let vb = d.crt_encode_many(&enc_all(&vb), q).unwrap();
+                let v = Tensor::new(v, &[2, 4]);
+                let vb = Tensor::new(vb, &[2]);
+                let y = d.nn_dense_secret(&y, &v, &vb).unwrap();
+                let s = d.nn_sign(&y, "100%").unwrap();
+                for t in [m, a, y, s].iter() {
+                    d.crt_outputs(t.data()).unwrap();
+                }
+            }
+            let outs = d
+                .get_output()
+                .chunks(nprimes)
+                .map(|xs| dec(crt_inv_factor(xs, q)))
+                .collect_vec();
+
+            // plaintext reference
+            let mut z = vec![0; 3 * 4 * 4];
+            for o in 0..3 {
+                for i in 0..4 {
+                    for j in 0..4 {
+                        let mut acc = kb[o];
+                        for c in 0..2 {
+                            for di in 0..2 {
+                                for dj in 0..2 {
+                                    acc += x[c * 25 + (i + di) * 5 + j + dj]
+                                        * k[((o * 2 + c) * 2 + di) * 2 + dj];
+                                }
+                            }
+                        }
+                        z[(o * 4 + i) * 4 + j] = std::cmp::max(acc, 0);
+                    }
+                }
+            }
+            let window = |o: usize, i: usize, j: usize| {
+                (0..4).map(move |t| (o * 4 + 2 * i + t / 2) * 4 + 2 * j + t % 2)
+            };
+            let idxs = (0..12).map(|t| (t / 4, t / 2 % 2, t % 2)).collect_vec();
+            let m = idxs
+                .iter()
+                .map(|&(o, i, j)| window(o, i, j).map(|t| z[t]).max().unwrap())
+                .collect_vec();
+            let a = idxs
+                .iter()
+                .map(|&(o, i, j)| window(o, i, j).map(|t| z[t]).sum::<i128>())
+                .collect_vec();
+            let y = (0..4)
+                .map(|i| wb[i] + (0..12).map(|j| w[i * 12 + j] * m[j]).sum::<i128>())
+                .collect_vec();
+            let y = (0..2)
+                .map(|i| vb[i] + (0..4).map(|j| v[i * 4 + j] * y[j]).sum::<i128>())
+                .collect_vec();
+            let s = y.iter().map(|&v| if v >= 0 { 1 } else { -1 }).collect_vec();
+            assert_eq!(outs, [m, a, y, s].concat());
+        }
+    }
+
+    #[test]
+    fn binary_division() {
+        let mut rng = thread_rng();
+        for i in 0..NITERS {
+            let nbits = 1 + rng.gen_usize() % 16;
+            let q = 1 << nbits;
+            let x = rng.gen_u128() % q;
+            let y = if i % 16 == 0 { 0 } else { rng.gen_u128() % q };
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let y = d.bin_encode(y, nbits).unwrap();
+                let (z, r) = d.bin_divmod(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                d.output_bundle(&r).unwrap();
+            }
+            let outs = d.get_output();
+            let z = util::u128_from_bits(&outs[..nbits]);
+            let r = util::u128_from_bits(&outs[nbits..]);
+            let should_be = x.checked_div(y).zip(x.checked_rem(y));
+            assert_eq!((z, r), should_be.unwrap_or((q - 1, x)), "x={} y={}", x, y);
+        }
+    }
+
+    #[test]
+    fn binary_signed_division() {
+        let mut rng = thread_rng();
+        let mut cases = vec![(i8::MIN, -1), (i8::MIN, 0), (-5, 0)];
+        cases.extend((0..NITERS).map(|_| (rng.gen_u16() as i8, rng.gen_u16() as i8)));
+        for (x, y) in cases {
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x as u8 as u128, 8).unwrap();
+                let y = d.bin_encode(y as u8 as u128, 8).unwrap();
+                let (z, r) = d.bin_divmod_signed(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                d.output_bundle(&r).unwrap();
+            }
+            let outs = d.get_output();
+            let z = util::u128_from_bits(&outs[..8]) as u8 as i8;
+            let r = util::u128_from_bits(&outs[8..]) as u8 as i8;
+            if y == 0 {
+                assert_eq!((z, r), (-1, x));
+            } else {
+                assert_eq!(
+                    (z, r),
+                    (x.wrapping_div(y), x.wrapping_rem(y)),
+                    "x={} y={}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn binary_constant_division() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let nbits = 1 + rng.gen_usize() % 64;
+            let q = 1 << nbits;
+            let x = rng.gen_u128() % q;
+            let c = rng.gen_u128() % (1 << (1 + rng.gen_usize() % 66));
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let (z, r) = d.bin_cdivmod(&x, c).unwrap();
+                d.output_bundle(&z).unwrap();
+                d.output_bundle(&r).unwrap();
+            }
+            let outs = d.get_output();
+            let z = util::u128_from_bits(&outs[..nbits]);
+            let r = util::u128_from_bits(&outs[nbits..]);
+            let should_be = x.checked_div(c).zip(x.checked_rem(c));
+            assert_eq!((z, r), should_be.unwrap_or((q - 1, x)), "x={} c={}", x, c);
+        }
+    }
+
     #[test]
     fn max() {
         let mut rng = thread_rng();
@@ -471,6 +849,121 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn binary_to_crt() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let nbits = 1 + rng.gen_usize() % 64;
+            let q = rng.gen_usable_composite_modulus();
+            let x = rng.gen_u128() % (1 << nbits);
+            let mut d = Dummy::new();
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let z = d.bin_to_crt(&x, q).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            assert_eq!(crt_inv_factor(&d.get_output(), q), x % q);
+        }
+    }
+
+    #[test] // cost of binary to CRT conversion
+    fn bin_to_crt_cost() {
+        let nbits = 32;
+        let q = util::modulus_with_width(32);
+        let mut informer = Informer::new();
+        let x = informer.bin_receive(nbits).unwrap();
+        informer.bin_to_crt(&x, q).unwrap();
+        let nodd = util::factor(q).into_iter().filter(|&p| p != 2).count();
+        assert_eq!(informer.num_projs(), nbits * nodd);
+        assert_eq!(informer.num_ciphertexts(), nbits * nodd);
+    }
+
+    #[test]
+    fn crt_to_binary() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let q = rng.gen_usable_composite_modulus();
+            let nbits = 1 + rng.gen_usize() % 128;
+            let x = rng.gen_u128() % q;
+            let mut d = Dummy::new();
+            {
+                let x = d.crt_encode(x, q).unwrap();
+                let z = d.crt_to_bin(&x, nbits).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let should_be = if nbits < 128 { x % (1 << nbits) } else { x };
+            assert_eq!(util::u128_from_bits(&d.get_output()), should_be);
+        }
+    }
+
+    #[test]
+    fn fixed_point_arithmetic() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let q = util::modulus_with_nprimes(7 + rng.gen_usize() % 5);
+            let precision = 1 + rng.gen_usize() % 6;
+            // keep the product of the encodings well within (-q/2, q/2)
+            let bound = ((q / 8) as f64).sqrt() as i128;
+            let xr = (rng.gen_u128() % (2 * bound as u128)) as i128 - bound;
+            let yr = (rng.gen_u128() % (2 * bound as u128)) as i128 - bound;
+            let scale = (1 << precision) as f64;
+            let (x, y) = (xr as f64 / scale, yr as f64 / scale);
+            let mut d = Dummy::new();
+            {
+                let x = d.fp_encode(x, q, precision).unwrap();
+                let y = d.fp_encode(y, q, precision).unwrap();
+                let z = d.fp_add(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.fp_sub(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.fp_mul(&x, &y).unwrap();
+                d.output_bundle(&z).unwrap();
+                let z = d.fp_div_pow2(&x, 3).unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let outs = d
+                .get_output()
+                .chunks(util::factor(q).len())
+                .map(|zs| util::from_fixed_point(crt_inv_factor(zs, q), q, precision))
+                .collect_vec();
+            let floor = |v: i128, k: usize| v.div_euclid(1 << k) as f64 / scale;
+            assert_eq!(outs[0], x + y);
+            assert_eq!(outs[1], x - y);
+            assert_eq!(outs[2], floor(xr * yr, precision));
+            assert_eq!(outs[3], floor(xr, 3));
+        }
+    }
+
+    #[test]
+    fn fixed_point_comparison() {
+        let mut rng = thread_rng();
+        for _ in 0..NITERS {
+            let q = util::modulus_with_nprimes(4 + rng.gen_usize() % 7); // exact relu supports up to 11 primes
+            let precision = rng.gen_usize() % 4;
+            let scale = (1 << precision) as f64;
+            let bound = q as i128 / 4;
+            let xr = (rng.gen_u128() % (2 * bound as u128)) as i128 - bound;
+            let yr = (rng.gen_u128() % (2 * bound as u128)) as i128 - bound;
+            let (x, y) = (xr as f64 / scale, yr as f64 / scale);
+            let mut d = Dummy::new();
+            {
+                let x = d.fp_encode(x, q, precision).unwrap();
+                let y = d.fp_encode(y, q, precision).unwrap();
+                let z = d.fp_lt(&x, &y, "100%").unwrap();
+                d.output(&z).unwrap();
+                let z = d.fp_geq(&x, &y, "100%").unwrap();
+                d.output(&z).unwrap();
+                let z = d.fp_relu(&x, "100%").unwrap();
+                d.output_bundle(&z).unwrap();
+            }
+            let outs = d.get_output();
+            assert_eq!(outs[0], (x < y) as u16);
+            assert_eq!(outs[1], (x >= y) as u16);
+            let z = util::from_fixed_point(crt_inv_factor(&outs[2..], q), q, precision);
+            assert_eq!(z, if x < 0.0 { 0.0 } else { x });
+        }
+    }
+
     #[test]
     fn test_mixed_radix_addition_msb_only() {
         let mut rng = thread_rng();
@@ -1,5 +1,6 @@
 //! Errors that may be output by this library.
 
+use crate::fancy::OutputMode;
 use scuttlebutt::Block;
 use std::fmt::{self, Display, Formatter};
 
@@ -33,6 +34,8 @@ pub enum FancyError {
     InvalidTruthTable,
     /// Uninitialized value encountered.
     UninitializedValue,
+    /// The output mode is not supported by this Fancy object.
+    UnsupportedOutputMode(OutputMode),
 }
 
 /// Errors from the dummy fancy object.
@@ -59,6 +62,10 @@ pub enum EvaluatorError {
     DecodingFailed,
     /// A communication error has occurred.
     CommunicationError(String),
+    /// The garbler was caught cheating.
+    CheatingDetected(String),
+    /// A truth table was missing.
+    TruthTableRequired,
     /// A fancy error has occurred.
     FancyError(FancyError),
 }
@@ -76,6 +83,10 @@ pub enum GarblerError {
     DeltaRequired,
     /// Encoding error.
     EncodingError,
+    /// Decoding an output wirelabel sent by the evaluator failed.
+    DecodingFailed,
+    /// The evaluator was caught cheating.
+    CheatingDetected(String),
     /// A fancy error has occurred.
     FancyError(FancyError),
 }
@@ -120,6 +131,9 @@ impl Display for FancyError {
             FancyError::UninitializedValue => {
                 "uninitialized value in circuit. is the circuit topologically sorted?".fmt(f)
             }
+            FancyError::UnsupportedOutputMode(mode) => {
+                write!(f, "output mode {:?} is not supported", mode)
+            }
         }
     }
 }
@@ -154,6 +168,10 @@ impl Display for EvaluatorError {
             EvaluatorError::NotEnoughEvaluatorInputs => "not enough evaluator inputs".fmt(f),
             EvaluatorError::DecodingFailed => write!(f, "decodiing failed"),
             EvaluatorError::CommunicationError(s) => write!(f, "communication error: {}", s),
+            EvaluatorError::CheatingDetected(s) => write!(f, "cheating detected: {}", s),
+            EvaluatorError::TruthTableRequired => {
+                "truth table required for row-reduced evaluator projection gates".fmt(f)
+            }
             EvaluatorError::FancyError(e) => write!(f, "fancy error: {}", e),
         }
     }
@@ -177,6 +195,15 @@ impl From<std::sync::mpsc::RecvError> for EvaluatorError {
     }
 }
 
+impl From<CircuitBuilderError> for EvaluatorError {
+    fn from(e: CircuitBuilderError) -> Self {
+        match e {
+            CircuitBuilderError::FancyError(e) => EvaluatorError::FancyError(e),
+            e => EvaluatorError::FancyError(FancyError::InvalidArg(e.to_string())),
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Garbler error
 
@@ -199,6 +226,8 @@ impl Display for GarblerError {
             GarblerError::EncodingError => {
                 "encoding failed: unequal length input values and moduli".fmt(f)
             }
+            GarblerError::DecodingFailed => "decoding failed".fmt(f),
+            GarblerError::CheatingDetected(s) => write!(f, "cheating detected: {}", s),
             GarblerError::FancyError(e) => write!(f, "{}", e),
         }
     }
@@ -222,6 +251,15 @@ impl From<std::sync::mpsc::SendError<Vec<Block>>> for GarblerError {
     }
 }
 
+impl From<CircuitBuilderError> for GarblerError {
+    fn from(e: CircuitBuilderError) -> Self {
+        match e {
+            CircuitBuilderError::FancyError(e) => GarblerError::FancyError(e),
+            e => GarblerError::FancyError(FancyError::InvalidArg(e.to_string())),
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // circuit builder error
 
@@ -274,6 +312,8 @@ pub enum CircuitParserError {
     ParseLineError(String),
     /// An error occurred parsing a gate type.
     ParseGateError(String),
+    /// The circuit is malformed or cannot be represented in the requested format.
+    InvalidCircuit(String),
 }
 
 impl Display for CircuitParserError {
@@ -284,6 +324,7 @@ impl Display for CircuitParserError {
             CircuitParserError::ParseIntError => write!(f, "unable to parse integer"),
             CircuitParserError::ParseLineError(s) => write!(f, "unable to parse line '{}'", s),
             CircuitParserError::ParseGateError(s) => write!(f, "unable to parse gate '{}'", s),
+            CircuitParserError::InvalidCircuit(s) => write!(f, "invalid circuit: {}", s),
         }
     }
 }
@@ -305,3 +346,72 @@ impl From<std::num::ParseIntError> for CircuitParserError {
         CircuitParserError::ParseIntError
     }
 }
+
+/// Errors emitted by oblivious transfer implementations.
+#[derive(Debug)]
+pub enum OtError {
+    /// An I/O error occurred.
+    IoError(std::io::Error),
+    /// The OT protocol failed.
+    ProtocolError(String),
+}
+
+impl Display for OtError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            OtError::IoError(e) => write!(f, "io error: {}", e),
+            OtError::ProtocolError(s) => write!(f, "oblivious transfer failed: {}", s),
+        }
+    }
+}
+
+impl From<std::io::Error> for OtError {
+    fn from(e: std::io::Error) -> OtError {
+        OtError::IoError(e)
+    }
+}
+
+impl From<OtError> for GarblerError {
+    fn from(e: OtError) -> Self {
+        GarblerError::CommunicationError(e.to_string())
+    }
+}
+
+impl From<OtError> for EvaluatorError {
+    fn from(e: OtError) -> Self {
+        EvaluatorError::CommunicationError(e.to_string())
+    }
+}
+
+/// Errors emitted when serializing or deserializing circuits, garbled circuits
+/// and encoders.
+#[derive(Debug)]
+pub enum SerializationError {
+    /// An I/O error occurred.
+    IoError(std::io::Error),
+    /// The data does not start with the expected header.
+    InvalidHeader,
+    /// The data was written by an unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// The data is malformed.
+    InvalidData(String),
+}
+
+impl Display for SerializationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SerializationError::IoError(e) => write!(f, "io error: {}", e),
+            SerializationError::InvalidHeader => "invalid header".fmt(f),
+            SerializationError::UnsupportedVersion(v) => {
+                write!(f, "unsupported format version {}", v)
+            }
+            SerializationError::InvalidData(s) => write!(f, "invalid data: {}", s),
+        }
+    }
+}
+
+impl From<std::io::Error> for SerializationError {
+    fn from(e: std::io::Error) -> SerializationError {
+        SerializationError::IoError(e)
+    }
+}
@@ -7,13 +7,20 @@ use crate::error::FancyError;
 use itertools::Itertools;
 
 mod binary;
+mod branch;
 mod bundle;
 mod crt;
+mod fixed_point;
 mod input;
+mod neural;
 pub use binary::{BinaryBundle, BinaryGadgets};
+pub(crate) use branch::{build_branches, multiplex};
+pub use branch::{Branch, FancyBranch};
 pub use bundle::{Bundle, BundleGadgets};
 pub use crt::{CrtBundle, CrtGadgets};
+pub use fixed_point::{FixedPointBundle, FixedPointGadgets};
 pub use input::FancyInput;
+pub use neural::{CrtTensor, NeuralGadgets, Tensor};
 
 /// An object that has some modulus. Basic object of `Fancy` computations.
 pub trait HasModulus {
@@ -21,6 +28,22 @@ pub trait HasModulus {
     fn modulus(&self) -> u16;
 }
 
+/// Which parties learn an output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Only the evaluator learns the output. This is what `Fancy::output` does.
+    Evaluator,
+    /// Only the garbler learns the output: the evaluator sends back its wirelabel,
+    /// which the garbler decodes.
+    Garbler,
+    /// Both parties learn the output.
+    Both,
+    /// The output is additively secret shared mod its modulus. The garbler's share is
+    /// random and the evaluator learns the output minus the garbler's share, so for
+    /// mod 2 outputs these are XOR shares.
+    Shared,
+}
+
 /// DSL for the basic computations supported by `fancy-garbling`.
 pub trait Fancy {
     /// The underlying wire datatype created by an object implementing `Fancy`.
@@ -256,4 +279,38 @@ pub trait Fancy {
         }
         Ok(())
     }
+
+    /// Open a scope called `name`, which groups the gates created until the matching
+    /// `pop_scope`. Scopes nest. The built-in gadgets put the gates they create in a
+    /// scope named after themselves, which `Informer` uses to attribute costs and
+    /// `CircuitBuilder` records in the circuit.
+    ///
+    /// Does nothing by default.
+    fn push_scope(&mut self, _name: &str) {}
+
+    /// Close the innermost open scope.
+    ///
+    /// Does nothing by default.
+    fn pop_scope(&mut self) {}
+
+    /// Run `f` inside a scope called `name`, closing it even if `f` fails.
+    fn scoped<T, G>(&mut self, name: &str, f: G) -> Result<T, Self::Error>
+    where
+        G: FnOnce(&mut Self) -> Result<T, Self::Error>,
+    {
+        self.push_scope(name);
+        let res = f(self);
+        self.pop_scope();
+        res
+    }
+
+    /// Process this wire as output, revealing it to the parties given by `mode`.
+    ///
+    /// Only `OutputMode::Evaluator` is supported by default.
+    fn output_with_mode(&mut self, x: &Self::Item, mode: OutputMode) -> Result<(), Self::Error> {
+        match mode {
+            OutputMode::Evaluator => self.output(x),
+            _ => Err(Self::Error::from(FancyError::UnsupportedOutputMode(mode))),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::error::FancyError;
 use crate::fancy::bundle::{Bundle, BundleGadgets};
+use crate::fancy::crt::CrtBundle;
 use crate::fancy::{Fancy, HasModulus};
 use crate::util;
 use itertools::Itertools;
@@ -36,6 +37,10 @@ impl<W: Clone + HasModulus> From<Bundle<W>> for BinaryBundle<W> {
     }
 }
 
+/// Below this many bits, `bin_mul_karatsuba` uses schoolbook multiplication, which
+/// needs fewer ANDs.
+const KARATSUBA_CUTOFF: usize = 16;
+
 impl<F: Fancy> BinaryGadgets for F {}
 
 /// Extension trait for `Fancy` providing gadgets that operate over bundles of mod2 wires.
@@ -65,7 +70,7 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         x: &BinaryBundle<Self::Item>,
         y: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        self.mul_bundles(&x, &y).map(BinaryBundle)
+        self.scoped("bin_and", |f| f.mul_bundles(&x, &y).map(BinaryBundle))
     }
 
     /// Or the bits of two bundles together pairwise.
@@ -74,12 +79,14 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         x: &BinaryBundle<Self::Item>,
         y: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        x.wires()
-            .iter()
-            .zip(y.wires().iter())
-            .map(|(x, y)| self.or(x, y))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(BinaryBundle::new)
+        self.scoped("bin_or", |f| {
+            x.wires()
+                .iter()
+                .zip(y.wires().iter())
+                .map(|(x, y)| f.or(x, y))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(BinaryBundle::new)
+        })
     }
 
     /// Binary addition. Returns the result and the carry.
@@ -88,20 +95,22 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         xs: &BinaryBundle<Self::Item>,
         ys: &BinaryBundle<Self::Item>,
     ) -> Result<(BinaryBundle<Self::Item>, Self::Item), Self::Error> {
-        if xs.moduli() != ys.moduli() {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
-        let xwires = xs.wires();
-        let ywires = ys.wires();
-        let (mut z, mut c) = self.adder(&xwires[0], &ywires[0], None)?;
-        let mut bs = vec![z];
-        for i in 1..xwires.len() {
-            let res = self.adder(&xwires[i], &ywires[i], Some(&c))?;
-            z = res.0;
-            c = res.1;
-            bs.push(z);
-        }
-        Ok((BinaryBundle::new(bs), c))
+        self.scoped("bin_addition", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let xwires = xs.wires();
+            let ywires = ys.wires();
+            let (mut z, mut c) = f.adder(&xwires[0], &ywires[0], None)?;
+            let mut bs = vec![z];
+            for i in 1..xwires.len() {
+                let res = f.adder(&xwires[i], &ywires[i], Some(&c))?;
+                z = res.0;
+                c = res.1;
+                bs.push(z);
+            }
+            Ok((BinaryBundle::new(bs), c))
+        })
     }
 
     /// Binary addition. Avoids creating extra gates for the final carry.
@@ -110,26 +119,28 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         xs: &BinaryBundle<Self::Item>,
         ys: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        if xs.moduli() != ys.moduli() {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
-        let xwires = xs.wires();
-        let ywires = ys.wires();
-        let (mut z, mut c) = self.adder(&xwires[0], &ywires[0], None)?;
-        let mut bs = vec![z];
-        for i in 1..xwires.len() - 1 {
-            let res = self.adder(&xwires[i], &ywires[i], Some(&c))?;
-            z = res.0;
-            c = res.1;
+        self.scoped("bin_addition_no_carry", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let xwires = xs.wires();
+            let ywires = ys.wires();
+            let (mut z, mut c) = f.adder(&xwires[0], &ywires[0], None)?;
+            let mut bs = vec![z];
+            for i in 1..xwires.len() - 1 {
+                let res = f.adder(&xwires[i], &ywires[i], Some(&c))?;
+                z = res.0;
+                c = res.1;
+                bs.push(z);
+            }
+            z = f.add_many(&[
+                xwires.last().unwrap().clone(),
+                ywires.last().unwrap().clone(),
+                c,
+            ])?;
             bs.push(z);
-        }
-        z = self.add_many(&[
-            xwires.last().unwrap().clone(),
-            ywires.last().unwrap().clone(),
-            c,
-        ])?;
-        bs.push(z);
-        Ok(BinaryBundle::new(bs))
+            Ok(BinaryBundle::new(bs))
+        })
     }
 
     /// Binary multiplication.
@@ -141,30 +152,183 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         xs: &BinaryBundle<Self::Item>,
         ys: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        if xs.moduli() != ys.moduli() {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
-
-        let xwires = xs.wires();
-        let ywires = ys.wires();
+        self.scoped("bin_multiplication_lower_half", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
 
-        let mut sum = xwires
-            .iter()
-            .map(|x| self.and(x, &ywires[0]))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(BinaryBundle::new)?;
+            let xwires = xs.wires();
+            let ywires = ys.wires();
 
-        for i in 1..xwires.len() {
-            let mul = xwires
+            let mut sum = xwires
                 .iter()
-                .map(|x| self.and(x, &ywires[i]))
+                .map(|x| f.and(x, &ywires[0]))
                 .collect::<Result<Vec<Self::Item>, Self::Error>>()
                 .map(BinaryBundle::new)?;
-            let shifted = self.shift(&mul, i).map(BinaryBundle)?;
-            sum = self.bin_addition_no_carry(&sum, &shifted)?;
-        }
 
-        Ok(sum)
+            for i in 1..xwires.len() {
+                let mul = xwires
+                    .iter()
+                    .map(|x| f.and(x, &ywires[i]))
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                    .map(BinaryBundle::new)?;
+                let shifted = f.shift(&mul, i).map(BinaryBundle)?;
+                sum = f.bin_addition_no_carry(&sum, &shifted)?;
+            }
+
+            Ok(sum)
+        })
+    }
+
+    /// Full-width binary multiplication, returning a bundle with twice as many bits as
+    /// the inputs.
+    ///
+    /// Schoolbook multiplication, adding one partial product per bit of `ys`. Costs
+    /// `2n^2 - n` ANDs for `n` bit inputs.
+    fn bin_mul_full(
+        &mut self,
+        xs: &BinaryBundle<Self::Item>,
+        ys: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_mul_full", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let xwires = xs.wires();
+            let ywires = ys.wires();
+            let n = xwires.len();
+
+            let mut out = Vec::with_capacity(2 * n);
+            let mut hi = xwires
+                .iter()
+                .map(|x| f.and(x, &ywires[0]))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+            let mut carry = f.constant(0, 2)?;
+
+            // hi holds the n bits of the running sum above the bits already in out
+            for y in ywires.iter().skip(1) {
+                let partial = xwires
+                    .iter()
+                    .map(|x| f.and(x, y))
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                    .map(BinaryBundle::new)?;
+                out.push(hi.remove(0));
+                hi.push(carry);
+                let (sum, c) = f.bin_addition(&BinaryBundle::new(hi), &partial)?;
+                hi = sum.wires().to_vec();
+                carry = c;
+            }
+            out.extend(hi);
+            out.push(carry);
+
+            Ok(BinaryBundle::new(out))
+        })
+    }
+
+    /// Full-width twos complement multiplication, returning a bundle with twice as many
+    /// bits as the inputs.
+    ///
+    /// Multiplies the absolute values using `bin_mul_full` and negates the product when
+    /// the signs differ.
+    fn bin_mul_full_signed(
+        &mut self,
+        xs: &BinaryBundle<Self::Item>,
+        ys: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_mul_full_signed", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let x_sign = xs.wires().last().unwrap();
+            let y_sign = ys.wires().last().unwrap();
+            let x_abs = f.bin_abs(xs)?;
+            let y_abs = f.bin_abs(ys)?;
+            let prod = f.bin_mul_full(&x_abs, &y_abs)?;
+            let neg_prod = f.bin_twos_complement(&prod)?;
+            let signs_differ = f.xor(x_sign, y_sign)?;
+            select(f, &signs_differ, prod.wires(), neg_prod.wires()).map(BinaryBundle::new)
+        })
+    }
+
+    /// Full-width binary multiplication using Karatsuba's algorithm, returning a bundle
+    /// with twice as many bits as the inputs.
+    ///
+    /// Splits each input into low `h = n/2` and high `m = n-h` bit halves and computes
+    /// the three products `x0 y0`, `x1 y1` and `(x0 + x1)(y0 + y1)` recursively, falling
+    /// back to `bin_mul_full` below 16 bits, where it needs fewer ANDs. The middle term
+    /// is computed as `!(!z1 + z0 + z2)` so that subtracting costs a single addition.
+    fn bin_mul_karatsuba(
+        &mut self,
+        xs: &BinaryBundle<Self::Item>,
+        ys: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_mul_karatsuba", |f| {
+            if xs.moduli() != ys.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let n = xs.size();
+            if n < KARATSUBA_CUTOFF {
+                return f.bin_mul_full(xs, ys);
+            }
+            let h = n / 2;
+            let m = n - h;
+            let zero = f.constant(0, 2)?;
+
+            let (x0, x1) = xs.wires().split_at(h);
+            let (y0, y1) = ys.wires().split_at(h);
+            let z0 = f.bin_mul_karatsuba(
+                &BinaryBundle::new(x0.to_vec()),
+                &BinaryBundle::new(y0.to_vec()),
+            )?;
+            let z2 = f.bin_mul_karatsuba(
+                &BinaryBundle::new(x1.to_vec()),
+                &BinaryBundle::new(y1.to_vec()),
+            )?;
+
+            // the m+1 bit sums of the halves
+            let mut sums = Vec::with_capacity(2);
+            for (lo, hi) in [(x0, x1), (y0, y1)].iter() {
+                let mut lo = lo.to_vec();
+                lo.resize(m, zero.clone());
+                let (sum, carry) =
+                    f.bin_addition(&BinaryBundle::new(lo), &BinaryBundle::new(hi.to_vec()))?;
+                let mut sum = sum.wires().to_vec();
+                sum.push(carry);
+                sums.push(BinaryBundle::new(sum));
+            }
+            let z1 = f.bin_mul_karatsuba(&sums[0], &sums[1])?;
+
+            // the middle term z1 - z0 - z2 = x0 y1 + x1 y0 fits in 2m+1 bits
+            let mut z0_ext = z0.wires().to_vec();
+            z0_ext.resize(2 * m, zero.clone());
+            let (z02, carry) = f.bin_addition(&BinaryBundle::new(z0_ext), &z2)?;
+            let mut z02 = z02.wires().to_vec();
+            z02.push(carry);
+            let not_z1 = z1.wires()[..2 * m + 1]
+                .iter()
+                .map(|w| f.negate(w))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+            let diff =
+                f.bin_addition_no_carry(&BinaryBundle::new(not_z1), &BinaryBundle::new(z02))?;
+            let mut mid = diff
+                .wires()
+                .iter()
+                .map(|w| f.negate(w))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+
+            // z0 and z2 occupy disjoint bits of the product, so only the middle term is added
+            let mut out = z0.wires().to_vec();
+            out.extend(z2.wires().iter().cloned());
+            mid.resize(2 * n - h, zero);
+            let hi = f.bin_addition_no_carry(
+                &BinaryBundle::new(out[h..].to_vec()),
+                &BinaryBundle::new(mid),
+            )?;
+            out.truncate(h);
+            out.extend(hi.wires().iter().cloned());
+
+            Ok(BinaryBundle::new(out))
+        })
     }
 
     /// Compute the twos complement of the input bundle (which must be base 2).
@@ -172,14 +336,16 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         &mut self,
         xs: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        let not_xs = xs
-            .wires()
-            .iter()
-            .map(|x| self.negate(x))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(BinaryBundle::new)?;
-        let one = self.bin_constant_bundle(1, xs.size())?;
-        self.bin_addition_no_carry(&not_xs, &one)
+        self.scoped("bin_twos_complement", |f| {
+            let not_xs = xs
+                .wires()
+                .iter()
+                .map(|x| f.negate(x))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(BinaryBundle::new)?;
+            let one = f.bin_constant_bundle(1, xs.size())?;
+            f.bin_addition_no_carry(&not_xs, &one)
+        })
     }
 
     /// Subtract two binary bundles. Returns the result and whether it underflowed.
@@ -190,8 +356,10 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         xs: &BinaryBundle<Self::Item>,
         ys: &BinaryBundle<Self::Item>,
     ) -> Result<(BinaryBundle<Self::Item>, Self::Item), Self::Error> {
-        let neg_ys = self.bin_twos_complement(&ys)?;
-        self.bin_addition(&xs, &neg_ys)
+        self.scoped("bin_subtraction", |f| {
+            let neg_ys = f.bin_twos_complement(&ys)?;
+            f.bin_addition(&xs, &neg_ys)
+        })
     }
 
     /// If `x=0` return `c1` as a bundle of constant bits, else return `c2`.
@@ -202,20 +370,22 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         c2: u128,
         nbits: usize,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        let c1_bs = util::u128_to_bits(c1, nbits)
-            .into_iter()
-            .map(|x: u16| x > 0)
-            .collect_vec();
-        let c2_bs = util::u128_to_bits(c2, nbits)
-            .into_iter()
-            .map(|x: u16| x > 0)
-            .collect_vec();
-        c1_bs
-            .into_iter()
-            .zip(c2_bs.into_iter())
-            .map(|(b1, b2)| self.mux_constant_bits(x, b1, b2))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(BinaryBundle::new)
+        self.scoped("bin_multiplex_constant_bits", |f| {
+            let c1_bs = util::u128_to_bits(c1, nbits)
+                .into_iter()
+                .map(|x: u16| x > 0)
+                .collect_vec();
+            let c2_bs = util::u128_to_bits(c2, nbits)
+                .into_iter()
+                .map(|x: u16| x > 0)
+                .collect_vec();
+            c1_bs
+                .into_iter()
+                .zip(c2_bs.into_iter())
+                .map(|(b1, b2)| f.mux_constant_bits(x, b1, b2))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(BinaryBundle::new)
+        })
     }
 
     /// Write the constant in binary and that gives you the shift amounts, Eg.. 7x is 4x+2x+x.
@@ -225,15 +395,17 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         c: u128,
         nbits: usize,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        let zero = self.bin_constant_bundle(0, nbits)?;
-        util::u128_to_bits(c, nbits)
-            .into_iter()
-            .enumerate()
-            .filter_map(|(i, b)| if b > 0 { Some(i) } else { None })
-            .fold(Ok(zero), |z, shift_amt| {
-                let s = self.shift(x, shift_amt).map(BinaryBundle)?;
-                self.bin_addition_no_carry(&(z?), &s)
-            })
+        self.scoped("bin_cmul", |f| {
+            let zero = f.bin_constant_bundle(0, nbits)?;
+            util::u128_to_bits(c, nbits)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, b)| if b > 0 { Some(i) } else { None })
+                .fold(Ok(zero), |z, shift_amt| {
+                    let s = f.shift(x, shift_amt).map(BinaryBundle)?;
+                    f.bin_addition_no_carry(&(z?), &s)
+                })
+        })
     }
 
     /// Compute the absolute value of a binary bundle.
@@ -241,9 +413,11 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         &mut self,
         x: &BinaryBundle<Self::Item>,
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        let sign = x.wires().last().unwrap();
-        let negated = self.bin_twos_complement(x)?;
-        self.multiplex(&sign, x, &negated).map(BinaryBundle)
+        self.scoped("bin_abs", |f| {
+            let sign = x.wires().last().unwrap();
+            let negated = f.bin_twos_complement(x)?;
+            f.multiplex(&sign, x, &negated).map(BinaryBundle)
+        })
     }
 
     /// Returns 1 if `x < y`.
@@ -252,28 +426,30 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         x: &BinaryBundle<Self::Item>,
         y: &BinaryBundle<Self::Item>,
     ) -> Result<Self::Item, Self::Error> {
-        // underflow indicates y != 0 && x >= y
-        // requiring special care to remove the y != 0, which is what follows.
-        let (_, lhs) = self.bin_subtraction(x, y)?;
-
-        // Now we build a clause equal to (y == 0 || x >= y), which we can OR with
-        // lhs to remove the y==0 aspect.
-        // check if y==0
-        let y_contains_1 = self.or_many(y.wires())?;
-        let y_eq_0 = self.negate(&y_contains_1)?;
-
-        // if x != 0, then x >= y, ... assuming x is not negative
-        let x_contains_1 = self.or_many(x.wires())?;
-
-        // y == 0 && x >= y
-        let rhs = self.and(&y_eq_0, &x_contains_1)?;
-
-        // (y != 0 && x >= y) || (y == 0 && x >= y)
-        // => x >= y && (y != 0 || y == 0)\
-        // => x >= y && 1
-        // => x >= y
-        let geq = self.or(&lhs, &rhs)?;
-        self.negate(&geq)
+        self.scoped("bin_lt", |f| {
+            // underflow indicates y != 0 && x >= y
+            // requiring special care to remove the y != 0, which is what follows.
+            let (_, lhs) = f.bin_subtraction(x, y)?;
+
+            // Now we build a clause equal to (y == 0 || x >= y), which we can OR with
+            // lhs to remove the y==0 aspect.
+            // check if y==0
+            let y_contains_1 = f.or_many(y.wires())?;
+            let y_eq_0 = f.negate(&y_contains_1)?;
+
+            // if x != 0, then x >= y, ... assuming x is not negative
+            let x_contains_1 = f.or_many(x.wires())?;
+
+            // y == 0 && x >= y
+            let rhs = f.and(&y_eq_0, &x_contains_1)?;
+
+            // (y != 0 && x >= y) || (y == 0 && x >= y)
+            // => x >= y && (y != 0 || y == 0)\
+            // => x >= y && 1
+            // => x >= y
+            let geq = f.or(&lhs, &rhs)?;
+            f.negate(&geq)
+        })
     }
 
     /// Returns 1 if `x >= y`.
@@ -282,8 +458,10 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         x: &BinaryBundle<Self::Item>,
         y: &BinaryBundle<Self::Item>,
     ) -> Result<Self::Item, Self::Error> {
-        let z = self.bin_lt(x, y)?;
-        self.negate(&z)
+        self.scoped("bin_geq", |f| {
+            let z = f.bin_lt(x, y)?;
+            f.negate(&z)
+        })
     }
 
     /// Compute the maximum bundle in `xs`.
@@ -291,56 +469,498 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         &mut self,
         xs: &[BinaryBundle<Self::Item>],
     ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
-        if xs.len() < 2 {
-            return Err(Self::Error::from(FancyError::InvalidArgNum {
-                got: xs.len(),
-                needed: 2,
-            }));
-        }
-        xs.iter().skip(1).fold(Ok(xs[0].clone()), |x, y| {
-            x.map(|x| {
-                let pos = self.bin_lt(&x, y)?;
-                let neg = self.negate(&pos)?;
-                x.wires()
-                    .iter()
-                    .zip(y.wires().iter())
-                    .map(|(x, y)| {
-                        let xp = self.mul(x, &neg)?;
-                        let yp = self.mul(y, &pos)?;
-                        self.add(&xp, &yp)
+        self.scoped("bin_max", |f| {
+            if xs.len() < 2 {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: xs.len(),
+                    needed: 2,
+                }));
+            }
+            xs.iter().skip(1).fold(Ok(xs[0].clone()), |x, y| {
+                x.map(|x| {
+                    let pos = f.bin_lt(&x, y)?;
+                    let neg = f.negate(&pos)?;
+                    x.wires()
+                        .iter()
+                        .zip(y.wires().iter())
+                        .map(|(x, y)| {
+                            let xp = f.mul(x, &neg)?;
+                            let yp = f.mul(y, &pos)?;
+                            f.add(&xp, &yp)
+                        })
+                        .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                        .map(BinaryBundle::new)
+                })?
+            })
+        })
+    }
+
+    /// Unsigned binary division using restoring long division. Returns the quotient
+    /// and the remainder.
+    ///
+    /// Division by zero returns a quotient with every bit set and the remainder `x`, as
+    /// in RISC-V. Each of the `n` steps costs an `n+1` bit subtraction and `n+1` ANDs
+    /// to select the new partial remainder.
+    fn bin_divmod(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<(BinaryBundle<Self::Item>, BinaryBundle<Self::Item>), Self::Error> {
+        self.scoped("bin_divmod", |f| {
+            if x.moduli() != y.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let n = x.size();
+            let zero = f.constant(0, 2)?;
+
+            // with y = 0 every step subtracts nothing and sets its quotient bit
+            let y_nonzero = if n == 1 {
+                y.wires()[0].clone()
+            } else {
+                f.or_many(y.wires())?
+            };
+            let y_zero = f.negate(&y_nonzero)?;
+
+            // the partial remainder is less than y before each shift, so it fits in n+1 bits
+            let mut ys = y.wires().to_vec();
+            ys.push(zero.clone());
+            let neg_y = f.bin_twos_complement(&BinaryBundle::new(ys))?;
+
+            let mut r = vec![zero.clone(); n + 1];
+            let mut q = vec![zero; n];
+            for i in (0..n).rev() {
+                r.pop();
+                r.insert(0, x.wires()[i].clone());
+                // the carry indicates y != 0 && r >= y
+                let (d, carry) = f.bin_addition(&BinaryBundle::new(r.clone()), &neg_y)?;
+                let qi = f.or(&carry, &y_zero)?;
+                r = select(f, &qi, &r, d.wires())?;
+                q[i] = qi;
+            }
+            r.truncate(n);
+
+            Ok((BinaryBundle::new(q), BinaryBundle::new(r)))
+        })
+    }
+
+    /// Unsigned binary division. Division by zero returns every bit set.
+    fn bin_div(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_div", |f| f.bin_divmod(x, y).map(|(q, _)| q))
+    }
+
+    /// Unsigned binary remainder. The remainder of division by zero is `x`.
+    fn bin_rem(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_rem", |f| f.bin_divmod(x, y).map(|(_, r)| r))
+    }
+
+    /// Signed twos complement division, rounding towards zero. Returns the quotient
+    /// and the remainder, which has the sign of `x`.
+    ///
+    /// As in RISC-V, division by zero returns the quotient `-1` and the remainder `x`,
+    /// and the overflowing division of the most negative value by `-1` returns that
+    /// value and the remainder `0`.
+    fn bin_divmod_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<(BinaryBundle<Self::Item>, BinaryBundle<Self::Item>), Self::Error> {
+        self.scoped("bin_divmod_signed", |f| {
+            if x.moduli() != y.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let x_sign = x.wires().last().unwrap();
+            let y_sign = y.wires().last().unwrap();
+            let x_abs = f.bin_abs(x)?;
+            let y_abs = f.bin_abs(y)?;
+            let (q, r) = f.bin_divmod(&x_abs, &y_abs)?;
+
+            // the unsigned quotient of division by zero is already -1, so leave it alone
+            let y_nonzero = if y.size() == 1 {
+                y.wires()[0].clone()
+            } else {
+                f.or_many(y.wires())?
+            };
+            let signs_differ = f.xor(x_sign, y_sign)?;
+            let negate_q = f.and(&signs_differ, &y_nonzero)?;
+
+            let neg_q = f.bin_twos_complement(&q)?;
+            let q = select(f, &negate_q, q.wires(), neg_q.wires())?;
+            let neg_r = f.bin_twos_complement(&r)?;
+            let r = select(f, x_sign, r.wires(), neg_r.wires())?;
+            Ok((BinaryBundle::new(q), BinaryBundle::new(r)))
+        })
+    }
+
+    /// Signed twos complement division, rounding towards zero. Division by zero
+    /// returns `-1`.
+    fn bin_div_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_div_signed", |f| {
+            f.bin_divmod_signed(x, y).map(|(q, _)| q)
+        })
+    }
+
+    /// Signed twos complement remainder, which has the sign of `x`. The remainder of
+    /// division by zero is `x`.
+    fn bin_rem_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_rem_signed", |f| {
+            f.bin_divmod_signed(x, y).map(|(_, r)| r)
+        })
+    }
+
+    /// Unsigned binary division by the constant `c`. Returns the quotient and the
+    /// remainder.
+    ///
+    /// When `c` has `m` bits, the first `m-1` steps of long division never subtract,
+    /// so this only needs `n-m+1` steps over `m+1` bit partial remainders. Division by
+    /// zero behaves as in `bin_divmod`.
+    fn bin_cdivmod(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        c: u128,
+    ) -> Result<(BinaryBundle<Self::Item>, BinaryBundle<Self::Item>), Self::Error> {
+        self.scoped("bin_cdivmod", |f| {
+            let n = x.size();
+            let m = (128 - c.leading_zeros()) as usize;
+            if c == 0 {
+                let ones = f.bin_constant_bundle(u128::MAX, n)?;
+                return Ok((ones, x.clone()));
+            }
+            if m > n {
+                let zero = f.bin_constant_bundle(0, n)?;
+                return Ok((zero, x.clone()));
+            }
+            let zero = f.constant(0, 2)?;
+
+            // -c in m+1 bits is the complement of c-1
+            let neg_c_bits = util::u128_to_bits(c - 1, m)
+                .into_iter()
+                .map(|b| 1 - b)
+                .chain(std::iter::once(1))
+                .collect_vec();
+            let neg_c = f.constant_bundle(&neg_c_bits, &vec![2; m + 1])?;
+            let neg_c = BinaryBundle::from(neg_c);
+
+            // the top m-1 bits of x are less than c, so start with them as the remainder
+            let mut r = x.wires()[n - m + 1..].to_vec();
+            r.resize(m + 1, zero.clone());
+            let mut q = vec![zero.clone(); n];
+            for i in (0..=n - m).rev() {
+                r.pop();
+                r.insert(0, x.wires()[i].clone());
+                // the carry indicates r >= c
+                let (d, carry) = f.bin_addition(&BinaryBundle::new(r.clone()), &neg_c)?;
+                r = select(f, &carry, &r, d.wires())?;
+                q[i] = carry;
+            }
+            r.truncate(m);
+            r.resize(n, zero);
+
+            Ok((BinaryBundle::new(q), BinaryBundle::new(r)))
+        })
+    }
+
+    /// Unsigned binary division by the constant `c`.
+    fn bin_cdiv(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        c: u128,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_cdiv", |f| f.bin_cdivmod(x, c).map(|(q, _)| q))
+    }
+
+    /// Unsigned binary remainder of division by the constant `c`.
+    fn bin_crem(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        c: u128,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_crem", |f| f.bin_cdivmod(x, c).map(|(_, r)| r))
+    }
+
+    /// Shift `x` left by the secret amount `s`, filling with zeros.
+    ///
+    /// A barrel shifter: each bit `j` of `s` multiplexes between the current value and
+    /// its shift by `2^j`, and bits of `s` worth at least the width of `x` select zero.
+    fn bin_shl(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_shl", |f| {
+            let zero = f.constant(0, 2)?;
+            barrel_shift(f, x, s, false, |ws, k| {
+                (0..ws.len())
+                    .map(|i| {
+                        if i >= k {
+                            ws[i - k].clone()
+                        } else {
+                            zero.clone()
+                        }
                     })
-                    .collect::<Result<Vec<Self::Item>, Self::Error>>()
-                    .map(BinaryBundle::new)
-            })?
+                    .collect()
+            })
         })
     }
 
-    /// Demux a binary bundle into a unary vector.
-    fn bin_demux(
+    /// Logical shift of `x` right by the secret amount `s`, filling with zeros.
+    fn bin_shr(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_shr", |f| {
+            let zero = f.constant(0, 2)?;
+            barrel_shift(f, x, s, false, |ws, k| {
+                (0..ws.len())
+                    .map(|i| ws.get(i + k).unwrap_or(&zero).clone())
+                    .collect()
+            })
+        })
+    }
+
+    /// Arithmetic shift of `x` right by the secret amount `s`, filling with the sign
+    /// bit of `x`.
+    fn bin_shr_arithmetic(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_shr_arithmetic", |f| {
+            barrel_shift(f, x, s, false, |ws, k| {
+                let sign = ws.last().unwrap();
+                (0..ws.len())
+                    .map(|i| ws.get(i + k).unwrap_or(sign).clone())
+                    .collect()
+            })
+        })
+    }
+
+    /// Rotate `x` left by the secret amount `s`, modulo the width of `x`.
+    fn bin_rotl(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_rotl", |f| {
+            barrel_shift(f, x, s, true, |ws, k| {
+                let n = ws.len();
+                (0..n).map(|i| ws[(i + n - k) % n].clone()).collect()
+            })
+        })
+    }
+
+    /// Rotate `x` right by the secret amount `s`, modulo the width of `x`.
+    fn bin_rotr(
         &mut self,
-        x: &BinaryBundle<Self::Item>
-    ) -> Result<Vec<Self::Item>, Self::Error> {
-        let wires = x.wires();
-        let nbits = wires.len();
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_rotr", |f| {
+            barrel_shift(f, x, s, true, |ws, k| {
+                let n = ws.len();
+                (0..n).map(|i| ws[(i + k) % n].clone()).collect()
+            })
+        })
+    }
 
-        let mut outs = Vec::with_capacity(1<<nbits);
+    /// Sort `xs` in ascending order using Batcher's odd-even merge sort.
+    fn bin_sort(
+        &mut self,
+        xs: &[BinaryBundle<Self::Item>],
+    ) -> Result<Vec<BinaryBundle<Self::Item>>, Self::Error> {
+        self.scoped("bin_sort", |f| {
+            let empty = vec![Bundle::new(Vec::new()); xs.len()];
+            f.bin_sort_with_payloads(xs, &empty).map(|(xs, _)| xs)
+        })
+    }
 
-        for ix in 0 .. 1<<nbits {
-            let mut acc = wires[0].clone();
-            if (ix & 1) == 0 {
-                acc = self.negate(&acc)?;
+    /// Sort `xs` in ascending order using Batcher's odd-even merge sort, moving each
+    /// bundle in `payloads` along with the key at the same index.
+    ///
+    /// Each comparator costs a `bin_lt` and a `cswap` of the keys and the payloads.
+    fn bin_sort_with_payloads(
+        &mut self,
+        xs: &[BinaryBundle<Self::Item>],
+        payloads: &[Bundle<Self::Item>],
+    ) -> Result<(Vec<BinaryBundle<Self::Item>>, Vec<Bundle<Self::Item>>), Self::Error> {
+        self.scoped("bin_sort_with_payloads", |f| {
+            if payloads.len() != xs.len() {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: payloads.len(),
+                    needed: xs.len(),
+                }));
+            }
+            let mut xs = xs.to_vec();
+            let mut ps = payloads.to_vec();
+            for (i, j) in util::sorting_network(xs.len()) {
+                let swap = f.bin_lt(&xs[j], &xs[i])?;
+                let (lo, hi) = f.cswap(&swap, &xs[i], &xs[j])?;
+                xs[i] = BinaryBundle::from(lo);
+                xs[j] = BinaryBundle::from(hi);
+                let (lo, hi) = f.cswap(&swap, &ps[i], &ps[j])?;
+                ps[i] = lo;
+                ps[j] = hi;
             }
-            for (i,w) in wires.iter().enumerate().skip(1) {
-                if ((ix >> i) & 1) > 0 {
-                    acc = self.and(&acc, w)?;
-                } else {
-                    let not_w = self.negate(w)?;
-                    acc = self.and(&acc, &not_w)?;
+            Ok((xs, ps))
+        })
+    }
+
+    /// Demux a binary bundle into a unary vector.
+    fn bin_demux(&mut self, x: &BinaryBundle<Self::Item>) -> Result<Vec<Self::Item>, Self::Error> {
+        self.scoped("bin_demux", |f| {
+            let wires = x.wires();
+            let nbits = wires.len();
+
+            let mut outs = Vec::with_capacity(1 << nbits);
+
+            for ix in 0..1 << nbits {
+                let mut acc = wires[0].clone();
+                if (ix & 1) == 0 {
+                    acc = f.negate(&acc)?;
+                }
+                for (i, w) in wires.iter().enumerate().skip(1) {
+                    if ((ix >> i) & 1) > 0 {
+                        acc = f.and(&acc, w)?;
+                    } else {
+                        let not_w = f.negate(w)?;
+                        acc = f.and(&acc, &not_w)?;
+                    }
                 }
+                outs.push(acc);
             }
-            outs.push(acc);
-        }
 
-        Ok(outs)
+            Ok(outs)
+        })
+    }
+
+    /// Convert a binary bundle, interpreted as an unsigned integer, into a CRT bundle
+    /// of its value mod `modulus`.
+    ///
+    /// Each bit is projected into each odd prime factor of `modulus`, costing one
+    /// ciphertext per bit per odd prime.
+    fn bin_to_crt(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        modulus: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        self.scoped("bin_to_crt", |f| {
+            if !x.is_binary() {
+                return Err(Self::Error::from(FancyError::ArgNotBinary));
+            }
+            util::factor(modulus)
+                .into_iter()
+                .map(|p| {
+                    if p == 2 {
+                        // only the lowest bit matters mod 2
+                        return match x.wires().first() {
+                            Some(w) => Ok(w.clone()),
+                            None => f.constant(0, 2),
+                        };
+                    }
+                    let mut place = 1;
+                    let terms = x
+                        .wires()
+                        .iter()
+                        .map(|w| {
+                            let tt = vec![0, place];
+                            place = (place as u32 * 2 % p as u32) as u16;
+                            f.proj(w, p, Some(tt))
+                        })
+                        .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+                    match terms.len() {
+                        0 => f.constant(0, p),
+                        1 => Ok(terms[0].clone()),
+                        _ => f.add_many(&terms),
+                    }
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)
+        })
+    }
+}
+
+/// If `b = 0` returns the bits `xs`, else `ys`, as `x + b(x + y)` using one AND per
+/// bit where `multiplex` uses two, which only works mod 2.
+fn select<F: Fancy + ?Sized>(
+    f: &mut F,
+    b: &F::Item,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    xs.iter()
+        .zip(ys.iter())
+        .map(|(x, y)| {
+            let diff = f.xor(x, y)?;
+            let sel = f.and(b, &diff)?;
+            f.xor(x, &sel)
+        })
+        .collect()
+}
+
+/// Barrel shifter over the bits of `x`, where `step(ws, k)` shifts the bits `ws` by `k`.
+///
+/// Uses one layer of `multiplex` per bit of `s`. Rotations shift by `2^j mod n` for
+/// each bit `j` of `s`, while shifts OR together the bits of `s` worth at least `n`
+/// and use a final layer to select `step(ws, n)`.
+fn barrel_shift<F, S>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    s: &BinaryBundle<F::Item>,
+    rotate: bool,
+    step: S,
+) -> Result<BinaryBundle<F::Item>, F::Error>
+where
+    F: BundleGadgets + ?Sized,
+    S: Fn(&[F::Item], usize) -> Vec<F::Item>,
+{
+    if !x.is_binary() || !s.is_binary() {
+        return Err(F::Error::from(FancyError::ArgNotBinary));
+    }
+    let n = x.size();
+    let mut z = Bundle::new(x.wires().to_vec());
+    let mut overflow = Vec::new();
+    let mut amt = if rotate { 1 % n } else { 1 };
+    for b in s.wires().iter() {
+        let k = amt;
+        amt = if rotate {
+            amt * 2 % n
+        } else {
+            amt.saturating_mul(2)
+        };
+        if !rotate && k >= n {
+            overflow.push(b.clone());
+            continue;
+        }
+        if k == 0 {
+            continue;
+        }
+        let shifted = Bundle::new(step(z.wires(), k));
+        z = f.multiplex(b, &z, &shifted)?;
+    }
+    if !overflow.is_empty() {
+        let big = if overflow.len() == 1 {
+            overflow.pop().unwrap()
+        } else {
+            f.or_many(&overflow)?
+        };
+        let filled = Bundle::new(step(z.wires(), n));
+        z = f.multiplex(&big, &z, &filled)?;
     }
+    Ok(BinaryBundle::from(z))
 }
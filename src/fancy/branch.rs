@@ -0,0 +1,194 @@
+//! Module containing `FancyBranch`, which conditionally executes one of several
+//! branches.
+
+use super::{Fancy, HasModulus};
+use crate::circuit::{Circuit, CircuitBuilder, CircuitRef};
+use crate::error::{CircuitBuilderError, FancyError};
+
+/// A branch of a conditional, built from `CircuitRef`s for its inputs.
+pub type Branch<'a> =
+    &'a dyn Fn(&mut CircuitBuilder, &[CircuitRef]) -> Result<Vec<CircuitRef>, CircuitBuilderError>;
+
+/// Extension trait for `Fancy` providing conditional execution of branches.
+///
+/// By default every branch is computed and the result selected with a multiplexer, so
+/// a conditional costs as much as all of its branches together. The streaming
+/// `Garbler` and `Evaluator` use stacked garbling instead, which costs about as much
+/// as the largest branch.
+///
+/// `CircuitBuilder` builds the multiplexer, and records the branches next to it so
+/// that `garble` and `GarbledCircuit::eval` stack them. Other ways of garbling a
+/// `Circuit`, such as `Circuit::eval` with a streaming `Garbler`, pay for every
+/// branch.
+pub trait FancyBranch: Fancy + Sized {
+    /// Compute `branches[cond]` on `inputs`, where `cond` has modulus
+    /// `branches.len()`. Every branch must return outputs with the same moduli.
+    ///
+    /// The branches are built into `Circuit`s with garbler inputs for `inputs`, so
+    /// they can be garbled separately.
+    fn branch(
+        &mut self,
+        cond: &Self::Item,
+        inputs: &[Self::Item],
+        branches: &[Branch],
+    ) -> Result<Vec<Self::Item>, Self::Error> {
+        let mut circuits = build_branches(cond, inputs, branches)?;
+        multiplex(self, cond, inputs, &mut circuits)
+    }
+}
+
+/// Compute every one of the branches `circuits` on `inputs` and select the one for
+/// `cond` with a multiplexer.
+pub(crate) fn multiplex<F: Fancy>(
+    f: &mut F,
+    cond: &F::Item,
+    inputs: &[F::Item],
+    circuits: &mut [Circuit],
+) -> Result<Vec<F::Item>, F::Error> {
+    f.scoped("branch", |f| {
+        let mut outputs: Vec<F::Item> = Vec::new();
+        for (i, c) in circuits.iter_mut().enumerate() {
+            let zs = c.eval(f, inputs, &[])?;
+            for (k, z) in zs.iter().enumerate() {
+                let tt = (0..cond.modulus())
+                    .map(|v| (v == i as u16) as u16)
+                    .collect();
+                let sel = f.proj(cond, z.modulus(), Some(tt))?;
+                let z = f.mul(&sel, z)?;
+                if i == 0 {
+                    outputs.push(z);
+                } else {
+                    outputs[k] = f.add(&outputs[k], &z)?;
+                }
+            }
+        }
+        Ok(outputs)
+    })
+}
+
+/// Build a `Circuit` for each of `branches` on inputs with the moduli of `inputs`,
+/// checking that there is one for each value of `cond` and that their outputs have
+/// the same moduli.
+pub(crate) fn build_branches<W: HasModulus>(
+    cond: &W,
+    inputs: &[W],
+    branches: &[Branch],
+) -> Result<Vec<Circuit>, FancyError> {
+    if cond.modulus() as usize != branches.len() {
+        return Err(FancyError::InvalidArgMod {
+            got: cond.modulus(),
+            needed: branches.len() as u16,
+        });
+    }
+    let moduli = inputs.iter().map(|x| x.modulus()).collect::<Vec<u16>>();
+    let mut circuits = Vec::with_capacity(branches.len());
+    for branch in branches.iter() {
+        let mut b = CircuitBuilder::new();
+        let xs = b.garbler_inputs(&moduli);
+        let zs = branch(&mut b, &xs).map_err(|e| match e {
+            CircuitBuilderError::FancyError(e) => e,
+            e => FancyError::InvalidArg(e.to_string()),
+        })?;
+        b.outputs(&zs)
+            .map_err(|e| FancyError::InvalidArg(e.to_string()))?;
+        circuits.push(b.finish());
+    }
+    let output_moduli = |c: &Circuit| {
+        c.output_refs
+            .iter()
+            .map(|r| r.modulus())
+            .collect::<Vec<u16>>()
+    };
+    if circuits
+        .iter()
+        .any(|c| output_moduli(c) != output_moduli(&circuits[0]))
+    {
+        return Err(FancyError::InvalidArg(
+            "branches have outputs with different moduli".to_string(),
+        ));
+    }
+    Ok(circuits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::dummy::Dummy;
+    use crate::fancy::FancyInput;
+    use crate::util::RngExt;
+    use rand::thread_rng;
+
+    fn add(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        Ok(vec![b.add(&xs[0], &xs[1])?])
+    }
+
+    fn mul(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        Ok(vec![b.mul(&xs[0], &xs[1])?])
+    }
+
+    fn square(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        Ok(vec![b.mul(&xs[0], &xs[0])?])
+    }
+
+    #[test]
+    fn dummy() {
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let x = rng.gen_u16() % 7;
+            let y = rng.gen_u16() % 7;
+            let c = rng.gen_u16() % 3;
+            let mut d = Dummy::new();
+            let cond = d.encode(c, 3).unwrap();
+            let xs = d.encode_many(&[x, y], &[7, 7]).unwrap();
+            let zs = d.branch(&cond, &xs, &[&add, &mul, &square]).unwrap();
+            d.outputs(&zs).unwrap();
+            let should_be = [(x + y) % 7, x * y % 7, x * x % 7][c as usize];
+            assert_eq!(d.get_output(), vec![should_be]);
+        }
+    }
+
+    #[test]
+    fn circuit_builder() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let cond = b.garbler_input(2);
+        let xs = b.evaluator_inputs(&[7, 7]);
+        let zs = b.branch(&cond, &xs, &[&add, &mul]).unwrap();
+        b.outputs(&zs).unwrap();
+        let mut c = b.finish();
+        for _ in 0..16 {
+            let cond = rng.gen_u16() % 2;
+            let x = rng.gen_u16() % 7;
+            let y = rng.gen_u16() % 7;
+            let should_be = if cond == 0 { (x + y) % 7 } else { x * y % 7 };
+            assert_eq!(c.eval_plain(&[cond], &[x, y]).unwrap(), vec![should_be]);
+        }
+    }
+
+    #[test]
+    fn invalid_branches() {
+        let mut d = Dummy::new();
+        let cond = d.encode(0, 3).unwrap();
+        let xs = d.encode_many(&[1, 2], &[7, 7]).unwrap();
+        // one branch for each value of the condition
+        assert!(d.branch(&cond, &xs, &[&add, &mul]).is_err());
+        // outputs of different moduli
+        let bit = |b: &mut CircuitBuilder,
+                   xs: &[CircuitRef]|
+         -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+            let z = b.proj(&xs[0], 2, Some(vec![0, 1, 0, 1, 0, 1, 0]))?;
+            Ok(vec![b.and(&z, &z)?])
+        };
+        assert!(d.branch(&cond, &xs, &[&add, &mul, &bit]).is_err());
+    }
+}
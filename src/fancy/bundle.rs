@@ -1,5 +1,5 @@
 use crate::error::FancyError;
-use crate::fancy::{Fancy, HasModulus};
+use crate::fancy::{Fancy, HasModulus, OutputMode};
 use itertools::Itertools;
 use std::ops::Index;
 
@@ -107,6 +107,18 @@ pub trait BundleGadgets: Fancy {
         Ok(())
     }
 
+    /// Output the wires that make up a bundle, revealing them according to `mode`.
+    fn output_bundle_with_mode(
+        &mut self,
+        x: &Bundle<Self::Item>,
+        mode: OutputMode,
+    ) -> Result<(), Self::Error> {
+        for w in x.wires() {
+            self.output_with_mode(w, mode)?;
+        }
+        Ok(())
+    }
+
     ////////////////////////////////////////////////////////////////////////////////
     // gadgets which are neither CRT or binary
 
@@ -162,12 +174,14 @@ pub trait BundleGadgets: Fancy {
         x: &Bundle<Self::Item>,
         y: &Bundle<Self::Item>,
     ) -> Result<Bundle<Self::Item>, Self::Error> {
-        x.wires()
-            .iter()
-            .zip(y.wires().iter())
-            .map(|(x, y)| self.mul(x, y))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(Bundle::new)
+        self.scoped("mul_bundles", |f| {
+            x.wires()
+                .iter()
+                .zip(y.wires().iter())
+                .map(|(x, y)| f.mul(x, y))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(Bundle::new)
+        })
     }
 
     /// Mixed radix addition.
@@ -175,78 +189,81 @@ pub trait BundleGadgets: Fancy {
         &mut self,
         xs: &[Bundle<Self::Item>],
     ) -> Result<Bundle<Self::Item>, Self::Error> {
-        let nargs = xs.len();
-        let n = xs[0].wires().len();
-
-        if nargs < 2 {
-            return Err(Self::Error::from(FancyError::InvalidArgNum {
-                got: nargs,
-                needed: 2,
-            }));
-        }
-        if !xs.iter().all(|x| x.moduli() == xs[0].moduli()) {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
-
-        let mut digit_carry = None;
-        let mut carry_carry = None;
-        let mut max_carry = 0;
-
-        let mut res = Vec::with_capacity(n);
-
-        for i in 0..n {
-            // all the ith digits, in one vec
-            let ds = xs.iter().map(|x| x.wires()[i].clone()).collect_vec();
-
-            // compute the digit -- easy
-            let digit_sum = self.add_many(&ds)?;
-            let digit = digit_carry.map_or(Ok(digit_sum.clone()), |d| self.add(&digit_sum, &d))?;
-
-            if i < n - 1 {
-                // compute the carries
-                let q = xs[0].wires()[i].modulus();
-                // max_carry currently contains the max carry from the previous iteration
-                let max_val = nargs as u16 * (q - 1) + max_carry;
-                // now it is the max carry of this iteration
-                max_carry = max_val / q;
-
-                let modded_ds = ds
-                    .iter()
-                    .map(|d| self.mod_change(d, max_val + 1))
-                    .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
-
-                let carry_sum = self.add_many(&modded_ds)?;
-                // add in the carry from the previous iteration
-                let carry =
-                    carry_carry.map_or(Ok(carry_sum.clone()), |c| self.add(&carry_sum, &c))?;
-
-                // carry now contains the carry information, we just have to project it to
-                // the correct moduli for the next iteration
-                let next_mod = xs[0].wires()[i + 1].modulus();
-                let tt = (0..=max_val).map(|i| (i / q) % next_mod).collect_vec();
-                digit_carry = Some(self.proj(&carry, next_mod, Some(tt))?);
-
-                let next_max_val = nargs as u16 * (next_mod - 1) + max_carry;
+        self.scoped("mixed_radix_addition", |f| {
+            let nargs = xs.len();
+            let n = xs[0].wires().len();
+
+            if nargs < 2 {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: nargs,
+                    needed: 2,
+                }));
+            }
+            if !xs.iter().all(|x| x.moduli() == xs[0].moduli()) {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
 
-                if i < n - 2 {
-                    if max_carry < next_mod {
-                        carry_carry =
-                            Some(self.mod_change(digit_carry.as_ref().unwrap(), next_max_val + 1)?);
+            let mut digit_carry = None;
+            let mut carry_carry = None;
+            let mut max_carry = 0;
+
+            let mut res = Vec::with_capacity(n);
+
+            for i in 0..n {
+                // all the ith digits, in one vec
+                let ds = xs.iter().map(|x| x.wires()[i].clone()).collect_vec();
+
+                // compute the digit -- easy
+                let digit_sum = f.add_many(&ds)?;
+                let digit = digit_carry.map_or(Ok(digit_sum.clone()), |d| f.add(&digit_sum, &d))?;
+
+                if i < n - 1 {
+                    // compute the carries
+                    let q = xs[0].wires()[i].modulus();
+                    // max_carry currently contains the max carry from the previous iteration
+                    let max_val = nargs as u16 * (q - 1) + max_carry;
+                    // now it is the max carry of this iteration
+                    max_carry = max_val / q;
+
+                    let modded_ds = ds
+                        .iter()
+                        .map(|d| f.mod_change(d, max_val + 1))
+                        .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+
+                    let carry_sum = f.add_many(&modded_ds)?;
+                    // add in the carry from the previous iteration
+                    let carry =
+                        carry_carry.map_or(Ok(carry_sum.clone()), |c| f.add(&carry_sum, &c))?;
+
+                    // carry now contains the carry information, we just have to project it to
+                    // the correct moduli for the next iteration
+                    let next_mod = xs[0].wires()[i + 1].modulus();
+                    let tt = (0..=max_val).map(|i| (i / q) % next_mod).collect_vec();
+                    digit_carry = Some(f.proj(&carry, next_mod, Some(tt))?);
+
+                    let next_max_val = nargs as u16 * (next_mod - 1) + max_carry;
+
+                    if i < n - 2 {
+                        if max_carry < next_mod {
+                            carry_carry = Some(
+                                f.mod_change(digit_carry.as_ref().unwrap(), next_max_val + 1)?,
+                            );
+                        } else {
+                            let tt = (0..=max_val).map(|i| i / q).collect_vec();
+                            carry_carry = Some(f.proj(&carry, next_max_val + 1, Some(tt))?);
+                        }
                     } else {
-                        let tt = (0..=max_val).map(|i| i / q).collect_vec();
-                        carry_carry = Some(self.proj(&carry, next_max_val + 1, Some(tt))?);
+                        // next digit is MSB so we dont need carry_carry
+                        carry_carry = None;
                     }
                 } else {
-                    // next digit is MSB so we dont need carry_carry
+                    digit_carry = None;
                     carry_carry = None;
                 }
-            } else {
-                digit_carry = None;
-                carry_carry = None;
+                res.push(digit);
             }
-            res.push(digit);
-        }
-        Ok(Bundle(res))
+            Ok(Bundle(res))
+        })
     }
 
     /// Mixed radix addition only returning the MSB.
@@ -254,65 +271,67 @@ pub trait BundleGadgets: Fancy {
         &mut self,
         xs: &[Bundle<Self::Item>],
     ) -> Result<Self::Item, Self::Error> {
-        let nargs = xs.len();
-        let n = xs[0].wires().len();
+        self.scoped("mixed_radix_addition_msb_only", |f| {
+            let nargs = xs.len();
+            let n = xs[0].wires().len();
+
+            if nargs < 2 {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: nargs,
+                    needed: 2,
+                }));
+            }
+            if !xs.iter().all(|x| x.moduli() == xs[0].moduli()) {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
 
-        if nargs < 2 {
-            return Err(Self::Error::from(FancyError::InvalidArgNum {
-                got: nargs,
-                needed: 2,
-            }));
-        }
-        if !xs.iter().all(|x| x.moduli() == xs[0].moduli()) {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
+            let mut opt_carry = None;
+            let mut max_carry = 0;
 
-        let mut opt_carry = None;
-        let mut max_carry = 0;
-
-        for i in 0..n - 1 {
-            // all the ith digits, in one vec
-            let ds = xs.iter().map(|x| x.wires()[i].clone()).collect_vec();
-            // compute the carry
-            let q = xs[0].moduli()[i];
-            // max_carry currently contains the max carry from the previous iteration
-            let max_val = nargs as u16 * (q - 1) + max_carry;
-            // now it is the max carry of this iteration
-            max_carry = max_val / q;
-
-            // mod change the digits to the max sum possible plus the max carry of the
-            // previous iteration
-            let modded_ds = ds
-                .iter()
-                .map(|d| self.mod_change(d, max_val + 1))
-                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
-            // add them up
-            let sum = self.add_many(&modded_ds)?;
-            // add in the carry
-            let sum_with_carry = opt_carry
-                .as_ref()
-                .map_or(Ok(sum.clone()), |c| self.add(&sum, &c))?;
-
-            // carry now contains the carry information, we just have to project it to
-            // the correct moduli for the next iteration. It will either be used to
-            // compute the next carry, if i < n-2, or it will be used to compute the
-            // output MSB, in which case it should be the modulus of the SB
-            let next_mod = if i < n - 2 {
-                nargs as u16 * (xs[0].moduli()[i + 1] - 1) + max_carry + 1
-            } else {
-                xs[0].moduli()[i + 1] // we will be adding the carry to the MSB
-            };
+            for i in 0..n - 1 {
+                // all the ith digits, in one vec
+                let ds = xs.iter().map(|x| x.wires()[i].clone()).collect_vec();
+                // compute the carry
+                let q = xs[0].moduli()[i];
+                // max_carry currently contains the max carry from the previous iteration
+                let max_val = nargs as u16 * (q - 1) + max_carry;
+                // now it is the max carry of this iteration
+                max_carry = max_val / q;
 
-            let tt = (0..=max_val).map(|i| (i / q) % next_mod).collect_vec();
-            opt_carry = Some(self.proj(&sum_with_carry, next_mod, Some(tt))?);
-        }
+                // mod change the digits to the max sum possible plus the max carry of the
+                // previous iteration
+                let modded_ds = ds
+                    .iter()
+                    .map(|d| f.mod_change(d, max_val + 1))
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+                // add them up
+                let sum = f.add_many(&modded_ds)?;
+                // add in the carry
+                let sum_with_carry = opt_carry
+                    .as_ref()
+                    .map_or(Ok(sum.clone()), |c| f.add(&sum, &c))?;
+
+                // carry now contains the carry information, we just have to project it to
+                // the correct moduli for the next iteration. It will either be used to
+                // compute the next carry, if i < n-2, or it will be used to compute the
+                // output MSB, in which case it should be the modulus of the SB
+                let next_mod = if i < n - 2 {
+                    nargs as u16 * (xs[0].moduli()[i + 1] - 1) + max_carry + 1
+                } else {
+                    xs[0].moduli()[i + 1] // we will be adding the carry to the MSB
+                };
 
-        // compute the msb
-        let ds = xs.iter().map(|x| x.wires()[n - 1].clone()).collect_vec();
-        let digit_sum = self.add_many(&ds)?;
-        opt_carry
-            .as_ref()
-            .map_or(Ok(digit_sum.clone()), |d| self.add(&digit_sum, &d))
+                let tt = (0..=max_val).map(|i| (i / q) % next_mod).collect_vec();
+                opt_carry = Some(f.proj(&sum_with_carry, next_mod, Some(tt))?);
+            }
+
+            // compute the msb
+            let ds = xs.iter().map(|x| x.wires()[n - 1].clone()).collect_vec();
+            let digit_sum = f.add_many(&ds)?;
+            opt_carry
+                .as_ref()
+                .map_or(Ok(digit_sum.clone()), |d| f.add(&digit_sum, &d))
+        })
     }
 
     /// If b=0 then return x, else return y.
@@ -322,12 +341,31 @@ pub trait BundleGadgets: Fancy {
         x: &Bundle<Self::Item>,
         y: &Bundle<Self::Item>,
     ) -> Result<Bundle<Self::Item>, Self::Error> {
-        x.wires()
-            .iter()
-            .zip(y.wires().iter())
-            .map(|(xwire, ywire)| self.mux(b, xwire, ywire))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(Bundle)
+        self.scoped("multiplex", |f| {
+            x.wires()
+                .iter()
+                .zip(y.wires().iter())
+                .map(|(xwire, ywire)| f.mux(b, xwire, ywire))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(Bundle)
+        })
+    }
+
+    /// If b=0 then return `(x, y)`, else return `(y, x)`.
+    ///
+    /// Uses one `multiplex` and derives the other output as `x + y` minus the first.
+    fn cswap(
+        &mut self,
+        b: &Self::Item,
+        x: &Bundle<Self::Item>,
+        y: &Bundle<Self::Item>,
+    ) -> Result<(Bundle<Self::Item>, Bundle<Self::Item>), Self::Error> {
+        self.scoped("cswap", |f| {
+            let z = f.multiplex(b, x, y)?;
+            let sum = f.add_bundles(x, y)?;
+            let w = f.sub_bundles(&sum, &z)?;
+            Ok((z, w))
+        })
     }
 
     /// If b=0 then return 0, else return x.
@@ -336,11 +374,13 @@ pub trait BundleGadgets: Fancy {
         b: &Self::Item,
         x: &Bundle<Self::Item>,
     ) -> Result<Bundle<Self::Item>, Self::Error> {
-        x.wires()
-            .iter()
-            .map(|xwire| self.mul(xwire, b))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(Bundle)
+        self.scoped("mask", |f| {
+            x.wires()
+                .iter()
+                .map(|xwire| f.mul(xwire, b))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(Bundle)
+        })
     }
 
     /// Shift residues, replacing them with zeros in the modulus of the least signifigant residue.
@@ -364,27 +404,29 @@ pub trait BundleGadgets: Fancy {
         x: &Bundle<Self::Item>,
         y: &Bundle<Self::Item>,
     ) -> Result<Self::Item, Self::Error> {
-        if x.moduli() != y.moduli() {
-            return Err(Self::Error::from(FancyError::UnequalModuli));
-        }
-        let wlen = x.wires().len() as u16;
-        let zs = x
-            .wires()
-            .iter()
-            .zip_eq(y.wires().iter())
-            .map(|(x, y)| {
-                // compute (x-y == 0) for each residue
-                let z = self.sub(x, y)?;
-                let mut eq_zero_tab = vec![0; x.modulus() as usize];
-                eq_zero_tab[0] = 1;
-                self.proj(&z, wlen + 1, Some(eq_zero_tab))
-            })
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
-        // add up the results, and output whether they equal zero or not, mod 2
-        let z = self.add_many(&zs)?;
-        let b = zs.len();
-        let mut tab = vec![0; b + 1];
-        tab[b] = 1;
-        self.proj(&z, 2, Some(tab))
+        self.scoped("eq_bundles", |f| {
+            if x.moduli() != y.moduli() {
+                return Err(Self::Error::from(FancyError::UnequalModuli));
+            }
+            let wlen = x.wires().len() as u16;
+            let zs = x
+                .wires()
+                .iter()
+                .zip_eq(y.wires().iter())
+                .map(|(x, y)| {
+                    // compute (x-y == 0) for each residue
+                    let z = f.sub(x, y)?;
+                    let mut eq_zero_tab = vec![0; x.modulus() as usize];
+                    eq_zero_tab[0] = 1;
+                    f.proj(&z, wlen + 1, Some(eq_zero_tab))
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+            // add up the results, and output whether they equal zero or not, mod 2
+            let z = f.add_many(&zs)?;
+            let b = zs.len();
+            let mut tab = vec![0; b + 1];
+            tab[b] = 1;
+            f.proj(&z, 2, Some(tab))
+        })
     }
 }
@@ -2,6 +2,7 @@
 
 use super::{Fancy, HasModulus};
 use crate::error::FancyError;
+use crate::fancy::binary::BinaryBundle;
 use crate::fancy::bundle::{Bundle, BundleGadgets};
 use crate::util;
 use itertools::Itertools;
@@ -108,7 +109,7 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         x: &CrtBundle<Self::Item>,
         y: &CrtBundle<Self::Item>,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        self.mul_bundles(x, y).map(CrtBundle)
+        self.scoped("crt_mul", |f| f.mul_bundles(x, y).map(CrtBundle))
     }
 
     /// Exponentiate `x` by the constant `c`.
@@ -117,17 +118,19 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         x: &CrtBundle<Self::Item>,
         c: u16,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        x.wires()
-            .iter()
-            .map(|x| {
-                let p = x.modulus();
-                let tab = (0..p)
-                    .map(|x| ((x as u64).pow(c as u32) % p as u64) as u16)
-                    .collect_vec();
-                self.proj(x, p, Some(tab))
-            })
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(CrtBundle::new)
+        self.scoped("crt_cexp", |f| {
+            x.wires()
+                .iter()
+                .map(|x| {
+                    let p = x.modulus();
+                    let tab = (0..p)
+                        .map(|x| ((x as u64).pow(c as u32) % p as u64) as u16)
+                        .collect_vec();
+                    f.proj(x, p, Some(tab))
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)
+        })
     }
 
     /// Compute the remainder with respect to modulus `p`.
@@ -136,17 +139,19 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         x: &CrtBundle<Self::Item>,
         p: u16,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        let i = x.moduli().iter().position(|&q| p == q).ok_or_else(|| {
-            Self::Error::from(FancyError::InvalidArg(
-                "p is not a modulus in this bundle!".to_string(),
-            ))
-        })?;
-        let w = &x.wires()[i];
-        x.moduli()
-            .iter()
-            .map(|&q| self.mod_change(w, q))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(CrtBundle::new)
+        self.scoped("crt_rem", |f| {
+            let i = x.moduli().iter().position(|&q| p == q).ok_or_else(|| {
+                Self::Error::from(FancyError::InvalidArg(
+                    "p is not a modulus in this bundle!".to_string(),
+                ))
+            })?;
+            let w = &x.wires()[i];
+            x.moduli()
+                .iter()
+                .map(|&q| f.mod_change(w, q))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)
+        })
     }
 
     ////////////////////////////////////////////////////////////////////////////////
@@ -159,37 +164,39 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         bun: &CrtBundle<Self::Item>,
         ms: &[u16],
     ) -> Result<Self::Item, Self::Error> {
-        let ndigits = ms.len();
+        self.scoped("crt_fractional_mixed_radix", |f| {
+            let ndigits = ms.len();
 
-        let q = util::product(&bun.moduli());
-        let M = util::product(ms);
+            let q = util::product(&bun.moduli());
+            let M = util::product(ms);
 
-        let mut ds = Vec::new();
+            let mut ds = Vec::new();
 
-        for wire in bun.wires().iter() {
-            let p = wire.modulus();
+            for wire in bun.wires().iter() {
+                let p = wire.modulus();
 
-            let mut tabs = vec![Vec::with_capacity(p as usize); ndigits];
+                let mut tabs = vec![Vec::with_capacity(p as usize); ndigits];
 
-            for x in 0..p {
-                let crt_coef = util::inv(((q / p as u128) % p as u128) as i128, p as i128);
-                let y = (M as f64 * x as f64 * crt_coef as f64 / p as f64).round() as u128 % M;
-                let digits = util::as_mixed_radix(y, ms);
-                for i in 0..ndigits {
-                    tabs[i].push(digits[i]);
+                for x in 0..p {
+                    let crt_coef = util::inv(((q / p as u128) % p as u128) as i128, p as i128);
+                    let y = (M as f64 * x as f64 * crt_coef as f64 / p as f64).round() as u128 % M;
+                    let digits = util::as_mixed_radix(y, ms);
+                    for i in 0..ndigits {
+                        tabs[i].push(digits[i]);
+                    }
                 }
-            }
 
-            let new_ds = tabs
-                .into_iter()
-                .enumerate()
-                .map(|(i, tt)| self.proj(wire, ms[i], Some(tt)))
-                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+                let new_ds = tabs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, tt)| f.proj(wire, ms[i], Some(tt)))
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
 
-            ds.push(Bundle::new(new_ds));
-        }
+                ds.push(Bundle::new(new_ds));
+            }
 
-        self.mixed_radix_addition_msb_only(&ds)
+            f.mixed_radix_addition_msb_only(&ds)
+        })
     }
 
     /// Compute `max(x,0)`.
@@ -201,24 +208,26 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         accuracy: &str,
         output_moduli: Option<&[u16]>,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        let factors_of_m = &get_ms(x, accuracy);
-        let res = self.crt_fractional_mixed_radix(x, factors_of_m)?;
-
-        // project the MSB to 0/1, whether or not it is less than p/2
-        let p = *factors_of_m.last().unwrap();
-        let mask_tt = (0..p).map(|x| (x < p / 2) as u16).collect_vec();
-        let mask = self.proj(&res, 2, Some(mask_tt))?;
-
-        // use the mask to either output x or 0
-        output_moduli
-            .map(|ps| x.with_moduli(ps))
-            .as_ref()
-            .unwrap_or(x)
-            .wires()
-            .iter()
-            .map(|x| self.mul(x, &mask))
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(CrtBundle::new)
+        self.scoped("crt_relu", |f| {
+            let factors_of_m = &get_ms(x, accuracy);
+            let res = f.crt_fractional_mixed_radix(x, factors_of_m)?;
+
+            // project the MSB to 0/1, whether or not it is less than p/2
+            let p = *factors_of_m.last().unwrap();
+            let mask_tt = (0..p).map(|x| (x < p / 2) as u16).collect_vec();
+            let mask = f.proj(&res, 2, Some(mask_tt))?;
+
+            // use the mask to either output x or 0
+            output_moduli
+                .map(|ps| x.with_moduli(ps))
+                .as_ref()
+                .unwrap_or(x)
+                .wires()
+                .iter()
+                .map(|x| f.mul(x, &mask))
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)
+        })
     }
 
     /// Return 0 if `x` is positive and 1 if `x` is negative.
@@ -227,11 +236,13 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         x: &CrtBundle<Self::Item>,
         accuracy: &str,
     ) -> Result<Self::Item, Self::Error> {
-        let factors_of_m = &get_ms(x, accuracy);
-        let res = self.crt_fractional_mixed_radix(x, factors_of_m)?;
-        let p = *factors_of_m.last().unwrap();
-        let tt = (0..p).map(|x| (x >= p / 2) as u16).collect_vec();
-        self.proj(&res, 2, Some(tt))
+        self.scoped("crt_sign", |f| {
+            let factors_of_m = &get_ms(x, accuracy);
+            let res = f.crt_fractional_mixed_radix(x, factors_of_m)?;
+            let p = *factors_of_m.last().unwrap();
+            let tt = (0..p).map(|x| (x >= p / 2) as u16).collect_vec();
+            f.proj(&res, 2, Some(tt))
+        })
     }
 
     /// Return `if x >= 0 then 1 else -1`, where `-1` is interpreted as `Q-1`.
@@ -243,16 +254,18 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         accuracy: &str,
         output_moduli: Option<&[u16]>,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        let sign = self.crt_sign(x, accuracy)?;
-        output_moduli
-            .unwrap_or(&x.moduli())
-            .iter()
-            .map(|&p| {
-                let tt = vec![1, p - 1];
-                self.proj(&sign, p, Some(tt))
-            })
-            .collect::<Result<Vec<Self::Item>, Self::Error>>()
-            .map(CrtBundle::new)
+        self.scoped("crt_sgn", |f| {
+            let sign = f.crt_sign(x, accuracy)?;
+            output_moduli
+                .unwrap_or(&x.moduli())
+                .iter()
+                .map(|&p| {
+                    let tt = vec![1, p - 1];
+                    f.proj(&sign, p, Some(tt))
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)
+        })
     }
 
     /// Returns 1 if `x < y`.
@@ -262,8 +275,10 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         y: &CrtBundle<Self::Item>,
         accuracy: &str,
     ) -> Result<Self::Item, Self::Error> {
-        let z = self.crt_sub(x, y)?;
-        self.crt_sign(&z, accuracy)
+        self.scoped("crt_lt", |f| {
+            let z = f.crt_sub(x, y)?;
+            f.crt_sign(&z, accuracy)
+        })
     }
 
     /// Returns 1 if `x >= y`.
@@ -273,8 +288,10 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         y: &CrtBundle<Self::Item>,
         accuracy: &str,
     ) -> Result<Self::Item, Self::Error> {
-        let z = self.crt_lt(x, y, accuracy)?;
-        self.negate(&z)
+        self.scoped("crt_geq", |f| {
+            let z = f.crt_lt(x, y, accuracy)?;
+            f.negate(&z)
+        })
     }
 
     /// Compute the maximum bundle in `xs`.
@@ -283,27 +300,152 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         xs: &[CrtBundle<Self::Item>],
         accuracy: &str,
     ) -> Result<CrtBundle<Self::Item>, Self::Error> {
-        if xs.len() < 2 {
-            return Err(Self::Error::from(FancyError::InvalidArgNum {
-                got: xs.len(),
-                needed: 2,
-            }));
-        }
-        xs.iter().skip(1).fold(Ok(xs[0].clone()), |x, y| {
-            x.map(|x| {
-                let pos = self.crt_lt(&x, y, accuracy)?;
-                let neg = self.negate(&pos)?;
-                x.wires()
-                    .iter()
-                    .zip(y.wires().iter())
-                    .map(|(x, y)| {
-                        let xp = self.mul(x, &neg)?;
-                        let yp = self.mul(y, &pos)?;
-                        self.add(&xp, &yp)
+        self.scoped("crt_max", |f| {
+            if xs.len() < 2 {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: xs.len(),
+                    needed: 2,
+                }));
+            }
+            xs.iter().skip(1).fold(Ok(xs[0].clone()), |x, y| {
+                x.map(|x| {
+                    let pos = f.crt_lt(&x, y, accuracy)?;
+                    let neg = f.negate(&pos)?;
+                    x.wires()
+                        .iter()
+                        .zip(y.wires().iter())
+                        .map(|(x, y)| {
+                            let xp = f.mul(x, &neg)?;
+                            let yp = f.mul(y, &pos)?;
+                            f.add(&xp, &yp)
+                        })
+                        .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                        .map(CrtBundle::new)
+                })?
+            })
+        })
+    }
+
+    /// Sort `xs` in ascending order using Batcher's odd-even merge sort.
+    ///
+    /// Comparisons use `crt_lt` with accuracy `accuracy`, so the differences between
+    /// the values must not overflow.
+    fn crt_sort(
+        &mut self,
+        xs: &[CrtBundle<Self::Item>],
+        accuracy: &str,
+    ) -> Result<Vec<CrtBundle<Self::Item>>, Self::Error> {
+        self.scoped("crt_sort", |f| {
+            let empty = vec![Bundle::new(Vec::new()); xs.len()];
+            f.crt_sort_with_payloads(xs, &empty, accuracy)
+                .map(|(xs, _)| xs)
+        })
+    }
+
+    /// Sort `xs` in ascending order using Batcher's odd-even merge sort, moving each
+    /// bundle in `payloads` along with the key at the same index.
+    fn crt_sort_with_payloads(
+        &mut self,
+        xs: &[CrtBundle<Self::Item>],
+        payloads: &[Bundle<Self::Item>],
+        accuracy: &str,
+    ) -> Result<(Vec<CrtBundle<Self::Item>>, Vec<Bundle<Self::Item>>), Self::Error> {
+        self.scoped("crt_sort_with_payloads", |f| {
+            if payloads.len() != xs.len() {
+                return Err(Self::Error::from(FancyError::InvalidArgNum {
+                    got: payloads.len(),
+                    needed: xs.len(),
+                }));
+            }
+            let mut xs = xs.to_vec();
+            let mut ps = payloads.to_vec();
+            for (i, j) in util::sorting_network(xs.len()) {
+                let swap = f.crt_lt(&xs[j], &xs[i], accuracy)?;
+                let (lo, hi) = f.cswap(&swap, &xs[i], &xs[j])?;
+                xs[i] = CrtBundle(lo);
+                xs[j] = CrtBundle(hi);
+                let (lo, hi) = f.cswap(&swap, &ps[i], &ps[j])?;
+                ps[i] = lo;
+                ps[j] = hi;
+            }
+            Ok((xs, ps))
+        })
+    }
+
+    /// Convert a CRT bundle into a binary bundle of the low `nbits` bits of its value.
+    ///
+    /// Uses Garner's algorithm to compute the mixed radix digits `v_i` of `x` with
+    /// respect to its moduli `p_1, ..., p_k`, projects each `v_i` times its place value
+    /// `p_1 * ... * p_{i-1}` into bits, and sums the results using
+    /// `mixed_radix_addition`. Computing `v_i` costs `p_j - 1` ciphertexts for each
+    /// `j < i`, and each bit of its place value costs `p_i - 1` ciphertexts.
+    fn crt_to_bin(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        nbits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.scoped("crt_to_bin", |f| {
+            let ps = x.moduli();
+            // bits above the width of the composite modulus are always zero
+            let Q = x.composite_modulus();
+            let width = std::cmp::min(nbits, (128 - (Q - 1).leading_zeros()) as usize);
+
+            // mixed radix digits: v_i = (x_i - sum_{j<i} v_j P_j) / P_i mod p_i, where P_j
+            // is the place value of the jth digit
+            let mut digits: Vec<Self::Item> = Vec::with_capacity(ps.len());
+            for (i, xi) in x.wires().iter().enumerate() {
+                let p = ps[i] as u128;
+                let place = ps[..i].iter().fold(1, |acc, &q| acc * q as u128 % p);
+                let inv = util::inv(place as i128, p as i128) as u128;
+                let mut terms = vec![f.cmul(xi, inv as u16)?];
+                let mut place_j = 1;
+                for (j, v) in digits.iter().enumerate() {
+                    let tt = (0..ps[j] as u128)
+                        .map(|v| ((p - v * place_j % p) * inv % p) as u16)
+                        .collect_vec();
+                    terms.push(f.proj(v, ps[i], Some(tt))?);
+                    place_j = place_j * ps[j] as u128 % p;
+                }
+                let digit = if terms.len() == 1 {
+                    terms.pop().unwrap()
+                } else {
+                    f.add_many(&terms)?
+                };
+                digits.push(digit);
+            }
+
+            // project each digit times its place value into bits
+            let zero = f.constant(0, 2)?;
+            let mut place = 1u128;
+            let mut terms = Vec::with_capacity(ps.len());
+            for (v, &p) in digits.iter().zip(ps.iter()) {
+                let bits = (0..width)
+                    .map(|k| {
+                        let tt = (0..p as u128)
+                            .map(|v| ((v * place) >> k & 1) as u16)
+                            .collect_vec();
+                        if tt.iter().all(|&b| b == 0) {
+                            Ok(zero.clone())
+                        } else if p == 2 && tt == [0, 1] {
+                            Ok(v.clone())
+                        } else {
+                            f.proj(v, 2, Some(tt))
+                        }
                     })
-                    .collect::<Result<Vec<Self::Item>, Self::Error>>()
-                    .map(CrtBundle::new)
-            })?
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+                terms.push(Bundle::new(bits));
+                place *= p as u128;
+            }
+
+            let mut bits = if width == 0 {
+                Vec::new()
+            } else if terms.len() == 1 {
+                terms.pop().unwrap().wires().to_vec()
+            } else {
+                f.mixed_radix_addition(&terms)?.wires().to_vec()
+            };
+            bits.resize(nbits, zero);
+            Ok(BinaryBundle::new(bits))
         })
     }
 }
@@ -0,0 +1,230 @@
+//! Module containing `FixedPointGadgets`, which are gadgets for fixed-point arithmetic
+//! over CRT bundles.
+
+use super::{Fancy, HasModulus};
+use crate::error::FancyError;
+use crate::fancy::binary::{BinaryBundle, BinaryGadgets};
+use crate::fancy::crt::{CrtBundle, CrtGadgets};
+use crate::util;
+use std::ops::Deref;
+
+/// CRT bundle holding a signed fixed-point value with `precision` fractional bits.
+///
+/// The value `x` is encoded as the integer `X = round(x * 2^precision)` modulo the
+/// composite modulus `Q` of the bundle, with negative encodings represented as
+/// `Q - |X|`, as in `crt_sign`. The representable encodings are thus those with
+/// `-ceil(Q/2) <= X <= floor(Q/2) - 1`, and results outside this range wrap around.
+#[derive(Clone)]
+pub struct FixedPointBundle<W: Clone + HasModulus> {
+    bundle: CrtBundle<W>,
+    precision: usize,
+}
+
+impl<W: Clone + HasModulus> FixedPointBundle<W> {
+    /// Create a new fixed-point bundle from a vector of wires.
+    pub fn new(ws: Vec<W>, precision: usize) -> FixedPointBundle<W> {
+        FixedPointBundle::from_crt(CrtBundle::new(ws), precision)
+    }
+
+    /// Interpret a CRT bundle as a fixed-point value with `precision` fractional bits.
+    pub fn from_crt(bundle: CrtBundle<W>, precision: usize) -> FixedPointBundle<W> {
+        FixedPointBundle { bundle, precision }
+    }
+
+    /// Extract the underlying CRT bundle from this fixed-point bundle.
+    pub fn extract(self) -> CrtBundle<W> {
+        self.bundle
+    }
+
+    /// Return the number of fractional bits of this fixed-point bundle.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+}
+
+impl<W: Clone + HasModulus> Deref for FixedPointBundle<W> {
+    type Target = CrtBundle<W>;
+
+    fn deref(&self) -> &CrtBundle<W> {
+        &self.bundle
+    }
+}
+
+impl<F: Fancy> FixedPointGadgets for F {}
+
+/// Extension trait for `Fancy` providing fixed-point gadgets based on CRT bundles.
+pub trait FixedPointGadgets: Fancy + CrtGadgets + BinaryGadgets {
+    /// Creates a bundle of constant wires for the fixed-point encoding of `x` with
+    /// `precision` fractional bits under composite modulus `q`.
+    fn fp_constant_bundle(
+        &mut self,
+        x: f64,
+        q: u128,
+        precision: usize,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let x = util::to_fixed_point(x, q, precision);
+        self.crt_constant_bundle(x, q)
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+    }
+
+    /// Add two fixed-point bundles. Exact as long as the sum of their encodings is
+    /// representable.
+    fn fp_add(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        y: &FixedPointBundle<Self::Item>,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let precision = check_precision(x, y)?;
+        self.crt_add(x, y)
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+    }
+
+    /// Subtract two fixed-point bundles. Exact as long as the difference of their
+    /// encodings is representable.
+    fn fp_sub(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        y: &FixedPointBundle<Self::Item>,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let precision = check_precision(x, y)?;
+        self.crt_sub(x, y)
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+    }
+
+    /// Multiply `x` by the constant `c`, rounding the result down to the precision of
+    /// `x`.
+    ///
+    /// With `X` and `C` the encodings of `x` and `c`, the result is the encoding
+    /// `floor(X * C / 2^precision)`, under the same bounds on `X * C` as `fp_mul`.
+    fn fp_cmul(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        c: f64,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let c = util::to_fixed_point(c, x.composite_modulus(), x.precision());
+        let z = self.crt_cmul(x, c)?;
+        self.fp_div_pow2(&FixedPointBundle::from_crt(z, x.precision()), x.precision())
+    }
+
+    /// Multiply two fixed-point bundles, rounding the result down to their precision.
+    ///
+    /// With `X` and `Y` the encodings of `x` and `y`, the result is the encoding
+    /// `floor(X * Y / 2^precision)`. This is exact as long as `X * Y` is within the
+    /// bounds of `fp_div_pow2` with `k = precision`, that is
+    /// `-floor(Q / 2^(precision + 1)) * 2^precision <= X * Y <= floor(Q/2) - 1`.
+    fn fp_mul(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        y: &FixedPointBundle<Self::Item>,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let precision = check_precision(x, y)?;
+        let z = self.crt_mul(x, y)?;
+        self.fp_div_pow2(&FixedPointBundle::from_crt(z, precision), precision)
+    }
+
+    /// Divide `x` by the public power `2^k`, rounding down.
+    ///
+    /// Shifts `x` by a multiple of `2^k` close to `Q/2` so that it is nonnegative,
+    /// extracts the low `k + 1` bits of the shifted value using `crt_to_bin`, subtracts
+    /// the low `k` bits using `bin_to_crt`, and multiplies by the inverse of `2^k`
+    /// modulo each odd prime. Bit `k` is the residue of the quotient mod 2.
+    ///
+    /// With `X` the encoding of `x`, the result is the encoding `floor(X / 2^k)`,
+    /// keeping the precision of `x`. This is exact as long as the shifted value does
+    /// not wrap around, that is `X >= -floor(Q / 2^(k + 1)) * 2^k`, which holds for
+    /// all nonnegative `X` and in particular whenever `X >= 2^k - Q/2`.
+    fn fp_div_pow2(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        k: usize,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        if k == 0 {
+            return Ok(x.clone());
+        }
+        let q = x.composite_modulus();
+        let shift = if k < 127 { q >> (k + 1) } else { 0 };
+        let offset = self.crt_constant_bundle(shift << k, q)?;
+        let xp = self.crt_add(x, &offset)?;
+
+        let bits = self.crt_to_bin(&xp, k + 1)?;
+        let low = BinaryBundle::new(bits.wires()[..k].to_vec());
+        let r = self.bin_to_crt(&low, q)?;
+
+        let ws = xp
+            .wires()
+            .iter()
+            .zip(r.wires().iter())
+            .map(|(xi, ri)| {
+                let p = xi.modulus();
+                if p == 2 {
+                    Ok(bits.wires()[k].clone())
+                } else {
+                    let pow = (0..k).fold(1, |acc, _| acc * 2 % p as i128);
+                    let inv = util::inv(pow, p as i128) as u16;
+                    let z = self.sub(xi, ri)?;
+                    self.cmul(&z, inv)
+                }
+            })
+            .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+
+        let shift = self.crt_constant_bundle(shift, q)?;
+        self.crt_sub(&CrtBundle::new(ws), &shift)
+            .map(|b| FixedPointBundle::from_crt(b, x.precision()))
+    }
+
+    /// Return 0 if `x` is positive and 1 if `x` is negative.
+    fn fp_sign(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        accuracy: &str,
+    ) -> Result<Self::Item, Self::Error> {
+        self.crt_sign(x, accuracy)
+    }
+
+    /// Returns 1 if `x < y`.
+    fn fp_lt(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        y: &FixedPointBundle<Self::Item>,
+        accuracy: &str,
+    ) -> Result<Self::Item, Self::Error> {
+        check_precision(x, y)?;
+        self.crt_lt(x, y, accuracy)
+    }
+
+    /// Returns 1 if `x >= y`.
+    fn fp_geq(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        y: &FixedPointBundle<Self::Item>,
+        accuracy: &str,
+    ) -> Result<Self::Item, Self::Error> {
+        check_precision(x, y)?;
+        self.crt_geq(x, y, accuracy)
+    }
+
+    /// Compute the ReLU of `x`, keeping its precision.
+    fn fp_relu(
+        &mut self,
+        x: &FixedPointBundle<Self::Item>,
+        accuracy: &str,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        self.crt_relu(x, accuracy, None)
+            .map(|b| FixedPointBundle::from_crt(b, x.precision()))
+    }
+}
+
+/// Return the common precision of `x` and `y`, or an error if they differ.
+fn check_precision<W: Clone + HasModulus>(
+    x: &FixedPointBundle<W>,
+    y: &FixedPointBundle<W>,
+) -> Result<usize, FancyError> {
+    if x.precision() != y.precision() {
+        return Err(FancyError::InvalidArg(format!(
+            "unequal fixed-point precisions {} and {}",
+            x.precision(),
+            y.precision()
+        )));
+    }
+    Ok(x.precision())
+}
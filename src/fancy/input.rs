@@ -149,6 +149,60 @@ pub trait FancyInput: Fancy {
         Ok(buns)
     }
 
+    /// Encode a fixed-point input bundle with `precision` fractional bits.
+    fn fp_encode(
+        &mut self,
+        value: f64,
+        modulus: u128,
+        precision: usize,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        let x = util::to_fixed_point(value, modulus, precision);
+        self.crt_encode(x, modulus)
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+    }
+
+    /// Receive a fixed-point input bundle with `precision` fractional bits.
+    fn fp_receive(
+        &mut self,
+        modulus: u128,
+        precision: usize,
+    ) -> Result<FixedPointBundle<Self::Item>, Self::Error> {
+        self.crt_receive(modulus)
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+    }
+
+    /// Encode many fixed-point input bundles with `precision` fractional bits.
+    fn fp_encode_many(
+        &mut self,
+        values: &[f64],
+        modulus: u128,
+        precision: usize,
+    ) -> Result<Vec<FixedPointBundle<Self::Item>>, Self::Error> {
+        let xs = values
+            .iter()
+            .map(|x| util::to_fixed_point(*x, modulus, precision))
+            .collect_vec();
+        let buns = self.crt_encode_many(&xs, modulus)?;
+        Ok(buns
+            .into_iter()
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+            .collect())
+    }
+
+    /// Receive many fixed-point input bundles with `precision` fractional bits.
+    fn fp_receive_many(
+        &mut self,
+        n: usize,
+        modulus: u128,
+        precision: usize,
+    ) -> Result<Vec<FixedPointBundle<Self::Item>>, Self::Error> {
+        let buns = self.crt_receive_many(n, modulus)?;
+        Ok(buns
+            .into_iter()
+            .map(|b| FixedPointBundle::from_crt(b, precision))
+            .collect())
+    }
+
     /// Encode a binary input bundle.
     fn bin_encode(
         &mut self,
@@ -0,0 +1,308 @@
+//! Module containing `NeuralGadgets`, which are neural network layers over tensors of
+//! CRT bundles.
+
+use super::{Fancy, HasModulus};
+use crate::error::FancyError;
+use crate::fancy::crt::{CrtBundle, CrtGadgets};
+
+/// Row-major tensor with an arbitrary shape.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor<T> {
+    shape: Vec<usize>,
+    data: Vec<T>,
+}
+
+/// Tensor of CRT bundles.
+pub type CrtTensor<W> = Tensor<CrtBundle<W>>;
+
+impl<T> Tensor<T> {
+    /// Create a new tensor with shape `shape` from its elements in row-major order.
+    ///
+    /// Panics if the number of elements does not match the shape.
+    pub fn new(data: Vec<T>, shape: &[usize]) -> Tensor<T> {
+        assert_eq!(
+            data.len(),
+            shape.iter().product::<usize>(),
+            "tensor data does not match shape {:?}",
+            shape
+        );
+        Tensor {
+            shape: shape.to_vec(),
+            data,
+        }
+    }
+
+    /// Return the shape of the tensor.
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Return the elements of the tensor in row-major order.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Extract the elements of the tensor in row-major order.
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+
+    /// Return the element at `index`. Panics if `index` is out of bounds.
+    pub fn get(&self, index: &[usize]) -> &T {
+        assert_eq!(index.len(), self.shape.len(), "wrong number of indices");
+        let offset = index
+            .iter()
+            .zip(self.shape.iter())
+            .fold(0, |acc, (&i, &n)| {
+                assert!(i < n, "index {:?} out of bounds", index);
+                acc * n + i
+            });
+        &self.data[offset]
+    }
+
+    /// Reinterpret the tensor with a new shape with the same number of elements.
+    pub fn reshape(self, shape: &[usize]) -> Tensor<T> {
+        Tensor::new(self.data, shape)
+    }
+}
+
+impl<F: Fancy> NeuralGadgets for F {}
+
+/// Extension trait for `Fancy` providing neural network layers over `CrtTensor`s.
+///
+/// Values are signed integers mod the composite modulus, as in `crt_sign`, so the
+/// weights and activations must be scaled so that no layer overflows it.
+pub trait NeuralGadgets: Fancy + CrtGadgets {
+    /// Dense layer with public weights of shape `[m, n]` and biases of shape `[m]`,
+    /// applied to `x` of shape `[n]`.
+    fn nn_dense(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        weights: &Tensor<u128>,
+        biases: &Tensor<u128>,
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let (m, n) = dense_shape(x.shape(), weights.shape(), biases.shape())?;
+        let q = composite_modulus(x)?;
+        let mut out = Vec::with_capacity(m);
+        for i in 0..m {
+            let mut z = self.crt_constant_bundle(*biases.get(&[i]), q)?;
+            for j in 0..n {
+                let y = self.crt_cmul(x.get(&[j]), *weights.get(&[i, j]))?;
+                z = self.crt_add(&z, &y)?;
+            }
+            out.push(z);
+        }
+        Ok(Tensor::new(out, &[m]))
+    }
+
+    /// Dense layer with secret weights of shape `[m, n]` and biases of shape `[m]`,
+    /// applied to `x` of shape `[n]`.
+    fn nn_dense_secret(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        weights: &CrtTensor<Self::Item>,
+        biases: &CrtTensor<Self::Item>,
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let (m, n) = dense_shape(x.shape(), weights.shape(), biases.shape())?;
+        let mut out = Vec::with_capacity(m);
+        for i in 0..m {
+            let mut z = biases.get(&[i]).clone();
+            for j in 0..n {
+                let y = self.crt_mul(x.get(&[j]), weights.get(&[i, j]))?;
+                z = self.crt_add(&z, &y)?;
+            }
+            out.push(z);
+        }
+        Ok(Tensor::new(out, &[m]))
+    }
+
+    /// 2D convolution of `x` of shape `[c, h, w]` with public kernels of shape
+    /// `[k, c, kh, kw]` and biases of shape `[k]`, without padding.
+    ///
+    /// Returns a tensor of shape `[k, (h - kh) / sh + 1, (w - kw) / sw + 1]`, where
+    /// `stride = (sh, sw)`.
+    fn nn_conv2d(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        kernels: &Tensor<u128>,
+        biases: &Tensor<u128>,
+        stride: (usize, usize),
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let ks = kernels.shape();
+        if ks.len() != 4 || biases.shape() != [ks[0]] {
+            return Err(Self::Error::from(FancyError::InvalidArg(format!(
+                "invalid kernel shape {:?} or bias shape {:?}",
+                ks,
+                biases.shape()
+            ))));
+        }
+        let (nk, kh, kw) = (ks[0], ks[2], ks[3]);
+        let (c, oh, ow) = window_shape(x.shape(), ks[1], (kh, kw), stride)?;
+        let q = composite_modulus(x)?;
+        let mut out = Vec::with_capacity(nk * oh * ow);
+        for k in 0..nk {
+            for i in 0..oh {
+                for j in 0..ow {
+                    let mut z = self.crt_constant_bundle(*biases.get(&[k]), q)?;
+                    for ch in 0..c {
+                        for di in 0..kh {
+                            for dj in 0..kw {
+                                let v = x.get(&[ch, i * stride.0 + di, j * stride.1 + dj]);
+                                let y = self.crt_cmul(v, *kernels.get(&[k, ch, di, dj]))?;
+                                z = self.crt_add(&z, &y)?;
+                            }
+                        }
+                    }
+                    out.push(z);
+                }
+            }
+        }
+        Ok(Tensor::new(out, &[nk, oh, ow]))
+    }
+
+    /// Average pooling of `x` of shape `[c, h, w]` over windows of shape `window`.
+    ///
+    /// CRT values cannot be divided by the window size, so this returns the sum of each
+    /// window, which is the average scaled by `window.0 * window.1`. The scale can be
+    /// folded into the weights of the next layer.
+    fn nn_avg_pool2d(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        window: (usize, usize),
+        stride: (usize, usize),
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let c = x.shape().first().cloned().unwrap_or(0);
+        let (c, oh, ow) = window_shape(x.shape(), c, window, stride)?;
+        let mut out = Vec::with_capacity(c * oh * ow);
+        for ch in 0..c {
+            for i in 0..oh {
+                for j in 0..ow {
+                    let mut z = x.get(&[ch, i * stride.0, j * stride.1]).clone();
+                    for (di, dj) in window_offsets(window).skip(1) {
+                        let v = x.get(&[ch, i * stride.0 + di, j * stride.1 + dj]);
+                        z = self.crt_add(&z, v)?;
+                    }
+                    out.push(z);
+                }
+            }
+        }
+        Ok(Tensor::new(out, &[c, oh, ow]))
+    }
+
+    /// Max pooling of `x` of shape `[c, h, w]` over windows of shape `window`, using
+    /// `crt_max` with accuracy `accuracy`.
+    fn nn_max_pool2d(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        window: (usize, usize),
+        stride: (usize, usize),
+        accuracy: &str,
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let c = x.shape().first().cloned().unwrap_or(0);
+        let (c, oh, ow) = window_shape(x.shape(), c, window, stride)?;
+        let mut out = Vec::with_capacity(c * oh * ow);
+        for ch in 0..c {
+            for i in 0..oh {
+                for j in 0..ow {
+                    let vs = window_offsets(window)
+                        .map(|(di, dj)| x.get(&[ch, i * stride.0 + di, j * stride.1 + dj]).clone())
+                        .collect::<Vec<_>>();
+                    let z = if vs.len() == 1 {
+                        vs[0].clone()
+                    } else {
+                        self.crt_max(&vs, accuracy)?
+                    };
+                    out.push(z);
+                }
+            }
+        }
+        Ok(Tensor::new(out, &[c, oh, ow]))
+    }
+
+    /// Apply `crt_relu` to each element of `x`.
+    fn nn_relu(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        accuracy: &str,
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let out = x
+            .data()
+            .iter()
+            .map(|v| self.crt_relu(v, accuracy, None))
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+        Ok(Tensor::new(out, x.shape()))
+    }
+
+    /// Apply `crt_sgn` to each element of `x`, mapping it to `1` or `-1`.
+    fn nn_sign(
+        &mut self,
+        x: &CrtTensor<Self::Item>,
+        accuracy: &str,
+    ) -> Result<CrtTensor<Self::Item>, Self::Error> {
+        let out = x
+            .data()
+            .iter()
+            .map(|v| self.crt_sgn(v, accuracy, None))
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+        Ok(Tensor::new(out, x.shape()))
+    }
+}
+
+/// Check the shapes of a dense layer, returning its number of outputs and inputs.
+fn dense_shape(
+    x: &[usize],
+    weights: &[usize],
+    biases: &[usize],
+) -> Result<(usize, usize), FancyError> {
+    match (x, weights, biases) {
+        ([n], [m, n_], [m_]) if n == n_ && m == m_ => Ok((*m, *n)),
+        _ => Err(FancyError::InvalidArg(format!(
+            "invalid dense layer shapes: input {:?}, weights {:?}, biases {:?}",
+            x, weights, biases
+        ))),
+    }
+}
+
+/// Check that `x` has shape `[channels, h, w]` with room for at least one window,
+/// returning the number of channels and the output height and width.
+fn window_shape(
+    x: &[usize],
+    channels: usize,
+    window: (usize, usize),
+    stride: (usize, usize),
+) -> Result<(usize, usize, usize), FancyError> {
+    match x {
+        [c, h, w]
+            if *c == channels
+                && window.0 > 0
+                && window.1 > 0
+                && stride.0 > 0
+                && stride.1 > 0
+                && *h >= window.0
+                && *w >= window.1 =>
+        {
+            Ok((
+                *c,
+                (h - window.0) / stride.0 + 1,
+                (w - window.1) / stride.1 + 1,
+            ))
+        }
+        _ => Err(FancyError::InvalidArg(format!(
+            "invalid input shape {:?} for {} channels, window {:?} and stride {:?}",
+            x, channels, window, stride
+        ))),
+    }
+}
+
+/// The offsets of the elements of a window, in row-major order.
+fn window_offsets(window: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    (0..window.0).flat_map(move |i| (0..window.1).map(move |j| (i, j)))
+}
+
+/// The composite modulus shared by the elements of `x`.
+fn composite_modulus<W: Clone + HasModulus>(x: &CrtTensor<W>) -> Result<u128, FancyError> {
+    x.data()
+        .first()
+        .map(|b| b.composite_modulus())
+        .ok_or_else(|| FancyError::InvalidArg("empty tensor".to_string()))
+}
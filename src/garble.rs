@@ -2,23 +2,105 @@
 
 mod evaluator;
 mod garbler;
+mod privacy_free;
+mod stacked;
+mod three_halves;
 
+use crate::fancy::HasModulus;
 pub use crate::garble::evaluator::Evaluator;
+pub(crate) use crate::garble::evaluator::{decode_outputs, eval_mul, eval_proj};
 pub use crate::garble::garbler::Garbler;
+pub(crate) use crate::garble::garbler::{garble_mul, garble_output, garble_proj, PendingGate};
+pub use crate::garble::privacy_free::{PrivacyFreeEvaluator, PrivacyFreeGarbler};
+use crate::wire::Wire;
+
+/// How multiplication gates are garbled. The `Garbler` and the `Evaluator` of a
+/// computation must use the same scheme.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum MulScheme {
+    /// Half-gates for all moduli, with `q + qb - 2` ciphertexts per gate, and one more
+    /// if the moduli differ. A binary AND gate costs two ciphertexts.
+    #[default]
+    HalfGates,
+    /// The "three halves" scheme of Rosulek and Roy for binary AND gates, which costs
+    /// 1.5 ciphertexts and four bytes of control bits per gate. Multiplications with
+    /// other moduli still use half-gates.
+    ThreeHalves,
+}
+
+/// How projection gates are garbled. The `Garbler` and the `Evaluator` of a
+/// computation must use the same scheme.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProjScheme {
+    /// One ciphertext for every input value but the one whose wirelabel has color
+    /// zero, so `q_in - 1` ciphertexts per gate.
+    #[default]
+    Standard,
+    /// Free affine projections: like `Standard`, except that projections which are
+    /// a scalar multiplication followed by adding a constant need no ciphertexts.
+    /// These are projections with constant truth tables, and projections between
+    /// equal moduli with affine truth tables, which includes every projection from
+    /// mod 2 to mod 2. The evaluator must know the truth tables.
+    ///
+    /// Despite the name, no rows of other truth tables are removed: they keep the
+    /// `q_in - 1` ciphertexts of `Standard`.
+    RowReduced,
+}
+
+/// The coefficients `(a, b)` such that `tt[x] = a * x + b` for every input `x` of a
+/// projection from modulus `q_in` to `q_out` with truth table `tt`, if there are
+/// any which `affine_proj` can compute. `a` is zero unless the moduli are equal.
+pub(crate) fn affine_truth_table(q_in: u16, q_out: u16, tt: &[u16]) -> Option<(u16, u16)> {
+    let b = *tt.first()?;
+    let a = if q_in == q_out && tt.len() > 1 {
+        (tt[1] + q_out - b) % q_out
+    } else {
+        0
+    };
+    let (a_, b_, q) = (a as u32, b as u32, q_out as u32);
+    if tt.len() == q_in as usize
+        && tt
+            .iter()
+            .enumerate()
+            .all(|(x, &y)| y as u32 == (a_ * x as u32 + b_) % q)
+    {
+        Some((a, b))
+    } else {
+        None
+    }
+}
+
+/// The wirelabel mod `q_out` of `a` times the value of `x`, where `a` is zero unless
+/// `x` has modulus `q_out`. Adding `b` to the output of a projection with
+/// coefficients `(a, b)` is up to the garbler, which shifts the zero wire.
+pub(crate) fn affine_proj(x: &Wire, q_out: u16, a: u16) -> Wire {
+    if x.modulus() == q_out {
+        x.cmul(a)
+    } else {
+        Wire::zero(q_out)
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 // tests
 
 #[cfg(test)]
 mod nonstreaming {
-    use crate::circuit::{Circuit, CircuitBuilder};
+    use crate::circuit::{Circuit, CircuitBuilder, Gate};
     use crate::dummy::Dummy;
     use crate::dummy::DummyVal;
-    use crate::fancy::{Bundle, BundleGadgets, Fancy};
-    use crate::r#static::garble;
+    use crate::error::EvaluatorError;
+    use crate::fancy::{BinaryGadgets, Bundle, BundleGadgets, CrtGadgets, Fancy};
+    use crate::fancy::{FixedPointBundle, FixedPointGadgets};
+    use crate::garble::{Evaluator, MulScheme, ProjScheme};
+    use crate::r#static::{garble, garble_with_schemes, garble_with_seed, GarbledCircuit};
     use crate::util::{self, RngExt};
+    use crate::wire::Wire;
     use itertools::Itertools;
-    use rand::thread_rng;
+    use rand::{thread_rng, Rng};
+    use scuttlebutt::{Block, Channel};
 
     // helper
     fn garble_test_helper<F>(f: F)
@@ -292,19 +374,346 @@ mod nonstreaming {
         }
     }
 
+    #[test] // binary to CRT and back
+    fn bin_crt_conversion() {
+        let mut rng = thread_rng();
+        let nbits = 16;
+        let q = util::modulus_with_width(24);
+
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_evaluator_input(nbits);
+        let y = b.crt_evaluator_input(q);
+        let z = b.bin_to_crt(&x, q).unwrap();
+        let z = b.crt_add(&z, &y).unwrap();
+        b.output_bundle(&z).unwrap();
+        let w = b.crt_to_bin(&y, nbits).unwrap();
+        b.output_bundle(&w).unwrap();
+        let mut circ = b.finish();
+        let (en, gc) = garble(&mut circ).unwrap();
+
+        for _ in 0..16 {
+            let x = rng.gen_u128() % (1 << nbits);
+            let y = rng.gen_u128() % q;
+            let mut inps = util::u128_to_bits(x, nbits);
+            inps.extend(util::crt_factor(y, q));
+            let outs = gc
+                .eval(&mut circ, &[], &en.encode_evaluator_inputs(&inps))
+                .unwrap();
+            let nprimes = util::factor(q).len();
+            assert_eq!(util::crt_inv_factor(&outs[..nprimes], q), (x + y) % q);
+            assert_eq!(util::u128_from_bits(&outs[nprimes..]), y % (1 << nbits));
+        }
+    }
+
+    #[test] // fixed-point arithmetic, up to the edges of the range where it is exact
+    fn fixed_point_roundtrip() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let (p, k) = (4, 6);
+        let c = -1.5;
+
+        let mut b = CircuitBuilder::new();
+        let x = FixedPointBundle::from_crt(b.crt_evaluator_input(q), p);
+        let y = FixedPointBundle::from_crt(b.crt_evaluator_input(q), p);
+        let zs = [
+            b.fp_add(&x, &y).unwrap(),
+            b.fp_sub(&x, &y).unwrap(),
+            b.fp_mul(&x, &y).unwrap(),
+            b.fp_cmul(&x, c).unwrap(),
+            b.fp_div_pow2(&x, k).unwrap(),
+        ];
+        for z in zs.iter() {
+            b.output_bundle(z).unwrap();
+        }
+        let mut circ = b.finish();
+        let (en, gc) = garble(&mut circ).unwrap();
+
+        // the representable encodings, and the smallest encodings that fp_div_pow2
+        // divides exactly by 2^p and by 2^k
+        let (min, max) = (-((q as i128 + 1) / 2), (q / 2) as i128 - 1);
+        let (min_p, min_k) = (
+            -(((q >> (p + 1)) << p) as i128),
+            -(((q >> (k + 1)) << k) as i128),
+        );
+        let mut cases = vec![
+            (min_k, 1),
+            (max, 1),
+            (min_p, 1),
+            (min_p >> p, 1 << p),
+            (min, 0),
+        ];
+        for _ in 0..16 {
+            let bound = (q as f64 / 4.0).sqrt() as u128;
+            let x = (rng.gen_u128() % (2 * bound)) as i128 - bound as i128;
+            let y = (rng.gen_u128() % (2 * bound)) as i128 - bound as i128;
+            cases.push((x, y));
+        }
+
+        let scale = (1 << p) as f64;
+        let C = (c * scale) as i128;
+        let floor = |v: i128, k: usize| v.div_euclid(1 << k);
+        for &(x, y) in cases.iter() {
+            let inps = [x, y]
+                .iter()
+                .flat_map(|&v| util::crt_factor(util::to_fixed_point(v as f64 / scale, q, p), q))
+                .collect_vec();
+            let outs = gc
+                .eval(&mut circ, &[], &en.encode_evaluator_inputs(&inps))
+                .unwrap()
+                .chunks(util::factor(q).len())
+                .map(|zs| util::from_fixed_point(util::crt_inv_factor(zs, q), q, p))
+                .collect_vec();
+            let results = [
+                (x + y, x + y, min),
+                (x - y, x - y, min),
+                (x * y, floor(x * y, p), min_p),
+                (x * C, floor(x * C, p), min_p),
+                (x, floor(x, k), min_k),
+            ];
+            for (i, &(v, z, lowest)) in results.iter().enumerate() {
+                if lowest <= v && v <= max {
+                    assert_eq!(outs[i], z as f64 / scale, "output {} of {:?}", i, (x, y));
+                }
+            }
+        }
+
+        // just below the bound, the shift of fp_div_pow2 wraps around
+        let inps = util::crt_factor(util::to_fixed_point((min_k - 1) as f64 / scale, q, p), q);
+        let inps = [inps, util::crt_factor(0, q)].concat();
+        let outs = gc
+            .eval(&mut circ, &[], &en.encode_evaluator_inputs(&inps))
+            .unwrap();
+        let z = util::crt_inv_factor(&outs[4 * util::factor(q).len()..], q);
+        assert_ne!(
+            util::from_fixed_point(z, q, p),
+            floor(min_k - 1, k) as f64 / scale
+        );
+    }
+
+    #[test] // sorting binary keys with CRT payloads
+    fn bin_sort_with_payloads() {
+        let mut rng = thread_rng();
+        let n = 5;
+        let nbits = 6;
+        let q = util::modulus_with_width(4);
+        let nprimes = util::factor(q).len();
+
+        let mut b = CircuitBuilder::new();
+        let keys = (0..n).map(|_| b.bin_evaluator_input(nbits)).collect_vec();
+        let ids = (0..n)
+            .map(|_| b.crt_evaluator_input(q).extract())
+            .collect_vec();
+        let (keys, ids) = b.bin_sort_with_payloads(&keys, &ids).unwrap();
+        for key in keys.iter() {
+            b.output_bundle(key).unwrap();
+        }
+        b.output_bundles(&ids).unwrap();
+        let mut circ = b.finish();
+        let (en, gc) = garble(&mut circ).unwrap();
+
+        for _ in 0..16 {
+            let xs = (0..n).map(|_| rng.gen_u128() % (1 << nbits)).collect_vec();
+            let mut inps = xs
+                .iter()
+                .flat_map(|&x| util::u128_to_bits(x, nbits))
+                .collect_vec();
+            inps.extend((0..n).flat_map(|i| util::crt_factor(i as u128, q)));
+            let outs = gc
+                .eval(&mut circ, &[], &en.encode_evaluator_inputs(&inps))
+                .unwrap();
+            let (keys, ids) = outs.split_at(n * nbits);
+            let keys = keys.chunks(nbits).map(util::u128_from_bits).collect_vec();
+            let ids = ids
+                .chunks(nprimes)
+                .map(|x| util::crt_inv_factor(x, q) as usize)
+                .collect_vec();
+            let mut sorted = xs.clone();
+            sorted.sort();
+            assert_eq!(keys, sorted);
+            assert_eq!(ids.iter().map(|&i| xs[i]).collect_vec(), sorted);
+        }
+    }
+
+    #[test] // seeded garbling
+    fn seeded() {
+        let mut b = CircuitBuilder::new();
+        let mut rng = thread_rng();
+
+        let x = b.garbler_input(5);
+        let y = b.evaluator_input(3);
+        let c = b.constant(2, 5).unwrap();
+        let z = b.mul(&x, &y).unwrap();
+        let z = b.add(&z, &c).unwrap();
+        let z = b.mod_change(&z, 7).unwrap();
+        b.output(&z).unwrap();
+        let mut circ = b.finish();
+
+        let seed = rng.gen::<Block>();
+        let (en, gc) = garble_with_seed(&mut circ, seed).unwrap();
+
+        // regarbling from the seed reproduces the garbling exactly
+        let (en_, gc_) = garble_with_seed(&mut circ, seed).unwrap();
+        assert_eq!(en, en_);
+        assert_eq!(gc, gc_);
+
+        // but a different seed does not
+        let (en_, gc_) = garble_with_seed(&mut circ, rng.gen::<Block>()).unwrap();
+        assert_ne!(en, en_);
+        assert_ne!(gc, gc_);
+
+        for _ in 0..16 {
+            let x = rng.gen_u16() % 5;
+            let y = rng.gen_u16() % 3;
+            let X = en.encode_garbler_inputs(&[x]);
+            let Y = en.encode_evaluator_inputs(&[y]);
+            let Z = gc.eval(&mut circ, &X, &Y).unwrap();
+            assert_eq!(Z[0], (x * y + 2) % 5);
+        }
+    }
+
+    #[test] // three halves garbling
+    fn three_halves() {
+        let mut rng = thread_rng();
+
+        // a single AND gate, for every input
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(2);
+        let y = b.evaluator_input(2);
+        let z = b.mul(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+        for _ in 0..16 {
+            let (en, gc) =
+                garble_with_schemes(&mut c, MulScheme::ThreeHalves, ProjScheme::Standard).unwrap();
+            assert_eq!(gc.mul_scheme(), MulScheme::ThreeHalves);
+            // 28 bytes for the gate and 32 for the output, padded
+            assert_eq!(gc.size(), 4);
+            for (x, y) in (0..2).cartesian_product(0..2) {
+                let X = en.encode_garbler_inputs(&[x]);
+                let Y = en.encode_evaluator_inputs(&[y]);
+                assert_eq!(gc.eval(&mut c, &X, &Y).unwrap(), vec![x & y]);
+            }
+        }
+
+        // binary gadgets, with mod 3 gates in between so that the three halves gates
+        // are packed with the blocks of other gates
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_garbler_input(16);
+        let y = b.bin_evaluator_input(16);
+        let z = b.bin_multiplication_lower_half(&x, &y).unwrap();
+        let z = b.bin_addition_no_carry(&z, &x).unwrap();
+        let t = b.evaluator_input(3);
+        let u = b.mul(&t, &t).unwrap();
+        let u = b.proj(&u, 2, Some(vec![0, 1, 0])).unwrap();
+        let w = b.and(&z.wires()[0], &u).unwrap();
+        b.output_bundle(&z).unwrap();
+        b.output(&w).unwrap();
+        let mut c = b.finish();
+
+        let (_, gc) = garble(&mut c).unwrap();
+        let (en, gc3) =
+            garble_with_schemes(&mut c, MulScheme::ThreeHalves, ProjScheme::Standard).unwrap();
+        // each AND gate takes 28 bytes instead of two blocks
+        let nands = (0..c.gates.len())
+            .filter(|&i| matches!(c.gates[i], Gate::Mul { .. }) && c.gate_moduli[i] == 2)
+            .count();
+        let nbytes = 16 * (gc.size() - 2 * nands) + 28 * nands;
+        assert_eq!(gc3.size(), nbytes.div_ceil(16));
+        let gc3_ = GarbledCircuit::from_bytes(&gc3.to_bytes()).unwrap();
+        assert_eq!(gc3, gc3_);
+
+        for _ in 0..16 {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect_vec();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect_vec();
+            let X = en.encode_garbler_inputs(&gb);
+            let Y = en.encode_evaluator_inputs(&ev);
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            assert_eq!(gc3.eval(&mut c, &X, &Y).unwrap(), should_be);
+            assert_eq!(gc3_.eval(&mut c, &X, &Y).unwrap(), should_be);
+        }
+
+        // parallel evaluation only supports half-gates
+        assert!(gc3.eval_parallel(&c, &[], &[]).is_err());
+    }
+
+    #[test] // free affine projections
+    fn row_reduced_proj() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(2);
+        let y = b.evaluator_input(3);
+        // free: every projection mod 2 to mod 2, affine and constant ones
+        for tt in &[[0, 0], [1, 1], [0, 1], [1, 0]] {
+            let z = b.proj(&x, 2, Some(tt.to_vec())).unwrap();
+            b.output(&z).unwrap();
+        }
+        let z = b.proj(&y, 3, Some(vec![2, 1, 0])).unwrap();
+        b.output(&z).unwrap();
+        let c5 = b.proj(&y, 5, Some(vec![4, 4, 4])).unwrap();
+        // not affine, so two ciphertexts each
+        let z = b.proj(&y, 3, Some(vec![0, 1, 1])).unwrap();
+        b.output(&z).unwrap();
+        // free, and not adding to the depth
+        let z = b.proj(&z, 3, Some(vec![1, 2, 0])).unwrap();
+        b.output(&z).unwrap();
+        let w5 = b.proj(&y, 5, Some(vec![1, 3, 0])).unwrap();
+        let z = b.mul(&c5, &w5).unwrap();
+        let z = b.add(&z, &c5).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+
+        let (_, gc) = garble(&mut c).unwrap();
+        let (en, gc_) =
+            garble_with_schemes(&mut c, MulScheme::HalfGates, ProjScheme::RowReduced).unwrap();
+        assert_eq!(gc_.size(), gc.size() - 10);
+        assert_eq!(gc_, GarbledCircuit::from_bytes(&gc_.to_bytes()).unwrap());
+        let report = c.report().unwrap();
+        let report_ = c.report_with_proj_scheme(ProjScheme::RowReduced).unwrap();
+        assert_eq!(report_.num_ciphertexts, report.num_ciphertexts - 10);
+        assert_eq!(report_.num_projs, report.num_projs);
+        assert_eq!(report.depth.proj, 2);
+        assert_eq!(report_.depth.proj, 1);
+
+        for _ in 0..16 {
+            let gb = [rng.gen_u16() % 2];
+            let ev = [rng.gen_u16() % 3];
+            let X = en.encode_garbler_inputs(&gb);
+            let Y = en.encode_evaluator_inputs(&ev);
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            assert_eq!(gc_.eval(&mut c, &X, &Y).unwrap(), should_be);
+        }
+
+        // the evaluator needs the truth tables
+        let channel = Channel::new(std::io::empty(), Vec::new());
+        let mut ev = Evaluator::new(channel).with_proj_scheme(ProjScheme::RowReduced);
+        match ev.proj(&Wire::zero(2), 2, None) {
+            Err(EvaluatorError::TruthTableRequired) => (),
+            r => panic!("expected TruthTableRequired, got {:?}", r),
+        }
+    }
 }
 
 #[cfg(test)]
 mod streaming {
+    use crate::circuit::{CircuitBuilder, CircuitRef};
     use crate::dummy::{Dummy, DummyVal};
+    use crate::error::CircuitBuilderError;
+    use crate::r#static::{
+        garble, garble_batch_with_seeds, garble_parallel_with_seed, garble_with_seed,
+    };
     use crate::util::RngExt;
+    use crate::{Branch, Fancy, FancyBranch, FancyInput};
     use crate::{Evaluator, Garbler, Wire};
-    use crate::{Fancy, FancyInput};
     use itertools::Itertools;
-    use rand::thread_rng;
-    use scuttlebutt::{AesRng, Channel};
+    use rand::{thread_rng, Rng};
+    use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
     use std::io::{BufReader, BufWriter};
     use std::os::unix::net::UnixStream;
+    use std::rc::Rc;
 
     type MyChannel = Channel<BufReader<UnixStream>, BufWriter<UnixStream>>;
 
@@ -359,6 +768,35 @@ mod streaming {
         assert_eq!(result, should_be)
     }
 
+    #[test]
+    fn from_seed() {
+        // garble the same computation twice from the same seed, collecting the
+        // ciphertexts
+        fn garble_from_seed(seed: Block, inputs: &[u16], mods: &[u16]) -> Vec<u8> {
+            let channel = Channel::new(std::io::empty(), Vec::new());
+            let mut gb = Garbler::<_, AesRng>::from_seed(channel.clone(), seed, &[]);
+            let (gb_inp, ev_inp) = gb.encode_many_wires(inputs, mods).unwrap();
+            for w in ev_inp.iter() {
+                gb.send_wire(w).unwrap();
+            }
+            let z = gb.mul(&gb_inp[0], &gb_inp[1]).unwrap();
+            let z = gb.proj(&z, 5, Some(vec![1, 2, 3, 4, 0, 1, 2])).unwrap();
+            gb.output(&z).unwrap();
+            drop(gb);
+            Rc::try_unwrap(channel.writer()).unwrap().into_inner()
+        }
+
+        let mut rng = thread_rng();
+        let seed = rng.gen::<Block>();
+        let inputs = [rng.gen_u16() % 7, rng.gen_u16() % 3];
+        let bytes = garble_from_seed(seed, &inputs, &[7, 3]);
+        assert_eq!(bytes, garble_from_seed(seed, &inputs, &[7, 3]));
+        assert_ne!(
+            bytes,
+            garble_from_seed(rng.gen::<Block>(), &inputs, &[7, 3])
+        );
+    }
+
     #[test]
     fn addition() {
         fn fancy_addition<F: Fancy>(b: &mut F, xs: &[F::Item]) {
@@ -455,6 +893,147 @@ mod streaming {
         }
     }
 
+    // branches of a conditional on two inputs mod 7, with different numbers of gates
+    fn add(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        Ok(vec![b.add(&xs[0], &xs[1])?])
+    }
+
+    fn product(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        let mut z = b.mul(&xs[0], &xs[1])?;
+        for _ in 0..32 {
+            z = b.mul(&z, &xs[1])?;
+        }
+        Ok(vec![z])
+    }
+
+    fn parity(
+        b: &mut CircuitBuilder,
+        xs: &[CircuitRef],
+    ) -> Result<Vec<CircuitRef>, CircuitBuilderError> {
+        let tt = (0..7).map(|x| x % 2).collect_vec();
+        let x = b.proj(&xs[0], 2, Some(tt.clone()))?;
+        let y = b.proj(&xs[1], 2, Some(tt))?;
+        let z = b.and(&x, &y)?;
+        b.mod_change(&z, 7).map(|z| vec![z])
+    }
+
+    fn fancy_branch<F: FancyBranch>(f: &mut F, xs: &[F::Item], branches: &[Branch]) {
+        let zs = f.branch(&xs[0], &xs[1..], branches).unwrap();
+        f.outputs(&zs).unwrap();
+    }
+
+    #[test]
+    fn branch() {
+        for _ in 0..16 {
+            streaming_test(
+                move |b, xs| fancy_branch(b, xs, &[&add, &product, &parity]),
+                move |b, xs| fancy_branch(b, xs, &[&add, &product, &parity]),
+                move |b, xs| fancy_branch(b, xs, &[&add, &product, &parity]),
+                &[3, 7, 7],
+            );
+            streaming_test(
+                move |b, xs| fancy_branch(b, xs, &[&parity, &add]),
+                move |b, xs| fancy_branch(b, xs, &[&parity, &add]),
+                move |b, xs| fancy_branch(b, xs, &[&parity, &add]),
+                &[2, 7, 7],
+            );
+        }
+    }
+
+    #[test]
+    fn branch_communication() {
+        // garble `f` on the inputs, returning the ciphertexts and how many of them `f`
+        // sent
+        fn garble<F>(inputs: &[u16], mods: &[u16], f: F) -> (Vec<u8>, usize)
+        where
+            F: FnOnce(&mut Garbler<Channel<std::io::Empty, Vec<u8>>, AesRng>, &[Wire]),
+        {
+            let channel = Channel::new(std::io::empty(), Vec::new());
+            let mut gb = Garbler::new(channel.clone(), AesRng::new(), &[]);
+            let (gb_inp, ev_inp) = gb.encode_many_wires(inputs, mods).unwrap();
+            for w in ev_inp.iter() {
+                gb.send_wire(w).unwrap();
+            }
+            let before = channel.clone().writer().borrow().len();
+            f(&mut gb, &gb_inp);
+            let after = channel.clone().writer().borrow().len();
+            drop(gb);
+            let bytes = Rc::try_unwrap(channel.writer()).unwrap().into_inner();
+            (bytes, after - before)
+        }
+
+        let mut rng = thread_rng();
+        let xs = [rng.gen_u16() % 7, rng.gen_u16() % 7];
+        let (_, branch_size) = garble(&xs, &[7, 7], |gb, xs| {
+            let mut b = CircuitBuilder::new();
+            let inps = b.garbler_inputs(&[7, 7]);
+            let zs = product(&mut b, &inps).unwrap();
+            b.outputs(&zs).unwrap();
+            b.finish().eval(gb, xs, &[]).unwrap();
+        });
+        for cond in 0..2 {
+            let inputs = [cond, xs[0], xs[1]];
+            let (bytes, size) = garble(&inputs, &[2, 7, 7], |gb, xs| {
+                fancy_branch(gb, xs, &[&product, &product])
+            });
+            // stacking two copies of the branch costs less than sending both
+            assert!(size > branch_size);
+            assert!(size < 2 * branch_size);
+
+            let mut ev = Evaluator::new(Channel::new(&bytes[..], std::io::sink()));
+            let ev_inp = [2, 7, 7]
+                .iter()
+                .map(|q| ev.read_wire(*q).unwrap())
+                .collect_vec();
+            fancy_branch(&mut ev, &ev_inp, &[&product, &product]);
+            let should_be = (0..33).fold(xs[0], |z, _| z * xs[1] % 7);
+            assert_eq!(ev.decode_output().unwrap(), vec![should_be]);
+        }
+    }
+
+    #[test]
+    fn static_branch() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let cond = b.garbler_input(3);
+        let xs = b.evaluator_inputs(&[7, 7]);
+        let zs = b
+            .branch(&cond, &xs, &[&product, &parity, &product])
+            .unwrap();
+        b.outputs(&zs).unwrap();
+        let mut c = b.finish();
+        assert_eq!(c.conditionals.len(), 1);
+
+        let seed = rng.gen::<Block>();
+        let (en, gc) = garble_with_seed(&mut c, seed).unwrap();
+        assert_eq!(gc.size(), c.garbled_size().unwrap());
+        // parallel and batched garbling fall back to stacking sequentially
+        assert_eq!(garble_parallel_with_seed(&c, seed).unwrap(), (en, gc));
+        let (en, gc) = garble_batch_with_seeds(&mut c, &[seed]).unwrap().remove(0);
+        for _ in 0..16 {
+            let cond = rng.gen_u16() % 3;
+            let x = rng.gen_u16() % 7;
+            let y = rng.gen_u16() % 7;
+            let should_be = c.eval_plain(&[cond], &[x, y]).unwrap();
+            let X = en.encode_garbler_inputs(&[cond]);
+            let Y = en.encode_evaluator_inputs(&[x, y]);
+            assert_eq!(gc.eval(&mut c, &X, &Y).unwrap(), should_be);
+            assert_eq!(gc.eval_parallel(&c, &X, &Y).unwrap(), should_be);
+        }
+
+        // the multiplexer left in the circuit pays for every branch
+        let mut mux = c.clone();
+        mux.conditionals.clear();
+        let (_, gc_mux) = garble(&mut mux).unwrap();
+        assert_eq!(gc_mux.size(), mux.garbled_size().unwrap());
+        assert!(gc.size() < gc_mux.size());
+    }
 }
 
 #[cfg(test)]
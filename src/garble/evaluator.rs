@@ -1,5 +1,8 @@
 use crate::error::{EvaluatorError, FancyError};
-use crate::fancy::{Fancy, HasModulus};
+use crate::fancy::{build_branches, Branch, Fancy, FancyBranch, HasModulus, OutputMode};
+use crate::garble::stacked;
+use crate::garble::three_halves::{self, THREE_HALVES_BYTES};
+use crate::garble::{affine_proj, affine_truth_table, MulScheme, ProjScheme};
 use crate::util::{output_tweak, tweak, tweak2};
 use crate::wire::Wire;
 use scuttlebutt::{AbstractChannel, Block};
@@ -13,6 +16,8 @@ pub struct Evaluator<C> {
     current_gate: usize,
     pub(crate) output_cts: Vec<Vec<Block>>,
     pub(crate) output_wires: Vec<Wire>,
+    scheme: MulScheme,
+    proj_scheme: ProjScheme,
 }
 
 impl<C: AbstractChannel> Evaluator<C> {
@@ -23,35 +28,27 @@ impl<C: AbstractChannel> Evaluator<C> {
             current_gate: 0,
             output_cts: Vec::new(),
             output_wires: Vec::new(),
+            scheme: MulScheme::default(),
+            proj_scheme: ProjScheme::default(),
         }
     }
 
-    /// Decode the output received during the Fancy computation.
+    /// Evaluate multiplication gates garbled with `scheme` instead of half-gates.
+    pub fn with_mul_scheme(mut self, scheme: MulScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Evaluate projection gates garbled with `scheme`.
+    pub fn with_proj_scheme(mut self, scheme: ProjScheme) -> Self {
+        self.proj_scheme = scheme;
+        self
+    }
+
+    /// Decode the outputs revealed to the evaluator during the Fancy computation, and
+    /// the evaluator's shares of shared outputs, in the order they were output.
     pub fn decode_output(&self) -> Result<Vec<u16>, EvaluatorError> {
-        debug_assert_eq!(
-            self.output_wires.len(),
-            self.output_cts.len(),
-            "got {} wires, but have {} output ciphertexts",
-            self.output_wires.len(),
-            self.output_cts.len()
-        );
-
-        let mut outs = Vec::with_capacity(self.output_wires.len());
-        for i in 0..self.output_wires.len() {
-            let q = self.output_wires[i].modulus();
-            debug_assert_eq!(q as usize, self.output_cts[i].len());
-            for k in 0..q {
-                let h = self.output_wires[i].hash(output_tweak(i, k));
-                if h == self.output_cts[i][k as usize] {
-                    outs.push(k);
-                    break;
-                }
-            }
-        }
-        if self.output_wires.len() != outs.len() {
-            return Err(EvaluatorError::DecodingFailed);
-        }
-        Ok(outs)
+        decode_outputs(&self.output_wires, &self.output_cts)
     }
 
     /// The current non-free gate index of the garbling computation.
@@ -62,6 +59,14 @@ impl<C: AbstractChannel> Evaluator<C> {
         current
     }
 
+    /// Send the wirelabel of an output to the garbler, which decodes it.
+    #[inline]
+    fn send_output(&mut self, x: &Wire) -> Result<(), EvaluatorError> {
+        self.channel.write_block(&x.as_block())?;
+        self.channel.flush()?;
+        Ok(())
+    }
+
     /// Read a Wire from the reader.
     #[inline]
     pub fn read_wire(&mut self, modulus: u16) -> Result<Wire, EvaluatorError> {
@@ -107,6 +112,12 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         }
         let q = A.modulus();
         let qb = B.modulus();
+        if q == 2 && qb == 2 && self.scheme == MulScheme::ThreeHalves {
+            let mut gate = [0; THREE_HALVES_BYTES];
+            self.channel.read_bytes(&mut gate)?;
+            let gate_num = self.current_gate();
+            return Ok(three_halves::eval_and(A, B, gate_num, &gate));
+        }
         let unequal = q != qb;
         let ngates = q as usize + qb as usize - 2 + unequal as usize;
         let mut gate = Vec::with_capacity(ngates);
@@ -117,53 +128,25 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
             }
         }
         let gate_num = self.current_gate();
-        let g = tweak2(gate_num as u64, 0);
-
-        // garbler's half gate
-        let L = if A.color() == 0 {
-            A.hashback(g, q)
-        } else {
-            let ct_left = gate[A.color() as usize - 1];
-            Wire::from_block(ct_left ^ A.hash(g), q)
-        };
-
-        // evaluator's half gate
-        let R = if B.color() == 0 {
-            B.hashback(g, q)
-        } else {
-            let ct_right = gate[(q + B.color()) as usize - 2];
-            Wire::from_block(ct_right ^ B.hash(g), q)
-        };
-
-        // hack for unequal mods
-        let new_b_color = if unequal {
-            let minitable = *gate.last().unwrap();
-            let ct = u128::from(minitable) >> (B.color() * 16);
-            let pt = u128::from(B.hash(tweak2(gate_num as u64, 1))) ^ ct;
-            pt as u16
-        } else {
-            B.color()
-        };
-
-        let res = L.plus_mov(&R.plus_mov(&A.cmul(new_b_color)));
-        Ok(res)
+        Ok(eval_mul(A, B, gate_num, &gate))
     }
 
     #[inline]
-    fn proj(&mut self, x: &Wire, q: u16, _: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
+    fn proj(&mut self, x: &Wire, q: u16, tt: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
+        if self.proj_scheme == ProjScheme::RowReduced {
+            let tt = tt.ok_or(EvaluatorError::TruthTableRequired)?;
+            if let Some((a, _)) = affine_truth_table(x.modulus(), q, &tt) {
+                return Ok(affine_proj(x, q, a));
+            }
+        }
         let ngates = (x.modulus() - 1) as usize;
         let mut gate = Vec::with_capacity(ngates);
         for _ in 0..ngates {
             let block = self.channel.read_block()?;
             gate.push(block);
         }
-        let t = tweak(self.current_gate());
-        if x.color() == 0 {
-            Ok(x.hashback(t, q))
-        } else {
-            let ct = gate[x.color() as usize - 1];
-            Ok(Wire::from_block(ct ^ x.hash(t), q))
-        }
+        let gate_num = self.current_gate();
+        Ok(eval_proj(x, q, gate_num, &gate))
     }
 
     #[inline]
@@ -178,4 +161,116 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         self.output_wires.push(x.clone());
         Ok(())
     }
+
+    #[inline]
+    fn output_with_mode(&mut self, x: &Wire, mode: OutputMode) -> Result<(), EvaluatorError> {
+        match mode {
+            OutputMode::Evaluator | OutputMode::Shared => self.output(x),
+            OutputMode::Garbler => self.send_output(x),
+            OutputMode::Both => {
+                self.output(x)?;
+                self.send_output(x)
+            }
+        }
+    }
+}
+
+impl<C: AbstractChannel> FancyBranch for Evaluator<C> {
+    /// Evaluate the conditional garbled with stacked garbling.
+    fn branch(
+        &mut self,
+        cond: &Wire,
+        inputs: &[Wire],
+        branches: &[Branch],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        let mut cs = build_branches(cond, inputs, branches)?;
+        let gate_num = self.current_gate();
+        stacked::eval_conditional(
+            &mut self.channel,
+            &mut cs,
+            cond,
+            inputs,
+            gate_num,
+            (self.scheme, self.proj_scheme),
+        )
+    }
+}
+
+/// Evaluate a multiplication gate, where `A` has the larger modulus, using the
+/// ciphertexts in `gate`.
+pub(crate) fn eval_mul(A: &Wire, B: &Wire, gate_num: usize, gate: &[Block]) -> Wire {
+    let q = A.modulus();
+    let qb = B.modulus();
+    let unequal = q != qb;
+    let g = tweak2(gate_num as u64, 0);
+
+    // garbler's half gate
+    let L = if A.color() == 0 {
+        A.hashback(g, q)
+    } else {
+        let ct_left = gate[A.color() as usize - 1];
+        Wire::from_block(ct_left ^ A.hash(g), q)
+    };
+
+    // evaluator's half gate
+    let R = if B.color() == 0 {
+        B.hashback(g, q)
+    } else {
+        let ct_right = gate[(q + B.color()) as usize - 2];
+        Wire::from_block(ct_right ^ B.hash(g), q)
+    };
+
+    // hack for unequal mods
+    let new_b_color = if unequal {
+        let minitable = *gate.last().unwrap();
+        let ct = u128::from(minitable) >> (B.color() * 16);
+        let pt = u128::from(B.hash(tweak2(gate_num as u64, 1))) ^ ct;
+        pt as u16
+    } else {
+        B.color()
+    };
+
+    L.plus_mov(&R.plus_mov(&A.cmul(new_b_color)))
+}
+
+/// Evaluate a projection gate with output modulus `q`, using the ciphertexts in `gate`.
+pub(crate) fn eval_proj(x: &Wire, q: u16, gate_num: usize, gate: &[Block]) -> Wire {
+    let t = tweak(gate_num);
+    if x.color() == 0 {
+        x.hashback(t, q)
+    } else {
+        let ct = gate[x.color() as usize - 1];
+        Wire::from_block(ct ^ x.hash(t), q)
+    }
+}
+
+/// Decode output wires using the output ciphertexts produced by the garbler.
+pub(crate) fn decode_outputs(
+    wires: &[Wire],
+    cts: &[Vec<Block>],
+) -> Result<Vec<u16>, EvaluatorError> {
+    debug_assert_eq!(
+        wires.len(),
+        cts.len(),
+        "got {} wires, but have {} output ciphertexts",
+        wires.len(),
+        cts.len()
+    );
+
+    let mut outs = Vec::with_capacity(wires.len());
+    for i in 0..wires.len() {
+        let q = wires[i].modulus();
+        debug_assert_eq!(q as usize, cts[i].len());
+        for k in 0..q {
+            let h = wires[i].hash(output_tweak(i, k));
+            if h == cts[i][k as usize] {
+                outs.push(k);
+                break;
+            }
+        }
+    }
+    if wires.len() != outs.len() {
+        return Err(EvaluatorError::DecodingFailed);
+    }
+    Ok(outs)
 }
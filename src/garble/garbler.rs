@@ -1,8 +1,12 @@
 use crate::error::{FancyError, GarblerError};
-use crate::fancy::{BinaryBundle, CrtBundle, Fancy, HasModulus};
-use crate::util::{output_tweak, tweak, tweak2, RngExt};
+use crate::fancy::{
+    build_branches, BinaryBundle, Branch, CrtBundle, Fancy, FancyBranch, HasModulus, OutputMode,
+};
+use crate::garble::{affine_proj, affine_truth_table, MulScheme, ProjScheme};
+use crate::garble::{stacked, three_halves};
+use crate::util::{output_tweak, tccr_hash_many, tweak, tweak2, RngExt};
 use crate::wire::Wire;
-use rand::{CryptoRng, RngCore};
+use rand::{CryptoRng, RngCore, SeedableRng};
 use scuttlebutt::{AbstractChannel, Block};
 use std::collections::HashMap;
 
@@ -12,7 +16,12 @@ pub struct Garbler<C, RNG> {
     deltas: HashMap<u16, Wire>, // map from modulus to associated delta wire-label.
     current_output: usize,
     current_gate: usize,
+    outputs: Vec<u16>,
     rng: RNG,
+    scheme: MulScheme,
+    proj_scheme: ProjScheme,
+    hash_inputs: Vec<(Block, Block)>, // reused between gates to avoid allocating
+    hashes: Vec<Block>,
 }
 
 impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
@@ -27,10 +36,30 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
                 .collect(),
             current_gate: 0,
             current_output: 0,
+            outputs: Vec::new(),
             rng,
+            scheme: MulScheme::default(),
+            proj_scheme: ProjScheme::default(),
+            hash_inputs: Vec::new(),
+            hashes: Vec::new(),
         }
     }
 
+    /// Garble multiplication gates with `scheme` instead of half-gates. The evaluator
+    /// must use the same scheme.
+    #[inline]
+    pub fn with_mul_scheme(mut self, scheme: MulScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Garble projection gates with `scheme`. The evaluator must use the same scheme.
+    #[inline]
+    pub fn with_proj_scheme(mut self, scheme: ProjScheme) -> Self {
+        self.proj_scheme = scheme;
+        self
+    }
+
     /// The current non-free gate index of the garbling computation
     #[inline]
     fn current_gate(&mut self) -> usize {
@@ -67,6 +96,32 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
         self.deltas
     }
 
+    /// The outputs revealed to the garbler, and the garbler's shares of shared outputs,
+    /// in the order they were output.
+    #[inline]
+    pub fn get_output(&self) -> Vec<u16> {
+        self.outputs.clone()
+    }
+
+    /// Receive the evaluator's wirelabel for the output with zero wire `X`, and decode it.
+    fn receive_output(&mut self, X: &Wire) -> Result<u16, GarblerError> {
+        let q = X.modulus();
+        let D = self.delta(q);
+        // the evaluator must see everything garbled so far before it can respond
+        self.channel.flush()?;
+        let label = Wire::from_block(self.channel.read_block()?, q);
+        let mut X_ = X.clone();
+        for k in 0..q {
+            if k > 0 {
+                X_.plus_eq(&D);
+            }
+            if X_ == label {
+                return Ok(k);
+            }
+        }
+        Err(GarblerError::DecodingFailed)
+    }
+
     /// Send a wire using the Sender.
     #[inline]
     pub fn send_wire(&mut self, wire: &Wire) -> Result<(), GarblerError> {
@@ -131,6 +186,163 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
     }
 }
 
+impl<C, RNG> Garbler<C, RNG>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore + SeedableRng<Seed = Block>,
+{
+    /// Create a new garbler which derives all of its deltas and zero wirelabels from
+    /// `seed`, so that garbling the same computation with the same seed produces the
+    /// same wirelabels and ciphertexts.
+    #[inline]
+    pub fn from_seed(channel: C, seed: Block, reused_deltas: &[Wire]) -> Self {
+        Garbler::new(channel, RNG::from_seed(seed), reused_deltas)
+    }
+}
+
+/// A multiplication or projection gate waiting on the hashes of its wirelabels, so
+/// that they can be computed together with those of other gates.
+pub(crate) enum PendingGate {
+    /// A gate garbled without hashes, with the zero wire of its output.
+    Done(Wire),
+    /// A half-gate multiplication, where `A` has the larger modulus. Its hashes start
+    /// at `start`.
+    Mul {
+        A: Wire,
+        B: Wire,
+        D: Wire,
+        Db: Wire,
+        r: u16,
+        start: usize,
+    },
+    /// A projection, whose hashes start at `start`.
+    Proj {
+        A: Wire,
+        q_out: u16,
+        tt: Vec<u16>,
+        Dout: Wire,
+        start: usize,
+    },
+}
+
+impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
+    /// Start garbling the multiplication of `A` and `B`, drawing its randomness and
+    /// pushing the inputs of its hashes to `inputs`. Three halves gates need no
+    /// hashes from `inputs`, and are garbled right away.
+    pub(crate) fn start_mul(
+        &mut self,
+        A: &Wire,
+        B: &Wire,
+        inputs: &mut Vec<(Block, Block)>,
+    ) -> Result<PendingGate, GarblerError> {
+        if A.modulus() < B.modulus() {
+            return self.start_mul(B, A, inputs);
+        }
+
+        let q = A.modulus();
+        let qb = B.modulus();
+        let gate_num = self.current_gate();
+
+        let D = self.delta(q);
+        if q == 2 && qb == 2 && self.scheme == MulScheme::ThreeHalves {
+            let (gate, C) = three_halves::garble_and(A, B, gate_num, &D, &mut self.rng);
+            self.channel.write_bytes(&gate)?;
+            return Ok(PendingGate::Done(C));
+        }
+        let Db = self.delta(qb);
+
+        // hack for unequal moduli
+        let r = if q != qb {
+            // would need to pack minitable into more than one u128 to support qb > 8
+            if qb > 8 {
+                return Err(GarblerError::AsymmetricHalfGateModuliMax8(qb))?;
+            }
+            self.rng.gen_u16() % q
+        } else {
+            B.color() // secret value known only to the garbler (ev knows r+b)
+        };
+
+        let start = inputs.len();
+        mul_hash_inputs(A, B, gate_num, &D, &Db, inputs);
+        Ok(PendingGate::Mul {
+            A: A.clone(),
+            B: B.clone(),
+            D,
+            Db,
+            r,
+            start,
+        })
+    }
+
+    /// Start garbling the projection of `A` with truth table `tt`, pushing the inputs
+    /// of its hashes to `inputs`. Affine projections are free with
+    /// `ProjScheme::RowReduced`, and are garbled right away.
+    pub(crate) fn start_proj(
+        &mut self,
+        A: &Wire,
+        q_out: u16,
+        tt: Option<Vec<u16>>,
+        inputs: &mut Vec<(Block, Block)>,
+    ) -> Result<PendingGate, GarblerError> {
+        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
+
+        let q_in = A.modulus();
+        if self.proj_scheme == ProjScheme::RowReduced {
+            if let Some((a, b)) = affine_truth_table(q_in, q_out, &tt) {
+                let Dout = self.delta(q_out);
+                return Ok(PendingGate::Done(
+                    affine_proj(A, q_out, a).minus_mov(&Dout.cmul(b)),
+                ));
+            }
+        }
+        let gate_num = self.current_gate();
+
+        let Din = self.delta(q_in);
+        let Dout = self.delta(q_out);
+
+        let start = inputs.len();
+        proj_hash_inputs(A, gate_num, &Din, inputs);
+        Ok(PendingGate::Proj {
+            A: A.clone(),
+            q_out,
+            tt,
+            Dout,
+            start,
+        })
+    }
+
+    /// Finish garbling `gate` using the hashes of the inputs pushed when starting it,
+    /// sending its ciphertexts. Returns the zero wire of its output.
+    pub(crate) fn finish_gate(
+        &mut self,
+        gate: PendingGate,
+        hashes: &[Block],
+    ) -> Result<Wire, GarblerError> {
+        let (gate, C) = match gate {
+            PendingGate::Done(C) => return Ok(C),
+            PendingGate::Mul {
+                A,
+                B,
+                D,
+                Db,
+                r,
+                start,
+            } => garble_mul_hashed(&A, &B, &D, &Db, r, &hashes[start..]),
+            PendingGate::Proj {
+                A,
+                q_out,
+                tt,
+                Dout,
+                start,
+            } => garble_proj_hashed(&A, q_out, &tt, &Dout, &hashes[start..]),
+        };
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(C)
+    }
+}
+
 impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Fancy for Garbler<C, RNG> {
     type Item = Wire;
     type Error = GarblerError;
@@ -166,187 +378,335 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Fancy for Garbler<C, RNG> {
 
     #[inline]
     fn mul(&mut self, A: &Wire, B: &Wire) -> Result<Wire, GarblerError> {
-        if A.modulus() < B.modulus() {
-            return self.mul(B, A);
-        }
-
-        let q = A.modulus();
-        let qb = B.modulus();
-        let gate_num = self.current_gate();
+        let mut inputs = std::mem::take(&mut self.hash_inputs);
+        let mut hashes = std::mem::take(&mut self.hashes);
+        inputs.clear();
+        let gate = self.start_mul(A, B, &mut inputs)?;
+        tccr_hash_many(&inputs, &mut hashes);
+        let C = self.finish_gate(gate, &hashes);
+        self.hash_inputs = inputs;
+        self.hashes = hashes;
+        C
+    }
 
-        let D = self.delta(q);
-        let Db = self.delta(qb);
+    #[inline]
+    fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
+        let mut inputs = std::mem::take(&mut self.hash_inputs);
+        let mut hashes = std::mem::take(&mut self.hashes);
+        inputs.clear();
+        let gate = self.start_proj(A, q_out, tt, &mut inputs)?;
+        tccr_hash_many(&inputs, &mut hashes);
+        let C = self.finish_gate(gate, &hashes);
+        self.hash_inputs = inputs;
+        self.hashes = hashes;
+        C
+    }
 
-        let r;
-        let mut gate = vec![Block::default(); q as usize + qb as usize - 2];
+    #[inline]
+    fn output(&mut self, X: &Wire) -> Result<(), GarblerError> {
+        let i = self.current_output();
+        let D = self.delta(X.modulus());
+        let cts = garble_output(X, i, &D);
+        for block in cts.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(())
+    }
 
-        // hack for unequal moduli
-        if q != qb {
-            // would need to pack minitable into more than one u128 to support qb > 8
-            if qb > 8 {
-                return Err(GarblerError::AsymmetricHalfGateModuliMax8(qb))?;
+    #[inline]
+    fn output_with_mode(&mut self, X: &Wire, mode: OutputMode) -> Result<(), GarblerError> {
+        match mode {
+            OutputMode::Evaluator => self.output(X),
+            OutputMode::Garbler => {
+                let x = self.receive_output(X)?;
+                self.outputs.push(x);
+                Ok(())
             }
-
-            r = self.rng.gen_u16() % q;
-            let t = tweak2(gate_num as u64, 1);
-
-            let mut minitable = vec![u128::default(); qb as usize];
-            let mut B_ = B.clone();
-            for b in 0..qb {
-                if b > 0 {
-                    B_.plus_eq(&Db);
-                }
-                let new_color = ((r + b) % q) as u128;
-                let ct = (u128::from(B_.hash(t)) & 0xFFFF) ^ new_color;
-                minitable[B_.color() as usize] = ct;
+            OutputMode::Both => {
+                self.output(X)?;
+                let x = self.receive_output(X)?;
+                self.outputs.push(x);
+                Ok(())
             }
-
-            let mut packed = 0;
-            for i in 0..qb as usize {
-                packed += minitable[i] << (16 * i);
+            OutputMode::Shared => {
+                // shifting the zero wire by the garbler's share makes the evaluator
+                // decode the output minus the share
+                let q = X.modulus();
+                let share = self.rng.gen_u16() % q;
+                let D = self.delta(q);
+                self.output(&X.plus(&D.cmul(share)))?;
+                self.outputs.push(share);
+                Ok(())
             }
-            gate.push(Block::from(packed));
-        } else {
-            r = B.color(); // secret value known only to the garbler (ev knows r+b)
         }
+    }
+}
 
-        let g = tweak2(gate_num as u64, 0);
-
-        // X = H(A+aD) + arD such that a + A.color == 0
-        let alpha = (q - A.color()) % q; // alpha = -A.color
-        let X = A
-            .plus(&D.cmul(alpha))
-            .hashback(g, q)
-            .plus_mov(&D.cmul(alpha * r % q));
-
-        // Y = H(B + bD) + (b + r)A such that b + B.color == 0
-        let beta = (qb - B.color()) % qb;
-        let Y = B
-            .plus(&Db.cmul(beta))
-            .hashback(g, q)
-            .plus_mov(&A.cmul((beta + r) % q));
-
-        let mut precomp = Vec::with_capacity(q as usize);
+impl<C: AbstractChannel, RNG: CryptoRng + RngCore> FancyBranch for Garbler<C, RNG> {
+    /// Garble the conditional with stacked garbling, sending about as many ciphertexts
+    /// as the largest branch.
+    fn branch(
+        &mut self,
+        cond: &Wire,
+        inputs: &[Wire],
+        branches: &[Branch],
+    ) -> Result<Vec<Wire>, GarblerError> {
+        let mut cs = build_branches(cond, inputs, branches)?;
+        let gate_num = self.current_gate();
+        let Dc = self.delta(cond.modulus());
+        let Din = inputs
+            .iter()
+            .map(|x| self.delta(x.modulus()))
+            .collect::<Vec<Wire>>();
+        let Dout = cs[0]
+            .output_refs
+            .iter()
+            .map(|r| self.delta(r.modulus()))
+            .collect::<Vec<Wire>>();
+        let (bytes, outputs) = stacked::garble_conditional(
+            &mut cs,
+            cond,
+            inputs,
+            gate_num,
+            &Dc,
+            &Din,
+            &Dout,
+            (self.scheme, self.proj_scheme),
+            &mut self.rng,
+        )?;
+        self.channel.write_bytes(&bytes)?;
+        Ok(outputs)
+    }
+}
 
-        // precompute a lookup table of X.minus(&D_cmul[(a * r % q)])
-        //                            = X.plus(&D_cmul[((q - (a * r % q)) % q)])
-        let mut X_ = X.clone();
-        precomp.push(X_.as_block());
-        for _ in 1..q {
-            X_.plus_eq(&D);
-            precomp.push(X_.as_block());
-        }
+/// Garble a multiplication gate, where `A` has the larger modulus, `D` and `Db` are the
+/// deltas for the moduli of `A` and `B`, and `r` is the garbler's secret offset: the
+/// color of `B` when the moduli are equal, and a random value mod `q` otherwise.
+/// Returns the ciphertexts and the zero wire of the output.
+pub(crate) fn garble_mul(
+    A: &Wire,
+    B: &Wire,
+    gate_num: usize,
+    D: &Wire,
+    Db: &Wire,
+    r: u16,
+) -> (Vec<Block>, Wire) {
+    let mut inputs = Vec::new();
+    let mut hashes = Vec::new();
+    mul_hash_inputs(A, B, gate_num, D, Db, &mut inputs);
+    tccr_hash_many(&inputs, &mut hashes);
+    garble_mul_hashed(A, B, D, Db, r, &hashes)
+}
 
-        let mut A_ = A.clone();
-        for a in 0..q {
-            if a > 0 {
-                A_.plus_eq(&D);
-            }
-            // garbler's half-gate: outputs X-arD
-            // G = H(A+aD) ^ X+a(-r)D = H(A+aD) ^ X-arD
-            if A_.color() != 0 {
-                gate[A_.color() as usize - 1] =
-                    A_.hash(g) ^ precomp[((q - (a * r % q)) % q) as usize];
+/// Push the inputs of the hashes of a multiplication gate to `inputs`: `B + bDb` for
+/// the minitable if the moduli differ, then `A + aD` and `B + bDb` for the half-gates.
+fn mul_hash_inputs(
+    A: &Wire,
+    B: &Wire,
+    gate_num: usize,
+    D: &Wire,
+    Db: &Wire,
+    inputs: &mut Vec<(Block, Block)>,
+) {
+    let g = tweak2(gate_num as u64, 0);
+    if A.modulus() != B.modulus() {
+        let t = tweak2(gate_num as u64, 1);
+        let mut B_ = B.clone();
+        for b in 0..B.modulus() {
+            if b > 0 {
+                B_.plus_eq(Db);
             }
+            inputs.push((t, B_.as_block()));
         }
-
-        precomp.clear();
-
-        // precompute a lookup table of Y.minus(&A_cmul[((b+r) % q)])
-        //                            = Y.plus(&A_cmul[((q - ((b+r) % q)) % q)])
-        let mut Y_ = Y.clone();
-        precomp.push(Y_.as_block());
-        for _ in 1..q {
-            Y_.plus_eq(&A);
-            precomp.push(Y_.as_block());
+    }
+    for (X, DX) in [(A, D), (B, Db)].iter() {
+        let mut X_ = (*X).clone();
+        for x in 0..X.modulus() {
+            if x > 0 {
+                X_.plus_eq(DX);
+            }
+            inputs.push((g, X_.as_block()));
         }
+    }
+}
 
+/// Garble a multiplication gate as `garble_mul` does, given the hashes of the inputs
+/// pushed by `mul_hash_inputs`.
+fn garble_mul_hashed(
+    A: &Wire,
+    B: &Wire,
+    D: &Wire,
+    Db: &Wire,
+    r: u16,
+    hashes: &[Block],
+) -> (Vec<Block>, Wire) {
+    let q = A.modulus();
+    let qb = B.modulus();
+    let mut gate = vec![Block::default(); q as usize + qb as usize - 2];
+
+    // hack for unequal moduli
+    let hashes = if q != qb {
+        let mut minitable = vec![u128::default(); qb as usize];
         let mut B_ = B.clone();
         for b in 0..qb {
             if b > 0 {
-                B_.plus_eq(&Db);
-            }
-            // evaluator's half-gate: outputs Y-(b+r)D
-            // G = H(B+bD) + Y-(b+r)A
-            if B_.color() != 0 {
-                gate[q as usize - 1 + B_.color() as usize - 1] =
-                    B_.hash(g) ^ precomp[((q - ((b + r) % q)) % q) as usize];
+                B_.plus_eq(Db);
             }
+            let new_color = ((r + b) % q) as u128;
+            let ct = (u128::from(hashes[b as usize]) & 0xFFFF) ^ new_color;
+            minitable[B_.color() as usize] = ct;
         }
 
-        for block in gate.iter() {
-            self.channel.write_block(block)?;
+        let mut packed = 0;
+        for i in 0..qb as usize {
+            packed += minitable[i] << (16 * i);
         }
-        Ok(X.plus_mov(&Y))
+        gate.push(Block::from(packed));
+        &hashes[qb as usize..]
+    } else {
+        hashes
+    };
+    // the hashes of A + aD and B + bDb
+    let (hs_a, hs_b) = hashes.split_at(q as usize);
+
+    // X = H(A+aD) + arD such that a + A.color == 0
+    let alpha = (q - A.color()) % q; // alpha = -A.color
+    let X = Wire::from_hash(hs_a[alpha as usize], q).plus_mov(&D.cmul(alpha * r % q));
+
+    // Y = H(B + bD) + (b + r)A such that b + B.color == 0
+    let beta = (qb - B.color()) % qb;
+    let Y = Wire::from_hash(hs_b[beta as usize], q).plus_mov(&A.cmul((beta + r) % q));
+
+    let mut precomp = Vec::with_capacity(q as usize);
+
+    // precompute a lookup table of X.minus(&D_cmul[(a * r % q)])
+    //                            = X.plus(&D_cmul[((q - (a * r % q)) % q)])
+    let mut X_ = X.clone();
+    precomp.push(X_.as_block());
+    for _ in 1..q {
+        X_.plus_eq(D);
+        precomp.push(X_.as_block());
     }
 
-    #[inline]
-    fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
-        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
-
-        let q_in = A.modulus();
-        let mut gate = vec![Block::default(); q_in as usize - 1];
-
-        let tao = A.color();
-        let g = tweak(self.current_gate());
+    let mut A_ = A.clone();
+    for a in 0..q {
+        if a > 0 {
+            A_.plus_eq(D);
+        }
+        // garbler's half-gate: outputs X-arD
+        // G = H(A+aD) ^ X+a(-r)D = H(A+aD) ^ X-arD
+        if A_.color() != 0 {
+            gate[A_.color() as usize - 1] =
+                hs_a[a as usize] ^ precomp[((q - (a * r % q)) % q) as usize];
+        }
+    }
 
-        let Din = self.delta(q_in);
-        let Dout = self.delta(q_out);
+    precomp.clear();
 
-        // output zero-wire
-        // W_g^0 <- -H(g, W_{a_1}^0 - \tao\Delta_m) - \phi(-\tao)\Delta_n
-        let C = A
-            .plus(&Din.cmul((q_in - tao) % q_in))
-            .hashback(g, q_out)
-            .plus_mov(&Dout.cmul((q_out - tt[((q_in - tao) % q_in) as usize]) % q_out));
-
-        // precompute `let C_ = C.plus(&Dout.cmul(tt[x as usize]))`
-        let C_precomputed = {
-            let mut C_ = C.clone();
-            (0..q_out)
-                .map(|x| {
-                    if x > 0 {
-                        C_.plus_eq(&Dout);
-                    }
-                    C_.as_block()
-                })
-                .collect::<Vec<Block>>()
-        };
+    // precompute a lookup table of Y.minus(&A_cmul[((b+r) % q)])
+    //                            = Y.plus(&A_cmul[((q - ((b+r) % q)) % q)])
+    let mut Y_ = Y.clone();
+    precomp.push(Y_.as_block());
+    for _ in 1..q {
+        Y_.plus_eq(&A);
+        precomp.push(Y_.as_block());
+    }
 
-        let mut A_ = A.clone();
-        for x in 0..q_in {
-            if x > 0 {
-                A_.plus_eq(&Din); // avoiding expensive cmul for `A_ = A.plus(&Din.cmul(x))`
-            }
+    let mut B_ = B.clone();
+    for b in 0..qb {
+        if b > 0 {
+            B_.plus_eq(Db);
+        }
+        // evaluator's half-gate: outputs Y-(b+r)D
+        // G = H(B+bD) + Y-(b+r)A
+        if B_.color() != 0 {
+            gate[q as usize - 1 + B_.color() as usize - 1] =
+                hs_b[b as usize] ^ precomp[((q - ((b + r) % q)) % q) as usize];
+        }
+    }
 
-            let ix = (tao as usize + x as usize) % q_in as usize;
-            if ix == 0 {
-                continue;
-            }
+    (gate, X.plus_mov(&Y))
+}
 
-            let ct = A_.hash(g) ^ C_precomputed[tt[x as usize] as usize];
-            gate[ix - 1] = ct;
-        }
+/// Garble a projection gate with truth table `tt`, where `Din` and `Dout` are the deltas
+/// for the input and output moduli. Returns the ciphertexts and the zero wire of the
+/// output.
+pub(crate) fn garble_proj(
+    A: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    gate_num: usize,
+    Din: &Wire,
+    Dout: &Wire,
+) -> (Vec<Block>, Wire) {
+    let mut inputs = Vec::with_capacity(A.modulus() as usize);
+    let mut hashes = Vec::with_capacity(A.modulus() as usize);
+    proj_hash_inputs(A, gate_num, Din, &mut inputs);
+    tccr_hash_many(&inputs, &mut hashes);
+    garble_proj_hashed(A, q_out, tt, Dout, &hashes)
+}
 
-        for block in gate.iter() {
-            self.channel.write_block(block)?;
+/// Push the inputs of the hashes of a projection gate, `A + xDin` for each `x`, to
+/// `inputs`.
+fn proj_hash_inputs(A: &Wire, gate_num: usize, Din: &Wire, inputs: &mut Vec<(Block, Block)>) {
+    let g = tweak(gate_num);
+    let mut A_ = A.clone();
+    for x in 0..A.modulus() {
+        if x > 0 {
+            A_.plus_eq(Din); // avoiding expensive cmul for `A_ = A.plus(&Din.cmul(x))`
         }
-        Ok(C)
+        inputs.push((g, A_.as_block()));
     }
+}
 
-    #[inline]
-    fn output(&mut self, X: &Wire) -> Result<(), GarblerError> {
-        let q = X.modulus();
-        let mut cts = Vec::with_capacity(q as usize);
-        let i = self.current_output();
-        let D = self.delta(q);
-        for k in 0..q {
-            let t = output_tweak(i, k);
-            cts.push(X.plus(&D.cmul(k)).hash(t));
-        }
-        for block in cts.iter() {
-            self.channel.write_block(block)?;
+/// Garble a projection gate as `garble_proj` does, given the hashes of the inputs
+/// pushed by `proj_hash_inputs`.
+fn garble_proj_hashed(
+    A: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    Dout: &Wire,
+    hashes: &[Block],
+) -> (Vec<Block>, Wire) {
+    let q_in = A.modulus();
+    let mut gate = vec![Block::default(); q_in as usize - 1];
+
+    let tao = A.color();
+
+    // output zero-wire
+    // W_g^0 <- -H(g, W_{a_1}^0 - \tao\Delta_m) - \phi(-\tao)\Delta_n
+    let C = Wire::from_hash(hashes[((q_in - tao) % q_in) as usize], q_out)
+        .plus_mov(&Dout.cmul((q_out - tt[((q_in - tao) % q_in) as usize]) % q_out));
+
+    // precompute `let C_ = C.plus(&Dout.cmul(tt[x as usize]))`
+    let C_precomputed = {
+        let mut C_ = C.clone();
+        (0..q_out)
+            .map(|x| {
+                if x > 0 {
+                    C_.plus_eq(Dout);
+                }
+                C_.as_block()
+            })
+            .collect::<Vec<Block>>()
+    };
+
+    for x in 0..q_in {
+        let ix = (tao as usize + x as usize) % q_in as usize;
+        if ix == 0 {
+            continue;
         }
-        Ok(())
+
+        let ct = hashes[x as usize] ^ C_precomputed[tt[x as usize] as usize];
+        gate[ix - 1] = ct;
     }
+
+    (gate, C)
+}
+
+/// Garble the `i`th output wire, producing a ciphertext for each of its possible values.
+pub(crate) fn garble_output(X: &Wire, i: usize, D: &Wire) -> Vec<Block> {
+    (0..X.modulus())
+        .map(|k| X.plus(&D.cmul(k)).hash(output_tweak(i, k)))
+        .collect()
 }
@@ -0,0 +1,528 @@
+//! Privacy-free garbling, for when the evaluator knows every input, as in
+//! zero-knowledge proofs from garbled circuits (see `zk`). It follows the
+//! privacy-free half-gates of Zahur, Rosulek and Evans, "Two Halves Make a Whole"
+//! (EUROCRYPT 2015), extended to arbitrary moduli.
+//!
+//! The garbled circuit only has to keep the evaluator from computing wirelabels of
+//! values it does not hold, so every zero wire has color zero and every delta has
+//! color one. The color of a wirelabel is then its value, which the evaluator reads
+//! off to pick its ciphertexts and to decode outputs without any help.
+//!
+//! Constants, addition, subtraction and scalar multiplication are free. A
+//! multiplication costs `qb - 1` ciphertexts, where `qb` is the smaller of the two
+//! moduli, so a binary AND gate costs one. A projection costs `q_in - 1` ciphertexts,
+//! or none if its truth table is affine in the sense of `ProjScheme::RowReduced`. The
+//! evaluator must know the truth tables.
+
+use crate::error::{EvaluatorError, FancyError, GarblerError};
+use crate::fancy::{Fancy, HasModulus};
+use crate::garble::{affine_proj, affine_truth_table};
+use crate::util::tweak;
+use crate::wire::Wire;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use scuttlebutt::{AbstractChannel, Block};
+use std::collections::HashMap;
+
+/// Streaming privacy-free garbler.
+pub struct PrivacyFreeGarbler<C, RNG> {
+    channel: C,
+    deltas: HashMap<u16, Wire>,
+    current_gate: usize,
+    outputs: Vec<Wire>,
+    rng: RNG,
+}
+
+/// Streaming privacy-free evaluator, which knows the value of every wire.
+pub struct PrivacyFreeEvaluator<C> {
+    channel: C,
+    current_gate: usize,
+    outputs: Vec<Wire>,
+}
+
+impl<C: AbstractChannel, RNG: CryptoRng + RngCore> PrivacyFreeGarbler<C, RNG> {
+    /// Create a new privacy-free garbler.
+    #[inline]
+    pub fn new(channel: C, rng: RNG) -> Self {
+        PrivacyFreeGarbler {
+            channel,
+            deltas: HashMap::new(),
+            current_gate: 0,
+            outputs: Vec::new(),
+            rng,
+        }
+    }
+
+    /// The current non-free gate index of the garbling computation.
+    #[inline]
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    /// Create a delta if it has not been created yet for this modulus, otherwise just
+    /// return the existing one.
+    #[inline]
+    pub fn delta(&mut self, q: u16) -> Wire {
+        if let Some(delta) = self.deltas.get(&q) {
+            return delta.clone();
+        }
+        let w = Wire::rand_delta(&mut self.rng, q);
+        self.deltas.insert(q, w.clone());
+        w
+    }
+
+    /// Get the deltas, consuming the garbler.
+    #[inline]
+    pub fn get_deltas(self) -> HashMap<u16, Wire> {
+        self.deltas
+    }
+
+    /// Send a wire using the Sender.
+    #[inline]
+    pub fn send_wire(&mut self, wire: &Wire) -> Result<(), GarblerError> {
+        self.channel.write_block(&wire.as_block())?;
+        Ok(())
+    }
+
+    /// Encode a wire, producing the zero wire as well as the encoded value.
+    #[inline]
+    pub fn encode_wire(&mut self, val: u16, modulus: u16) -> (Wire, Wire) {
+        let zero = Wire::rand(&mut self.rng, modulus).with_color(0);
+        let delta = self.delta(modulus);
+        let enc = zero.plus(&delta.cmul(val));
+        (zero, enc)
+    }
+
+    /// Encode many wires, producing zero wires as well as encoded values.
+    #[inline]
+    pub fn encode_many_wires(
+        &mut self,
+        vals: &[u16],
+        moduli: &[u16],
+    ) -> Result<(Vec<Wire>, Vec<Wire>), GarblerError> {
+        if vals.len() != moduli.len() {
+            return Err(GarblerError::EncodingError);
+        }
+        Ok(vals
+            .iter()
+            .zip(moduli.iter())
+            .map(|(x, q)| self.encode_wire(*x, *q))
+            .unzip())
+    }
+
+    /// Decode the evaluator's wirelabels for the outputs, in the order they were
+    /// output, checking that each is the wirelabel of the value given by its color.
+    pub fn decode_output(&self, labels: &[Wire]) -> Result<Vec<u16>, GarblerError> {
+        if labels.len() != self.outputs.len() {
+            return Err(GarblerError::DecodingFailed);
+        }
+        let mut outs = Vec::with_capacity(labels.len());
+        for (zero, label) in self.outputs.iter().zip(labels.iter()) {
+            let x = label.color();
+            let D = &self.deltas[&zero.modulus()];
+            if label.modulus() != zero.modulus() || zero.plus(&D.cmul(x)) != *label {
+                return Err(GarblerError::DecodingFailed);
+            }
+            outs.push(x);
+        }
+        Ok(outs)
+    }
+}
+
+impl<C, RNG> PrivacyFreeGarbler<C, RNG>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore + SeedableRng<Seed = Block>,
+{
+    /// Create a new privacy-free garbler which derives all of its deltas and zero
+    /// wirelabels from `seed`.
+    #[inline]
+    pub fn from_seed(channel: C, seed: Block) -> Self {
+        PrivacyFreeGarbler::new(channel, RNG::from_seed(seed))
+    }
+}
+
+impl<C: AbstractChannel> PrivacyFreeEvaluator<C> {
+    /// Create a new privacy-free evaluator.
+    pub fn new(channel: C) -> Self {
+        PrivacyFreeEvaluator {
+            channel,
+            current_gate: 0,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// The current non-free gate index of the garbling computation.
+    #[inline]
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    /// Read a Wire from the reader.
+    #[inline]
+    pub fn read_wire(&mut self, modulus: u16) -> Result<Wire, EvaluatorError> {
+        let block = self.channel.read_block()?;
+        Ok(Wire::from_block(block, modulus))
+    }
+
+    /// The wirelabels of the outputs, in the order they were output, which the
+    /// garbler decodes to check the result.
+    pub fn output_wires(&self) -> &[Wire] {
+        &self.outputs
+    }
+
+    /// Decode the outputs, in the order they were output.
+    pub fn decode_output(&self) -> Vec<u16> {
+        self.outputs.iter().map(Wire::color).collect()
+    }
+
+    /// Read `n` ciphertexts of a gate.
+    fn read_gate(&mut self, n: usize) -> Result<Vec<Block>, EvaluatorError> {
+        (0..n).map(|_| Ok(self.channel.read_block()?)).collect()
+    }
+}
+
+impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Fancy for PrivacyFreeGarbler<C, RNG> {
+    type Item = Wire;
+    type Error = GarblerError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wire, GarblerError> {
+        // the evaluator knows the wirelabel of a constant
+        Ok(Wire::zero(q)
+            .with_color(x % q)
+            .minus_mov(&self.delta(q).cmul(x)))
+    }
+
+    #[inline]
+    fn add(&mut self, x: &Wire, y: &Wire) -> Result<Wire, GarblerError> {
+        if x.modulus() != y.modulus() {
+            return Err(GarblerError::FancyError(FancyError::UnequalModuli));
+        }
+        Ok(x.plus(y))
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &Wire, y: &Wire) -> Result<Wire, GarblerError> {
+        if x.modulus() != y.modulus() {
+            return Err(GarblerError::FancyError(FancyError::UnequalModuli));
+        }
+        Ok(x.minus(y))
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &Wire, c: u16) -> Result<Wire, GarblerError> {
+        Ok(x.cmul(c))
+    }
+
+    #[inline]
+    fn mul(&mut self, A: &Wire, B: &Wire) -> Result<Wire, GarblerError> {
+        if A.modulus() < B.modulus() {
+            return self.mul(B, A);
+        }
+        let gate_num = self.current_gate();
+        let Db = self.delta(B.modulus());
+        let (gate, C) = garble_mul(A, B, gate_num, &Db);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(C)
+    }
+
+    #[inline]
+    fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
+        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
+        let q_in = A.modulus();
+        let Dout = self.delta(q_out);
+        if let Some((a, b)) = affine_truth_table(q_in, q_out, &tt) {
+            return Ok(affine_proj(A, q_out, a)
+                .plus_mov(&Wire::zero(q_out).with_color(b))
+                .minus_mov(&Dout.cmul(b)));
+        }
+        let gate_num = self.current_gate();
+        let Din = self.delta(q_in);
+        let (gate, C) = garble_proj(A, q_out, &tt, gate_num, &Din, &Dout);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(C)
+    }
+
+    #[inline]
+    fn output(&mut self, X: &Wire) -> Result<(), GarblerError> {
+        self.outputs.push(X.clone());
+        Ok(())
+    }
+}
+
+impl<C: AbstractChannel> Fancy for PrivacyFreeEvaluator<C> {
+    type Item = Wire;
+    type Error = EvaluatorError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wire, EvaluatorError> {
+        Ok(Wire::zero(q).with_color(x % q))
+    }
+
+    #[inline]
+    fn add(&mut self, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        if x.modulus() != y.modulus() {
+            return Err(EvaluatorError::FancyError(FancyError::UnequalModuli));
+        }
+        Ok(x.plus(y))
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        if x.modulus() != y.modulus() {
+            return Err(EvaluatorError::FancyError(FancyError::UnequalModuli));
+        }
+        Ok(x.minus(y))
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &Wire, c: u16) -> Result<Wire, EvaluatorError> {
+        Ok(x.cmul(c))
+    }
+
+    #[inline]
+    fn mul(&mut self, A: &Wire, B: &Wire) -> Result<Wire, EvaluatorError> {
+        if A.modulus() < B.modulus() {
+            return self.mul(B, A);
+        }
+        let gate = self.read_gate(B.modulus() as usize - 1)?;
+        let gate_num = self.current_gate();
+        Ok(eval_mul(A, B, gate_num, &gate))
+    }
+
+    #[inline]
+    fn proj(&mut self, x: &Wire, q: u16, tt: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
+        let tt = tt.ok_or(EvaluatorError::TruthTableRequired)?;
+        if let Some((a, b)) = affine_truth_table(x.modulus(), q, &tt) {
+            return Ok(affine_proj(x, q, a).plus_mov(&Wire::zero(q).with_color(b)));
+        }
+        let gate = self.read_gate(x.modulus() as usize - 1)?;
+        let gate_num = self.current_gate();
+        Ok(eval_proj(x, q, &tt, gate_num, &gate))
+    }
+
+    #[inline]
+    fn output(&mut self, x: &Wire) -> Result<(), EvaluatorError> {
+        self.outputs.push(x.clone());
+        Ok(())
+    }
+}
+
+/// Garble a multiplication gate, where `A` has the larger modulus and `Db` is the
+/// delta for the modulus of `B`. Returns the ciphertexts and the zero wire of the
+/// output.
+///
+/// The evaluator holding `B + bD` gets `C - bA` from ciphertext `b - 1`, or `C`
+/// itself from the hash when `b` is zero, and adds `b` times its wirelabel of `A`.
+fn garble_mul(A: &Wire, B: &Wire, gate_num: usize, Db: &Wire) -> (Vec<Block>, Wire) {
+    let q = A.modulus();
+    let t = tweak(gate_num);
+    let C = B.hashback(t, q).with_color(0);
+    let mut gate = Vec::with_capacity(B.modulus() as usize - 1);
+    let mut B_ = B.clone();
+    for b in 1..B.modulus() {
+        B_.plus_eq(Db);
+        gate.push(B_.hash(t) ^ C.minus(&A.cmul(b)).as_block());
+    }
+    (gate, C)
+}
+
+/// Evaluate a multiplication gate, where `A` has the larger modulus, using the
+/// ciphertexts in `gate`.
+fn eval_mul(A: &Wire, B: &Wire, gate_num: usize, gate: &[Block]) -> Wire {
+    let q = A.modulus();
+    let t = tweak(gate_num);
+    match B.color() {
+        0 => B.hashback(t, q).with_color(0),
+        b => Wire::from_block(gate[b as usize - 1] ^ B.hash(t), q).plus_mov(&A.cmul(b)),
+    }
+}
+
+/// Garble a projection gate with truth table `tt`, where `Din` and `Dout` are the
+/// deltas for the input and output moduli. Returns the ciphertexts and the zero wire
+/// of the output.
+///
+/// The wirelabel for input zero is the hash of the input wirelabel, so only the other
+/// `q_in - 1` inputs need ciphertexts.
+fn garble_proj(
+    A: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    gate_num: usize,
+    Din: &Wire,
+    Dout: &Wire,
+) -> (Vec<Block>, Wire) {
+    let t = tweak(gate_num);
+    let C = A
+        .hashback(t, q_out)
+        .with_color(tt[0])
+        .minus_mov(&Dout.cmul(tt[0]));
+    let mut gate = Vec::with_capacity(A.modulus() as usize - 1);
+    let mut A_ = A.clone();
+    for x in 1..A.modulus() {
+        A_.plus_eq(Din);
+        gate.push(A_.hash(t) ^ C.plus(&Dout.cmul(tt[x as usize])).as_block());
+    }
+    (gate, C)
+}
+
+/// Evaluate a projection gate with output modulus `q` and truth table `tt`, using the
+/// ciphertexts in `gate`.
+fn eval_proj(x: &Wire, q: u16, tt: &[u16], gate_num: usize, gate: &[Block]) -> Wire {
+    let t = tweak(gate_num);
+    match x.color() {
+        0 => x.hashback(t, q).with_color(tt[0]),
+        c => Wire::from_block(gate[c as usize - 1] ^ x.hash(t), q),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Circuit, CircuitBuilder, Gate};
+    use crate::fancy::{BinaryGadgets, BundleGadgets, CrtGadgets};
+    use crate::util::RngExt;
+    use itertools::Itertools;
+    use rand::thread_rng;
+    use scuttlebutt::{AesRng, Channel};
+
+    // Garble `c` privacy-free on the given inputs, returning the ciphertexts, the
+    // evaluator's output wirelabels and outputs, and the outputs the garbler decodes
+    // from them.
+    fn garble_and_eval(
+        c: &mut Circuit,
+        gb_inps: &[u16],
+        ev_inps: &[u16],
+    ) -> (usize, Vec<Wire>, Vec<u16>, Vec<u16>) {
+        let gb_mods = (0..c.num_garbler_inputs())
+            .map(|i| c.garbler_input_mod(i))
+            .collect_vec();
+        let ev_mods = (0..c.num_evaluator_inputs())
+            .map(|i| c.evaluator_input_mod(i))
+            .collect_vec();
+        let channel = Channel::new(std::io::empty(), Vec::new());
+        let mut gb = PrivacyFreeGarbler::new(channel.clone(), AesRng::new());
+        let (X0, X) = gb.encode_many_wires(gb_inps, &gb_mods).unwrap();
+        let (Y0, Y) = gb.encode_many_wires(ev_inps, &ev_mods).unwrap();
+        let outputs = c.eval(&mut gb, &X0, &Y0).unwrap();
+        c.process_outputs(&outputs, &mut gb).unwrap();
+        let bytes = channel.writer().borrow().clone();
+
+        let mut ev = PrivacyFreeEvaluator::new(Channel::new(&bytes[..], std::io::sink()));
+        let outputs = c.eval(&mut ev, &X, &Y).unwrap();
+        c.process_outputs(&outputs, &mut ev).unwrap();
+        let labels = ev.output_wires().to_vec();
+        let decoded = gb.decode_output(&labels).unwrap();
+        (bytes.len() / 16, labels, ev.decode_output(), decoded)
+    }
+
+    #[test]
+    fn binary() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_garbler_input(16);
+        let y = b.bin_evaluator_input(16);
+        let z = b.bin_multiplication_lower_half(&x, &y).unwrap();
+        let z = b.bin_xor(&z, &x).unwrap();
+        b.output_bundle(&z).unwrap();
+        let mut c = b.finish();
+        let nands = (0..c.gates.len())
+            .filter(|&i| matches!(c.gates[i], Gate::Mul { .. }))
+            .count();
+        let nprojs = (0..c.gates.len())
+            .filter(|&i| matches!(c.gates[i], Gate::Proj { .. }))
+            .count();
+        assert_eq!(nprojs, 0);
+
+        for _ in 0..16 {
+            let gb = (0..16).map(|_| rng.gen_u16() % 2).collect_vec();
+            let ev = (0..16).map(|_| rng.gen_u16() % 2).collect_vec();
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            let (size, _, out, decoded) = garble_and_eval(&mut c, &gb, &ev);
+            // one ciphertext per AND gate
+            assert_eq!(size, nands);
+            assert_eq!(out, should_be);
+            assert_eq!(decoded, should_be);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut rng = thread_rng();
+        let q = crate::util::modulus_with_nprimes(4);
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_add(&z, &x).unwrap();
+        let w = b.crt_constant_bundle(17, q).unwrap();
+        let z = b.crt_sub(&z, &w).unwrap();
+        b.output_bundle(&z).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        // mixed moduli, and affine projections
+        let u = b.garbler_input(7);
+        let v = b.evaluator_input(3);
+        let uv = b.mul(&u, &v).unwrap();
+        b.output(&uv).unwrap();
+        let p = b.proj(&v, 3, Some(vec![2, 0, 1])).unwrap();
+        b.output(&p).unwrap();
+        let p = b.proj(&v, 5, Some(vec![4, 4, 4])).unwrap();
+        b.output(&p).unwrap();
+        let p = b.proj(&u, 2, Some(vec![0, 1, 1, 0, 1, 0, 0])).unwrap();
+        b.output(&p).unwrap();
+        let mut c = b.finish();
+
+        for _ in 0..8 {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect_vec();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect_vec();
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            let (_, _, out, decoded) = garble_and_eval(&mut c, &gb, &ev);
+            assert_eq!(out, should_be);
+            assert_eq!(decoded, should_be);
+        }
+    }
+
+    #[test]
+    fn forged_output() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let x = b.evaluator_input(5);
+        let y = b.evaluator_input(5);
+        let z = b.mul(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+
+        let channel = Channel::new(std::io::empty(), Vec::new());
+        let mut gb = PrivacyFreeGarbler::new(channel.clone(), AesRng::new());
+        let (Y0, Y) = gb.encode_many_wires(&[2, 3], &[5, 5]).unwrap();
+        let outputs = c.eval(&mut gb, &[], &Y0).unwrap();
+        c.process_outputs(&outputs, &mut gb).unwrap();
+        let bytes = channel.writer().borrow().clone();
+        let mut ev = PrivacyFreeEvaluator::new(Channel::new(&bytes[..], std::io::sink()));
+        let outputs = c.eval(&mut ev, &[], &Y).unwrap();
+        c.process_outputs(&outputs, &mut ev).unwrap();
+        assert_eq!(ev.decode_output(), vec![1]);
+
+        // changing the color of the output wirelabel does not change its value
+        let label = &ev.output_wires()[0];
+        let forged = label.with_color((2 + rng.gen_u16() % 4) % 5);
+        assert!(gb.decode_output(std::slice::from_ref(label)).is_ok());
+        match gb.decode_output(&[forged]) {
+            Err(GarblerError::DecodingFailed) => (),
+            r => panic!("expected DecodingFailed, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Stacked garbling of conditionals, following Heath and Kolesnikov, "Stacked
+//! Garbling: Garbled Circuit Proportional to Longest Execution Path" (CRYPTO 2020).
+//!
+//! Each branch is garbled by its own `Garbler`, with its own deltas, from a random
+//! seed, and the garbler sends the XOR of the garbled branches, padded to the
+//! longest, instead of all of them. For a conditional with `b` branches:
+//!
+//! 1. Using its wirelabel of the condition, the evaluator decrypts a seed for each
+//!    branch: the right one for every inactive branch, and a garbage one for the
+//!    active branch.
+//! 2. For each branch, the evaluator translates the wirelabels of the inputs into
+//!    wirelabels of the branch, which are garbage unless the branch is active.
+//! 3. The evaluator does not know which branch is active, so for each branch it
+//!    regarbles the others from their seeds, XORs them out of the stacked garbling,
+//!    and evaluates the branch on what is left. Only the evaluation of the active
+//!    branch is meaningful.
+//! 4. The garbler knows the garbage seeds, so it also knows the garbage the
+//!    evaluator computes for each inactive branch. It sends a table for each output
+//!    of each branch which translates both the valid wirelabels and the garbage into
+//!    additive shares of the output wirelabel.
+//!
+//! Besides the largest branch, this costs `b^2` ciphertexts, `b^2 q` for each input
+//! of modulus `q`, and `2b (q + b - 1)` for each output of modulus `q`. The
+//! garbler evaluates each branch `b - 1` times, and the evaluator garbles and
+//! evaluates each branch once.
+
+use crate::circuit::Circuit;
+use crate::error::{EvaluatorError, GarblerError};
+use crate::fancy::HasModulus;
+use crate::garble::{Evaluator, Garbler, MulScheme, ProjScheme};
+use crate::util::tweak2;
+use crate::wire::Wire;
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
+use std::collections::HashMap;
+
+/// A branch garbled from a seed.
+struct GarbledBranch {
+    /// The ciphertexts of the branch.
+    bytes: Vec<u8>,
+    /// The zero wires of the inputs.
+    inputs: Vec<Wire>,
+    /// The zero wires of the outputs.
+    outputs: Vec<Wire>,
+    /// The deltas of the branch.
+    deltas: HashMap<u16, Wire>,
+}
+
+/// Garble the branch `c` from `seed`.
+fn garble_branch(
+    c: &mut Circuit,
+    seed: Block,
+    schemes: (MulScheme, ProjScheme),
+) -> Result<GarbledBranch, GarblerError> {
+    let channel = Channel::new(std::io::empty(), Vec::new());
+    let mut gb = Garbler::<_, AesRng>::from_seed(channel.clone(), seed, &[])
+        .with_mul_scheme(schemes.0)
+        .with_proj_scheme(schemes.1);
+    let inputs = (0..c.num_garbler_inputs())
+        .map(|i| gb.encode_wire(0, c.garbler_input_mod(i)).0)
+        .collect::<Vec<Wire>>();
+    let outputs = c.eval(&mut gb, &inputs, &[])?;
+    let deltas = gb.get_deltas();
+    let bytes = std::mem::take(&mut *channel.writer().borrow_mut());
+    Ok(GarbledBranch {
+        bytes,
+        inputs,
+        outputs,
+        deltas,
+    })
+}
+
+/// Evaluate the branch `c` on `inputs` using the ciphertexts in `bytes`.
+fn eval_branch(
+    c: &mut Circuit,
+    bytes: &[u8],
+    inputs: &[Wire],
+    schemes: (MulScheme, ProjScheme),
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let mut ev = Evaluator::new(Channel::new(bytes, std::io::sink()))
+        .with_mul_scheme(schemes.0)
+        .with_proj_scheme(schemes.1);
+    c.eval(&mut ev, inputs, &[])
+}
+
+/// XOR `y` into `x`, extending `x` with zeros if it is shorter.
+fn xor(x: &mut Vec<u8>, y: &[u8]) {
+    if x.len() < y.len() {
+        x.resize(y.len(), 0);
+    }
+    for (a, b) in x.iter_mut().zip(y.iter()) {
+        *a ^= b;
+    }
+}
+
+/// Append `block` to `bytes`.
+fn write(bytes: &mut Vec<u8>, block: Block) {
+    bytes.extend_from_slice(&<[u8; 16]>::from(block));
+}
+
+/// The XOR of all of `seeds` but the `i`th, which the outputs of branch `i` are
+/// hashed with, so that evaluating it under different seeds never gives the same
+/// wirelabels.
+fn others(seeds: &[Block], i: usize) -> Block {
+    seeds
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| *j != i)
+        .fold(Block::default(), |acc, (_, s)| acc ^ *s)
+}
+
+/// The key of the row for the wirelabels `C` and `X` of a demultiplexer table.
+fn demux_key(C: &Wire, X: &Wire, t: Block) -> Block {
+    X.hash(C.hash(t))
+}
+
+/// The tag and key of the entry for the wirelabel `X` of a multiplexer table, where
+/// `s` is the XOR of the seeds the evaluator used to compute it.
+fn mux_tag_and_key(X: &Wire, s: Block, t: (Block, Block)) -> (Block, Block) {
+    (X.hash(t.0 ^ s), X.hash(t.1 ^ s))
+}
+
+/// Garble the conditional with branches `cs` on the condition with zero wire `cond`
+/// and the inputs with zero wires `inputs`, as gate `gate_num`. `Dc`, `Din` and
+/// `Dout` are the deltas of the condition, the inputs and the outputs. Returns the
+/// bytes to send and the zero wires of the outputs.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn garble_conditional<RNG: CryptoRng + RngCore>(
+    cs: &mut [Circuit],
+    cond: &Wire,
+    inputs: &[Wire],
+    gate_num: usize,
+    Dc: &Wire,
+    Din: &[Wire],
+    Dout: &[Wire],
+    schemes: (MulScheme, ProjScheme),
+    rng: &mut RNG,
+) -> Result<(Vec<u8>, Vec<Wire>), GarblerError> {
+    let b = cs.len();
+    let g = gate_num as u64;
+    let mut t = 0;
+    let mut next_tweak = || {
+        t += 1;
+        tweak2(g, t - 1)
+    };
+    let seeds = (0..b).map(|_| rng.gen()).collect::<Vec<Block>>();
+    let garbage_seeds = (0..b).map(|_| rng.gen()).collect::<Vec<Block>>();
+    let branches = cs
+        .iter_mut()
+        .zip(seeds.iter())
+        .map(|(c, s)| garble_branch(c, *s, schemes))
+        .collect::<Result<Vec<GarbledBranch>, GarblerError>>()?;
+    let garbage = cs
+        .iter_mut()
+        .zip(garbage_seeds.iter())
+        .map(|(c, s)| Ok(garble_branch(c, *s, schemes)?.bytes))
+        .collect::<Result<Vec<Vec<u8>>, GarblerError>>()?;
+    let mut bytes = Vec::new();
+
+    // The seeds the evaluator decrypts when branch `v` is active, by the color of the
+    // wirelabel of the condition.
+    let qc = b as u16;
+    let tweaks = (0..b).map(|_| next_tweak()).collect::<Vec<Block>>();
+    let mut seed_table = vec![Block::default(); b * b];
+    for v in 0..qc {
+        let C = cond.plus(&Dc.cmul(v));
+        for i in 0..b {
+            let seed = if i == v as usize {
+                garbage_seeds[i]
+            } else {
+                seeds[i]
+            };
+            seed_table[C.color() as usize * b + i] = C.hash(tweaks[i]) ^ seed;
+        }
+    }
+    seed_table.into_iter().for_each(|x| write(&mut bytes, x));
+
+    // The input wirelabels of each branch, where `garbage_inputs[i][v]` are those the
+    // evaluator gets for branch `i` when branch `v` is active.
+    let mut garbage_inputs = vec![vec![Vec::with_capacity(inputs.len()); b]; b];
+    for (i, branch) in branches.iter().enumerate() {
+        for (k, (X, D)) in inputs.iter().zip(Din.iter()).enumerate() {
+            let q = X.modulus();
+            let t = next_tweak();
+            let mut table = vec![Block::default(); b * q as usize];
+            for v in 0..qc {
+                let C = cond.plus(&Dc.cmul(v));
+                let G = Wire::rand(rng, q);
+                for x in 0..q {
+                    let X_ = X.plus(&D.cmul(x));
+                    let label = if v as usize == i {
+                        branch.inputs[k].plus(&branch.deltas[&q].cmul(x))
+                    } else {
+                        G.clone()
+                    };
+                    let ix = C.color() as usize * q as usize + X_.color() as usize;
+                    table[ix] = demux_key(&C, &X_, t) ^ label.as_block();
+                }
+                garbage_inputs[i][v as usize].push(G);
+            }
+            table.into_iter().for_each(|x| write(&mut bytes, x));
+        }
+    }
+
+    let mut stack = Vec::new();
+    for branch in branches.iter() {
+        xor(&mut stack, &branch.bytes);
+    }
+    bytes.extend_from_slice(&(stack.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&stack);
+
+    // The output wirelabels the evaluator computes for branch `i` when branch `v` is
+    // active, and the XOR of the seeds it used.
+    let mut garbage_outputs = vec![vec![(Vec::new(), Block::default()); b]; b];
+    for v in 0..b {
+        let mut evaluator_seeds = seeds.clone();
+        evaluator_seeds[v] = garbage_seeds[v];
+        for i in (0..b).filter(|&i| i != v) {
+            let mut material = branches[i].bytes.clone();
+            xor(&mut material, &branches[v].bytes);
+            xor(&mut material, &garbage[v]);
+            material.resize(stack.len(), 0);
+            let outputs = eval_branch(&mut cs[i], &material, &garbage_inputs[i][v], schemes)
+                .map_err(|e| GarblerError::CommunicationError(e.to_string()))?;
+            garbage_outputs[v][i] = (outputs, others(&evaluator_seeds, i));
+        }
+    }
+
+    // Each output is the sum of the entries the evaluator finds in the tables of
+    // the branches. The entries for the active branch `v` are `R[v] + x * D`, and
+    // the entries for the garbage of the inactive branches are random shares of
+    // `Z - R[v]`, where `Z` is the zero wire of the output.
+    let mut outputs = Vec::with_capacity(Dout.len());
+    for (k, D) in Dout.iter().enumerate() {
+        let q = D.modulus();
+        let Z = Wire::rand(rng, q);
+        let R = (0..b).map(|_| Wire::rand(rng, q)).collect::<Vec<Wire>>();
+        let mut shares = vec![vec![None; b]; b];
+        for v in 0..b {
+            let mut rest = Z.minus(&R[v]);
+            let inactive = (0..b).filter(|&i| i != v).collect::<Vec<usize>>();
+            for (n, &i) in inactive.iter().enumerate() {
+                let share = if n + 1 == inactive.len() {
+                    rest.clone()
+                } else {
+                    Wire::rand(rng, q)
+                };
+                rest.minus_eq(&share);
+                shares[i][v] = Some(share);
+            }
+        }
+        for (i, branch) in branches.iter().enumerate() {
+            let t = (next_tweak(), next_tweak());
+            let zero = &branch.outputs[k];
+            let delta = &branch.deltas[&q];
+            let s = others(&seeds, i);
+            let mut entries = (0..q)
+                .map(|x| {
+                    let label = zero.plus(&delta.cmul(x));
+                    (mux_tag_and_key(&label, s, t), R[i].plus(&D.cmul(x)))
+                })
+                .collect::<Vec<((Block, Block), Wire)>>();
+            for v in (0..b).filter(|&v| v != i) {
+                let (labels, s) = &garbage_outputs[v][i];
+                let share = shares[i][v].take().expect("one share per inactive branch");
+                entries.push((mux_tag_and_key(&labels[k], *s, t), share));
+            }
+            entries.sort_by_key(|((tag, _), _)| u128::from(*tag));
+            for ((tag, key), payload) in entries {
+                write(&mut bytes, tag);
+                write(&mut bytes, key ^ payload.as_block());
+            }
+        }
+        outputs.push(Z);
+    }
+    Ok((bytes, outputs))
+}
+
+/// Evaluate the conditional with branches `cs` on the condition `cond` and the
+/// inputs `inputs`, as gate `gate_num`, reading the garbler's bytes from `channel`.
+/// Returns the outputs.
+pub(crate) fn eval_conditional<C: AbstractChannel>(
+    channel: &mut C,
+    cs: &mut [Circuit],
+    cond: &Wire,
+    inputs: &[Wire],
+    gate_num: usize,
+    schemes: (MulScheme, ProjScheme),
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let b = cs.len();
+    let g = gate_num as u64;
+    let mut t = 0;
+    let mut next_tweak = || {
+        t += 1;
+        tweak2(g, t - 1)
+    };
+
+    let tweaks = (0..b).map(|_| next_tweak()).collect::<Vec<Block>>();
+    let table = channel.read_blocks(b * b)?;
+    let row = cond.color() as usize * b;
+    let seeds = (0..b)
+        .map(|i| table[row + i] ^ cond.hash(tweaks[i]))
+        .collect::<Vec<Block>>();
+
+    let mut branch_inputs = vec![Vec::with_capacity(inputs.len()); b];
+    for branch_inputs in branch_inputs.iter_mut() {
+        for X in inputs.iter() {
+            let q = X.modulus();
+            let t = next_tweak();
+            let table = channel.read_blocks(b * q as usize)?;
+            let ix = cond.color() as usize * q as usize + X.color() as usize;
+            let label = table[ix] ^ demux_key(cond, X, t);
+            branch_inputs.push(Wire::from_block(label, q));
+        }
+    }
+
+    let n = channel.read_u64()? as usize;
+    let mut stack = vec![0; n];
+    channel.read_bytes(&mut stack)?;
+    let mut garbled = Vec::with_capacity(b);
+    for (c, seed) in cs.iter_mut().zip(seeds.iter()) {
+        let bytes = garble_branch(c, *seed, schemes)
+            .map_err(|e| EvaluatorError::CommunicationError(e.to_string()))?
+            .bytes;
+        xor(&mut stack, &bytes);
+        garbled.push(bytes);
+    }
+    // `stack` is now the XOR of the stacked garbling and all the regarbled branches.
+    let mut branch_outputs = Vec::with_capacity(b);
+    for (i, c) in cs.iter_mut().enumerate() {
+        let mut material = stack.clone();
+        xor(&mut material, &garbled[i]);
+        material.truncate(n);
+        branch_outputs.push(eval_branch(c, &material, &branch_inputs[i], schemes)?);
+    }
+
+    let noutputs = branch_outputs.first().map_or(0, |zs| zs.len());
+    let mut outputs = Vec::with_capacity(noutputs);
+    for k in 0..noutputs {
+        let q = branch_outputs[0][k].modulus();
+        let mut Z = Wire::zero(q);
+        for (i, zs) in branch_outputs.iter().enumerate() {
+            let t = (next_tweak(), next_tweak());
+            let (tag, key) = mux_tag_and_key(&zs[k], others(&seeds, i), t);
+            let entries = channel.read_blocks(2 * (q as usize + b - 1))?;
+            let ct = entries
+                .chunks(2)
+                .find(|entry| entry[0] == tag)
+                .map(|entry| entry[1])
+                .ok_or_else(|| {
+                    EvaluatorError::CommunicationError(
+                        "no multiplexer entry for the output of a branch".to_string(),
+                    )
+                })?;
+            Z.plus_eq(&Wire::from_block(ct ^ key, q));
+        }
+        outputs.push(Z);
+    }
+    Ok(outputs)
+}
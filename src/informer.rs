@@ -1,8 +1,17 @@
 //! `Informer` runs a fancy computation and learns information from it.
 
 use crate::error::{FancyError, InformerError};
-use crate::fancy::{Fancy, FancyInput, HasModulus};
-use std::collections::{HashMap, HashSet};
+use crate::fancy::{Fancy, FancyBranch, FancyInput, HasModulus, OutputMode};
+use crate::garble::{affine_truth_table, ProjScheme};
+use crate::twopac::input_bits;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Size in bits of a wirelabel or ciphertext.
+const BLOCK_BITS: usize = 128;
+
+/// Communication in bits of one IKNP oblivious transfer: 256 bits for one random
+/// and one 128 bit string dependent on the random one.
+const OT_BITS: usize = 384;
 
 /// Implements `Fancy`. Used to learn information about a `Fancy` computation in
 /// a lightweight way.
@@ -11,7 +20,7 @@ pub struct Informer {
     evaluator_input_moduli: Vec<u16>,
     constants: HashSet<(u16, u16)>,
     nreuses: usize,
-    outputs: Vec<u16>,
+    outputs: Vec<(u16, OutputMode)>,
     nadds: usize,
     nsubs: usize,
     ncmuls: usize,
@@ -19,12 +28,175 @@ pub struct Informer {
     nprojs: usize,
     nciphertexts: usize,
     moduli: HashMap<u16, usize>,
+    ciphertext_moduli: HashMap<u16, usize>,
+    output_depths: Vec<Depth>,
+    scopes: Vec<ScopeNode>,
+    scope_stack: Vec<usize>,
+    mul_widths: Vec<usize>,
+    proj_widths: Vec<usize>,
+    widths: Vec<usize>,
+    proj_scheme: ProjScheme,
+}
+
+/// The depth of a value in a fancy computation, in gates which need ciphertexts.
+/// Each field is the length of the longest path to the value counting only some
+/// gates, so the longest paths for different fields may differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Depth {
+    /// Multiplicative depth, counting only multiplications.
+    pub mul: usize,
+    /// Projection depth, counting only projections.
+    pub proj: usize,
+    /// Total depth, counting both multiplications and projections. This is the level
+    /// at which `garble_parallel` computes the value.
+    pub total: usize,
+}
+
+impl Depth {
+    fn max(self, other: Depth) -> Depth {
+        Depth {
+            mul: self.mul.max(other.mul),
+            proj: self.proj.max(other.proj),
+            total: self.total.max(other.total),
+        }
+    }
+}
+
+/// Information about a fancy computation gathered by an `Informer`, with estimates
+/// of the communication needed to garble it. With the `serde1` feature enabled, it
+/// can be serialized, for example to JSON to track the size of gadgets over time.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct InformerReport {
+    /// Number of garbler inputs.
+    pub num_garbler_inputs: usize,
+    /// Number of evaluator inputs.
+    pub num_evaluator_inputs: usize,
+    /// Number of reused values.
+    pub num_reused_values: usize,
+    /// Number of outputs.
+    pub num_outputs: usize,
+    /// Number of ciphertexts needed to decode the outputs, counting the wirelabels the
+    /// evaluator sends back for outputs revealed to the garbler.
+    pub num_output_ciphertexts: usize,
+    /// Number of distinct constants.
+    pub num_consts: usize,
+    /// Number of additions.
+    pub num_adds: usize,
+    /// Number of subtractions.
+    pub num_subs: usize,
+    /// Number of scalar multiplications.
+    pub num_cmuls: usize,
+    /// Number of multiplications.
+    pub num_muls: usize,
+    /// Number of projections.
+    pub num_projs: usize,
+    /// Number of ciphertexts in the garbled gates.
+    pub num_ciphertexts: usize,
+    /// Depth of the computation, as in `Informer::depth`.
+    pub depth: Depth,
+    /// Widths of the levels of the computation, as in `Informer::level_widths`.
+    pub level_widths: Vec<usize>,
+    /// Number of wires with each modulus.
+    pub wire_moduli: BTreeMap<u16, usize>,
+    /// Number of ciphertexts in the garbled gates, by the modulus of the gate output.
+    pub ciphertext_moduli: BTreeMap<u16, usize>,
+    /// Number of oblivious transfers for the evaluator inputs, one per bit.
+    pub num_ots: usize,
+    /// Communication in bits for the garbler input wirelabels.
+    pub garbler_input_bits: usize,
+    /// Communication in bits for the oblivious transfers.
+    pub ot_bits: usize,
+    /// Communication in bits for the output decoding ciphertexts, in both directions.
+    pub output_bits: usize,
+    /// Communication in bits for the constant wirelabels.
+    pub constant_bits: usize,
+    /// Communication in bits for the garbled gates.
+    pub ciphertext_bits: usize,
+    /// Total communication in bits.
+    pub total_bits: usize,
+    /// Gates and ciphertexts in each scope, as in `Informer::scope_costs`.
+    pub scopes: ScopeCost,
 }
 
-/// The item type used by `Informer`. It simply contains the modulus of the
-/// wire-label.
+impl InformerReport {
+    /// Print the report, in the format of `Informer::print_info`.
+    pub fn print(&self) {
+        let kb = |bits: usize| bits as f64 / 1000.0;
+        println!("computation info:");
+        println!(
+            "  garbler inputs:     {:16} // communication: {:.2} Kb",
+            self.num_garbler_inputs,
+            kb(self.garbler_input_bits)
+        );
+        println!(
+            "  evaluator inputs:   {:16} // communication: {:.2} Kb",
+            self.num_evaluator_inputs,
+            kb(self.ot_bits)
+        );
+        println!("  reused values:      {:16}", self.num_reused_values);
+        println!("  outputs:            {:16}", self.num_outputs);
+        println!(
+            "  output ciphertexts: {:16} // communication: {:.2} Kb",
+            self.num_output_ciphertexts,
+            kb(self.output_bits)
+        );
+        println!(
+            "  constants:          {:16} // communication: {:.2} Kb",
+            self.num_consts,
+            kb(self.constant_bits)
+        );
+        println!("  additions:          {:16}", self.num_adds);
+        println!("  subtractions:       {:16}", self.num_subs);
+        println!("  cmuls:              {:16}", self.num_cmuls);
+        println!("  projections:        {:16}", self.num_projs);
+        println!("  multiplications:    {:16}", self.num_muls);
+        println!("  mul depth:          {:16}", self.depth.mul);
+        println!("  proj depth:         {:16}", self.depth.proj);
+        println!("  total depth:        {:16}", self.depth.total);
+        if let Some((level, width)) = self
+            .level_widths
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, w)| **w)
+        {
+            println!(
+                "  widest level:       {:16} // at depth {}",
+                width,
+                level + 1
+            );
+        }
+        println!(
+            "  ciphertexts:        {:16} // communication: {:.2} Mb ({:.2} Kb)",
+            self.num_ciphertexts,
+            kb(self.ciphertext_bits) / 1000.0,
+            kb(self.ciphertext_bits)
+        );
+        println!(
+            "  total communication:  {:11.2} Mb",
+            kb(self.total_bits) / 1000.0
+        );
+        println!("  wire moduli: {:#?}", self.wire_moduli);
+        if !self.scopes.children.is_empty() {
+            println!("  scopes:");
+            self.scopes.print(4);
+        }
+    }
+}
+
+/// The item type used by `Informer`. It contains the modulus of the wire-label and
+/// the depth of its value.
 #[derive(Clone, Debug)]
-pub struct InformerVal(u16);
+pub struct InformerVal(u16, Depth);
+
+impl InformerVal {
+    /// The depth of this value.
+    pub fn depth(&self) -> Depth {
+        self.1
+    }
+}
 
 impl HasModulus for InformerVal {
     fn modulus(&self) -> u16 {
@@ -32,6 +204,120 @@ impl HasModulus for InformerVal {
     }
 }
 
+/// Name of the scope covering the whole computation.
+const ROOT_SCOPE: &str = "all";
+
+/// A scope of the computation, merging the calls of a scope with the same name inside
+/// the same parent.
+struct ScopeNode {
+    name: String,
+    calls: usize,
+    gates: usize,
+    ciphertexts: usize,
+    children: Vec<usize>,
+}
+
+impl ScopeNode {
+    fn new(name: &str) -> ScopeNode {
+        ScopeNode {
+            name: name.to_string(),
+            calls: 0,
+            gates: 0,
+            ciphertexts: 0,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// The gates and ciphertexts created inside a scope of a fancy computation, including
+/// those of the scopes nested in it. As in a flame graph, the calls of a scope with
+/// the same name inside the same parent scope are merged.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScopeCost {
+    /// Name of the scope. The root scope, which covers the whole computation, is
+    /// called `all`.
+    pub name: String,
+    /// Number of times the scope was opened.
+    pub calls: usize,
+    /// Number of gates created in the scope, not counting constants.
+    pub gates: usize,
+    /// Number of ciphertexts created in the scope.
+    pub ciphertexts: usize,
+    /// The scopes nested in this scope, in the order they were first opened.
+    pub children: Vec<ScopeCost>,
+}
+
+impl ScopeCost {
+    /// Number of gates created in the scope outside of the scopes nested in it.
+    pub fn self_gates(&self) -> usize {
+        self.gates - self.children.iter().map(|c| c.gates).sum::<usize>()
+    }
+
+    /// Number of ciphertexts created in the scope outside of the scopes nested in it.
+    pub fn self_ciphertexts(&self) -> usize {
+        self.ciphertexts - self.children.iter().map(|c| c.ciphertexts).sum::<usize>()
+    }
+
+    /// Find the scope nested in this one by following the names in `path`.
+    pub fn get(&self, path: &[&str]) -> Option<&ScopeCost> {
+        match path.split_first() {
+            None => Some(self),
+            Some((name, rest)) => self
+                .children
+                .iter()
+                .find(|c| c.name == *name)
+                .and_then(|c| c.get(rest)),
+        }
+    }
+
+    /// Write the ciphertexts of each scope in the folded stack format read by flame
+    /// graph tools such as `flamegraph.pl`, one line `all;outer;inner n` per scope
+    /// with `n` of its own ciphertexts.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        self.write_folded("", &mut out);
+        out
+    }
+
+    fn write_folded(&self, prefix: &str, out: &mut String) {
+        let path = if prefix.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{};{}", prefix, self.name)
+        };
+        if self.self_ciphertexts() > 0 {
+            out.push_str(&format!("{} {}\n", path, self.self_ciphertexts()));
+        }
+        for c in self.children.iter() {
+            c.write_folded(&path, out);
+        }
+    }
+
+    fn print(&self, indent: usize) {
+        println!(
+            "{:indent$}{}: {} ciphertexts in {} gates over {} calls",
+            "",
+            self.name,
+            self.ciphertexts,
+            self.gates,
+            self.calls,
+            indent = indent
+        );
+        for c in self.children.iter() {
+            c.print(indent + 2);
+        }
+    }
+}
+
+/// Count one more gate at depth `d` of `widths`, where depths start at 1.
+fn count_level(widths: &mut Vec<usize>, d: usize) {
+    if widths.len() < d {
+        widths.resize(d, 0);
+    }
+    widths[d - 1] += 1;
+}
+
 impl Informer {
     /// Make a new `Informer`.
     pub fn new() -> Informer {
@@ -48,89 +334,123 @@ impl Informer {
             nprojs: 0,
             nciphertexts: 0,
             moduli: HashMap::new(),
+            ciphertext_moduli: HashMap::new(),
+            output_depths: Vec::new(),
+            scopes: vec![ScopeNode::new(ROOT_SCOPE)],
+            scope_stack: Vec::new(),
+            mul_widths: Vec::new(),
+            proj_widths: Vec::new(),
+            widths: Vec::new(),
+            proj_scheme: ProjScheme::default(),
         }
     }
 
+    /// Count the ciphertexts of projections as if they were garbled with `scheme`.
+    /// Projections which need no ciphertexts are still counted as projections, but
+    /// do not add to the depth.
+    pub fn with_proj_scheme(mut self, scheme: ProjScheme) -> Informer {
+        self.proj_scheme = scheme;
+        self
+    }
+
     /// Print information about the fancy computation.
     ///
     /// For example, below is the output when run on `circuits/AES-non-expanded.txt`:
     /// ```
     /// computation info:
-    ///   garbler inputs:                  128 // comms cost: 16 Kb
-    ///   evaluator inputs:                128 // comms cost: 48 Kb
-    ///   reused values:                    12
+    ///   garbler inputs:                  128 // communication: 16.38 Kb
+    ///   evaluator inputs:                128 // communication: 49.15 Kb
+    ///   reused values:                     0
     ///   outputs:                         128
-    ///   output ciphertexts:              256 // comms cost: 32 Kb
-    ///   constants:                         1 // comms cost: 0.125 Kb
+    ///   output ciphertexts:              256 // communication: 32.77 Kb
+    ///   constants:                         1 // communication: 0.13 Kb
     ///   additions:                     25124
     ///   subtractions:                   1692
     ///   cmuls:                             0
     ///   projections:                       0
     ///   multiplications:                6800
-    ///   ciphertexts:                   13600 // comms cost: 1.66 Mb (1700.00 Kb)
-    ///   total comms cost:            1.75 Mb // 1700.00 Kb
+    ///   mul depth:                        40
+    ///   proj depth:                        0
+    ///   total depth:                      40
+    ///   widest level:                    360 // at depth 4
+    ///   ciphertexts:                   13600 // communication: 1.74 Mb (1740.80 Kb)
+    ///   total communication:         1.84 Mb
+    ///   wire moduli: {
+    ///     2: 33617,
+    /// }
     /// ```
     pub fn print_info(&self) {
-        let mut total = 0.0;
-        println!("computation info:");
-        let comm = self.num_garbler_inputs() as f64 * 128.0 / 1000.0;
-
-        println!(
-            "  garbler inputs:     {:16} // communication: {:.2} Kb",
-            self.num_garbler_inputs(),
-            comm
-        );
-        total += comm;
-        // The cost of IKNP is 256 bits for one random and one 128 bit string
-        // dependent on the random one. This is for each input bit, so for
-        // modulus `q` we need to do `log2(q)` OTs.
-        let comm = self.evaluator_input_moduli.iter().fold(0.0, |acc, q| {
-            acc + (*q as f64).log2().ceil() * 384.0 / 1000.0
-        });
-
-        println!(
-            "  evaluator inputs:   {:16} // communication: {:.2} Kb",
-            self.num_evaluator_inputs(),
-            comm
-        );
-        total += comm;
-        let comm = self.num_output_ciphertexts() as f64 * 128.0 / 1000.0;
-
-        println!("  reused values:      {:16}", self.nreuses);
+        self.report().print();
+    }
 
-        println!("  outputs:            {:16}", self.num_outputs());
-        println!(
-            "  output ciphertexts: {:16} // communication: {:.2} Kb",
-            self.num_output_ciphertexts(),
-            comm
-        );
-        total += comm;
-        let comm = self.num_consts() as f64 * 128.0 / 1000.0;
+    /// Collect the information about the fancy computation into an `InformerReport`.
+    pub fn report(&self) -> InformerReport {
+        let num_ots = self
+            .evaluator_input_moduli
+            .iter()
+            .map(|&q| input_bits(q))
+            .sum::<usize>();
+        let garbler_input_bits = self.num_garbler_inputs() * BLOCK_BITS;
+        let ot_bits = num_ots * OT_BITS;
+        let output_bits = self.num_output_ciphertexts() * BLOCK_BITS;
+        let constant_bits = self.num_consts() * BLOCK_BITS;
+        let ciphertext_bits = self.num_ciphertexts() * BLOCK_BITS;
+        InformerReport {
+            num_garbler_inputs: self.num_garbler_inputs(),
+            num_evaluator_inputs: self.num_evaluator_inputs(),
+            num_reused_values: self.nreuses,
+            num_outputs: self.num_outputs(),
+            num_output_ciphertexts: self.num_output_ciphertexts(),
+            num_consts: self.num_consts(),
+            num_adds: self.num_adds(),
+            num_subs: self.num_subs(),
+            num_cmuls: self.num_cmuls(),
+            num_muls: self.num_muls(),
+            num_projs: self.num_projs(),
+            num_ciphertexts: self.num_ciphertexts(),
+            depth: self.depth(),
+            level_widths: self.level_widths(),
+            wire_moduli: self.moduli.iter().map(|(&q, &n)| (q, n)).collect(),
+            ciphertext_moduli: self
+                .ciphertext_moduli
+                .iter()
+                .map(|(&q, &n)| (q, n))
+                .collect(),
+            num_ots,
+            garbler_input_bits,
+            ot_bits,
+            output_bits,
+            constant_bits,
+            ciphertext_bits,
+            total_bits: garbler_input_bits
+                + ot_bits
+                + output_bits
+                + constant_bits
+                + ciphertext_bits,
+            scopes: self.scope_costs(),
+        }
+    }
 
-        println!(
-            "  constants:          {:16} // communication: {:.2} Kb",
-            self.num_consts(),
-            comm
-        );
-        total += comm;
-
-        println!("  additions:          {:16}", self.num_adds());
-        println!("  subtractions:       {:16}", self.num_subs());
-        println!("  cmuls:              {:16}", self.num_cmuls());
-        println!("  projections:        {:16}", self.num_projs());
-        println!("  multiplications:    {:16}", self.num_muls());
-        let cs = self.num_ciphertexts();
-        let kb = cs as f64 * 128.0 / 1000.0;
-        let mb = kb / 1000.0;
-        println!(
-            "  ciphertexts:        {:16} // communication: {:.2} Mb ({:.2} Kb)",
-            cs, mb, kb
-        );
-        total += kb;
+    /// Gates and ciphertexts created in each scope opened with `push_scope`, nested
+    /// like a flame graph below a root scope covering the whole computation.
+    pub fn scope_costs(&self) -> ScopeCost {
+        self.scope_cost(0)
+    }
 
-        let mb = total / 1000.0;
-        println!("  total communication:  {:11.2} Mb", mb);
-        println!("  wire moduli: {:#?}", self.moduli);
+    fn scope_cost(&self, ix: usize) -> ScopeCost {
+        let node = &self.scopes[ix];
+        let children = node
+            .children
+            .iter()
+            .map(|&c| self.scope_cost(c))
+            .collect::<Vec<ScopeCost>>();
+        ScopeCost {
+            name: node.name.clone(),
+            calls: node.calls.max(1),
+            gates: node.gates + children.iter().map(|c| c.gates).sum::<usize>(),
+            ciphertexts: node.ciphertexts + children.iter().map(|c| c.ciphertexts).sum::<usize>(),
+            children,
+        }
     }
 
     /// Number of garbler inputs in the fancy computation.
@@ -163,9 +483,18 @@ impl Informer {
         self.outputs.len()
     }
 
-    /// Number of output ciphertexts.
+    /// Number of output ciphertexts. The garbler sends `q` to decode an output mod `q`
+    /// revealed to the evaluator or shared, and the evaluator sends one back for each
+    /// output revealed to the garbler.
     pub fn num_output_ciphertexts(&self) -> usize {
-        self.outputs.iter().map(|&m| m as usize).sum()
+        self.outputs
+            .iter()
+            .map(|&(q, mode)| match mode {
+                OutputMode::Evaluator | OutputMode::Shared => q as usize,
+                OutputMode::Garbler => 1,
+                OutputMode::Both => q as usize + 1,
+            })
+            .sum()
     }
 
     /// Number of additions in the fancy computation.
@@ -198,6 +527,59 @@ impl Informer {
         self.nciphertexts
     }
 
+    /// Depth of each output in the fancy computation.
+    pub fn output_depths(&self) -> Vec<Depth> {
+        self.output_depths.clone()
+    }
+
+    /// Depth of the fancy computation, the largest depth of any output in each field.
+    pub fn depth(&self) -> Depth {
+        self.output_depths
+            .iter()
+            .fold(Depth::default(), |acc, d| acc.max(*d))
+    }
+
+    /// Indices of the outputs at the end of a critical path, those whose total depth
+    /// is the total depth of the fancy computation.
+    pub fn critical_outputs(&self) -> Vec<usize> {
+        let total = self.depth().total;
+        (0..self.output_depths.len())
+            .filter(|&i| self.output_depths[i].total == total)
+            .collect()
+    }
+
+    /// Number of multiplications and projections at each total depth, starting from
+    /// depth 1. These can be garbled in parallel.
+    pub fn level_widths(&self) -> Vec<usize> {
+        self.widths.clone()
+    }
+
+    /// Number of multiplications at each multiplicative depth, starting from depth 1.
+    pub fn mul_level_widths(&self) -> Vec<usize> {
+        self.mul_widths.clone()
+    }
+
+    /// Number of projections at each projection depth, starting from depth 1.
+    pub fn proj_level_widths(&self) -> Vec<usize> {
+        self.proj_widths.clone()
+    }
+
+    fn update_ciphertexts(&mut self, q: u16, n: usize) {
+        self.nciphertexts += n;
+        *self.ciphertext_moduli.entry(q).or_insert(0) += n;
+        let scope = self.current_scope();
+        self.scopes[scope].ciphertexts += n;
+    }
+
+    fn update_gates(&mut self) {
+        let scope = self.current_scope();
+        self.scopes[scope].gates += 1;
+    }
+
+    fn current_scope(&self) -> usize {
+        self.scope_stack.last().cloned().unwrap_or(0)
+    }
+
     fn update_moduli(&mut self, q: u16) {
         let entry = self.moduli.entry(q).or_insert(0);
         *entry += 1;
@@ -205,13 +587,18 @@ impl Informer {
 }
 
 impl FancyInput for Informer {
+    /// Count an input of the other party, which is an evaluator input from the
+    /// garbler's point of view.
     fn receive(&mut self, modulus: u16) -> Result<Self::Item, Self::Error> {
-        self.garbler_input_moduli.push(modulus);
-        Ok(InformerVal(modulus))
+        self.evaluator_input_moduli.push(modulus);
+        Ok(InformerVal(modulus, Depth::default()))
     }
 
+    /// Count an input of our own, which is a garbler input from the garbler's point
+    /// of view.
     fn encode(&mut self, _value: u16, modulus: u16) -> Result<Self::Item, Self::Error> {
-        self.receive(modulus)
+        self.garbler_input_moduli.push(modulus);
+        Ok(InformerVal(modulus, Depth::default()))
     }
 
     fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<Self::Item>, Self::Error> {
@@ -220,13 +607,19 @@ impl FancyInput for Informer {
 
     fn encode_many(
         &mut self,
-        _values: &[u16],
+        values: &[u16],
         moduli: &[u16],
     ) -> Result<Vec<Self::Item>, Self::Error> {
-        moduli.iter().map(|q| self.receive(*q)).collect()
+        values
+            .iter()
+            .zip(moduli.iter())
+            .map(|(x, q)| self.encode(*x, *q))
+            .collect()
     }
 }
 
+impl FancyBranch for Informer {}
+
 impl Fancy for Informer {
     type Item = InformerVal;
     type Error = InformerError;
@@ -234,7 +627,7 @@ impl Fancy for Informer {
     fn constant(&mut self, val: u16, q: u16) -> Result<InformerVal, InformerError> {
         self.constants.insert((val, q));
         self.update_moduli(q);
-        Ok(InformerVal(q))
+        Ok(InformerVal(q, Depth::default()))
     }
 
     fn add(&mut self, x: &InformerVal, y: &InformerVal) -> Result<InformerVal, InformerError> {
@@ -242,8 +635,9 @@ impl Fancy for Informer {
             Err(FancyError::UnequalModuli)?;
         }
         self.nadds += 1;
+        self.update_gates();
         self.update_moduli(x.modulus());
-        Ok(InformerVal(x.modulus()))
+        Ok(InformerVal(x.modulus(), x.depth().max(y.depth())))
     }
 
     fn sub(&mut self, x: &InformerVal, y: &InformerVal) -> Result<InformerVal, InformerError> {
@@ -251,14 +645,16 @@ impl Fancy for Informer {
             Err(FancyError::UnequalModuli)?;
         }
         self.nsubs += 1;
+        self.update_gates();
         self.update_moduli(x.modulus());
-        Ok(InformerVal(x.modulus()))
+        Ok(InformerVal(x.modulus(), x.depth().max(y.depth())))
     }
 
     fn cmul(&mut self, x: &InformerVal, _: u16) -> Result<InformerVal, InformerError> {
         self.ncmuls += 1;
+        self.update_gates();
         self.update_moduli(x.modulus());
-        Ok(InformerVal(x.modulus()))
+        Ok(InformerVal(x.modulus(), x.depth()))
     }
 
     fn mul(&mut self, x: &InformerVal, y: &InformerVal) -> Result<InformerVal, InformerError> {
@@ -266,29 +662,249 @@ impl Fancy for Informer {
             return self.mul(y, x);
         }
         self.nmuls += 1;
-        self.nciphertexts += x.modulus() as usize + y.modulus() as usize - 2;
+        self.update_gates();
+        let mut nciphertexts = x.modulus() as usize + y.modulus() as usize - 2;
         if x.modulus() != y.modulus() {
             // there is an extra ciphertext to support nonequal inputs
-            self.nciphertexts += 1;
+            nciphertexts += 1;
         }
+        self.update_ciphertexts(x.modulus(), nciphertexts);
         self.update_moduli(x.modulus());
-        Ok(InformerVal(x.modulus()))
+        let mut depth = x.depth().max(y.depth());
+        depth.mul += 1;
+        depth.total += 1;
+        count_level(&mut self.mul_widths, depth.mul);
+        count_level(&mut self.widths, depth.total);
+        Ok(InformerVal(x.modulus(), depth))
     }
 
     fn proj(
         &mut self,
         x: &InformerVal,
         q: u16,
-        _: Option<Vec<u16>>,
+        tt: Option<Vec<u16>>,
     ) -> Result<InformerVal, InformerError> {
         self.nprojs += 1;
-        self.nciphertexts += x.modulus() as usize - 1;
+        self.update_gates();
+        if self.proj_scheme == ProjScheme::RowReduced
+            && tt.is_some_and(|tt| affine_truth_table(x.modulus(), q, &tt).is_some())
+        {
+            self.update_moduli(q);
+            return Ok(InformerVal(q, x.depth()));
+        }
+        self.update_ciphertexts(q, x.modulus() as usize - 1);
         self.update_moduli(q);
-        Ok(InformerVal(q))
+        let mut depth = x.depth();
+        depth.proj += 1;
+        depth.total += 1;
+        count_level(&mut self.proj_widths, depth.proj);
+        count_level(&mut self.widths, depth.total);
+        Ok(InformerVal(q, depth))
     }
 
     fn output(&mut self, x: &InformerVal) -> Result<(), InformerError> {
-        self.outputs.push(x.modulus());
+        self.output_with_mode(x, OutputMode::Evaluator)
+    }
+
+    fn output_with_mode(&mut self, x: &InformerVal, mode: OutputMode) -> Result<(), InformerError> {
+        self.outputs.push((x.modulus(), mode));
+        self.output_depths.push(x.depth());
         Ok(())
     }
+
+    fn push_scope(&mut self, name: &str) {
+        let parent = self.current_scope();
+        let existing = self.scopes[parent]
+            .children
+            .iter()
+            .cloned()
+            .find(|&c| self.scopes[c].name == name);
+        let scope = existing.unwrap_or_else(|| {
+            self.scopes.push(ScopeNode::new(name));
+            let scope = self.scopes.len() - 1;
+            self.scopes[parent].children.push(scope);
+            scope
+        });
+        self.scopes[scope].calls += 1;
+        self.scope_stack.push(scope);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scope_stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Circuit, CircuitBuilder};
+    use crate::fancy::{BinaryGadgets, BundleGadgets, CrtGadgets};
+    use crate::util;
+    use itertools::Itertools;
+
+    // check the output ciphertexts counted for a mod 5 output revealed with `mode`
+    fn check_output(mode: OutputMode, nciphertexts: usize) {
+        let mut informer = Informer::new();
+        let x = informer.receive(5).unwrap();
+        informer.output_with_mode(&x, mode).unwrap();
+        let report = informer.report();
+        assert_eq!(report.num_outputs, 1);
+        assert_eq!(report.num_output_ciphertexts, nciphertexts);
+        assert_eq!(report.output_bits, nciphertexts * BLOCK_BITS);
+    }
+
+    #[test]
+    fn evaluator_output() {
+        check_output(OutputMode::Evaluator, 5);
+    }
+
+    #[test]
+    fn garbler_output() {
+        // the garbler sends nothing, and the evaluator sends back its wirelabel
+        check_output(OutputMode::Garbler, 1);
+    }
+
+    #[test]
+    fn both_output() {
+        check_output(OutputMode::Both, 6);
+    }
+
+    #[test]
+    fn shared_output() {
+        check_output(OutputMode::Shared, 5);
+    }
+
+    #[test] // depths and level widths
+    fn informer_depth() {
+        let mut informer = Informer::new();
+        let x = informer.receive(3).unwrap();
+        let y = informer.receive(3).unwrap();
+        let a = informer.mul(&x, &y).unwrap();
+        let b = informer.proj(&a, 3, None).unwrap();
+        let c = informer.mul(&b, &y).unwrap();
+        let d = informer.add(&a, &x).unwrap();
+        informer.output(&c).unwrap();
+        informer.output(&d).unwrap();
+        informer.output(&x).unwrap();
+
+        let depth = |mul, proj, total| Depth { mul, proj, total };
+        assert_eq!(
+            informer.output_depths(),
+            [depth(2, 1, 3), depth(1, 0, 1), depth(0, 0, 0)]
+        );
+        assert_eq!(informer.depth(), depth(2, 1, 3));
+        assert_eq!(informer.critical_outputs(), [0]);
+        assert_eq!(informer.level_widths(), [1, 1, 1]);
+        assert_eq!(informer.mul_level_widths(), [1, 1]);
+        assert_eq!(informer.proj_level_widths(), [1]);
+
+        // a ripple-carry adder has a carry chain through every bit
+        let mut informer = Informer::new();
+        let x = informer.bin_receive(16).unwrap();
+        let y = informer.bin_receive(16).unwrap();
+        let z = informer.bin_addition_no_carry(&x, &y).unwrap();
+        informer.output_bundle(&z).unwrap();
+        assert_eq!(informer.depth(), depth(15, 0, 15));
+        assert_eq!(informer.critical_outputs(), [15]);
+        assert_eq!(informer.level_widths(), vec![1; 15]);
+    }
+
+    #[test] // machine-readable report
+    fn informer_report() {
+        let mut c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let report = c.report().unwrap();
+        assert_eq!(report.num_garbler_inputs, 128);
+        assert_eq!(report.num_evaluator_inputs, 128);
+        assert_eq!(report.num_muls, 6800);
+        assert_eq!(report.num_ciphertexts, 13600);
+        assert_eq!(
+            report.ciphertext_moduli.into_iter().collect_vec(),
+            [(2, 13600)]
+        );
+        assert_eq!(report.num_ots, 128);
+        assert_eq!(report.ot_bits, 128 * 384);
+        assert_eq!(report.ciphertext_bits, 13600 * 128);
+        assert_eq!(
+            report.total_bits,
+            (128 + 256 + 1 + 13600) * 128 + report.ot_bits
+        );
+
+        // ciphertexts by output modulus, and OTs per bit of the evaluator inputs
+        let mut informer = Informer::new();
+        let x = informer.encode(0, 5).unwrap();
+        let y = informer.receive(3).unwrap();
+        let z = informer.mul(&y, &x).unwrap();
+        informer.proj(&z, 7, None).unwrap();
+        let report = informer.report();
+        assert_eq!(report.ciphertext_moduli.get(&5), Some(&(5 + 3 - 2 + 1)));
+        assert_eq!(report.ciphertext_moduli.get(&7), Some(&4));
+        assert_eq!(report.num_ots, 2);
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn informer_report_json() {
+        let mut c = Circuit::parse("circuits/adder_32bit.txt").unwrap();
+        let report = c.report().unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(report, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test] // costs per scope
+    fn informer_scopes() {
+        let q = util::modulus_with_nprimes(4);
+        let mut informer = Informer::new();
+        let x = informer.crt_receive(q).unwrap();
+        let y = informer.crt_receive(q).unwrap();
+        let z = informer.crt_mul(&x, &y).unwrap();
+        informer.push_scope("layer");
+        let z = informer.crt_relu(&z, "100%", None).unwrap();
+        let z = informer.crt_relu(&z, "100%", None).unwrap();
+        informer.pop_scope();
+        informer.output_bundle(&z).unwrap();
+
+        let costs = informer.scope_costs();
+        assert_eq!(costs.name, "all");
+        assert_eq!(costs.ciphertexts, informer.num_ciphertexts());
+        assert_eq!(
+            costs.gates,
+            informer.num_adds()
+                + informer.num_subs()
+                + informer.num_cmuls()
+                + informer.num_muls()
+                + informer.num_projs()
+        );
+        let mul = costs.get(&["crt_mul"]).unwrap();
+        let mul_ciphertexts = util::factor(q).iter().map(|p| 2 * *p as usize - 2).sum();
+        assert_eq!((mul.calls, mul.ciphertexts), (1, mul_ciphertexts));
+        let layer = costs.get(&["layer"]).unwrap();
+        let relu = costs.get(&["layer", "crt_relu"]).unwrap();
+        assert_eq!(relu.calls, 2);
+        assert_eq!(relu.ciphertexts, costs.ciphertexts - mul_ciphertexts);
+        assert_eq!(layer.self_ciphertexts(), 0);
+        assert_eq!(costs.self_ciphertexts(), 0);
+        assert!(!relu.children.is_empty());
+
+        let folded = costs.folded();
+        let total: usize = folded
+            .lines()
+            .map(|l| l.rsplit(' ').next().unwrap().parse::<usize>().unwrap())
+            .sum();
+        assert_eq!(total, costs.ciphertexts);
+        assert!(folded.lines().all(|l| l.starts_with("all;")));
+        assert!(folded.contains("all;layer;crt_relu;"));
+
+        // circuits replay the scopes they were built with
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        b.push_scope("layer");
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.pop_scope();
+        b.output_bundle(&z).unwrap();
+        let report = b.finish().report().unwrap();
+        assert_eq!(report.scopes.get(&["layer", "crt_relu"]), Some(relu));
+    }
 }
@@ -25,10 +25,14 @@ pub mod error;
 mod fancy;
 mod garble;
 pub mod informer;
+pub mod ot;
 mod parser;
+mod serialization;
 pub mod r#static;
+pub mod twopac;
 pub mod util;
 mod wire;
+pub mod zk;
 
 pub use crate::error::FancyError;
 pub use crate::fancy::*;
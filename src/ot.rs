@@ -0,0 +1,100 @@
+//! Oblivious transfer interfaces used to deliver the evaluator's input wirelabels.
+//!
+//! `fancy-garbling` does not ship a secure OT protocol. The two-party `Garbler` and
+//! `Evaluator` in `twopac` are instead generic over the `OtSender` and `OtReceiver`
+//! traits, so any OT implementation (base OT, OT extension, ...) can be plugged in.
+
+use crate::error::OtError;
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{AbstractChannel, Block};
+
+/// The sender side of a 1-out-of-2 oblivious transfer of `Block`s.
+pub trait OtSender: Sized {
+    /// Run any setup the protocol requires, such as base OTs.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, OtError>;
+
+    /// Obliviously transfer one block out of each pair in `inputs`.
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[(Block, Block)],
+        rng: &mut RNG,
+    ) -> Result<(), OtError>;
+}
+
+/// The receiver side of a 1-out-of-2 oblivious transfer of `Block`s.
+pub trait OtReceiver: Sized {
+    /// Run any setup the protocol requires, such as base OTs.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, OtError>;
+
+    /// Receive the block selected by each choice bit in `choices`.
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        channel: &mut C,
+        choices: &[bool],
+        rng: &mut RNG,
+    ) -> Result<Vec<Block>, OtError>;
+}
+
+/// An **insecure** OT sender which sends both blocks of every pair in the clear.
+///
+/// Only useful for testing two-party code without a real OT implementation.
+pub struct DummySender;
+
+/// An **insecure** OT receiver matching `DummySender`.
+///
+/// Only useful for testing two-party code without a real OT implementation.
+pub struct DummyReceiver;
+
+impl OtSender for DummySender {
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        _: &mut C,
+        _: &mut RNG,
+    ) -> Result<Self, OtError> {
+        Ok(DummySender)
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[(Block, Block)],
+        _: &mut RNG,
+    ) -> Result<(), OtError> {
+        for (m0, m1) in inputs.iter() {
+            channel.write_block(m0)?;
+            channel.write_block(m1)?;
+        }
+        channel.flush()?;
+        Ok(())
+    }
+}
+
+impl OtReceiver for DummyReceiver {
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        _: &mut C,
+        _: &mut RNG,
+    ) -> Result<Self, OtError> {
+        Ok(DummyReceiver)
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        channel: &mut C,
+        choices: &[bool],
+        _: &mut RNG,
+    ) -> Result<Vec<Block>, OtError> {
+        let mut out = Vec::with_capacity(choices.len());
+        for b in choices.iter() {
+            let m0 = channel.read_block()?;
+            let m1 = channel.read_block()?;
+            out.push(if *b { m1 } else { m0 });
+        }
+        Ok(out)
+    }
+}
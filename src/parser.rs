@@ -5,13 +5,15 @@
 // See LICENSE for licensing information.
 
 //! Functions for parsing and running a circuit file based on the format given
-//! here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+//! here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>, in both the old "Bristol"
+//! format and the newer "Bristol Fashion" format.
 
 use crate::circuit::{Circuit, CircuitRef, Gate};
 use crate::error::CircuitParserError as Error;
+use itertools::Itertools;
 use regex::{Captures, Regex};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::str::FromStr;
 
 enum GateType {
@@ -40,6 +42,73 @@ fn regex2captures<'t>(re: &Regex, line: &'t str) -> Result<Captures<'t>, Error>
         .ok_or_else(|| Error::ParseLineError(line.to_string()))
 }
 
+/// A Bristol Fashion gate line: `nin nout in_1 .. in_nin out_1 .. out_nout OP`.
+struct BristolGate {
+    ins: Vec<usize>,
+    outs: Vec<usize>,
+    op: String,
+}
+
+/// A gate of a `Circuit` as written in Bristol Fashion, referring to the gates
+/// defining its operands.
+enum BristolOp {
+    Input,
+    Eq(u16),
+    Eqw(usize),
+    Inv(usize),
+    Xor(usize, usize),
+    And(usize, usize),
+}
+
+impl BristolOp {
+    fn operands(&self) -> Vec<usize> {
+        match *self {
+            BristolOp::Input | BristolOp::Eq(_) => vec![],
+            BristolOp::Eqw(x) | BristolOp::Inv(x) => vec![x],
+            BristolOp::Xor(x, y) | BristolOp::And(x, y) => vec![x, y],
+        }
+    }
+}
+
+#[inline]
+fn line2ints(line: &str) -> Result<Vec<usize>, Error> {
+    line.split_whitespace()
+        .map(|s| FromStr::from_str(s).map_err(Error::from))
+        .collect()
+}
+
+/// Parse a line of the form `n w_1 .. w_n` giving the widths of the input or
+/// output groups.
+#[inline]
+fn line2widths(line: &str) -> Result<Vec<usize>, Error> {
+    let xs = line2ints(line)?;
+    if xs.is_empty() || xs.len() != xs[0] + 1 {
+        return Err(Error::ParseLineError(line.to_string()));
+    }
+    Ok(xs[1..].to_vec())
+}
+
+#[inline]
+fn line2gate(line: &str) -> Result<BristolGate, Error> {
+    let toks = line.split_whitespace().collect::<Vec<&str>>();
+    let err = || Error::ParseLineError(line.to_string());
+    if toks.len() < 3 {
+        return Err(err());
+    }
+    let nin: usize = FromStr::from_str(toks[0])?;
+    let nout: usize = FromStr::from_str(toks[1])?;
+    if toks.len() != nin + nout + 3 {
+        return Err(err());
+    }
+    let ins = line2ints(&toks[2..2 + nin].join(" "))?;
+    let outs = line2ints(&toks[2 + nin..2 + nin + nout].join(" "))?;
+    Ok(BristolGate {
+        ins,
+        outs,
+        op: toks[2 + nin + nout].to_string(),
+    })
+}
+
 impl Circuit {
     /// Generates a new `Circuit` from file `filename`. The file must follow the
     /// format given here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>,
@@ -170,10 +239,378 @@ impl Circuit {
     }
 }
 
+impl Circuit {
+    /// Generates a new `Circuit` from the Bristol Fashion file `filename`, as
+    /// described here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+    ///
+    /// The first `ngarbler_groups` input groups become garbler inputs and the
+    /// remaining groups become evaluator inputs. The outputs of all output groups
+    /// are concatenated.
+    pub fn parse_bristol_fashion(filename: &str, ngarbler_groups: usize) -> Result<Self, Error> {
+        let f = File::open(filename)?;
+        Self::read_bristol_fashion(BufReader::new(f), ngarbler_groups)
+    }
+
+    /// Reads a Bristol Fashion circuit from `reader`. See `parse_bristol_fashion`.
+    pub fn read_bristol_fashion<R: BufRead>(
+        reader: R,
+        ngarbler_groups: usize,
+    ) -> Result<Self, Error> {
+        let mut lines = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                lines.push(line);
+            }
+        }
+        if lines.len() < 3 {
+            return Err(Error::InvalidCircuit("missing header".to_string()));
+        }
+
+        // Parse header: ngates nwires, then the input and output group widths.
+        let header = line2ints(&lines[0])?;
+        if header.len() != 2 {
+            return Err(Error::ParseLineError(lines[0].clone()));
+        }
+        let ngates = header[0];
+        let nwires = header[1];
+        let input_widths = line2widths(&lines[1])?;
+        let output_widths = line2widths(&lines[2])?;
+        if ngarbler_groups > input_widths.len() {
+            return Err(Error::InvalidCircuit(format!(
+                "{} garbler input groups requested but the circuit only has {}",
+                ngarbler_groups,
+                input_widths.len()
+            )));
+        }
+
+        let gates = lines[3..]
+            .iter()
+            .map(|line| line2gate(line))
+            .collect::<Result<Vec<BristolGate>, Error>>()?;
+        if gates.len() != ngates {
+            return Err(Error::InvalidCircuit(format!(
+                "expected {} gates but found {}",
+                ngates,
+                gates.len()
+            )));
+        }
+
+        // Negations and constants need a constant one wire. When present it takes
+        // the first slot, so every Bristol wire `w` is stored at `w + offset`.
+        let needs_one = gates
+            .iter()
+            .any(|g| g.op == "INV" || g.op == "NOT" || g.op == "EQ");
+        let offset = needs_one as usize;
+        let wire = |w: usize| {
+            if w < nwires {
+                Ok(CircuitRef {
+                    ix: w + offset,
+                    modulus: 2,
+                })
+            } else {
+                Err(Error::InvalidCircuit(format!(
+                    "wire {} out of range for a circuit with {} wires",
+                    w, nwires
+                )))
+            }
+        };
+
+        let mut circ = Self::new(Some(ngates + nwires));
+        let oneref = CircuitRef { ix: 0, modulus: 2 };
+        if needs_one {
+            circ.gates.push(Gate::Constant { val: 1 });
+            circ.const_refs.push(oneref);
+        }
+
+        // Process inputs.
+        let mut w = 0;
+        for (group, width) in input_widths.iter().enumerate() {
+            for _ in 0..*width {
+                if group < ngarbler_groups {
+                    let id = circ.garbler_input_refs.len();
+                    circ.gates.push(Gate::GarblerInput { id });
+                    circ.garbler_input_refs.push(wire(w)?);
+                } else {
+                    let id = circ.evaluator_input_refs.len();
+                    circ.gates.push(Gate::EvaluatorInput { id });
+                    circ.evaluator_input_refs.push(wire(w)?);
+                }
+                w += 1;
+            }
+        }
+
+        // Process outputs.
+        let noutputs = output_widths.iter().sum::<usize>();
+        if noutputs > nwires {
+            return Err(Error::InvalidCircuit(format!(
+                "{} outputs but only {} wires",
+                noutputs, nwires
+            )));
+        }
+        for i in 0..noutputs {
+            circ.output_refs.push(wire(nwires - noutputs + i)?);
+        }
+
+        let mut id = 0;
+        for g in gates.iter() {
+            match (g.op.as_str(), g.ins.len(), g.outs.len()) {
+                ("XOR", 2, 1) => circ.gates.push(Gate::Add {
+                    xref: wire(g.ins[0])?,
+                    yref: wire(g.ins[1])?,
+                    out: Some(wire(g.outs[0])?.ix),
+                }),
+                ("AND", 2, 1) => {
+                    circ.gates.push(Gate::Mul {
+                        xref: wire(g.ins[0])?,
+                        yref: wire(g.ins[1])?,
+                        id,
+                        out: Some(wire(g.outs[0])?.ix),
+                    });
+                    id += 1;
+                }
+                ("INV", 1, 1) | ("NOT", 1, 1) => circ.gates.push(Gate::Sub {
+                    xref: oneref,
+                    yref: wire(g.ins[0])?,
+                    out: Some(wire(g.outs[0])?.ix),
+                }),
+                ("EQW", 1, 1) => circ.gates.push(Gate::Cmul {
+                    xref: wire(g.ins[0])?,
+                    c: 1,
+                    out: Some(wire(g.outs[0])?.ix),
+                }),
+                // The input of an EQ gate is a constant rather than a wire.
+                ("EQ", 1, 1) if g.ins[0] < 2 => circ.gates.push(Gate::Cmul {
+                    xref: oneref,
+                    c: g.ins[0] as u16,
+                    out: Some(wire(g.outs[0])?.ix),
+                }),
+                ("MAND", nin, nout) if nin == 2 * nout => {
+                    for k in 0..nout {
+                        circ.gates.push(Gate::Mul {
+                            xref: wire(g.ins[k])?,
+                            yref: wire(g.ins[nout + k])?,
+                            id,
+                            out: Some(wire(g.outs[k])?.ix),
+                        });
+                        id += 1;
+                    }
+                }
+                _ => {
+                    return Err(Error::ParseGateError(format!(
+                        "{} {} {} {}",
+                        g.ins.len(),
+                        g.outs.len(),
+                        g.ins.iter().chain(g.outs.iter()).join(" "),
+                        g.op
+                    )));
+                }
+            }
+        }
+        if nwires + offset > circ.gates.len() {
+            return Err(Error::InvalidCircuit(format!(
+                "{} wires declared but only {} are assigned",
+                nwires,
+                circ.gates.len() - offset
+            )));
+        }
+        circ.gate_moduli = vec![2; circ.gates.len()];
+        circ.num_nonfree_gates = id;
+        Ok(circ)
+    }
+
+    /// Writes this circuit to `writer` in Bristol Fashion format.
+    ///
+    /// Only circuits where every wire is mod 2 can be written. Garbler inputs form
+    /// the first input group and evaluator inputs the second, with empty groups
+    /// omitted, and all outputs form a single output group. Gates with constant
+    /// operands are simplified, and outputs which are also used elsewhere in the
+    /// circuit are copied onto the final wires with `EQW`.
+    pub fn write_bristol_fashion<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        if let Some(q) = self.gate_moduli.iter().find(|q| **q != 2) {
+            return Err(Error::InvalidCircuit(format!(
+                "Bristol Fashion only supports mod 2 wires, found mod {}",
+                q
+            )));
+        }
+
+        // Reduce every gate to a Bristol Fashion gate over the indices of the gates
+        // defining its operands, since parsed circuits store values in arbitrary
+        // slots.
+        let mut defs: Vec<Option<usize>> = vec![None; self.gates.len()];
+        let mut bgates = Vec::with_capacity(self.gates.len());
+        let resolve = |defs: &[Option<usize>], r: &CircuitRef| {
+            defs.get(r.ix)
+                .cloned()
+                .unwrap_or(None)
+                .ok_or_else(|| Error::InvalidCircuit(format!("uninitialized wire {}", r.ix)))
+        };
+        for (i, gate) in self.gates.iter().enumerate() {
+            let constant = |x: usize, bgates: &[BristolOp]| match bgates[x] {
+                BristolOp::Eq(v) => Some(v),
+                _ => None,
+            };
+            let (bgate, out) = match gate {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } => (BristolOp::Input, None),
+                Gate::Constant { val } => (BristolOp::Eq(val % 2), None),
+                Gate::Add { xref, yref, out } | Gate::Sub { xref, yref, out } => {
+                    let x = resolve(&defs, xref)?;
+                    let y = resolve(&defs, yref)?;
+                    let op = match (constant(x, &bgates), constant(y, &bgates)) {
+                        (Some(a), Some(b)) => BristolOp::Eq((a + b) % 2),
+                        (Some(0), None) => BristolOp::Eqw(y),
+                        (Some(_), None) => BristolOp::Inv(y),
+                        (None, Some(0)) => BristolOp::Eqw(x),
+                        (None, Some(_)) => BristolOp::Inv(x),
+                        (None, None) => BristolOp::Xor(x, y),
+                    };
+                    (op, *out)
+                }
+                Gate::Mul {
+                    xref, yref, out, ..
+                } => {
+                    let x = resolve(&defs, xref)?;
+                    let y = resolve(&defs, yref)?;
+                    let op = match (constant(x, &bgates), constant(y, &bgates)) {
+                        (Some(0), _) | (_, Some(0)) => BristolOp::Eq(0),
+                        (Some(_), Some(_)) => BristolOp::Eq(1),
+                        (Some(_), None) => BristolOp::Eqw(y),
+                        (None, Some(_)) => BristolOp::Eqw(x),
+                        (None, None) => BristolOp::And(x, y),
+                    };
+                    (op, *out)
+                }
+                Gate::Cmul { xref, c, out } => {
+                    let x = resolve(&defs, xref)?;
+                    let op = match constant(x, &bgates) {
+                        _ if c % 2 == 0 => BristolOp::Eq(0),
+                        Some(v) => BristolOp::Eq(v),
+                        None => BristolOp::Eqw(x),
+                    };
+                    (op, *out)
+                }
+                Gate::Proj { xref, tt, out, .. } => {
+                    let x = resolve(&defs, xref)?;
+                    if tt.len() != 2 {
+                        return Err(Error::InvalidCircuit(format!(
+                            "invalid truth table {:?}",
+                            tt
+                        )));
+                    }
+                    let op = match (constant(x, &bgates), tt[0], tt[1]) {
+                        (Some(v), _, _) => BristolOp::Eq(tt[v as usize] % 2),
+                        (None, a, b) if a % 2 == b % 2 => BristolOp::Eq(a % 2),
+                        (None, 0, _) => BristolOp::Eqw(x),
+                        (None, _, _) => BristolOp::Inv(x),
+                    };
+                    (op, *out)
+                }
+            };
+            bgates.push(bgate);
+            defs[out.unwrap_or(i)] = Some(i);
+        }
+        let outputs = self
+            .output_refs
+            .iter()
+            .map(|r| resolve(&defs, r))
+            .collect::<Result<Vec<usize>, Error>>()?;
+
+        let mut nuses = vec![0; self.gates.len()];
+        for bgate in bgates.iter() {
+            for x in bgate.operands() {
+                nuses[x] += 1;
+            }
+        }
+        let mut noutput_uses = vec![0; self.gates.len()];
+        for x in outputs.iter() {
+            noutput_uses[*x] += 1;
+        }
+
+        // Output gates used nowhere else are written last, directly onto the
+        // output wires, and unused constants are not written at all.
+        let deferred = |i: usize| match bgates[i] {
+            BristolOp::Input => false,
+            _ => nuses[i] == 0 && noutput_uses[i] == 1,
+        };
+        let skipped = |i: usize| match bgates[i] {
+            BristolOp::Input => true,
+            BristolOp::Eq(_) => nuses[i] == 0,
+            _ => deferred(i),
+        };
+
+        let ngb = self.garbler_input_refs.len();
+        let mut wires: Vec<Option<usize>> = vec![None; self.gates.len()];
+        for (i, gate) in self.gates.iter().enumerate() {
+            match gate {
+                Gate::GarblerInput { id } => wires[i] = Some(*id),
+                Gate::EvaluatorInput { id } => wires[i] = Some(ngb + *id),
+                _ => (),
+            }
+        }
+        let mut nwires = ngb + self.evaluator_input_refs.len();
+        let mut lines = Vec::new();
+        let wire = |wires: &[Option<usize>], x: usize| {
+            wires[x].ok_or_else(|| Error::InvalidCircuit(format!("gate {} is unwritten", x)))
+        };
+        let gate_line = |i: usize, wires: &[Option<usize>], z: usize| -> Result<String, Error> {
+            let line = match bgates[i] {
+                BristolOp::Eq(v) => format!("1 1 {} {} EQ", v, z),
+                BristolOp::Eqw(x) => format!("1 1 {} {} EQW", wire(wires, x)?, z),
+                BristolOp::Inv(x) => format!("1 1 {} {} INV", wire(wires, x)?, z),
+                BristolOp::Xor(x, y) => {
+                    format!("2 1 {} {} {} XOR", wire(wires, x)?, wire(wires, y)?, z)
+                }
+                BristolOp::And(x, y) => {
+                    format!("2 1 {} {} {} AND", wire(wires, x)?, wire(wires, y)?, z)
+                }
+                BristolOp::Input => unreachable!(),
+            };
+            Ok(line)
+        };
+
+        for i in 0..self.gates.len() {
+            if !skipped(i) {
+                lines.push(gate_line(i, &wires, nwires)?);
+                wires[i] = Some(nwires);
+                nwires += 1;
+            }
+        }
+        for x in outputs.iter() {
+            if deferred(*x) {
+                lines.push(gate_line(*x, &wires, nwires)?);
+            } else {
+                lines.push(format!("1 1 {} {} EQW", wire(&wires, *x)?, nwires));
+            }
+            nwires += 1;
+        }
+
+        let input_widths = [ngb, self.evaluator_input_refs.len()]
+            .iter()
+            .filter(|n| **n > 0)
+            .map(|n| n.to_string())
+            .collect::<Vec<String>>();
+        writeln!(writer, "{} {}", lines.len(), nwires)?;
+        writeln!(writer, "{} {}", input_widths.len(), input_widths.join(" "))?;
+        if outputs.is_empty() {
+            writeln!(writer, "0")?;
+        } else {
+            writeln!(writer, "1 {}", outputs.len())?;
+        }
+        writeln!(writer)?;
+        for line in lines.iter() {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::circuit::Circuit;
+    use crate::circuit::{Circuit, CircuitBuilder};
     use crate::garble;
+    use crate::util::RngExt;
+    use crate::{BinaryGadgets, BundleGadgets, Fancy};
+    use rand::thread_rng;
 
     #[test]
     fn test_parser() {
@@ -212,4 +649,115 @@ mod tests {
         let ev = en.encode_evaluator_inputs(&vec![0u16; 128]);
         gc.eval(&mut circ, &gb, &ev).unwrap();
     }
+
+    const BRISTOL_FASHION: &str = "10 16
+3 2 2 1
+2 3 2
+
+4 2 0 1 2 3 5 6 MAND
+2 1 5 6 7 XOR
+1 1 4 8 INV
+1 1 1 9 EQ
+1 1 7 10 NOT
+2 1 7 8 11 AND
+1 1 9 12 EQW
+1 1 0 13 EQ
+2 1 10 4 14 XOR
+1 1 6 15 EQW
+";
+
+    #[test]
+    fn test_bristol_fashion_gates() {
+        let mut circ = Circuit::read_bristol_fashion(BRISTOL_FASHION.as_bytes(), 1).unwrap();
+        assert_eq!(circ.num_garbler_inputs(), 2);
+        assert_eq!(circ.num_evaluator_inputs(), 3);
+        assert_eq!(circ.noutputs(), 5);
+        for x in 0..32 {
+            let bits = crate::util::u128_to_bits(x, 5);
+            let (a, b, c) = (&bits[0..2], &bits[2..4], bits[4]);
+            let w7 = (a[0] & b[0]) ^ (a[1] & b[1]);
+            let should_be = vec![w7 & (1 - c), 1, 0, (1 - w7) ^ c, a[1] & b[1]];
+            let output = circ.eval_plain(a, &bits[2..5]).unwrap();
+            assert_eq!(output, should_be);
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_errors() {
+        let bad_groups = Circuit::read_bristol_fashion(BRISTOL_FASHION.as_bytes(), 4);
+        assert!(bad_groups.is_err());
+        let bad_gate = BRISTOL_FASHION.replace("EQW", "OR");
+        assert!(Circuit::read_bristol_fashion(bad_gate.as_bytes(), 1).is_err());
+        let bad_wire = BRISTOL_FASHION.replace("2 1 5 6 7 XOR", "2 1 5 16 7 XOR");
+        assert!(Circuit::read_bristol_fashion(bad_wire.as_bytes(), 1).is_err());
+    }
+
+    #[test]
+    fn test_bristol_fashion_roundtrip() {
+        let mut rng = thread_rng();
+        let mut circ = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let mut buf = Vec::new();
+        circ.write_bristol_fashion(&mut buf).unwrap();
+        let mut circ_ = Circuit::read_bristol_fashion(&buf[..], 1).unwrap();
+
+        // Writing the parsed circuit again produces the same file.
+        let mut buf_ = Vec::new();
+        circ_.write_bristol_fashion(&mut buf_).unwrap();
+        assert_eq!(buf, buf_);
+
+        let (en, gc) = garble(&mut circ_).unwrap();
+        for _ in 0..4 {
+            let pt = (0..128)
+                .map(|_| rng.gen_bool() as u16)
+                .collect::<Vec<u16>>();
+            let key = (0..128)
+                .map(|_| rng.gen_bool() as u16)
+                .collect::<Vec<u16>>();
+            let should_be = circ.eval_plain(&pt, &key).unwrap();
+            assert_eq!(circ_.eval_plain(&pt, &key).unwrap(), should_be);
+            let gb = en.encode_garbler_inputs(&pt);
+            let ev = en.encode_evaluator_inputs(&key);
+            assert_eq!(gc.eval(&mut circ_, &gb, &ev).unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_builder_roundtrip() {
+        let mut rng = thread_rng();
+        let nbits = 16;
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_garbler_input(nbits);
+        let y = b.bin_evaluator_input(nbits);
+        let one = b.constant(1, 2).unwrap();
+        let z = b.bin_addition_no_carry(&x, &y).unwrap();
+        let lt = b.bin_lt(&x, &y).unwrap();
+        let lt_ = b.negate(&lt).unwrap();
+        let c = b.and(&lt_, &one).unwrap();
+        b.output_bundle(&z).unwrap();
+        b.output(&lt).unwrap();
+        b.output(&c).unwrap();
+        b.output(&x.wires()[0]).unwrap();
+        let mut circ = b.finish();
+
+        let mut buf = Vec::new();
+        circ.write_bristol_fashion(&mut buf).unwrap();
+        let mut circ_ = Circuit::read_bristol_fashion(&buf[..], 1).unwrap();
+        for _ in 0..16 {
+            let x = crate::util::u128_to_bits(rng.gen_u128() % (1 << nbits), nbits);
+            let y = crate::util::u128_to_bits(rng.gen_u128() % (1 << nbits), nbits);
+            assert_eq!(
+                circ_.eval_plain(&x, &y).unwrap(),
+                circ.eval_plain(&x, &y).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_rejects_arithmetic() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        b.output(&x).unwrap();
+        let circ = b.finish();
+        assert!(circ.write_bristol_fashion(&mut Vec::new()).is_err());
+    }
 }
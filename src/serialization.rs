@@ -0,0 +1,1030 @@
+//! Compact, versioned binary serialization of `Circuit`, `GarbledCircuit` and
+//! `Encoder`, so circuits can be garbled offline and evaluated later.
+//!
+//! Every object starts with the magic bytes `FNCY`, a format version, and a byte
+//! identifying the kind of object. Integers are written as LEB128 varints and
+//! blocks as 16 little-endian bytes.
+//!
+//! Objects are always written in the latest version, and objects written by any
+//! earlier version can still be read: version 2 added scopes to circuits, and
+//! versions 3 and 4 added the multiplication and projection schemes to garbled
+//! circuits. The format of encoders has not changed since version 1.
+//!
+//! With the `serde1` feature enabled, these types also implement serde's
+//! `Serialize` and `Deserialize`.
+
+use crate::circuit::{Circuit, CircuitRef, Conditional, Gate, Scope};
+use crate::error::SerializationError as Error;
+use crate::fancy::HasModulus;
+use crate::garble::{MulScheme, ProjScheme};
+use crate::r#static::{Encoder, GarbledCircuit};
+use crate::wire::Wire;
+use scuttlebutt::Block;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"FNCY";
+const VERSION: u8 = 5;
+const VERSION_SCOPES: u8 = 2;
+const VERSION_MUL_SCHEME: u8 = 3;
+const VERSION_PROJ_SCHEME: u8 = 4;
+const VERSION_CONDITIONALS: u8 = 5;
+
+const KIND_CIRCUIT: u8 = 0;
+const KIND_GARBLED_CIRCUIT: u8 = 1;
+const KIND_ENCODER: u8 = 2;
+
+const GATE_GARBLER_INPUT: u8 = 0;
+const GATE_EVALUATOR_INPUT: u8 = 1;
+const GATE_CONSTANT: u8 = 2;
+const GATE_ADD: u8 = 3;
+const GATE_SUB: u8 = 4;
+const GATE_CMUL: u8 = 5;
+const GATE_MUL: u8 = 6;
+const GATE_PROJ: u8 = 7;
+
+const SCHEME_HALF_GATES: u8 = 0;
+const SCHEME_THREE_HALVES: u8 = 1;
+
+const SCHEME_STANDARD_PROJ: u8 = 0;
+const SCHEME_ROW_REDUCED_PROJ: u8 = 1;
+
+////////////////////////////////////////////////////////////////////////////////
+// primitives
+
+fn write_header<W: Write>(writer: &mut W, kind: u8) -> Result<(), Error> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, kind])?;
+    Ok(())
+}
+
+/// Read the header of an object of the given kind, returning its version.
+fn read_header<R: Read>(reader: &mut R, kind: u8) -> Result<u8, Error> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::InvalidHeader);
+    }
+    let version = read_u8(reader)?;
+    if version == 0 || version > VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    if read_u8(reader)? != kind {
+        return Err(Error::InvalidHeader);
+    }
+    Ok(version)
+}
+
+#[inline]
+fn write_u8<W: Write>(writer: &mut W, x: u8) -> Result<(), Error> {
+    writer.write_all(&[x])?;
+    Ok(())
+}
+
+#[inline]
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut buf = [0];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut x: u64) -> Result<(), Error> {
+    while x >= 0x80 {
+        write_u8(writer, (x as u8) | 0x80)?;
+        x >>= 7;
+    }
+    write_u8(writer, x as u8)
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut x = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(reader)?;
+        x |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(x);
+        }
+    }
+    Err(Error::InvalidData("varint too long".to_string()))
+}
+
+#[inline]
+fn write_usize<W: Write>(writer: &mut W, x: usize) -> Result<(), Error> {
+    write_varint(writer, x as u64)
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    let x = read_varint(reader)?;
+    if x > usize::MAX as u64 {
+        return Err(Error::InvalidData(format!("integer {} too large", x)));
+    }
+    Ok(x as usize)
+}
+
+#[inline]
+fn write_u16<W: Write>(writer: &mut W, x: u16) -> Result<(), Error> {
+    write_varint(writer, u64::from(x))
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, Error> {
+    let x = read_varint(reader)?;
+    if x > u64::from(u16::MAX) {
+        return Err(Error::InvalidData(format!("integer {} too large", x)));
+    }
+    Ok(x as u16)
+}
+
+/// Read a length, refusing lengths longer than the data could possibly contain.
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    let n = read_usize(reader)?;
+    if n > 1 << 40 {
+        return Err(Error::InvalidData(format!("length {} too large", n)));
+    }
+    Ok(n)
+}
+
+fn read_modulus<R: Read>(reader: &mut R) -> Result<u16, Error> {
+    let q = read_u16(reader)?;
+    if q < 2 {
+        return Err(Error::InvalidData(format!("invalid modulus {}", q)));
+    }
+    Ok(q)
+}
+
+#[inline]
+fn write_block<W: Write>(writer: &mut W, block: &Block) -> Result<(), Error> {
+    let bytes: [u8; 16] = (*block).into();
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+#[inline]
+fn read_block<R: Read>(reader: &mut R) -> Result<Block, Error> {
+    let mut bytes = [0; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(Block::from(bytes))
+}
+
+fn write_wire<W: Write>(writer: &mut W, wire: &Wire) -> Result<(), Error> {
+    write_u16(writer, wire.modulus())?;
+    write_block(writer, &wire.as_block())
+}
+
+fn read_wire<R: Read>(reader: &mut R) -> Result<Wire, Error> {
+    let q = read_modulus(reader)?;
+    let block = read_block(reader)?;
+    Ok(Wire::from_block(block, q))
+}
+
+fn write_wires<W: Write>(writer: &mut W, wires: &[Wire]) -> Result<(), Error> {
+    write_usize(writer, wires.len())?;
+    for wire in wires.iter() {
+        write_wire(writer, wire)?;
+    }
+    Ok(())
+}
+
+fn read_wires<R: Read>(reader: &mut R) -> Result<Vec<Wire>, Error> {
+    let n = read_len(reader)?;
+    (0..n).map(|_| read_wire(reader)).collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// circuits
+
+fn write_ref<W: Write>(writer: &mut W, r: &CircuitRef) -> Result<(), Error> {
+    write_usize(writer, r.ix)?;
+    write_u16(writer, r.modulus)
+}
+
+fn read_ref<R: Read>(reader: &mut R) -> Result<CircuitRef, Error> {
+    let ix = read_usize(reader)?;
+    let modulus = read_modulus(reader)?;
+    Ok(CircuitRef { ix, modulus })
+}
+
+fn write_refs<W: Write>(writer: &mut W, refs: &[CircuitRef]) -> Result<(), Error> {
+    write_usize(writer, refs.len())?;
+    for r in refs.iter() {
+        write_ref(writer, r)?;
+    }
+    Ok(())
+}
+
+fn read_refs<R: Read>(reader: &mut R) -> Result<Vec<CircuitRef>, Error> {
+    let n = read_len(reader)?;
+    (0..n).map(|_| read_ref(reader)).collect()
+}
+
+/// Output slots are written shifted by one, with zero meaning `None`.
+fn write_out<W: Write>(writer: &mut W, out: Option<usize>) -> Result<(), Error> {
+    write_usize(writer, out.map_or(0, |ix| ix + 1))
+}
+
+fn read_out<R: Read>(reader: &mut R) -> Result<Option<usize>, Error> {
+    let out = read_usize(reader)?;
+    Ok(out.checked_sub(1))
+}
+
+fn write_string<W: Write>(writer: &mut W, x: &str) -> Result<(), Error> {
+    write_usize(writer, x.len())?;
+    writer.write_all(x.as_bytes())?;
+    Ok(())
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let n = read_len(reader)?;
+    let mut bytes = Vec::new();
+    reader.by_ref().take(n as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != n {
+        return Err(Error::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    String::from_utf8(bytes).map_err(|e| Error::InvalidData(e.to_string()))
+}
+
+fn write_gate<W: Write>(writer: &mut W, gate: &Gate) -> Result<(), Error> {
+    match gate {
+        Gate::GarblerInput { id } => {
+            write_u8(writer, GATE_GARBLER_INPUT)?;
+            write_usize(writer, *id)
+        }
+        Gate::EvaluatorInput { id } => {
+            write_u8(writer, GATE_EVALUATOR_INPUT)?;
+            write_usize(writer, *id)
+        }
+        Gate::Constant { val } => {
+            write_u8(writer, GATE_CONSTANT)?;
+            write_u16(writer, *val)
+        }
+        Gate::Add { xref, yref, out } => {
+            write_u8(writer, GATE_ADD)?;
+            write_ref(writer, xref)?;
+            write_ref(writer, yref)?;
+            write_out(writer, *out)
+        }
+        Gate::Sub { xref, yref, out } => {
+            write_u8(writer, GATE_SUB)?;
+            write_ref(writer, xref)?;
+            write_ref(writer, yref)?;
+            write_out(writer, *out)
+        }
+        Gate::Cmul { xref, c, out } => {
+            write_u8(writer, GATE_CMUL)?;
+            write_ref(writer, xref)?;
+            write_u16(writer, *c)?;
+            write_out(writer, *out)
+        }
+        Gate::Mul {
+            xref,
+            yref,
+            id,
+            out,
+        } => {
+            write_u8(writer, GATE_MUL)?;
+            write_ref(writer, xref)?;
+            write_ref(writer, yref)?;
+            write_usize(writer, *id)?;
+            write_out(writer, *out)
+        }
+        Gate::Proj { xref, tt, id, out } => {
+            write_u8(writer, GATE_PROJ)?;
+            write_ref(writer, xref)?;
+            write_usize(writer, tt.len())?;
+            for x in tt.iter() {
+                write_u16(writer, *x)?;
+            }
+            write_usize(writer, *id)?;
+            write_out(writer, *out)
+        }
+    }
+}
+
+fn read_gate<R: Read>(reader: &mut R) -> Result<Gate, Error> {
+    let gate = match read_u8(reader)? {
+        GATE_GARBLER_INPUT => Gate::GarblerInput {
+            id: read_usize(reader)?,
+        },
+        GATE_EVALUATOR_INPUT => Gate::EvaluatorInput {
+            id: read_usize(reader)?,
+        },
+        GATE_CONSTANT => Gate::Constant {
+            val: read_u16(reader)?,
+        },
+        GATE_ADD => Gate::Add {
+            xref: read_ref(reader)?,
+            yref: read_ref(reader)?,
+            out: read_out(reader)?,
+        },
+        GATE_SUB => Gate::Sub {
+            xref: read_ref(reader)?,
+            yref: read_ref(reader)?,
+            out: read_out(reader)?,
+        },
+        GATE_CMUL => Gate::Cmul {
+            xref: read_ref(reader)?,
+            c: read_u16(reader)?,
+            out: read_out(reader)?,
+        },
+        GATE_MUL => Gate::Mul {
+            xref: read_ref(reader)?,
+            yref: read_ref(reader)?,
+            id: read_usize(reader)?,
+            out: read_out(reader)?,
+        },
+        GATE_PROJ => {
+            let xref = read_ref(reader)?;
+            let n = read_len(reader)?;
+            let tt = (0..n)
+                .map(|_| read_u16(reader))
+                .collect::<Result<Vec<u16>, Error>>()?;
+            Gate::Proj {
+                xref,
+                tt,
+                id: read_usize(reader)?,
+                out: read_out(reader)?,
+            }
+        }
+        tag => return Err(Error::InvalidData(format!("unknown gate type {}", tag))),
+    };
+    Ok(gate)
+}
+
+/// Check that a deserialized circuit is well formed, so that evaluating or garbling
+/// it returns an error rather than panicking: every reference and output slot is in
+/// range, every reference has the modulus of the value it refers to, every input
+/// gate has a corresponding input reference, and every projection has a truth table
+/// with an entry for each value of its input and entries less than its modulus.
+fn validate_circuit(c: &Circuit) -> Result<(), Error> {
+    let n = c.gates.len();
+    if c.gate_moduli.len() != n {
+        return Err(Error::InvalidData(format!(
+            "{} gate moduli for {} gates",
+            c.gate_moduli.len(),
+            n
+        )));
+    }
+    let check_ref = |r: &CircuitRef| {
+        if r.ix < n {
+            Ok(())
+        } else {
+            Err(Error::InvalidData(format!("reference {} out of range", r)))
+        }
+    };
+    let check_modulus = |r: &CircuitRef, q: Option<u16>| match q {
+        Some(q) if q != r.modulus() => Err(Error::InvalidData(format!(
+            "reference {} to a value of modulus {}",
+            r, q
+        ))),
+        _ => Ok(()),
+    };
+    // The modulus of the value in each slot as the gates are evaluated in order,
+    // since gates may write their output to the slot of another gate.
+    let mut slots: Vec<Option<u16>> = vec![None; n];
+    for (i, gate) in c.gates.iter().enumerate() {
+        let q = c.gate_moduli[i];
+        let (refs, out) = match gate {
+            Gate::GarblerInput { id } if *id >= c.garbler_input_refs.len() => {
+                return Err(Error::InvalidData(format!(
+                    "garbler input {} out of range",
+                    id
+                )));
+            }
+            Gate::EvaluatorInput { id } if *id >= c.evaluator_input_refs.len() => {
+                return Err(Error::InvalidData(format!(
+                    "evaluator input {} out of range",
+                    id
+                )));
+            }
+            Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } | Gate::Constant { .. } => {
+                (vec![], None)
+            }
+            Gate::Add { xref, yref, out }
+            | Gate::Sub { xref, yref, out }
+            | Gate::Mul {
+                xref, yref, out, ..
+            } => (vec![xref, yref], *out),
+            Gate::Cmul { xref, out, .. } | Gate::Proj { xref, out, .. } => (vec![xref], *out),
+        };
+        for r in refs.iter() {
+            check_ref(r)?;
+            check_modulus(r, slots[r.ix])?;
+        }
+        // the modulus of the output, which is that of the larger operand of a `Mul`
+        let expected = match gate {
+            Gate::Add { xref, .. } | Gate::Sub { xref, .. } | Gate::Cmul { xref, .. } => {
+                Some(xref.modulus())
+            }
+            Gate::Mul { xref, yref, .. } => Some(xref.modulus().max(yref.modulus())),
+            Gate::Proj { xref, tt, .. } => {
+                if tt.len() != xref.modulus() as usize {
+                    return Err(Error::InvalidData(format!(
+                        "projection of {} has a truth table of length {}",
+                        xref,
+                        tt.len()
+                    )));
+                }
+                if let Some(x) = tt.iter().find(|x| **x >= q) {
+                    return Err(Error::InvalidData(format!(
+                        "projection to modulus {} has truth table entry {}",
+                        q, x
+                    )));
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some(expected) = expected.filter(|expected| *expected != q) {
+            return Err(Error::InvalidData(format!(
+                "gate {} has modulus {} instead of {}",
+                i, q, expected
+            )));
+        }
+        match out {
+            Some(ix) if ix >= n => {
+                return Err(Error::InvalidData(format!(
+                    "output slot {} out of range",
+                    ix
+                )));
+            }
+            _ => (),
+        }
+        slots[out.unwrap_or(i)] = Some(q);
+    }
+    for r in c
+        .garbler_input_refs
+        .iter()
+        .chain(c.evaluator_input_refs.iter())
+        .chain(c.const_refs.iter())
+    {
+        check_ref(r)?;
+        check_modulus(r, Some(c.gate_moduli[r.ix]))?;
+    }
+    for r in c.output_refs.iter() {
+        check_ref(r)?;
+        check_modulus(r, slots[r.ix])?;
+    }
+    c.check_scopes().map_err(Error::InvalidData)?;
+    c.check_conditionals().map_err(Error::InvalidData)
+}
+
+/// Read a circuit in the binary format from `reader`. Only the conditionals of the
+/// circuit itself are stacked, so the branches of conditionals have none, which keeps
+/// the recursion to one level.
+fn read_circuit<R: Read>(reader: &mut R, conditionals: bool) -> Result<Circuit, Error> {
+    let version = read_header(reader, KIND_CIRCUIT)?;
+    let n = read_len(reader)?;
+    let mut c = Circuit::new(None);
+    for _ in 0..n {
+        c.gate_moduli.push(read_modulus(reader)?);
+        c.gates.push(read_gate(reader)?);
+    }
+    c.garbler_input_refs = read_refs(reader)?;
+    c.evaluator_input_refs = read_refs(reader)?;
+    c.const_refs = read_refs(reader)?;
+    c.output_refs = read_refs(reader)?;
+    c.num_nonfree_gates = read_usize(reader)?;
+    if version >= VERSION_SCOPES {
+        let n = read_len(reader)?;
+        for _ in 0..n {
+            c.scopes.push(Scope {
+                name: read_string(reader)?,
+                parent: read_out(reader)?,
+            });
+        }
+        let n = read_len(reader)?;
+        for _ in 0..n {
+            c.gate_scopes.push(read_out(reader)?);
+        }
+    }
+    if version >= VERSION_CONDITIONALS {
+        let n = read_len(reader)?;
+        if n > 0 && !conditionals {
+            return Err(Error::InvalidData(
+                "conditional in the branch of a conditional".to_string(),
+            ));
+        }
+        for _ in 0..n {
+            let cond = read_ref(reader)?;
+            let inputs = read_refs(reader)?;
+            let start = read_usize(reader)?;
+            let end = read_usize(reader)?;
+            let outputs = read_refs(reader)?;
+            let nbranches = read_len(reader)?;
+            let branches = (0..nbranches)
+                .map(|_| read_circuit(reader, false))
+                .collect::<Result<Vec<Circuit>, Error>>()?;
+            c.conditionals.push(Conditional {
+                cond,
+                inputs,
+                branches,
+                start,
+                end,
+                outputs,
+            });
+        }
+    }
+    validate_circuit(&c)?;
+    Ok(c)
+}
+
+impl Circuit {
+    /// Write the circuit to `writer` in the binary format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        write_header(writer, KIND_CIRCUIT)?;
+        write_usize(writer, self.gates.len())?;
+        for (gate, q) in self.gates.iter().zip(self.gate_moduli.iter()) {
+            write_u16(writer, *q)?;
+            write_gate(writer, gate)?;
+        }
+        write_refs(writer, &self.garbler_input_refs)?;
+        write_refs(writer, &self.evaluator_input_refs)?;
+        write_refs(writer, &self.const_refs)?;
+        write_refs(writer, &self.output_refs)?;
+        write_usize(writer, self.num_nonfree_gates)?;
+        write_usize(writer, self.scopes.len())?;
+        for scope in self.scopes.iter() {
+            write_string(writer, &scope.name)?;
+            write_out(writer, scope.parent)?;
+        }
+        write_usize(writer, self.gate_scopes.len())?;
+        for s in self.gate_scopes.iter() {
+            write_out(writer, *s)?;
+        }
+        write_usize(writer, self.conditionals.len())?;
+        for c in self.conditionals.iter() {
+            write_ref(writer, &c.cond)?;
+            write_refs(writer, &c.inputs)?;
+            write_usize(writer, c.start)?;
+            write_usize(writer, c.end)?;
+            write_refs(writer, &c.outputs)?;
+            write_usize(writer, c.branches.len())?;
+            for b in c.branches.iter() {
+                b.write_to(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a circuit in the binary format from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Circuit, Error> {
+        read_circuit(reader, true)
+    }
+
+    /// Serialize the circuit into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserialize a circuit from bytes.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Circuit, Error> {
+        Circuit::read_from(&mut bytes)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// garbled circuits
+
+impl GarbledCircuit {
+    /// Write the garbled circuit to `writer` in the binary format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        write_header(writer, KIND_GARBLED_CIRCUIT)?;
+        write_u8(
+            writer,
+            match self.mul_scheme {
+                MulScheme::HalfGates => SCHEME_HALF_GATES,
+                MulScheme::ThreeHalves => SCHEME_THREE_HALVES,
+            },
+        )?;
+        write_u8(
+            writer,
+            match self.proj_scheme {
+                ProjScheme::Standard => SCHEME_STANDARD_PROJ,
+                ProjScheme::RowReduced => SCHEME_ROW_REDUCED_PROJ,
+            },
+        )?;
+        write_usize(writer, self.blocks.len())?;
+        for block in self.blocks.iter() {
+            write_block(writer, block)?;
+        }
+        Ok(())
+    }
+
+    /// Read a garbled circuit in the binary format from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<GarbledCircuit, Error> {
+        let version = read_header(reader, KIND_GARBLED_CIRCUIT)?;
+        let mul_scheme = if version < VERSION_MUL_SCHEME {
+            MulScheme::HalfGates
+        } else {
+            match read_u8(reader)? {
+                SCHEME_HALF_GATES => MulScheme::HalfGates,
+                SCHEME_THREE_HALVES => MulScheme::ThreeHalves,
+                x => return Err(Error::InvalidData(format!("unknown scheme {}", x))),
+            }
+        };
+        let proj_scheme = if version < VERSION_PROJ_SCHEME {
+            ProjScheme::Standard
+        } else {
+            match read_u8(reader)? {
+                SCHEME_STANDARD_PROJ => ProjScheme::Standard,
+                SCHEME_ROW_REDUCED_PROJ => ProjScheme::RowReduced,
+                x => return Err(Error::InvalidData(format!("unknown scheme {}", x))),
+            }
+        };
+        let n = read_len(reader)?;
+        let blocks = (0..n)
+            .map(|_| read_block(reader))
+            .collect::<Result<Vec<Block>, Error>>()?;
+        Ok(GarbledCircuit::with_schemes(
+            blocks,
+            mul_scheme,
+            proj_scheme,
+        ))
+    }
+
+    /// Serialize the garbled circuit into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 * self.blocks.len() + 16);
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserialize a garbled circuit from bytes.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<GarbledCircuit, Error> {
+        GarbledCircuit::read_from(&mut bytes)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// encoders
+
+impl Encoder {
+    /// Write the encoder to `writer` in the binary format.
+    ///
+    /// The encoder contains the zero wirelabels of every input and the deltas, so
+    /// it must be kept secret from the evaluator.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        write_header(writer, KIND_ENCODER)?;
+        write_wires(writer, &self.garbler_inputs)?;
+        write_wires(writer, &self.evaluator_inputs)?;
+        // Write the deltas in order of modulus so the output is deterministic.
+        let mut moduli = self.deltas.keys().cloned().collect::<Vec<u16>>();
+        moduli.sort();
+        let deltas = moduli
+            .iter()
+            .map(|q| self.deltas[q].clone())
+            .collect::<Vec<Wire>>();
+        write_wires(writer, &deltas)
+    }
+
+    /// Read an encoder in the binary format from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Encoder, Error> {
+        read_header(reader, KIND_ENCODER)?;
+        let garbler_inputs = read_wires(reader)?;
+        let evaluator_inputs = read_wires(reader)?;
+        let deltas = read_wires(reader)?
+            .into_iter()
+            .map(|w| (w.modulus(), w))
+            .collect::<HashMap<u16, Wire>>();
+        for w in garbler_inputs.iter().chain(evaluator_inputs.iter()) {
+            if !deltas.contains_key(&w.modulus()) {
+                return Err(Error::InvalidData(format!(
+                    "no delta for input modulus {}",
+                    w.modulus()
+                )));
+            }
+        }
+        Ok(Encoder::new(garbler_inputs, evaluator_inputs, deltas))
+    }
+
+    /// Serialize the encoder into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserialize an encoder from bytes.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Encoder, Error> {
+        Encoder::read_from(&mut bytes)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// serde helpers
+
+/// Serde support for `Block`s, which are serialized as 16 little-endian bytes.
+#[cfg(feature = "serde1")]
+pub(crate) mod serde_block {
+    use scuttlebutt::Block;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(block: &Block, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: [u8; 16] = (*block).into();
+        bytes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Block, D::Error> {
+        let bytes = <[u8; 16]>::deserialize(deserializer)?;
+        Ok(Block::from(bytes))
+    }
+}
+
+/// Serde support for vectors of `Block`s.
+#[cfg(feature = "serde1")]
+pub(crate) mod serde_blocks {
+    use scuttlebutt::Block;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(blocks: &[Block], serializer: S) -> Result<S::Ok, S::Error> {
+        blocks
+            .iter()
+            .map(|block| (*block).into())
+            .collect::<Vec<[u8; 16]>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Block>, D::Error> {
+        let bytes = Vec::<[u8; 16]>::deserialize(deserializer)?;
+        Ok(bytes.into_iter().map(Block::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::util::RngExt;
+    use crate::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy, FancyBranch};
+    use rand::thread_rng;
+
+    fn test_circuit() -> Circuit {
+        let q = crate::util::modulus_with_width(10);
+        let mut b = CircuitBuilder::new();
+        let xs = b.crt_garbler_input(q);
+        let ys = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&xs, &ys).unwrap();
+        b.push_scope("relu");
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.pop_scope();
+        b.output_bundle(&z).unwrap();
+        let a = b.bin_garbler_input(8);
+        let c = b.bin_evaluator_input(8);
+        let s = b.bin_addition_no_carry(&a, &c).unwrap();
+        b.output_bundle(&s).unwrap();
+        let t = b.constant(1, 2).unwrap();
+        let t = b.add(&t, &s.wires()[0]).unwrap();
+        b.output(&t).unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn circuit_roundtrip() {
+        let c = test_circuit();
+        let bytes = c.to_bytes();
+        let c_ = Circuit::from_bytes(&bytes).unwrap();
+        assert_eq!(c, c_);
+        assert_eq!(bytes, c_.to_bytes());
+
+        let parsed = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let parsed_ = Circuit::from_bytes(&parsed.to_bytes()).unwrap();
+        assert_eq!(parsed, parsed_);
+    }
+
+    #[test]
+    fn conditional_roundtrip() {
+        let mut b = CircuitBuilder::new();
+        let cond = b.garbler_input(2);
+        let xs = b.evaluator_inputs(&[5, 5]);
+        let zs = b
+            .branch(
+                &cond,
+                &xs,
+                &[&|b, xs| Ok(vec![b.add(&xs[0], &xs[1])?]), &|b, xs| {
+                    Ok(vec![b.mul(&xs[0], &xs[1])?])
+                }],
+            )
+            .unwrap();
+        b.outputs(&zs).unwrap();
+        let c = b.finish();
+        assert_eq!(c.conditionals.len(), 1);
+        let bytes = c.to_bytes();
+        assert_eq!(Circuit::from_bytes(&bytes).unwrap(), c);
+
+        // a conditional reading a value computed after it
+        let mut c_ = c.clone();
+        c_.conditionals[0].inputs[0].ix = c.conditionals[0].start;
+        match Circuit::from_bytes(&c_.to_bytes()) {
+            Err(Error::InvalidData(_)) => (),
+            r => panic!("expected InvalidData, got {:?}", r),
+        }
+
+        // a branch with a conditional of its own
+        let mut c_ = c.clone();
+        c_.conditionals[0].branches[0] = c.clone();
+        match Circuit::from_bytes(&c_.to_bytes()) {
+            Err(Error::InvalidData(_)) => (),
+            r => panic!("expected InvalidData, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn garble_offline() {
+        let mut rng = thread_rng();
+        let mut c = test_circuit();
+        let (en, gc) = crate::garble(&mut c).unwrap();
+
+        let c_bytes = c.to_bytes();
+        let en_bytes = en.to_bytes();
+        let gc_bytes = gc.to_bytes();
+
+        let mut c = Circuit::from_bytes(&c_bytes).unwrap();
+        let en = Encoder::from_bytes(&en_bytes).unwrap();
+        let gc = GarbledCircuit::from_bytes(&gc_bytes).unwrap();
+        assert_eq!(gc_bytes, gc.to_bytes());
+        assert_eq!(en_bytes, en.to_bytes());
+
+        for _ in 0..16 {
+            let gb_inps = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect::<Vec<u16>>();
+            let ev_inps = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect::<Vec<u16>>();
+            let should_be = c.eval_plain(&gb_inps, &ev_inps).unwrap();
+            let xs = en.encode_garbler_inputs(&gb_inps);
+            let ys = en.encode_evaluator_inputs(&ev_inps);
+            let res = gc.eval(&mut c, &xs, &ys).unwrap();
+            assert_eq!(res, should_be);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let c = test_circuit();
+        let bytes = c.to_bytes();
+
+        match Circuit::from_bytes(b"nope") {
+            Err(Error::InvalidHeader) => (),
+            r => panic!("expected InvalidHeader, got {:?}", r),
+        }
+        let mut bad = bytes.clone();
+        bad[4] = VERSION + 1;
+        match Circuit::from_bytes(&bad) {
+            Err(Error::UnsupportedVersion(v)) => assert_eq!(v, VERSION + 1),
+            r => panic!("expected UnsupportedVersion, got {:?}", r),
+        }
+        bad[4] = 0;
+        match Circuit::from_bytes(&bad) {
+            Err(Error::UnsupportedVersion(0)) => (),
+            r => panic!("expected UnsupportedVersion, got {:?}", r),
+        }
+        match GarbledCircuit::from_bytes(&bytes) {
+            Err(Error::InvalidHeader) => (),
+            r => panic!("expected InvalidHeader, got {:?}", r),
+        }
+        match Circuit::from_bytes(&bytes[..bytes.len() - 1]) {
+            Err(Error::IoError(_)) => (),
+            r => panic!("expected IoError, got {:?}", r),
+        }
+
+        // an output referring past the end of the circuit
+        let mut c_ = c.clone();
+        c_.output_refs.push(CircuitRef {
+            ix: c.gates.len(),
+            modulus: 2,
+        });
+        match Circuit::from_bytes(&c_.to_bytes()) {
+            Err(Error::InvalidData(_)) => (),
+            r => panic!("expected InvalidData, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reads_older_versions() {
+        let mut c = test_circuit();
+        c.scopes.clear();
+        c.gate_scopes.clear();
+        let (en, gc) = crate::garble(&mut c).unwrap();
+
+        // circuits before version 5 end without the conditional count, and before
+        // version 2 without the scope and gate scope counts
+        let mut bytes = c.to_bytes();
+        assert_eq!(bytes.pop(), Some(0));
+        bytes[4] = VERSION_CONDITIONALS - 1;
+        assert_eq!(Circuit::from_bytes(&bytes).unwrap(), c);
+        assert_eq!(bytes.split_off(bytes.len() - 2), [0, 0]);
+        bytes[4] = VERSION_SCOPES - 1;
+        assert_eq!(Circuit::from_bytes(&bytes).unwrap(), c);
+
+        // garbled circuits before versions 3 and 4 lack the scheme bytes
+        let mut bytes = gc.to_bytes();
+        bytes.remove(7);
+        bytes[4] = VERSION_PROJ_SCHEME - 1;
+        assert_eq!(GarbledCircuit::from_bytes(&bytes).unwrap(), gc);
+        bytes.remove(6);
+        for version in 1..VERSION_MUL_SCHEME {
+            bytes[4] = version;
+            assert_eq!(GarbledCircuit::from_bytes(&bytes).unwrap(), gc);
+        }
+
+        let mut bytes = en.to_bytes();
+        for version in 1..=VERSION {
+            bytes[4] = version;
+            assert_eq!(Encoder::from_bytes(&bytes).unwrap(), en);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_circuits() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let y = b.evaluator_input(3);
+        let z = b.add(&x, &y).unwrap();
+        let z = b.proj(&z, 2, Some(vec![0, 1, 0])).unwrap();
+        b.output(&z).unwrap();
+        let c = b.finish();
+        assert_eq!(Circuit::from_bytes(&c.to_bytes()).unwrap(), c);
+
+        let rejects = |c: &Circuit| match Circuit::from_bytes(&c.to_bytes()) {
+            Err(Error::InvalidData(_)) => (),
+            r => panic!("expected InvalidData, got {:?}", r),
+        };
+        let set_tt = |c: &mut Circuit, tt_: Vec<u16>| {
+            for gate in c.gates.iter_mut() {
+                if let Gate::Proj { ref mut tt, .. } = gate {
+                    *tt = tt_.clone();
+                }
+            }
+        };
+
+        // an input gate without an input reference
+        let mut c_ = c.clone();
+        c_.gates.push(Gate::EvaluatorInput { id: 1 });
+        c_.gate_moduli.push(3);
+        rejects(&c_);
+        let mut c_ = c.clone();
+        c_.gates.push(Gate::GarblerInput { id: 1 });
+        c_.gate_moduli.push(3);
+        rejects(&c_);
+
+        // a truth table too short for the modulus of its input
+        let mut c_ = c.clone();
+        set_tt(&mut c_, vec![0, 1]);
+        rejects(&c_);
+
+        // a truth table entry too large for the modulus of the output
+        let mut c_ = c.clone();
+        set_tt(&mut c_, vec![0, 1, 2]);
+        rejects(&c_);
+
+        // references with the wrong modulus
+        let mut c_ = c.clone();
+        c_.output_refs[0].modulus = 3;
+        rejects(&c_);
+        let mut c_ = c.clone();
+        c_.garbler_input_refs[0].modulus = 5;
+        rejects(&c_);
+        let mut c_ = c.clone();
+        if let Gate::Add { ref mut yref, .. } = c_.gates[2] {
+            yref.modulus = 5;
+        }
+        rejects(&c_);
+
+        // a gate whose modulus does not match its operands
+        let mut c_ = c.clone();
+        c_.gate_moduli[2] = 5;
+        rejects(&c_);
+    }
+
+    #[test]
+    fn varints() {
+        let mut rng = thread_rng();
+        let mut xs = vec![0, 1, 127, 128, 255, 256, 16383, 16384, u64::MAX];
+        xs.extend((0..100).map(|_| rng.gen_u128() as u64 >> (rng.gen_u16() % 64)));
+        for x in xs {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, x).unwrap();
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), x);
+        }
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut c = test_circuit();
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(c, serde_json::from_str::<Circuit>(&json).unwrap());
+
+        let (en, gc) = crate::garble(&mut c).unwrap();
+        let en_ = serde_json::from_str::<Encoder>(&serde_json::to_string(&en).unwrap()).unwrap();
+        let gc_ =
+            serde_json::from_str::<GarbledCircuit>(&serde_json::to_string(&gc).unwrap()).unwrap();
+        assert_eq!(en.to_bytes(), en_.to_bytes());
+        assert_eq!(gc.to_bytes(), gc_.to_bytes());
+    }
+}
@@ -10,34 +10,78 @@
 use crate::circuit::Circuit;
 use crate::error::{EvaluatorError, GarblerError};
 use crate::fancy::HasModulus;
-use crate::garble::{Evaluator, Garbler};
+use crate::garble::{Evaluator, Garbler, MulScheme, ProjScheme};
 use crate::wire::Wire;
 use itertools::Itertools;
+use rand::{CryptoRng, RngCore, SeedableRng};
 use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 
+mod batch;
+mod parallel;
+pub use batch::{
+    garble_batch, garble_batch_with_rngs, garble_batch_with_rngs_and_schemes,
+    garble_batch_with_seeds,
+};
+pub use parallel::{garble_parallel, garble_parallel_with_rng, garble_parallel_with_seed};
+
 /// Static evaluator for a circuit, created by the `garble` function.
 ///
 /// Uses `Evaluator` under the hood to actually implement the evaluation.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct GarbledCircuit {
-    blocks: Vec<Block>,
+    #[cfg_attr(feature = "serde1", serde(with = "crate::serialization::serde_blocks"))]
+    pub(crate) blocks: Vec<Block>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) mul_scheme: MulScheme,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) proj_scheme: ProjScheme,
 }
 
 impl GarbledCircuit {
-    /// Create a new object from a vector of garbled gates and constant wires.
+    /// Create a new object from a vector of garbled gates and constant wires, garbled
+    /// with the default schemes.
     pub fn new(blocks: Vec<Block>) -> Self {
-        GarbledCircuit { blocks }
+        GarbledCircuit::with_schemes(blocks, MulScheme::default(), ProjScheme::default())
+    }
+
+    /// Create a new object from a vector of garbled gates and constant wires, whose
+    /// multiplication and projection gates are garbled with `mul_scheme` and
+    /// `proj_scheme`.
+    pub fn with_schemes(
+        blocks: Vec<Block>,
+        mul_scheme: MulScheme,
+        proj_scheme: ProjScheme,
+    ) -> Self {
+        GarbledCircuit {
+            blocks,
+            mul_scheme,
+            proj_scheme,
+        }
     }
 
-    /// The number of garbled rows and constant wires in the garbled circuit.
+    /// The number of blocks in the garbled circuit. With the three halves scheme,
+    /// the ciphertexts of gates may share blocks, and the last block is padded.
     #[inline]
     pub fn size(&self) -> usize {
         self.blocks.len()
     }
 
+    /// The scheme used to garble the multiplication gates of the garbled circuit.
+    #[inline]
+    pub fn mul_scheme(&self) -> MulScheme {
+        self.mul_scheme
+    }
+
+    /// The scheme used to garble the projection gates of the garbled circuit.
+    #[inline]
+    pub fn proj_scheme(&self) -> ProjScheme {
+        self.proj_scheme
+    }
+
     /// Evaluate the garbled circuit.
     pub fn eval(
         &self,
@@ -46,66 +90,158 @@ impl GarbledCircuit {
         evaluator_inputs: &[Wire],
     ) -> Result<Vec<u16>, EvaluatorError> {
         let channel = Channel::new(GarbledReader::new(&self.blocks), GarbledWriter::new(None));
-        let mut evaluator = Evaluator::new(channel);
-        let outputs = c.eval(&mut evaluator, garbler_inputs, evaluator_inputs)?;
+        let mut evaluator = Evaluator::new(channel)
+            .with_mul_scheme(self.mul_scheme)
+            .with_proj_scheme(self.proj_scheme);
+        let outputs = c.eval_branching(&mut evaluator, garbler_inputs, evaluator_inputs)?;
         c.process_outputs(&outputs, &mut evaluator)?;
         evaluator.decode_output()
     }
 }
 
 /// Garble a circuit without streaming.
+///
+/// The conditionals built with `CircuitBuilder::branch` are stacked, so they cost
+/// about as much as their largest branch.
 pub fn garble(c: &mut Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
-    let channel = Channel::new(
-        GarbledReader::new(&[]),
-        GarbledWriter::new(Some(c.num_nonfree_gates)),
-    );
-    let channel_ = channel.clone();
+    garble_with_rng(c, AesRng::new())
+}
 
-    let rng = AesRng::new();
-    let mut garbler = Garbler::new(channel_, rng, &[]);
+/// Garble a circuit without streaming, deriving all deltas and zero wirelabels from
+/// `seed`.
+///
+/// Garbling the same circuit with the same seed always produces the same garbled
+/// circuit and encoder, so a garbling can be regenerated from its seed, for instance
+/// to check a garbled circuit received from someone else.
+pub fn garble_with_seed(
+    c: &mut Circuit,
+    seed: Block,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_with_rng(c, AesRng::from_seed(seed))
+}
 
-    // get input wires, ignoring encoded values
-    let gb_inps = (0..c.num_garbler_inputs())
-        .map(|i| {
-            let q = c.garbler_input_mod(i);
-            let (zero, _) = garbler.encode_wire(0, q);
-            zero
-        })
-        .collect_vec();
+/// Garble a circuit without streaming, drawing all randomness from `rng`.
+pub fn garble_with_rng<RNG: CryptoRng + RngCore>(
+    c: &mut Circuit,
+    rng: RNG,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_with_rng_and_schemes(c, rng, MulScheme::default(), ProjScheme::default())
+}
 
-    let ev_inps = (0..c.num_evaluator_inputs())
-        .map(|i| {
-            let q = c.evaluator_input_mod(i);
-            let (zero, _) = garbler.encode_wire(0, q);
-            zero
-        })
-        .collect_vec();
+/// Garble a circuit without streaming, garbling multiplication and projection gates
+/// with `mul_scheme` and `proj_scheme`.
+///
+/// The resulting garbled circuit records the schemes, so `GarbledCircuit::eval`
+/// evaluates it with the same ones.
+pub fn garble_with_schemes(
+    c: &mut Circuit,
+    mul_scheme: MulScheme,
+    proj_scheme: ProjScheme,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_with_rng_and_schemes(c, AesRng::new(), mul_scheme, proj_scheme)
+}
 
-    let outputs = c.eval(&mut garbler, &gb_inps, &ev_inps)?;
+/// Garble a circuit without streaming, drawing all randomness from `rng` and
+/// garbling multiplication and projection gates with `mul_scheme` and `proj_scheme`.
+pub fn garble_with_rng_and_schemes<RNG: CryptoRng + RngCore>(
+    c: &mut Circuit,
+    rng: RNG,
+    mul_scheme: MulScheme,
+    proj_scheme: ProjScheme,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    let mut gb = StaticGarbler::new(c, rng, mul_scheme, proj_scheme);
+    let outputs = c.eval_branching(&mut gb.garbler, &gb.gb_inps, &gb.ev_inps)?;
+    c.process_outputs(&outputs, &mut gb.garbler)?;
+    Ok(gb.finish())
+}
 
-    c.process_outputs(&outputs, &mut garbler)?;
+/// The channel a `StaticGarbler` writes the garbled circuit to.
+pub(crate) type StaticChannel = Channel<GarbledReader, GarbledWriter>;
 
-    let en = Encoder::new(gb_inps, ev_inps, garbler.get_deltas());
+/// A `Garbler` writing a garbled circuit to memory, with the zero wirelabels of the
+/// inputs of the circuit it garbles.
+pub(crate) struct StaticGarbler<RNG> {
+    channel: StaticChannel,
+    pub(crate) garbler: Garbler<StaticChannel, RNG>,
+    pub(crate) gb_inps: Vec<Wire>,
+    pub(crate) ev_inps: Vec<Wire>,
+    mul_scheme: MulScheme,
+    proj_scheme: ProjScheme,
+}
 
-    let gc = GarbledCircuit::new(
-        Rc::try_unwrap(channel.writer())
-            .unwrap()
-            .into_inner()
-            .blocks,
-    );
+impl<RNG: CryptoRng + RngCore> StaticGarbler<RNG> {
+    /// Create a garbler for `c` drawing randomness from `rng`, and encode its inputs.
+    pub(crate) fn new(
+        c: &Circuit,
+        rng: RNG,
+        mul_scheme: MulScheme,
+        proj_scheme: ProjScheme,
+    ) -> Self {
+        let channel = Channel::new(
+            GarbledReader::new(&[]),
+            GarbledWriter::new(Some(c.num_nonfree_gates)),
+        );
 
-    Ok((en, gc))
+        let mut garbler = Garbler::new(channel.clone(), rng, &[])
+            .with_mul_scheme(mul_scheme)
+            .with_proj_scheme(proj_scheme);
+
+        // get input wires, ignoring encoded values
+        let gb_inps = (0..c.num_garbler_inputs())
+            .map(|i| {
+                let q = c.garbler_input_mod(i);
+                let (zero, _) = garbler.encode_wire(0, q);
+                zero
+            })
+            .collect_vec();
+
+        let ev_inps = (0..c.num_evaluator_inputs())
+            .map(|i| {
+                let q = c.evaluator_input_mod(i);
+                let (zero, _) = garbler.encode_wire(0, q);
+                zero
+            })
+            .collect_vec();
+
+        StaticGarbler {
+            channel,
+            garbler,
+            gb_inps,
+            ev_inps,
+            mul_scheme,
+            proj_scheme,
+        }
+    }
+
+    /// Return the encoder and the garbled circuit, once the circuit and its outputs
+    /// have been garbled.
+    pub(crate) fn finish(self) -> (Encoder, GarbledCircuit) {
+        // consuming the garbler drops its handle on the channel, so the blocks can be
+        // taken
+        let en = Encoder::new(self.gb_inps, self.ev_inps, self.garbler.get_deltas());
+        let gc = GarbledCircuit::with_schemes(
+            Rc::try_unwrap(self.channel.writer())
+                .unwrap()
+                .into_inner()
+                .into_blocks(),
+            self.mul_scheme,
+            self.proj_scheme,
+        );
+
+        (en, gc)
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 // Encoder
 
 /// Encode inputs statically.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Encoder {
-    garbler_inputs: Vec<Wire>,
-    evaluator_inputs: Vec<Wire>,
-    deltas: HashMap<u16, Wire>,
+    pub(crate) garbler_inputs: Vec<Wire>,
+    pub(crate) evaluator_inputs: Vec<Wire>,
+    pub(crate) deltas: HashMap<u16, Wire>,
 }
 
 impl Encoder {
@@ -171,15 +307,18 @@ impl Encoder {
 
 /// Implementation of the `Read` trait for use by the `Evaluator`.
 #[derive(Debug)]
-struct GarbledReader {
-    blocks: Vec<Block>,
+pub(crate) struct GarbledReader {
+    bytes: Vec<u8>,
     index: usize,
 }
 
 impl GarbledReader {
     fn new(blocks: &[Block]) -> Self {
         Self {
-            blocks: blocks.to_vec(),
+            bytes: blocks
+                .iter()
+                .flat_map(|block| <[u8; 16]>::from(*block).to_vec())
+                .collect(),
             index: 0,
         }
     }
@@ -187,22 +326,21 @@ impl GarbledReader {
 
 impl std::io::Read for GarbledReader {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        assert_eq!(buf.len() % 16, 0);
-        for data in buf.chunks_mut(16) {
-            let block: [u8; 16] = self.blocks[self.index].into();
-            for (a, b) in data.iter_mut().zip(block.iter()) {
-                *a = *b;
-            }
-            self.index += 1;
-        }
-        Ok(buf.len())
+        let n = buf.len().min(self.bytes.len() - self.index);
+        buf[..n].copy_from_slice(&self.bytes[self.index..self.index + n]);
+        self.index += n;
+        Ok(n)
     }
 }
 
 /// Implementation of the `Write` trait for use by `Garbler`.
+///
+/// Writes which are not a multiple of 16 bytes, such as three halves gates, are
+/// packed into the following blocks.
 #[derive(Debug)]
 pub struct GarbledWriter {
     blocks: Vec<Block>,
+    partial: Vec<u8>,
 }
 
 impl GarbledWriter {
@@ -213,23 +351,35 @@ impl GarbledWriter {
         } else {
             Vec::new()
         };
-        Self { blocks }
+        Self {
+            blocks,
+            partial: Vec::with_capacity(16),
+        }
+    }
+
+    /// The blocks written, where the last block is padded with zeros.
+    fn into_blocks(mut self) -> Vec<Block> {
+        if !self.partial.is_empty() {
+            self.partial.resize(16, 0);
+            let bytes: [u8; 16] = self.partial[..].try_into().unwrap();
+            self.blocks.push(Block::from(bytes));
+        }
+        self.blocks
     }
 }
 
 impl std::io::Write for GarbledWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        for item in buf.chunks(16) {
-            let bytes: [u8; 16] = match item.try_into() {
-                Ok(bytes) => bytes,
-                Err(_) => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        "unable to map bytes to block",
-                    ));
-                }
-            };
-            self.blocks.push(Block::from(bytes));
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let n = rest.len().min(16 - self.partial.len());
+            self.partial.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            if self.partial.len() == 16 {
+                let bytes: [u8; 16] = self.partial[..].try_into().unwrap();
+                self.blocks.push(Block::from(bytes));
+                self.partial.clear();
+            }
         }
         Ok(buf.len())
     }
@@ -0,0 +1,311 @@
+//! Batched garbling of many instances of the same static circuit.
+//!
+//! The instances are garbled in lockstep through a `Garbler` for each: every gate is
+//! garbled for all instances before moving on to the next gate. The wirelabels that
+//! the multiplication and projection gates of the instances hash are gathered and
+//! hashed in one loop with `util::tccr_hash_many`, and the operands of each gate are
+//! only resolved once for the whole batch.
+//!
+//! Each instance is garbled by the same code as `garble_with_rng_and_schemes`, so it
+//! gets the same garbled circuit and encoder as garbling it alone with the same RNG
+//! and schemes. Three halves gates are garbled one instance at a time, and circuits
+//! with conditionals are garbled one instance after another so that they are
+//! stacked.
+
+use super::{garble_with_rng_and_schemes, Encoder, GarbledCircuit, StaticChannel, StaticGarbler};
+use crate::circuit::Circuit;
+use crate::error::GarblerError;
+use crate::fancy::{Fancy, HasModulus};
+use crate::garble::{Garbler, MulScheme, PendingGate, ProjScheme};
+use crate::util::tccr_hash_many;
+use crate::wire::Wire;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use scuttlebutt::{AesRng, Block};
+
+/// Garble `n` instances of a circuit without streaming.
+pub fn garble_batch(
+    c: &mut Circuit,
+    n: usize,
+) -> Result<Vec<(Encoder, GarbledCircuit)>, GarblerError> {
+    garble_batch_with_rngs(c, (0..n).map(|_| AesRng::new()).collect())
+}
+
+/// Garble an instance of a circuit without streaming for each of `seeds`, deriving
+/// all deltas and zero wirelabels of the instance from its seed.
+///
+/// Produces the same garbled circuits and encoders as `garble_with_seed` with each
+/// seed.
+pub fn garble_batch_with_seeds(
+    c: &mut Circuit,
+    seeds: &[Block],
+) -> Result<Vec<(Encoder, GarbledCircuit)>, GarblerError> {
+    garble_batch_with_rngs(c, seeds.iter().map(|s| AesRng::from_seed(*s)).collect())
+}
+
+/// Garble an instance of a circuit without streaming for each of `rngs`, drawing
+/// all randomness of the instance from its RNG.
+pub fn garble_batch_with_rngs<RNG: CryptoRng + RngCore>(
+    c: &mut Circuit,
+    rngs: Vec<RNG>,
+) -> Result<Vec<(Encoder, GarbledCircuit)>, GarblerError> {
+    garble_batch_with_rngs_and_schemes(c, rngs, MulScheme::default(), ProjScheme::default())
+}
+
+/// Garble an instance of a circuit without streaming for each of `rngs`, drawing
+/// all randomness of the instance from its RNG and garbling multiplication and
+/// projection gates with `mul_scheme` and `proj_scheme`.
+pub fn garble_batch_with_rngs_and_schemes<RNG: CryptoRng + RngCore>(
+    c: &mut Circuit,
+    rngs: Vec<RNG>,
+    mul_scheme: MulScheme,
+    proj_scheme: ProjScheme,
+) -> Result<Vec<(Encoder, GarbledCircuit)>, GarblerError> {
+    if rngs.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !c.conditionals.is_empty() {
+        return rngs
+            .into_iter()
+            .map(|rng| garble_with_rng_and_schemes(c, rng, mul_scheme, proj_scheme))
+            .collect();
+    }
+    // every gate stores a wire for each instance
+    let wires = Vec::with_capacity(c.gates.len() * rngs.len());
+    let mut batch = BatchGarbler {
+        instances: rngs
+            .into_iter()
+            .map(|rng| StaticGarbler::new(c, rng, mul_scheme, proj_scheme))
+            .collect(),
+        wires,
+        gates: Vec::new(),
+        hash_inputs: Vec::new(),
+        hashes: Vec::new(),
+    };
+    let gb_inps = batch.inputs(|gb| &gb.gb_inps);
+    let ev_inps = batch.inputs(|gb| &gb.ev_inps);
+    let outputs = c.eval(&mut batch, &gb_inps, &ev_inps)?;
+    c.process_outputs(&outputs, &mut batch)?;
+    Ok(batch
+        .instances
+        .into_iter()
+        .map(StaticGarbler::finish)
+        .collect())
+}
+
+/// A wire in every instance of a batch, whose zero wires are stored next to each
+/// other in the `wires` of the `BatchGarbler` from `start`.
+#[derive(Clone)]
+struct Wires {
+    start: usize,
+    q: u16,
+}
+
+impl HasModulus for Wires {
+    fn modulus(&self) -> u16 {
+        self.q
+    }
+}
+
+/// Garbles a batch of instances of a circuit, one gate of every instance at a time.
+struct BatchGarbler<RNG> {
+    instances: Vec<StaticGarbler<RNG>>,
+    // the zero wires of every instance, in one place so that garbling a free gate
+    // does not allocate
+    wires: Vec<Wire>,
+    // the gates of the instances waiting on their hashes, and the hashes, reused
+    // between gates to avoid allocating
+    gates: Vec<PendingGate>,
+    hash_inputs: Vec<(Block, Block)>,
+    hashes: Vec<Block>,
+}
+
+impl<RNG: CryptoRng + RngCore> BatchGarbler<RNG> {
+    /// Store the zero wires of the inputs of every instance, returning them by input.
+    fn inputs<F>(&mut self, wires: F) -> Vec<Wires>
+    where
+        F: Fn(&StaticGarbler<RNG>) -> &Vec<Wire>,
+    {
+        (0..wires(&self.instances[0]).len())
+            .map(|i| {
+                let start = self.wires.len();
+                for gb in self.instances.iter() {
+                    self.wires.push(wires(gb)[i].clone());
+                }
+                self.stored(start)
+            })
+            .collect()
+    }
+
+    /// The wires of every instance stored from `start`.
+    fn stored(&self, start: usize) -> Wires {
+        Wires {
+            start,
+            q: self.wires[start].modulus(),
+        }
+    }
+
+    /// Apply `f` to the garbler of every instance along with its index, storing the
+    /// wires it returns. `f` reads the wires of its operands from the slice it is
+    /// given.
+    fn each<F>(&mut self, mut f: F) -> Result<Wires, GarblerError>
+    where
+        F: FnMut(&mut Garbler<StaticChannel, RNG>, &[Wire], usize) -> Result<Wire, GarblerError>,
+    {
+        let start = self.wires.len();
+        for (k, gb) in self.instances.iter_mut().enumerate() {
+            let w = f(&mut gb.garbler, &self.wires, k)?;
+            self.wires.push(w);
+        }
+        Ok(self.stored(start))
+    }
+
+    /// Hash the inputs of the pending gates of every instance together, and finish
+    /// garbling them.
+    fn finish_gates(&mut self) -> Result<Wires, GarblerError> {
+        tccr_hash_many(&self.hash_inputs, &mut self.hashes);
+        let start = self.wires.len();
+        for (gb, gate) in self.instances.iter_mut().zip(self.gates.drain(..)) {
+            let w = gb.garbler.finish_gate(gate, &self.hashes)?;
+            self.wires.push(w);
+        }
+        Ok(self.stored(start))
+    }
+}
+
+impl<RNG: CryptoRng + RngCore> Fancy for BatchGarbler<RNG> {
+    type Item = Wires;
+    type Error = GarblerError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wires, GarblerError> {
+        self.each(|gb, _, _| gb.constant(x, q))
+    }
+
+    fn add(&mut self, x: &Wires, y: &Wires) -> Result<Wires, GarblerError> {
+        self.each(|gb, ws, k| gb.add(&ws[x.start + k], &ws[y.start + k]))
+    }
+
+    fn sub(&mut self, x: &Wires, y: &Wires) -> Result<Wires, GarblerError> {
+        self.each(|gb, ws, k| gb.sub(&ws[x.start + k], &ws[y.start + k]))
+    }
+
+    fn cmul(&mut self, x: &Wires, c: u16) -> Result<Wires, GarblerError> {
+        self.each(|gb, ws, k| gb.cmul(&ws[x.start + k], c))
+    }
+
+    fn mul(&mut self, x: &Wires, y: &Wires) -> Result<Wires, GarblerError> {
+        self.gates.clear();
+        self.hash_inputs.clear();
+        for (k, gb) in self.instances.iter_mut().enumerate() {
+            let (A, B) = (&self.wires[x.start + k], &self.wires[y.start + k]);
+            let gate = gb.garbler.start_mul(A, B, &mut self.hash_inputs)?;
+            self.gates.push(gate);
+        }
+        self.finish_gates()
+    }
+
+    fn proj(&mut self, x: &Wires, q: u16, tt: Option<Vec<u16>>) -> Result<Wires, GarblerError> {
+        self.gates.clear();
+        self.hash_inputs.clear();
+        for (k, gb) in self.instances.iter_mut().enumerate() {
+            let A = &self.wires[x.start + k];
+            let gate = gb
+                .garbler
+                .start_proj(A, q, tt.clone(), &mut self.hash_inputs)?;
+            self.gates.push(gate);
+        }
+        self.finish_gates()
+    }
+
+    fn output(&mut self, x: &Wires) -> Result<(), GarblerError> {
+        for (k, gb) in self.instances.iter_mut().enumerate() {
+            gb.garbler.output(&self.wires[x.start + k])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::fancy::{BinaryGadgets, BundleGadgets, CrtGadgets};
+    use crate::r#static::garble_with_rng_and_schemes;
+    use crate::util::RngExt;
+    use rand::{thread_rng, Rng};
+
+    // garble a batch of instances of `c` and check that each is identical to garbling
+    // it alone from the same seed, and that it evaluates correctly
+    fn test_circuit(c: &mut Circuit, mul_scheme: MulScheme, proj_scheme: ProjScheme) {
+        let mut rng = thread_rng();
+        let seeds = (0..5).map(|_| rng.gen::<Block>()).collect::<Vec<Block>>();
+        let rngs = seeds.iter().map(|s| AesRng::from_seed(*s)).collect();
+        let batch = garble_batch_with_rngs_and_schemes(c, rngs, mul_scheme, proj_scheme).unwrap();
+        assert_eq!(batch.len(), seeds.len());
+        for ((en, gc), seed) in batch.iter().zip(seeds.iter()) {
+            let (en_, gc_) =
+                garble_with_rng_and_schemes(c, AesRng::from_seed(*seed), mul_scheme, proj_scheme)
+                    .unwrap();
+            assert_eq!(*gc, gc_);
+            assert_eq!(*en, en_);
+
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect::<Vec<u16>>();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect::<Vec<u16>>();
+            let xs = en.encode_garbler_inputs(&gb);
+            let ys = en.encode_evaluator_inputs(&ev);
+            assert_eq!(
+                gc.eval(c, &xs, &ys).unwrap(),
+                c.eval_plain(&gb, &ev).unwrap()
+            );
+        }
+        // instances garbled with different seeds are independent
+        assert_ne!(batch[0].1, batch[1].1);
+    }
+
+    #[test]
+    fn aes() {
+        let mut c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        test_circuit(&mut c, MulScheme::HalfGates, ProjScheme::Standard);
+        test_circuit(&mut c, MulScheme::ThreeHalves, ProjScheme::Standard);
+    }
+
+    #[test]
+    fn mixed_moduli() {
+        let mut b = CircuitBuilder::new();
+        let qs = crate::util::primes_with_width(8);
+        let Q = crate::util::product(&qs);
+        let x = b.crt_garbler_input(Q);
+        let y = b.crt_evaluator_input(Q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let c = b.crt_constant_bundle(12345 % Q, Q).unwrap();
+        let z = b.crt_add(&z, &c).unwrap();
+        let z = b.crt_sgn(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+
+        // unequal moduli multiplications
+        let xs = b.garbler_inputs(&[2, 3, 5, 7]);
+        let ys = b.evaluator_inputs(&[7, 2, 3, 5]);
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            let z = b.mul(x, y).unwrap();
+            b.output(&z).unwrap();
+        }
+
+        let a = b.bin_garbler_input(8);
+        let c = b.bin_evaluator_input(8);
+        let a = b.bin_addition_no_carry(&a, &c).unwrap();
+        b.output_bundle(&a).unwrap();
+
+        let mut c = b.finish();
+        test_circuit(&mut c, MulScheme::HalfGates, ProjScheme::Standard);
+        test_circuit(&mut c, MulScheme::ThreeHalves, ProjScheme::RowReduced);
+    }
+
+    #[test]
+    fn empty_batch() {
+        let mut c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        assert!(garble_batch(&mut c, 0).unwrap().is_empty());
+    }
+}
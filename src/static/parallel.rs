@@ -0,0 +1,473 @@
+//! Level-scheduled parallel garbling and evaluation of static circuits.
+//!
+//! Gates are grouped into levels by their depth in non-free gates. The `Mul` and
+//! `Proj` gates in a level only depend on earlier levels, so they are hashed across
+//! the rayon thread pool, while the cheap free gates are computed in order afterwards.
+//! All randomness is drawn up front in the same order as the sequential garbler, so
+//! both produce the same garbled circuit from the same RNG.
+
+use super::{Encoder, GarbledCircuit};
+use crate::circuit::{Circuit, Gate};
+use crate::error::{EvaluatorError, FancyError, GarblerError};
+use crate::fancy::HasModulus;
+use crate::garble::{
+    decode_outputs, eval_mul, eval_proj, garble_mul, garble_output, garble_proj, MulScheme,
+    ProjScheme,
+};
+use crate::util::RngExt;
+use crate::wire::Wire;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use scuttlebutt::{AesRng, Block};
+use std::collections::HashMap;
+
+/// The order in which to compute the gates of a circuit.
+struct Schedule {
+    /// The gates defining the operands of each gate.
+    operands: Vec<Vec<usize>>,
+    /// The gates defining the outputs.
+    outputs: Vec<usize>,
+    /// The non-free gate index of each non-free gate, used for its tweak.
+    gate_nums: Vec<usize>,
+    /// The non-free and free gates in each level, in circuit order. Inputs and
+    /// constants are not included.
+    levels: Vec<(Vec<usize>, Vec<usize>)>,
+}
+
+impl Schedule {
+    fn new(c: &Circuit) -> Result<Self, FancyError> {
+        let (operands, outputs) = c.operand_gates()?;
+        let mut depths = vec![0; c.gates.len()];
+        let mut gate_nums = vec![0; c.gates.len()];
+        let mut levels: Vec<(Vec<usize>, Vec<usize>)> = vec![(Vec::new(), Vec::new())];
+        let mut gate_num = 0;
+        for (i, gate) in c.gates.iter().enumerate() {
+            let depth = operands[i].iter().map(|x| depths[*x]).max().unwrap_or(0);
+            match gate {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } | Gate::Constant { .. } => {
+                }
+                Gate::Add { .. } | Gate::Sub { .. } | Gate::Cmul { .. } => {
+                    depths[i] = depth;
+                    levels[depth].1.push(i);
+                }
+                Gate::Mul { .. } | Gate::Proj { .. } => {
+                    depths[i] = depth + 1;
+                    if levels.len() == depth + 1 {
+                        levels.push((Vec::new(), Vec::new()));
+                    }
+                    levels[depth + 1].0.push(i);
+                    gate_nums[i] = gate_num;
+                    gate_num += 1;
+                }
+            }
+        }
+        Ok(Schedule {
+            operands,
+            outputs,
+            gate_nums,
+            levels,
+        })
+    }
+
+    /// The moduli of the operands of a `Mul` gate, larger first, as the garbler and
+    /// evaluator order them.
+    fn mul_moduli(&self, c: &Circuit, i: usize) -> (u16, u16) {
+        let q = c.gate_moduli[self.operands[i][0]];
+        let qb = c.gate_moduli[self.operands[i][1]];
+        if q < qb {
+            (qb, q)
+        } else {
+            (q, qb)
+        }
+    }
+
+    /// Compute every gate, given `wires` holding the inputs and constants. The
+    /// function `nonfree` is called in parallel on the non-free gates of each level,
+    /// returning the output wire alongside some result which is collected by gate.
+    fn run<T, F>(
+        &self,
+        c: &Circuit,
+        wires: &mut [Option<Wire>],
+        nonfree: F,
+    ) -> Result<Vec<Option<T>>, FancyError>
+    where
+        T: Send,
+        F: Fn(usize, &[&Wire]) -> (T, Wire) + Sync,
+    {
+        let mut results = (0..c.gates.len()).map(|_| None).collect::<Vec<_>>();
+        for (nonfree_gates, free_gates) in self.levels.iter() {
+            let computed = nonfree_gates
+                .par_iter()
+                .map(|&i| {
+                    let xs = self.operand_wires(wires, i)?;
+                    Ok((i, nonfree(i, &xs)))
+                })
+                .collect::<Result<Vec<_>, FancyError>>()?;
+            for (i, (result, wire)) in computed {
+                results[i] = Some(result);
+                wires[i] = Some(wire);
+            }
+            for &i in free_gates.iter() {
+                let xs = self.operand_wires(wires, i)?;
+                let wire = match c.gates[i] {
+                    Gate::Add { .. } | Gate::Sub { .. } if xs[0].modulus() != xs[1].modulus() => {
+                        return Err(FancyError::UnequalModuli);
+                    }
+                    Gate::Add { .. } => xs[0].plus(xs[1]),
+                    Gate::Sub { .. } => xs[0].minus(xs[1]),
+                    Gate::Cmul { c, .. } => xs[0].cmul(c),
+                    _ => unreachable!(),
+                };
+                wires[i] = Some(wire);
+            }
+        }
+        Ok(results)
+    }
+
+    fn operand_wires<'a>(
+        &self,
+        wires: &'a [Option<Wire>],
+        i: usize,
+    ) -> Result<Vec<&'a Wire>, FancyError> {
+        self.operands[i]
+            .iter()
+            .map(|x| wires[*x].as_ref().ok_or(FancyError::UninitializedValue))
+            .collect()
+    }
+
+    /// The output wires of the circuit.
+    fn output_wires(&self, wires: &[Option<Wire>]) -> Result<Vec<Wire>, FancyError> {
+        self.outputs
+            .iter()
+            .map(|x| wires[*x].clone().ok_or(FancyError::UninitializedValue))
+            .collect()
+    }
+}
+
+fn delta<RNG: CryptoRng + RngCore>(deltas: &mut HashMap<u16, Wire>, rng: &mut RNG, q: u16) -> Wire {
+    deltas
+        .entry(q)
+        .or_insert_with(|| Wire::rand_delta(rng, q))
+        .clone()
+}
+
+/// Garble a circuit without streaming, computing independent gates in parallel.
+///
+/// Produces the same garbled circuit and encoder as `garble` would given the same
+/// randomness.
+pub fn garble_parallel(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_parallel_with_rng(c, AesRng::new())
+}
+
+/// Garble a circuit without streaming, computing independent gates in parallel and
+/// deriving all deltas and zero wirelabels from `seed`.
+///
+/// Produces the same garbled circuit and encoder as `garble_with_seed` with the same
+/// seed.
+pub fn garble_parallel_with_seed(
+    c: &Circuit,
+    seed: Block,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_parallel_with_rng(c, AesRng::from_seed(seed))
+}
+
+/// Garble a circuit without streaming, computing independent gates in parallel and
+/// drawing all randomness from `rng`.
+///
+/// Circuits with conditionals are garbled sequentially, so that they are stacked.
+pub fn garble_parallel_with_rng<RNG: CryptoRng + RngCore>(
+    c: &Circuit,
+    mut rng: RNG,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    if !c.conditionals.is_empty() {
+        return super::garble_with_rng(&mut c.clone(), rng);
+    }
+    let schedule = Schedule::new(c)?;
+    let mut deltas = HashMap::new();
+    let mut wires = vec![None; c.gates.len()];
+    let mut constants = HashMap::new();
+    let mut rs = HashMap::new();
+
+    // draw randomness in the order the sequential garbler does
+    let encode = |rng: &mut RNG, deltas: &mut HashMap<u16, Wire>, q: u16| {
+        let zero = Wire::rand(rng, q);
+        delta(deltas, rng, q);
+        zero
+    };
+    let gb_inps = (0..c.num_garbler_inputs())
+        .map(|i| encode(&mut rng, &mut deltas, c.garbler_input_mod(i)))
+        .collect::<Vec<Wire>>();
+    let ev_inps = (0..c.num_evaluator_inputs())
+        .map(|i| encode(&mut rng, &mut deltas, c.evaluator_input_mod(i)))
+        .collect::<Vec<Wire>>();
+    for (i, gate) in c.gates.iter().enumerate() {
+        let q = c.gate_moduli[i];
+        match *gate {
+            Gate::GarblerInput { id } => wires[i] = Some(gb_inps[id].clone()),
+            Gate::EvaluatorInput { id } => wires[i] = Some(ev_inps[id].clone()),
+            Gate::Constant { val } => {
+                let zero = Wire::rand(&mut rng, q);
+                let D = delta(&mut deltas, &mut rng, q);
+                constants.insert(i, zero.plus(&D.cmul(val)).as_block());
+                wires[i] = Some(zero);
+            }
+            Gate::Mul { .. } => {
+                let (q, qb) = schedule.mul_moduli(c, i);
+                delta(&mut deltas, &mut rng, q);
+                delta(&mut deltas, &mut rng, qb);
+                if q != qb {
+                    if qb > 8 {
+                        return Err(GarblerError::AsymmetricHalfGateModuliMax8(qb));
+                    }
+                    rs.insert(i, rng.gen_u16() % q);
+                }
+            }
+            Gate::Proj { .. } => {
+                delta(
+                    &mut deltas,
+                    &mut rng,
+                    c.gate_moduli[schedule.operands[i][0]],
+                );
+                delta(&mut deltas, &mut rng, q);
+            }
+            _ => (),
+        }
+    }
+    for x in schedule.outputs.iter() {
+        delta(&mut deltas, &mut rng, c.gate_moduli[*x]);
+    }
+
+    let deltas_ = &deltas;
+    let gates = schedule.run(c, &mut wires, |i, xs| {
+        let gate_num = schedule.gate_nums[i];
+        match c.gates[i] {
+            Gate::Mul { .. } => {
+                let (A, B) = if xs[0].modulus() < xs[1].modulus() {
+                    (xs[1], xs[0])
+                } else {
+                    (xs[0], xs[1])
+                };
+                let D = &deltas_[&A.modulus()];
+                let Db = &deltas_[&B.modulus()];
+                let r = rs.get(&i).cloned().unwrap_or_else(|| B.color());
+                garble_mul(A, B, gate_num, D, Db, r)
+            }
+            Gate::Proj { ref tt, .. } => {
+                let q_out = c.gate_moduli[i];
+                let Din = &deltas_[&xs[0].modulus()];
+                let Dout = &deltas_[&q_out];
+                garble_proj(xs[0], q_out, tt, gate_num, Din, Dout)
+            }
+            _ => unreachable!(),
+        }
+    })?;
+
+    let mut blocks = Vec::with_capacity(2 * c.num_nonfree_gates);
+    for (i, gate) in gates.into_iter().enumerate() {
+        if let Some(block) = constants.get(&i) {
+            blocks.push(*block);
+        } else if let Some(gate) = gate {
+            blocks.extend(gate);
+        }
+    }
+    let outputs = schedule.output_wires(&wires)?;
+    let cts = outputs
+        .par_iter()
+        .enumerate()
+        .map(|(i, X)| garble_output(X, i, &deltas[&X.modulus()]))
+        .collect::<Vec<Vec<Block>>>();
+    for ct in cts {
+        blocks.extend(ct);
+    }
+
+    let en = Encoder::new(gb_inps, ev_inps, deltas);
+    Ok((en, GarbledCircuit::new(blocks)))
+}
+
+impl GarbledCircuit {
+    /// Evaluate the garbled circuit, computing independent gates in parallel.
+    ///
+    /// Only garbled circuits garbled with the default schemes are supported. Circuits
+    /// with conditionals are evaluated sequentially.
+    pub fn eval_parallel(
+        &self,
+        c: &Circuit,
+        garbler_inputs: &[Wire],
+        evaluator_inputs: &[Wire],
+    ) -> Result<Vec<u16>, EvaluatorError> {
+        if !c.conditionals.is_empty() {
+            return self.eval(&mut c.clone(), garbler_inputs, evaluator_inputs);
+        }
+        if self.mul_scheme != MulScheme::default() || self.proj_scheme != ProjScheme::default() {
+            return Err(EvaluatorError::FancyError(FancyError::InvalidArg(format!(
+                "parallel evaluation does not support {:?} and {:?} garbling",
+                self.mul_scheme, self.proj_scheme
+            ))));
+        }
+        if garbler_inputs.len() != c.num_garbler_inputs() {
+            return Err(EvaluatorError::NotEnoughGarblerInputs);
+        }
+        if evaluator_inputs.len() != c.num_evaluator_inputs() {
+            return Err(EvaluatorError::NotEnoughEvaluatorInputs);
+        }
+        let schedule = Schedule::new(c)?;
+
+        // find where the ciphertexts of each gate start, and where the output
+        // ciphertexts start
+        let mut offsets = vec![0; c.gates.len() + 1];
+        let mut offset = 0;
+        for (i, gate) in c.gates.iter().enumerate() {
+            offsets[i] = offset;
+            offset += match gate {
+                Gate::Constant { .. } => 1,
+                Gate::Mul { .. } => {
+                    let (q, qb) = schedule.mul_moduli(c, i);
+                    q as usize + qb as usize - 2 + (q != qb) as usize
+                }
+                Gate::Proj { .. } => c.gate_moduli[schedule.operands[i][0]] as usize - 1,
+                _ => 0,
+            };
+        }
+        offsets[c.gates.len()] = offset;
+        offset += schedule
+            .outputs
+            .iter()
+            .map(|x| c.gate_moduli[*x] as usize)
+            .sum::<usize>();
+        if offset > self.blocks.len() {
+            return Err(EvaluatorError::CommunicationError(format!(
+                "expected {} blocks in garbled circuit, got {}",
+                offset,
+                self.blocks.len()
+            )));
+        }
+
+        let mut wires = vec![None; c.gates.len()];
+        for (i, gate) in c.gates.iter().enumerate() {
+            match *gate {
+                Gate::GarblerInput { id } => wires[i] = Some(garbler_inputs[id].clone()),
+                Gate::EvaluatorInput { id } => wires[i] = Some(evaluator_inputs[id].clone()),
+                Gate::Constant { .. } => {
+                    wires[i] = Some(Wire::from_block(self.blocks[offsets[i]], c.gate_moduli[i]))
+                }
+                _ => (),
+            }
+        }
+        schedule.run(c, &mut wires, |i, xs| {
+            let gate_num = schedule.gate_nums[i];
+            let wire = match c.gates[i] {
+                Gate::Mul { .. } => {
+                    let (A, B) = if xs[0].modulus() < xs[1].modulus() {
+                        (xs[1], xs[0])
+                    } else {
+                        (xs[0], xs[1])
+                    };
+                    eval_mul(A, B, gate_num, &self.blocks[offsets[i]..offsets[i + 1]])
+                }
+                Gate::Proj { .. } => eval_proj(
+                    xs[0],
+                    c.gate_moduli[i],
+                    gate_num,
+                    &self.blocks[offsets[i]..offsets[i + 1]],
+                ),
+                _ => unreachable!(),
+            };
+            ((), wire)
+        })?;
+
+        let outputs = schedule.output_wires(&wires)?;
+        let mut offset = offsets[c.gates.len()];
+        let cts = outputs
+            .iter()
+            .map(|X| {
+                let q = X.modulus() as usize;
+                offset += q;
+                self.blocks[offset - q..offset].to_vec()
+            })
+            .collect::<Vec<Vec<Block>>>();
+        decode_outputs(&outputs, &cts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::fancy::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy};
+    use crate::r#static::garble_with_seed;
+    use crate::util::RngExt;
+    use rand::{thread_rng, Rng};
+
+    // garble `c` sequentially and in parallel from the same seed, check that the
+    // results are identical, and that parallel evaluation agrees with the plaintext
+    fn test_circuit(c: &mut Circuit) {
+        let mut rng = thread_rng();
+        let seed = rng.gen::<Block>();
+        let (en, gc) = garble_with_seed(c, seed).unwrap();
+        let (en_, gc_) = garble_parallel_with_seed(c, seed).unwrap();
+        assert_eq!(gc, gc_);
+        assert_eq!(en, en_);
+
+        // the informer finds the same levels as the schedule
+        let widths = Schedule::new(c).unwrap().levels[1..]
+            .iter()
+            .map(|(nonfree, _)| nonfree.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(c.informer().unwrap().level_widths(), widths);
+
+        for _ in 0..4 {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect::<Vec<u16>>();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect::<Vec<u16>>();
+            let xs = en.encode_garbler_inputs(&gb);
+            let ys = en.encode_evaluator_inputs(&ev);
+            let should_be = c.eval_plain(&gb, &ev).unwrap();
+            assert_eq!(gc.eval_parallel(c, &xs, &ys).unwrap(), should_be);
+            assert_eq!(gc.eval(c, &xs, &ys).unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn aes() {
+        test_circuit(&mut Circuit::parse("circuits/AES-non-expanded.txt").unwrap());
+    }
+
+    #[test]
+    fn sha_1() {
+        test_circuit(&mut Circuit::parse("circuits/sha-1.txt").unwrap());
+    }
+
+    #[test]
+    fn mixed_moduli() {
+        let mut b = CircuitBuilder::new();
+        let qs = crate::util::primes_with_width(8);
+        let Q = crate::util::product(&qs);
+        let x = b.crt_garbler_input(Q);
+        let y = b.crt_evaluator_input(Q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_add(&z, &x).unwrap();
+        let c = b.crt_constant_bundle(12345 % Q, Q).unwrap();
+        let z = b.crt_mul(&z, &c).unwrap();
+        let z = b.crt_sgn(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+
+        // unequal moduli multiplications
+        let xs = b.garbler_inputs(&[2, 3, 5, 7, 11]);
+        let ys = b.evaluator_inputs(&[7, 2, 3, 5, 2]);
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            let z = b.mul(x, y).unwrap();
+            let z = b.mul(&z, y).unwrap();
+            b.output(&z).unwrap();
+        }
+
+        let a = b.bin_garbler_input(8);
+        let c = b.bin_constant_bundle(77, 8).unwrap();
+        let a = b.bin_multiplication_lower_half(&a, &c).unwrap();
+        b.output_bundle(&a).unwrap();
+
+        test_circuit(&mut b.finish());
+    }
+}
@@ -0,0 +1,381 @@
+//! Two-party computation built on the streaming garbler and evaluator.
+//!
+//! The `Garbler` and `Evaluator` here implement `FancyInput`, sending the garbler's
+//! input wirelabels directly and delivering the evaluator's input wirelabels using a
+//! pluggable oblivious transfer (see `ot`). A two-party computation is then a single
+//! generic function over `FancyInput` run on both sides.
+//!
+//! `Garbler` and `Evaluator` are secure against semi-honest adversaries only. The
+//! `MaliciousGarbler` and `MaliciousEvaluator` types run the same computations using
+//! cut-and-choose, which protects the evaluator against a malicious garbler.
+
+mod evaluator;
+mod garbler;
+mod malicious;
+
+pub use evaluator::Evaluator;
+pub use garbler::Garbler;
+pub use malicious::{MaliciousEvaluator, MaliciousGarbler};
+
+/// The number of bits needed to transfer an evaluator input of modulus `q`.
+pub(crate) fn input_bits(q: u16) -> usize {
+    (16 - (q - 1).leading_zeros()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::{DummyReceiver, DummySender, OtSender};
+    use crate::util::RngExt;
+    use crate::{
+        BinaryGadgets, Bundle, BundleGadgets, CrtBundle, CrtGadgets, Fancy, FancyInput, OutputMode,
+    };
+    use itertools::Itertools;
+    use rand::thread_rng;
+    use scuttlebutt::{AbstractChannel, AesRng, Channel};
+    use std::io::{BufReader, BufWriter};
+    use std::os::unix::net::UnixStream;
+
+    type MyChannel = Channel<BufReader<UnixStream>, BufWriter<UnixStream>>;
+
+    fn channel(stream: UnixStream) -> MyChannel {
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let writer = BufWriter::new(stream);
+        Channel::new(reader, writer)
+    }
+
+    fn mul_add<F: FancyInput>(
+        f: &mut F,
+        gb_inps: &[u16],
+        ev_inps: &[u16],
+        moduli: &[u16],
+        is_garbler: bool,
+    ) {
+        let (xs, ys) = if is_garbler {
+            let xs = f.encode_many(gb_inps, moduli).unwrap();
+            let ys = f.receive_many(moduli).unwrap();
+            (xs, ys)
+        } else {
+            let xs = f.receive_many(moduli).unwrap();
+            let ys = f.encode_many(ev_inps, moduli).unwrap();
+            (xs, ys)
+        };
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            let z = f.mul(x, y).unwrap();
+            let z = f.add(&z, x).unwrap();
+            f.output(&z).unwrap();
+        }
+    }
+
+    #[test]
+    fn semihonest_inputs() {
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let moduli = (0..8).map(|_| rng.gen_modulus()).collect_vec();
+            let gb_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+            let ev_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+
+            let should_be = gb_inps
+                .iter()
+                .zip(ev_inps.iter())
+                .zip(moduli.iter())
+                .map(|((x, y), q)| (x * y + x) % q)
+                .collect_vec();
+
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let moduli_ = moduli.clone();
+            let gb_inps_ = gb_inps.clone();
+            let handle = std::thread::spawn(move || {
+                let mut gb =
+                    Garbler::<MyChannel, AesRng, DummySender>::new(channel(sender), AesRng::new())
+                        .unwrap();
+                mul_add(&mut gb, &gb_inps_, &[], &moduli_, true);
+            });
+            let mut ev = Evaluator::<MyChannel, AesRng, DummyReceiver>::new(
+                channel(receiver),
+                AesRng::new(),
+            )
+            .unwrap();
+            mul_add(&mut ev, &[], &ev_inps, &moduli, false);
+            handle.join().unwrap();
+            assert_eq!(ev.decode_output().unwrap(), should_be);
+        }
+    }
+
+    fn crt_and_binary<F: FancyInput>(
+        f: &mut F,
+        inps: (u128, u128),
+        modulus: u128,
+        nbits: usize,
+        is_garbler: bool,
+    ) {
+        let (x, y, a, b) = if is_garbler {
+            let x = f.crt_encode(inps.0, modulus).unwrap();
+            let y = f.crt_receive(modulus).unwrap();
+            let a = f.bin_encode(inps.0 % (1 << nbits), nbits).unwrap();
+            let b = f.bin_receive(nbits).unwrap();
+            (x, y, a, b)
+        } else {
+            let x = f.crt_receive(modulus).unwrap();
+            let y = f.crt_encode(inps.1, modulus).unwrap();
+            let a = f.bin_receive(nbits).unwrap();
+            let b = f.bin_encode(inps.1 % (1 << nbits), nbits).unwrap();
+            (x, y, a, b)
+        };
+        let z: CrtBundle<F::Item> = f.crt_mul(&x, &y).unwrap();
+        f.crt_outputs(&[z]).unwrap();
+        let c = f.bin_addition_no_carry(&a, &b).unwrap();
+        f.output_bundle(&c).unwrap();
+    }
+
+    #[test]
+    fn semihonest_bundles() {
+        let mut rng = thread_rng();
+        let qs = crate::util::primes_with_width(10);
+        let Q = crate::util::product(&qs);
+        let nbits = 8;
+        for _ in 0..16 {
+            let x = rng.gen_u128() % Q;
+            let y = rng.gen_u128() % Q;
+
+            let mut should_be = crate::util::crt(x * y % Q, &qs);
+            let sum = (x % (1 << nbits) + y % (1 << nbits)) % (1 << nbits);
+            should_be.extend(crate::util::u128_to_bits(sum, nbits));
+
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut gb =
+                    Garbler::<MyChannel, AesRng, DummySender>::new(channel(sender), AesRng::new())
+                        .unwrap();
+                crt_and_binary(&mut gb, (x, 0), Q, nbits, true);
+            });
+            let mut ev = Evaluator::<MyChannel, AesRng, DummyReceiver>::new(
+                channel(receiver),
+                AesRng::new(),
+            )
+            .unwrap();
+            crt_and_binary(&mut ev, (0, y), Q, nbits, false);
+            handle.join().unwrap();
+            assert_eq!(ev.decode_output().unwrap(), should_be);
+        }
+    }
+
+    fn output_modes<F: FancyInput>(f: &mut F, inps: &[u16], moduli: &[u16], is_garbler: bool) {
+        let (xs, ys) = if is_garbler {
+            let xs = f.encode_many(inps, moduli).unwrap();
+            let ys = f.receive_many(moduli).unwrap();
+            (xs, ys)
+        } else {
+            let xs = f.receive_many(moduli).unwrap();
+            let ys = f.encode_many(inps, moduli).unwrap();
+            (xs, ys)
+        };
+        let modes = [
+            OutputMode::Evaluator,
+            OutputMode::Garbler,
+            OutputMode::Both,
+            OutputMode::Shared,
+        ];
+        for ((x, y), mode) in xs.iter().zip(ys.iter()).zip(modes.iter().cycle()) {
+            let z = f.mul(x, y).unwrap();
+            f.output_with_mode(&z, *mode).unwrap();
+        }
+        let z = Bundle::new(xs);
+        f.output_bundle_with_mode(&z, OutputMode::Shared).unwrap();
+    }
+
+    #[test]
+    fn semihonest_output_modes() {
+        let mut rng = thread_rng();
+        let moduli = (0..8).map(|_| rng.gen_modulus()).collect_vec();
+        let gb_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+        let ev_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let moduli_ = moduli.clone();
+        let gb_inps_ = gb_inps.clone();
+        let handle = std::thread::spawn(move || {
+            let mut gb =
+                Garbler::<MyChannel, AesRng, DummySender>::new(channel(sender), AesRng::new())
+                    .unwrap();
+            output_modes(&mut gb, &gb_inps_, &moduli_, true);
+            gb.get_output()
+        });
+        let mut ev =
+            Evaluator::<MyChannel, AesRng, DummyReceiver>::new(channel(receiver), AesRng::new())
+                .unwrap();
+        output_modes(&mut ev, &ev_inps, &moduli, false);
+        let mut gb_outs = handle.join().unwrap().into_iter();
+        let mut ev_outs = ev.decode_output().unwrap().into_iter();
+
+        for i in 0..8 {
+            let q = moduli[i];
+            let z = gb_inps[i] * ev_inps[i] % q;
+            match i % 4 {
+                0 => assert_eq!(ev_outs.next(), Some(z)),
+                1 => assert_eq!(gb_outs.next(), Some(z)),
+                2 => {
+                    assert_eq!(ev_outs.next(), Some(z));
+                    assert_eq!(gb_outs.next(), Some(z));
+                }
+                _ => {
+                    let share = (gb_outs.next().unwrap() + ev_outs.next().unwrap()) % q;
+                    assert_eq!(share, z);
+                }
+            }
+        }
+        for i in 0..8 {
+            let share = (gb_outs.next().unwrap() + ev_outs.next().unwrap()) % moduli[i];
+            assert_eq!(share, gb_inps[i]);
+        }
+        assert_eq!(gb_outs.next(), None);
+        assert_eq!(ev_outs.next(), None);
+    }
+
+    #[test]
+    fn malicious_inputs() {
+        let mut rng = thread_rng();
+        for _ in 0..4 {
+            let moduli = (0..8).map(|_| rng.gen_modulus()).collect_vec();
+            let gb_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+            let ev_inps = moduli.iter().map(|q| rng.gen_u16() % q).collect_vec();
+
+            let should_be = gb_inps
+                .iter()
+                .zip(ev_inps.iter())
+                .zip(moduli.iter())
+                .map(|((x, y), q)| (x * y + x) % q)
+                .collect_vec();
+
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let moduli_ = moduli.clone();
+            let gb_inps_ = gb_inps.clone();
+            let handle = std::thread::spawn(move || {
+                let mut gb = MaliciousGarbler::<MyChannel, AesRng, DummySender>::new(
+                    channel(sender),
+                    AesRng::new(),
+                    8,
+                )
+                .unwrap();
+                mul_add(&mut gb, &gb_inps_, &[], &moduli_, true);
+                gb.finish().unwrap();
+            });
+            let mut ev = MaliciousEvaluator::<MyChannel, AesRng, DummyReceiver>::new(
+                channel(receiver),
+                AesRng::new(),
+                8,
+            )
+            .unwrap();
+            mul_add(&mut ev, &[], &ev_inps, &moduli, false);
+            let res = ev.finish().unwrap();
+            handle.join().unwrap();
+            assert_eq!(res, should_be);
+        }
+    }
+
+    #[test]
+    fn malicious_bundles() {
+        let mut rng = thread_rng();
+        let qs = crate::util::primes_with_width(10);
+        let Q = crate::util::product(&qs);
+        let nbits = 8;
+        let x = rng.gen_u128() % Q;
+        let y = rng.gen_u128() % Q;
+
+        let mut should_be = crate::util::crt(x * y % Q, &qs);
+        let sum = (x % (1 << nbits) + y % (1 << nbits)) % (1 << nbits);
+        should_be.extend(crate::util::u128_to_bits(sum, nbits));
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut gb = MaliciousGarbler::<MyChannel, AesRng, DummySender>::new(
+                channel(sender),
+                AesRng::new(),
+                4,
+            )
+            .unwrap();
+            crt_and_binary(&mut gb, (x, 0), Q, nbits, true);
+            gb.finish().unwrap();
+        });
+        let mut ev = MaliciousEvaluator::<MyChannel, AesRng, DummyReceiver>::new(
+            channel(receiver),
+            AesRng::new(),
+            4,
+        )
+        .unwrap();
+        crt_and_binary(&mut ev, (0, y), Q, nbits, false);
+        let res = ev.finish().unwrap();
+        handle.join().unwrap();
+        assert_eq!(res, should_be);
+    }
+
+    #[test]
+    fn malicious_detects_wrong_circuit() {
+        let moduli = vec![5, 7, 2];
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let moduli_ = moduli.clone();
+        let handle = std::thread::spawn(move || {
+            let mut gb = MaliciousGarbler::<MyChannel, AesRng, DummySender>::new(
+                channel(sender),
+                AesRng::new(),
+                4,
+            )
+            .unwrap();
+            // garble a different computation than the evaluator expects
+            let xs = gb.encode_many(&[1, 2, 1], &moduli_).unwrap();
+            let ys = gb.receive_many(&moduli_).unwrap();
+            for (x, y) in xs.iter().zip(ys.iter()) {
+                let z = gb.sub(x, y).unwrap();
+                gb.output(&z).unwrap();
+            }
+            // the evaluator may hang up as soon as it catches the garbler cheating
+            let _ = gb.finish();
+        });
+        let mut ev = MaliciousEvaluator::<MyChannel, AesRng, DummyReceiver>::new(
+            channel(receiver),
+            AesRng::new(),
+            4,
+        )
+        .unwrap();
+        mul_add(&mut ev, &[], &[3, 4, 0], &moduli, false);
+        match ev.finish() {
+            Err(crate::error::EvaluatorError::CheatingDetected(_)) => (),
+            r => panic!("expected cheating to be detected, got {:?}", r),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn malicious_rejects_oversized_circuit() {
+        let moduli = vec![5, 7, 2];
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut channel = channel(sender);
+            DummySender::init(&mut channel, &mut AesRng::new()).unwrap();
+            // announce a garbled circuit far larger than the evaluator could store
+            channel.write_usize(usize::MAX).unwrap();
+            channel.flush().unwrap();
+        });
+        let mut ev = MaliciousEvaluator::<MyChannel, AesRng, DummyReceiver>::new(
+            channel(receiver),
+            AesRng::new(),
+            4,
+        )
+        .unwrap();
+        mul_add(&mut ev, &[], &[3, 4, 0], &moduli, false);
+        match ev.finish() {
+            Err(crate::error::EvaluatorError::CheatingDetected(_)) => (),
+            r => panic!("expected cheating to be detected, got {:?}", r),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn input_bits_covers_modulus() {
+        for q in 2..1000 {
+            let n = input_bits(q);
+            assert!(1 << n >= q as usize);
+            assert!(1 << (n - 1) < q as usize);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::input_bits;
+use crate::error::{EvaluatorError, FancyError};
+use crate::fancy::{Branch, Fancy, FancyBranch, FancyInput, OutputMode};
+use crate::garble::Evaluator as Ev;
+use crate::ot::OtReceiver;
+use crate::wire::Wire;
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+
+/// Semi-honest two-party evaluator, which obtains the wirelabels for its own inputs
+/// using oblivious transfer.
+pub struct Evaluator<C, RNG, OT> {
+    evaluator: Ev<C>,
+    channel: C,
+    ot: OT,
+    rng: RNG,
+}
+
+impl<C, RNG, OT> Evaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtReceiver,
+{
+    /// Create a new two-party evaluator, running the OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG) -> Result<Self, EvaluatorError> {
+        let ot = OT::init(&mut channel, &mut rng)?;
+        let evaluator = Ev::new(channel.clone());
+        Ok(Evaluator {
+            evaluator,
+            channel,
+            ot,
+            rng,
+        })
+    }
+
+    /// Decode the outputs revealed to the evaluator during the Fancy computation, and
+    /// the evaluator's shares of shared outputs, in the order they were output.
+    pub fn decode_output(&self) -> Result<Vec<u16>, EvaluatorError> {
+        self.evaluator.decode_output()
+    }
+}
+
+impl<C, RNG, OT> FancyInput for Evaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtReceiver,
+{
+    /// Obliviously receive the wirelabels for the evaluator's own inputs.
+    fn encode_many(&mut self, values: &[u16], moduli: &[u16]) -> Result<Vec<Wire>, EvaluatorError> {
+        if values.len() != moduli.len() {
+            return Err(EvaluatorError::FancyError(FancyError::InvalidArgNum {
+                got: values.len(),
+                needed: moduli.len(),
+            }));
+        }
+        let choices = values
+            .iter()
+            .zip(moduli.iter())
+            .flat_map(|(x, q)| (0..input_bits(*q)).map(move |i| x & (1 << i) != 0))
+            .collect::<Vec<bool>>();
+        let labels = self
+            .ot
+            .receive(&mut self.channel, &choices, &mut self.rng)?;
+        let mut labels = labels.into_iter();
+        let wires = moduli
+            .iter()
+            .map(|q| {
+                let mut wire = Wire::zero(*q);
+                for i in 0..input_bits(*q) {
+                    let label = labels
+                        .next()
+                        .ok_or(EvaluatorError::NotEnoughEvaluatorInputs)?;
+                    wire.plus_eq(&Wire::from_block(label, *q).cmul(1 << i));
+                }
+                Ok(wire)
+            })
+            .collect();
+        wires
+    }
+
+    /// Receive the wirelabels for the garbler's inputs.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<Wire>, EvaluatorError> {
+        moduli
+            .iter()
+            .map(|q| self.evaluator.read_wire(*q))
+            .collect()
+    }
+}
+
+impl<C, RNG, OT> FancyBranch for Evaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+{
+    #[inline]
+    fn branch(
+        &mut self,
+        cond: &Wire,
+        inputs: &[Wire],
+        branches: &[Branch],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        self.evaluator.branch(cond, inputs, branches)
+    }
+}
+
+impl<C, RNG, OT> Fancy for Evaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+{
+    type Item = Wire;
+    type Error = EvaluatorError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wire, EvaluatorError> {
+        self.evaluator.constant(x, q)
+    }
+
+    #[inline]
+    fn add(&mut self, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        self.evaluator.add(x, y)
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        self.evaluator.sub(x, y)
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &Wire, c: u16) -> Result<Wire, EvaluatorError> {
+        self.evaluator.cmul(x, c)
+    }
+
+    #[inline]
+    fn mul(&mut self, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        self.evaluator.mul(x, y)
+    }
+
+    #[inline]
+    fn proj(&mut self, x: &Wire, q: u16, tt: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
+        self.evaluator.proj(x, q, tt)
+    }
+
+    #[inline]
+    fn output(&mut self, x: &Wire) -> Result<(), EvaluatorError> {
+        self.evaluator.output(x)
+    }
+
+    #[inline]
+    fn output_with_mode(&mut self, x: &Wire, mode: OutputMode) -> Result<(), EvaluatorError> {
+        self.evaluator.output_with_mode(x, mode)
+    }
+}
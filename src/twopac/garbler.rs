@@ -0,0 +1,162 @@
+use super::input_bits;
+use crate::error::GarblerError;
+use crate::fancy::{Branch, Fancy, FancyBranch, FancyInput, OutputMode};
+use crate::garble::Garbler as Gb;
+use crate::ot::OtSender;
+use crate::wire::Wire;
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{AbstractChannel, Block};
+use std::collections::HashMap;
+
+/// Semi-honest two-party garbler, which delivers the evaluator's input wirelabels
+/// using oblivious transfer.
+pub struct Garbler<C, RNG, OT> {
+    garbler: Gb<C, RNG>,
+    channel: C,
+    ot: OT,
+    rng: RNG,
+}
+
+impl<C, RNG, OT> Garbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore + SeedableRng<Seed = Block>,
+    OT: OtSender,
+{
+    /// Create a new two-party garbler, running the OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG) -> Result<Self, GarblerError> {
+        let ot = OT::init(&mut channel, &mut rng)?;
+        let garbler = Gb::new(channel.clone(), RNG::from_seed(rng.gen()), &[]);
+        Ok(Garbler {
+            garbler,
+            channel,
+            ot,
+            rng,
+        })
+    }
+
+    /// The outputs revealed to the garbler, and the garbler's shares of shared outputs,
+    /// in the order they were output.
+    pub fn get_output(&self) -> Vec<u16> {
+        self.garbler.get_output()
+    }
+
+    /// Get the deltas, consuming the Garbler.
+    pub fn get_deltas(self) -> HashMap<u16, Wire> {
+        self.garbler.get_deltas()
+    }
+
+    /// Create the zero wire for an evaluator input of modulus `q`, along with the OT
+    /// inputs for each bit of the value.
+    ///
+    /// The zero wire is the sum of the zero wires of the bits, weighted by their
+    /// significance, so the evaluator can recombine the labels it receives.
+    fn evaluator_input(&mut self, q: u16) -> (Wire, Vec<(Block, Block)>) {
+        let delta = self.garbler.delta(q);
+        let mut wire = Wire::zero(q);
+        let inputs = (0..input_bits(q))
+            .map(|i| {
+                let zero = Wire::rand(&mut self.rng, q);
+                let one = zero.plus(&delta);
+                wire.plus_eq(&zero.cmul(1 << i));
+                (zero.as_block(), one.as_block())
+            })
+            .collect();
+        (wire, inputs)
+    }
+}
+
+impl<C, RNG, OT> FancyInput for Garbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore + SeedableRng<Seed = Block>,
+    OT: OtSender,
+{
+    /// Encode the garbler's own inputs, sending the evaluator their wirelabels.
+    fn encode_many(&mut self, values: &[u16], moduli: &[u16]) -> Result<Vec<Wire>, GarblerError> {
+        let (gbs, evs) = self.garbler.encode_many_wires(values, moduli)?;
+        for w in evs.iter() {
+            self.garbler.send_wire(w)?;
+        }
+        self.channel.flush()?;
+        Ok(gbs)
+    }
+
+    /// Obliviously send the evaluator the wirelabels for its inputs.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<Wire>, GarblerError> {
+        let mut wires = Vec::with_capacity(moduli.len());
+        let mut inputs = Vec::new();
+        for q in moduli.iter() {
+            let (wire, ot_inputs) = self.evaluator_input(*q);
+            wires.push(wire);
+            inputs.extend(ot_inputs);
+        }
+        self.ot.send(&mut self.channel, &inputs, &mut self.rng)?;
+        Ok(wires)
+    }
+}
+
+impl<C, RNG, OT> FancyBranch for Garbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    #[inline]
+    fn branch(
+        &mut self,
+        cond: &Wire,
+        inputs: &[Wire],
+        branches: &[Branch],
+    ) -> Result<Vec<Wire>, GarblerError> {
+        self.garbler.branch(cond, inputs, branches)
+    }
+}
+
+impl<C, RNG, OT> Fancy for Garbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    type Item = Wire;
+    type Error = GarblerError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wire, GarblerError> {
+        self.garbler.constant(x, q)
+    }
+
+    #[inline]
+    fn add(&mut self, x: &Wire, y: &Wire) -> Result<Wire, GarblerError> {
+        self.garbler.add(x, y)
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &Wire, y: &Wire) -> Result<Wire, GarblerError> {
+        self.garbler.sub(x, y)
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &Wire, c: u16) -> Result<Wire, GarblerError> {
+        self.garbler.cmul(x, c)
+    }
+
+    #[inline]
+    fn mul(&mut self, x: &Wire, y: &Wire) -> Result<Wire, GarblerError> {
+        self.garbler.mul(x, y)
+    }
+
+    #[inline]
+    fn proj(&mut self, x: &Wire, q: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
+        self.garbler.proj(x, q, tt)
+    }
+
+    #[inline]
+    fn output(&mut self, x: &Wire) -> Result<(), GarblerError> {
+        self.garbler.output(x)
+    }
+
+    #[inline]
+    fn output_with_mode(&mut self, x: &Wire, mode: OutputMode) -> Result<(), GarblerError> {
+        self.garbler.output_with_mode(x, mode)
+    }
+}
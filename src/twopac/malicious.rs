@@ -0,0 +1,790 @@
+//! Malicious-secure two-party computation using cut-and-choose.
+//!
+//! `MaliciousGarbler` and `MaliciousEvaluator` implement `Fancy` by building a
+//! `Circuit`, so existing gadget code runs unchanged. The protocol itself runs in
+//! `finish`, once the computation is known:
+//!
+//! 1. The garbler garbles `ncircuits` copies of the circuit with the static `garble`
+//!    API, each from its own random seed, and sends them all. It then commits to its
+//!    input wirelabels for every copy.
+//! 2. The evaluator picks a random linear hash of the garbler's inputs, and for every
+//!    copy the garbler sends the output ciphertexts of that hash, computed with free
+//!    gates on its input wirelabels.
+//! 3. The evaluator obtains a key for each bit of its inputs by oblivious transfer.
+//!    The garbler sends the evaluator's input wirelabels for every copy encrypted
+//!    under these keys, so the evaluator uses the same input in every copy.
+//! 4. The evaluator picks a random half of the copies to check, and the garbler
+//!    reveals their seeds. The evaluator regenerates each checked copy from its
+//!    seed, and aborts with `EvaluatorError::CheatingDetected` if it differs from
+//!    what was sent, if the input wirelabels it received are wrong, or if the hash
+//!    ciphertexts are wrong.
+//! 5. The garbler opens its commitments to its input wirelabels for the remaining
+//!    copies. The evaluator aborts unless the hash of the garbler's inputs decodes to
+//!    the same value in all of them, then evaluates them and outputs the majority
+//!    result.
+//!
+//! A garbler which corrupts enough copies to change the majority output is caught
+//! except with probability roughly `2^(-0.32 * ncircuits)`, so `ncircuits = 40`
+//! gives about 12 bits of statistical security and `ncircuits = 128` about 40.
+//!
+//! The garbler's inputs are checked for consistency across the evaluated copies as
+//! in Shelat and Shen, "Two-Output Secure Computation with Malicious Adversaries"
+//! (EUROCRYPT 2011). The garbler's input wirelabels are fixed before the hash is
+//! chosen, so copies evaluated on different inputs hash to the same value with
+//! probability at most `2^-40`. The circuit has extra garbler inputs holding random
+//! pads, one added to each row of the hash, so the hash reveals nothing about the
+//! garbler's inputs.
+//!
+//! Each bit of the evaluator's inputs is split into `INPUT_SHARES` random bits which
+//! XOR to it, as in Lindell and Pinkas, "An Efficient Protocol for Secure Two-Party
+//! Computation in the Presence of Malicious Adversaries" (EUROCRYPT 2007). Any
+//! `INPUT_SHARES - 1` of them are uniform, so a garbler which corrupts some of the
+//! evaluator's input wirelabels and watches whether the evaluator aborts (a selective
+//! failure attack) learns nothing about the evaluator's input, unless it corrupts a
+//! wirelabel for every share of a bit, in which case the evaluator aborts except with
+//! probability `2^-(INPUT_SHARES - 1)`.
+//!
+//! Security against a malicious evaluator relies on the `OtSender` and `OtReceiver`
+//! implementations also being malicious-secure.
+
+use super::input_bits;
+use crate::circuit::{CircuitBuilder, CircuitRef};
+use crate::error::{CircuitBuilderError, EvaluatorError, FancyError, GarblerError};
+use crate::fancy::{Fancy, FancyInput, HasModulus};
+use crate::garble::garble_output;
+use crate::ot::{OtReceiver, OtSender};
+use crate::r#static::{garble_with_rng, Encoder, GarbledCircuit};
+use crate::util::{output_tweak, RngExt};
+use crate::wire::Wire;
+use itertools::Itertools;
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::commitment::{Commitment, ShaCommitment};
+use scuttlebutt::{AbstractChannel, AesRng, Block, AES_HASH};
+
+/// The statistical security parameter of the checks on the inputs.
+const STATISTICAL_SECURITY: usize = 40;
+
+/// The number of random bits each bit of the evaluator's inputs is split into.
+const INPUT_SHARES: usize = STATISTICAL_SECURITY;
+
+/// Malicious-secure two-party garbler, using cut-and-choose over `ncircuits`
+/// garbled circuits.
+pub struct MaliciousGarbler<C, RNG, OT> {
+    builder: CircuitBuilder,
+    inputs: Vec<u16>,
+    garbler_moduli: Vec<u16>,
+    channel: C,
+    ot: OT,
+    rng: RNG,
+    ncircuits: usize,
+}
+
+/// Malicious-secure two-party evaluator, using cut-and-choose over `ncircuits`
+/// garbled circuits.
+pub struct MaliciousEvaluator<C, RNG, OT> {
+    builder: CircuitBuilder,
+    inputs: Vec<u16>,
+    garbler_moduli: Vec<u16>,
+    channel: C,
+    ot: OT,
+    rng: RNG,
+    ncircuits: usize,
+}
+
+/// Derive the OT inputs for the evaluator's inputs in a garbled circuit, one pair of
+/// wirelabels for each bit of each input.
+///
+/// The labels for the bits of an input, weighted by their significance, sum to the
+/// label of the input itself.
+fn evaluator_input_labels(en: &Encoder, rng: &mut AesRng) -> Vec<(Wire, Wire)> {
+    let mut labels = Vec::new();
+    for zero in en.evaluator_inputs.iter() {
+        let q = zero.modulus();
+        let delta = &en.deltas[&q];
+        let zeros = (1..input_bits(q))
+            .map(|i| (i, Wire::rand(rng, q)))
+            .collect::<Vec<(usize, Wire)>>();
+        let mut first = zero.clone();
+        for (i, w) in zeros.iter() {
+            first.minus_eq(&w.cmul(1 << i));
+        }
+        for w in std::iter::once(first).chain(zeros.into_iter().map(|(_, w)| w)) {
+            let one = w.plus(delta);
+            labels.push((w, one));
+        }
+    }
+    labels
+}
+
+/// Mask for the wirelabel of evaluator input bit `bit` in circuit `circuit`,
+/// derived from an OT key.
+fn mask(key: Block, circuit: usize, bit: usize) -> Block {
+    let tweak = Block::from(((circuit as u128) << 64) | bit as u128);
+    AES_HASH.tccr_hash(tweak, key)
+}
+
+/// Add an evaluator input of modulus `q`, built from `INPUT_SHARES` evaluator inputs
+/// mod 2 for each of its bits, which XOR to the bit.
+fn shared_evaluator_input(
+    b: &mut CircuitBuilder,
+    q: u16,
+) -> Result<CircuitRef, CircuitBuilderError> {
+    let mut x: Option<CircuitRef> = None;
+    for k in 0..input_bits(q) {
+        let shares = b.evaluator_inputs(&[2; INPUT_SHARES]);
+        let mut bit = shares[0];
+        for share in shares[1..].iter() {
+            bit = b.add(&bit, share)?;
+        }
+        if q != 2 {
+            bit = b.mod_change(&bit, q)?;
+        }
+        let term = b.cmul(&bit, ((1u32 << k) % q as u32) as u16)?;
+        x = Some(match x {
+            Some(x) => b.add(&x, &term)?,
+            None => term,
+        });
+    }
+    Ok(x.expect("every modulus has at least one bit"))
+}
+
+/// Split the bits of the evaluator's input `x` of modulus `q` into random shares, in
+/// the order of the inputs created by `shared_evaluator_input`.
+fn share_bits<R: Rng>(x: u16, q: u16, rng: &mut R) -> Vec<u16> {
+    let mut shares = Vec::with_capacity(input_bits(q) * INPUT_SHARES);
+    for k in 0..input_bits(q) {
+        let rest = (1..INPUT_SHARES)
+            .map(|_| rng.gen::<bool>() as u16)
+            .collect::<Vec<u16>>();
+        shares.push(rest.iter().fold((x >> k) & 1, |acc, s| acc ^ s));
+        shares.extend(rest);
+    }
+    shares
+}
+
+/// The moduli of the pads for the garbler's inputs of moduli `moduli`, one for each
+/// row of the hash checking their consistency.
+///
+/// Two different inputs of modulus `q` collide in a row with probability at most
+/// `1/p` for the smallest prime factor `p` of `q`, so there are enough rows of each
+/// modulus for `STATISTICAL_SECURITY` bits.
+fn input_pads(moduli: &[u16]) -> Vec<u16> {
+    moduli
+        .iter()
+        .cloned()
+        .sorted()
+        .dedup()
+        .flat_map(|q| {
+            let p = (2..=q).find(|p| q % p == 0).expect("q divides itself");
+            let rows = (STATISTICAL_SECURITY as f64 / f64::from(p).log2()).ceil() as usize;
+            vec![q; rows]
+        })
+        .collect()
+}
+
+/// A random linear hash of the garbler's inputs. Each row is a pad plus a random
+/// linear combination of the garbler's inputs of the same modulus.
+struct InputHash {
+    /// The index of the pad and the coefficient of each input, for each row.
+    rows: Vec<(usize, Vec<(usize, u16)>)>,
+}
+
+impl InputHash {
+    /// The hash derived from `seed`, for garbler inputs of moduli `moduli` followed
+    /// by pads of moduli `pads`.
+    fn new(moduli: &[u16], pads: &[u16], seed: Block) -> Self {
+        let mut rng = AesRng::from_seed(seed);
+        let rows = pads
+            .iter()
+            .enumerate()
+            .map(|(j, q)| {
+                let coeffs = moduli
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| *p == q)
+                    .map(|(i, _)| (i, rng.gen_u16() % q))
+                    .collect();
+                (moduli.len() + j, coeffs)
+            })
+            .collect();
+        InputHash { rows }
+    }
+
+    /// Compute the hash on the garbler's input wires `xs`, including the pads.
+    fn eval(&self, xs: &[Wire]) -> Vec<Wire> {
+        self.rows
+            .iter()
+            .map(|(pad, coeffs)| {
+                let mut z = xs[*pad].clone();
+                for (i, c) in coeffs.iter() {
+                    z.plus_eq(&xs[*i].cmul(*c));
+                }
+                z
+            })
+            .collect()
+    }
+
+    /// The output ciphertexts of the hash in the garbled circuit with encoder `en`,
+    /// numbered after the `noutputs` outputs of the circuit.
+    fn garble(&self, en: &Encoder, noutputs: usize) -> Vec<Vec<Block>> {
+        self.eval(&en.garbler_inputs)
+            .iter()
+            .enumerate()
+            .map(|(k, Z)| garble_output(Z, noutputs + k, &en.deltas[&Z.modulus()]))
+            .collect()
+    }
+
+    /// Decode the hash of the garbler's input wirelabels `xs` using the output
+    /// ciphertexts `cts`.
+    fn decode(&self, xs: &[Wire], cts: &[Vec<Block>], noutputs: usize) -> Option<Vec<u16>> {
+        self.eval(xs)
+            .iter()
+            .zip(cts.iter())
+            .enumerate()
+            .map(|(k, (Z, cts))| {
+                (0..Z.modulus())
+                    .find(|x| Z.hash(output_tweak(noutputs + k, *x)) == cts[*x as usize])
+            })
+            .collect()
+    }
+}
+
+/// Commit to the garbler's input wirelabels `inputs` with the randomness `opening`.
+fn commit(opening: [u8; 32], inputs: &[Wire]) -> [u8; 32] {
+    let mut commitment = ShaCommitment::new(opening);
+    for w in inputs.iter() {
+        commitment.input(w.as_block().as_ref());
+    }
+    commitment.finish()
+}
+
+impl<C, RNG, OT> MaliciousGarbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtSender,
+{
+    /// Create a new malicious garbler using `ncircuits` garbled circuits, running the
+    /// OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG, ncircuits: usize) -> Result<Self, GarblerError> {
+        if ncircuits < 2 {
+            return Err(GarblerError::FancyError(FancyError::InvalidArg(
+                "cut-and-choose requires at least two circuits".to_string(),
+            )));
+        }
+        let ot = OT::init(&mut channel, &mut rng)?;
+        Ok(MaliciousGarbler {
+            builder: CircuitBuilder::new(),
+            inputs: Vec::new(),
+            garbler_moduli: Vec::new(),
+            channel,
+            ot,
+            rng,
+            ncircuits,
+        })
+    }
+
+    /// Run the cut-and-choose protocol on the computation built so far.
+    pub fn finish(mut self) -> Result<(), GarblerError> {
+        let pads = input_pads(&self.garbler_moduli);
+        for q in pads.iter() {
+            let pad = self.rng.gen_u16() % q;
+            self.inputs.push(pad);
+        }
+        self.builder.garbler_inputs(&pads);
+        let mut c = std::mem::replace(&mut self.builder, CircuitBuilder::new()).finish();
+
+        let seeds = (0..self.ncircuits)
+            .map(|_| self.rng.gen())
+            .collect::<Vec<Block>>();
+        let mut encoders = Vec::with_capacity(self.ncircuits);
+        let mut labels = Vec::with_capacity(self.ncircuits);
+        for seed in seeds.iter() {
+            let mut rng = AesRng::from_seed(*seed);
+            let (en, gc) = garble_with_rng(&mut c, &mut rng)?;
+            labels.push(evaluator_input_labels(&en, &mut rng));
+            encoders.push(en);
+            self.channel.write_usize(gc.size())?;
+            for block in gc.blocks.iter() {
+                self.channel.write_block(block)?;
+            }
+        }
+        let inputs = encoders
+            .iter()
+            .map(|en| en.encode_garbler_inputs(&self.inputs))
+            .collect::<Vec<Vec<Wire>>>();
+        let openings = (0..self.ncircuits)
+            .map(|_| self.rng.gen::<[u8; 32]>())
+            .collect::<Vec<[u8; 32]>>();
+        for (opening, inputs) in openings.iter().zip(inputs.iter()) {
+            self.channel.write_bytes(&commit(*opening, inputs))?;
+        }
+        self.channel.flush()?;
+
+        let hash = InputHash::new(&self.garbler_moduli, &pads, self.channel.read_block()?);
+        for en in encoders.iter() {
+            for cts in hash.garble(en, c.noutputs()) {
+                for block in cts.iter() {
+                    self.channel.write_block(block)?;
+                }
+            }
+        }
+        self.channel.flush()?;
+
+        let nbits = labels[0].len();
+        let keys = (0..nbits)
+            .map(|_| (self.rng.gen(), self.rng.gen()))
+            .collect::<Vec<(Block, Block)>>();
+        self.ot.send(&mut self.channel, &keys, &mut self.rng)?;
+        for (i, labels) in labels.iter().enumerate() {
+            for (j, ((zero, one), (k0, k1))) in labels.iter().zip(keys.iter()).enumerate() {
+                self.channel
+                    .write_block(&(zero.as_block() ^ mask(*k0, i, j)))?;
+                self.channel
+                    .write_block(&(one.as_block() ^ mask(*k1, i, j)))?;
+            }
+        }
+        self.channel.flush()?;
+
+        let check = (0..self.ncircuits)
+            .map(|_| self.channel.read_bool())
+            .collect::<Result<Vec<bool>, std::io::Error>>()?;
+        if check.iter().all(|b| *b) {
+            return Err(GarblerError::CommunicationError(
+                "evaluator asked to check every circuit".to_string(),
+            ));
+        }
+        for i in 0..self.ncircuits {
+            if check[i] {
+                self.channel.write_block(&seeds[i])?;
+            } else {
+                self.channel.write_bytes(&openings[i])?;
+                for w in inputs[i].iter() {
+                    self.channel.write_block(&w.as_block())?;
+                }
+            }
+        }
+        self.channel.flush()?;
+        Ok(())
+    }
+}
+
+impl<C, RNG, OT> MaliciousEvaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtReceiver,
+{
+    /// Create a new malicious evaluator using `ncircuits` garbled circuits, running
+    /// the OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG, ncircuits: usize) -> Result<Self, EvaluatorError> {
+        if ncircuits < 2 {
+            return Err(EvaluatorError::FancyError(FancyError::InvalidArg(
+                "cut-and-choose requires at least two circuits".to_string(),
+            )));
+        }
+        let ot = OT::init(&mut channel, &mut rng)?;
+        Ok(MaliciousEvaluator {
+            builder: CircuitBuilder::new(),
+            inputs: Vec::new(),
+            garbler_moduli: Vec::new(),
+            channel,
+            ot,
+            rng,
+            ncircuits,
+        })
+    }
+
+    /// Run the cut-and-choose protocol on the computation built so far, returning
+    /// the decoded outputs.
+    pub fn finish(mut self) -> Result<Vec<u16>, EvaluatorError> {
+        let pads = input_pads(&self.garbler_moduli);
+        self.builder.garbler_inputs(&pads);
+        let mut c = std::mem::replace(&mut self.builder, CircuitBuilder::new()).finish();
+
+        // every circuit is garbled with the default schemes, so anything but the size
+        // they produce is the garbler cheating, and must not be allocated
+        let size = c.garbled_size()?;
+        let mut gcs = Vec::with_capacity(self.ncircuits);
+        for _ in 0..self.ncircuits {
+            let n = self.channel.read_usize()?;
+            if n != size {
+                return Err(EvaluatorError::CheatingDetected(format!(
+                    "garbled circuit of {} blocks instead of {}",
+                    n, size
+                )));
+            }
+            let blocks = (0..n)
+                .map(|_| self.channel.read_block())
+                .collect::<Result<Vec<Block>, std::io::Error>>()?;
+            gcs.push(GarbledCircuit::new(blocks));
+        }
+        let mut commitments = vec![[0; 32]; self.ncircuits];
+        for commitment in commitments.iter_mut() {
+            self.channel.read_bytes(commitment)?;
+        }
+
+        let seed = self.rng.gen::<Block>();
+        self.channel.write_block(&seed)?;
+        self.channel.flush()?;
+        let hash = InputHash::new(&self.garbler_moduli, &pads, seed);
+        let mut hash_cts = Vec::with_capacity(self.ncircuits);
+        for _ in 0..self.ncircuits {
+            let cts = pads
+                .iter()
+                .map(|q| self.channel.read_blocks(*q as usize))
+                .collect::<Result<Vec<Vec<Block>>, std::io::Error>>()?;
+            hash_cts.push(cts);
+        }
+
+        let moduli = c
+            .evaluator_input_refs
+            .iter()
+            .map(|r| r.modulus())
+            .collect::<Vec<u16>>();
+        let choices = self
+            .inputs
+            .iter()
+            .zip(moduli.iter())
+            .flat_map(|(x, q)| (0..input_bits(*q)).map(move |i| x & (1 << i) != 0))
+            .collect::<Vec<bool>>();
+        let keys = self
+            .ot
+            .receive(&mut self.channel, &choices, &mut self.rng)?;
+        // The evaluator's input wirelabels in each circuit, for the bits it chose.
+        let mut labels = Vec::with_capacity(self.ncircuits);
+        for i in 0..self.ncircuits {
+            let mut bits = Vec::with_capacity(choices.len());
+            for (j, (b, key)) in choices.iter().zip(keys.iter()).enumerate() {
+                let zero = self.channel.read_block()?;
+                let one = self.channel.read_block()?;
+                bits.push(if *b { one } else { zero } ^ mask(*key, i, j));
+            }
+            labels.push(bits);
+        }
+
+        let mut check = vec![false; self.ncircuits];
+        for i in
+            rand::seq::index::sample(&mut self.rng, self.ncircuits, self.ncircuits / 2).into_iter()
+        {
+            check[i] = true;
+        }
+        for b in check.iter() {
+            self.channel.write_bool(*b)?;
+        }
+        self.channel.flush()?;
+
+        let mut results = Vec::new();
+        let mut size = None;
+        let mut hashes = Vec::new();
+        for i in 0..self.ncircuits {
+            if check[i] {
+                let seed = self.channel.read_block()?;
+                let mut rng = AesRng::from_seed(seed);
+                let (en, gc) = garble_with_rng(&mut c, &mut rng)
+                    .map_err(|e| EvaluatorError::CommunicationError(e.to_string()))?;
+                if gc.blocks != gcs[i].blocks {
+                    return Err(EvaluatorError::CheatingDetected(format!(
+                        "garbled circuit {} does not match its seed",
+                        i
+                    )));
+                }
+                let expected = evaluator_input_labels(&en, &mut rng);
+                for ((zero, one), (b, label)) in
+                    expected.iter().zip(choices.iter().zip(labels[i].iter()))
+                {
+                    if (if *b { one } else { zero }).as_block() != *label {
+                        return Err(EvaluatorError::CheatingDetected(format!(
+                            "evaluator input wirelabels for circuit {} are invalid",
+                            i
+                        )));
+                    }
+                }
+                if hash.garble(&en, c.noutputs()) != hash_cts[i] {
+                    return Err(EvaluatorError::CheatingDetected(format!(
+                        "input hash ciphertexts for circuit {} do not match its seed",
+                        i
+                    )));
+                }
+                size = Some(gc.size());
+            } else {
+                let mut opening = [0; 32];
+                self.channel.read_bytes(&mut opening)?;
+                let gb_inps = c
+                    .garbler_input_refs
+                    .iter()
+                    .map(|r| Ok(Wire::from_block(self.channel.read_block()?, r.modulus())))
+                    .collect::<Result<Vec<Wire>, EvaluatorError>>()?;
+                if !ShaCommitment::check(&commitments[i], &commit(opening, &gb_inps)) {
+                    return Err(EvaluatorError::CheatingDetected(format!(
+                        "garbler input wirelabels for circuit {} do not match the commitment",
+                        i
+                    )));
+                }
+                hashes.push(hash.decode(&gb_inps, &hash_cts[i], c.noutputs()));
+                results.push((i, gb_inps));
+            }
+        }
+        if hashes.iter().any(|h| h.is_none() || *h != hashes[0]) {
+            return Err(EvaluatorError::CheatingDetected(
+                "the garbler used different inputs in the evaluated circuits".to_string(),
+            ));
+        }
+
+        // Every evaluated circuit has the same size as the checked circuits, so
+        // evaluating it cannot run out of ciphertexts.
+        let size = size.expect("at least one circuit is checked");
+        let mut outputs: Vec<(Vec<u16>, usize)> = Vec::new();
+        let nevaluated = results.len();
+        for (i, gb_inps) in results {
+            if gcs[i].size() != size {
+                continue;
+            }
+            let mut bits = labels[i].iter();
+            let ev_inps = moduli
+                .iter()
+                .map(|q| {
+                    let mut wire = Wire::zero(*q);
+                    for k in 0..input_bits(*q) {
+                        let label = bits.next().expect("one label per input bit");
+                        wire.plus_eq(&Wire::from_block(*label, *q).cmul(1 << k));
+                    }
+                    wire
+                })
+                .collect::<Vec<Wire>>();
+            if let Ok(out) = gcs[i].eval(&mut c, &gb_inps, &ev_inps) {
+                match outputs.iter_mut().find(|(o, _)| *o == out) {
+                    Some((_, n)) => *n += 1,
+                    None => outputs.push((out, 1)),
+                }
+            }
+        }
+        outputs
+            .into_iter()
+            .find(|(_, n)| 2 * n > nevaluated)
+            .map(|(out, _)| out)
+            .ok_or_else(|| {
+                EvaluatorError::CheatingDetected(
+                    "the evaluated circuits do not agree on the output".to_string(),
+                )
+            })
+    }
+}
+
+impl<C, RNG, OT> FancyInput for MaliciousGarbler<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtSender,
+{
+    /// Add inputs for the garbler's own values.
+    fn encode_many(
+        &mut self,
+        values: &[u16],
+        moduli: &[u16],
+    ) -> Result<Vec<CircuitRef>, GarblerError> {
+        if values.len() != moduli.len() {
+            return Err(GarblerError::EncodingError);
+        }
+        self.inputs.extend_from_slice(values);
+        self.garbler_moduli.extend_from_slice(moduli);
+        Ok(self.builder.garbler_inputs(moduli))
+    }
+
+    /// Add inputs for the evaluator's values, each built from shares of its bits.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<CircuitRef>, GarblerError> {
+        moduli
+            .iter()
+            .map(|q| Ok(shared_evaluator_input(&mut self.builder, *q)?))
+            .collect()
+    }
+}
+
+impl<C, RNG, OT> FancyInput for MaliciousEvaluator<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtReceiver,
+{
+    /// Add inputs for the evaluator's own values, each built from shares of its bits.
+    fn encode_many(
+        &mut self,
+        values: &[u16],
+        moduli: &[u16],
+    ) -> Result<Vec<CircuitRef>, EvaluatorError> {
+        if values.len() != moduli.len() {
+            return Err(EvaluatorError::FancyError(FancyError::InvalidArgNum {
+                got: values.len(),
+                needed: moduli.len(),
+            }));
+        }
+        let mut xs = Vec::with_capacity(values.len());
+        for (x, q) in values.iter().zip(moduli.iter()) {
+            let shares = share_bits(*x, *q, &mut self.rng);
+            self.inputs.extend(shares);
+            xs.push(shared_evaluator_input(&mut self.builder, *q)?);
+        }
+        Ok(xs)
+    }
+
+    /// Add inputs for the garbler's values.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<CircuitRef>, EvaluatorError> {
+        self.garbler_moduli.extend_from_slice(moduli);
+        Ok(self.builder.garbler_inputs(moduli))
+    }
+}
+
+impl<C, RNG, OT> Fancy for MaliciousGarbler<C, RNG, OT> {
+    type Item = CircuitRef;
+    type Error = GarblerError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.constant(x, q)?)
+    }
+
+    #[inline]
+    fn add(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.add(x, y)?)
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.sub(x, y)?)
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &CircuitRef, c: u16) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.cmul(x, c)?)
+    }
+
+    #[inline]
+    fn mul(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.mul(x, y)?)
+    }
+
+    #[inline]
+    fn proj(
+        &mut self,
+        x: &CircuitRef,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<CircuitRef, GarblerError> {
+        Ok(self.builder.proj(x, q, tt)?)
+    }
+
+    #[inline]
+    fn output(&mut self, x: &CircuitRef) -> Result<(), GarblerError> {
+        Ok(self.builder.output(x)?)
+    }
+}
+
+impl<C, RNG, OT> Fancy for MaliciousEvaluator<C, RNG, OT> {
+    type Item = CircuitRef;
+    type Error = EvaluatorError;
+
+    #[inline]
+    fn constant(&mut self, x: u16, q: u16) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.constant(x, q)?)
+    }
+
+    #[inline]
+    fn add(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.add(x, y)?)
+    }
+
+    #[inline]
+    fn sub(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.sub(x, y)?)
+    }
+
+    #[inline]
+    fn cmul(&mut self, x: &CircuitRef, c: u16) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.cmul(x, c)?)
+    }
+
+    #[inline]
+    fn mul(&mut self, x: &CircuitRef, y: &CircuitRef) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.mul(x, y)?)
+    }
+
+    #[inline]
+    fn proj(
+        &mut self,
+        x: &CircuitRef,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<CircuitRef, EvaluatorError> {
+        Ok(self.builder.proj(x, q, tt)?)
+    }
+
+    #[inline]
+    fn output(&mut self, x: &CircuitRef) -> Result<(), EvaluatorError> {
+        Ok(self.builder.output(x)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn shared_evaluator_inputs() {
+        let mut rng = thread_rng();
+        let moduli = [2, 3, 5, 16, 113];
+        let mut b = CircuitBuilder::new();
+        for q in moduli.iter() {
+            let x = shared_evaluator_input(&mut b, *q).unwrap();
+            b.output(&x).unwrap();
+        }
+        let mut c = b.finish();
+        for _ in 0..16 {
+            let xs = moduli
+                .iter()
+                .map(|q| rng.gen_u16() % q)
+                .collect::<Vec<u16>>();
+            let shares = xs
+                .iter()
+                .zip(moduli.iter())
+                .flat_map(|(x, q)| share_bits(*x, *q, &mut rng))
+                .collect::<Vec<u16>>();
+            assert_eq!(c.eval_plain(&[], &shares).unwrap(), xs);
+        }
+    }
+
+    #[test]
+    fn input_hash() {
+        let mut rng = thread_rng();
+        let moduli = [2, 5, 5, 7, 2];
+        let pads = input_pads(&moduli);
+        let mut b = CircuitBuilder::new();
+        let xs = b.garbler_inputs(&moduli);
+        b.garbler_inputs(&pads);
+        let z = b.add(&xs[1], &xs[2]).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+
+        let hash = InputHash::new(&moduli, &pads, rng.gen());
+        let hash_of = |c: &mut crate::circuit::Circuit, inputs: &[u16]| {
+            let (en, _) = crate::r#static::garble(c).unwrap();
+            let cts = hash.garble(&en, c.noutputs());
+            hash.decode(&en.encode_garbler_inputs(inputs), &cts, c.noutputs())
+                .unwrap()
+        };
+        let mut inputs = moduli
+            .iter()
+            .map(|q| rng.gen_u16() % q)
+            .collect::<Vec<u16>>();
+        inputs.extend(pads.iter().map(|q| rng.gen_u16() % q));
+        let h = hash_of(&mut c, &inputs);
+        // the same inputs hash to the same value in every garbling
+        assert_eq!(hash_of(&mut c, &inputs), h);
+        // different inputs do not
+        for i in 0..moduli.len() {
+            let mut inputs_ = inputs.clone();
+            inputs_[i] = (inputs_[i] + 1) % moduli[i];
+            assert_ne!(hash_of(&mut c, &inputs_), h);
+        }
+    }
+}
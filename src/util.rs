@@ -5,7 +5,7 @@
 #[cfg(feature = "nightly")]
 use core::arch::x86_64::*;
 use itertools::Itertools;
-use scuttlebutt::Block;
+use scuttlebutt::{Block, AES_HASH};
 
 ////////////////////////////////////////////////////////////////////////////////
 // tweak functions for garbling
@@ -48,6 +48,19 @@ pub fn output_tweak(i: usize, k: u16) -> Block {
     Block::from(left + k as u128)
 }
 
+/// Hash each `(tweak, x)` of `inputs` with fixed-key AES, as `Wire::hash` does,
+/// replacing the contents of `hashes` with the results. Each input is hashed with
+/// its own call to `tccr_hash`.
+#[inline]
+pub fn tccr_hash_many(inputs: &[(Block, Block)], hashes: &mut Vec<Block>) {
+    hashes.clear();
+    hashes.extend(
+        inputs
+            .iter()
+            .map(|&(tweak, x)| AES_HASH.tccr_hash(tweak, x)),
+    );
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // mixed radix stuff
 
@@ -257,6 +270,30 @@ pub fn crt_inv_factor(xs: &[u16], q: u128) -> u128 {
     crt_inv(xs, &factor(q))
 }
 
+/// Encode `x` as a fixed-point integer mod `q` with `precision` fractional bits.
+/// Negative values are represented as `q - |x|`.
+#[inline]
+pub fn to_fixed_point(x: f64, q: u128, precision: usize) -> u128 {
+    let v = (x * (1u128 << precision) as f64).round() as i128;
+    if v < 0 {
+        (q - (-v) as u128 % q) % q
+    } else {
+        v as u128 % q
+    }
+}
+
+/// Decode the fixed-point integer `x` mod `q` with `precision` fractional bits,
+/// interpreting values at least `q / 2` as negative.
+#[inline]
+pub fn from_fixed_point(x: u128, q: u128, precision: usize) -> f64 {
+    let v = if x >= q / 2 {
+        -((q - x) as f64)
+    } else {
+        x as f64
+    };
+    v / (1u128 << precision) as f64
+}
+
 /// Generic algorithm to invert inp_a mod inp_b. As ref so as to support BigInts without
 /// copying.
 #[inline]
@@ -386,6 +423,33 @@ pub fn is_power_of_2(x: u16) -> bool {
     (x & (x - 1)) == 0
 }
 
+/// Comparators of Batcher's odd-even merge sort on `n` elements, in order. Sorting
+/// ascending means putting the smaller of the elements at each pair `(i, j)`, where
+/// `i < j`, at `i`.
+///
+/// Works for any `n`, using `O(n log^2 n)` comparators.
+pub fn sorting_network(n: usize) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < n {
+                for i in 0..std::cmp::min(k, n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        pairs.push((i + j, i + j + k));
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    pairs
+}
+
 /// Extra Rng functionality, useful for `fancy-garbling`.
 pub trait RngExt: rand::Rng + Sized {
     /// Randomly generate a `bool`.
@@ -524,6 +588,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sorting_networks() {
+        // by the 0-1 principle, sorting every binary input suffices
+        for n in 0..12 {
+            let pairs = sorting_network(n);
+            for x in 0..1u32 << n {
+                let mut bits = (0..n).map(|i| x >> i & 1).collect::<Vec<_>>();
+                for &(i, j) in pairs.iter() {
+                    assert!(i < j);
+                    if bits[j] < bits[i] {
+                        bits.swap(i, j);
+                    }
+                }
+                assert!(bits.windows(2).all(|w| w[0] <= w[1]), "n={} x={:b}", n, x);
+            }
+        }
+    }
+
     #[test]
     fn base_q_conversion() {
         let mut rng = thread_rng();
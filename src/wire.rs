@@ -7,10 +7,12 @@ use scuttlebutt::{Block, AES_HASH};
 
 /// The core wire-label type.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub enum Wire {
     /// Representation of a `mod-2` wire.
     Mod2 {
         /// A 128-bit value.
+        #[cfg_attr(feature = "serde1", serde(with = "crate::serialization::serde_block"))]
         val: Block,
     },
     /// Representation of a `mod-3` wire.
@@ -180,6 +182,25 @@ impl Wire {
         }
     }
 
+    /// The same wire with its color digit replaced by `c`.
+    #[inline]
+    pub(crate) fn with_color(&self, c: u16) -> Self {
+        match self {
+            Wire::Mod2 { val } => Wire::Mod2 {
+                val: Block::from(u128::from(*val) & !1 | (c & 1) as u128),
+            },
+            Wire::Mod3 { lsb, msb } => Wire::Mod3 {
+                lsb: lsb & !1 | (c & 1) as u64,
+                msb: msb & !1 | (c >> 1 & 1) as u64,
+            },
+            Wire::ModN { q, ds } => {
+                let mut ds = ds.clone();
+                ds[0] = c;
+                Wire::ModN { q: *q, ds }
+            }
+        }
+    }
+
     /// Add two wires digit-wise, returning a new wire.
     #[inline]
     pub fn plus(&self, other: &Self) -> Self {
@@ -378,8 +399,14 @@ impl Wire {
     /// Uses fixed-key AES.
     #[inline]
     pub fn hashback(&self, tweak: Block, q: u16) -> Wire {
+        Self::from_hash(self.hash(tweak), q)
+    }
+
+    /// Convert the hash `block` of a wire to a wire of modulus `q`, as `hashback`
+    /// does.
+    #[inline]
+    pub(crate) fn from_hash(block: Block, q: u16) -> Wire {
         if q == 3 {
-            let block = self.hash(tweak);
             // We now have to convert `block` into a valid `Mod3` encoding. We
             // do this by using the `base_conversion` lookup capabilities to
             // build a `ModN` encoding, and then map this `ModN` encoding to a
@@ -394,10 +421,10 @@ impl Wire {
                     }
                     Wire::Mod3 { lsb, msb }
                 }
-                _ => panic!("[Wire::hashback] should never get here!"),
+                _ => panic!("[Wire::from_hash] should never get here!"),
             }
         } else {
-            Self::from_block(self.hash(tweak), q)
+            Self::from_block(block, q)
         }
     }
 }
@@ -454,6 +481,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn with_color() {
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            let q = 2 + (rng.gen_u16() % 110);
+            let x = Wire::rand(&mut rng, q);
+            let c = rng.gen_u16() % q;
+            let y = x.with_color(c);
+            assert_eq!(y.color(), c);
+            assert_eq!(y.digits()[1..], x.digits()[1..]);
+            assert_eq!(y.with_color(x.color()), x);
+        }
+    }
+
     #[test]
     fn negation() {
         let ref mut rng = thread_rng();
@@ -0,0 +1,458 @@
+//! Zero-knowledge proofs from privacy-free garbled circuits, following Jawurek,
+//! Kerschbaum and Orlandi, "Zero-Knowledge Using Garbled Circuits" (CCS 2013).
+//!
+//! The `Prover` knows a witness for the evaluator inputs of a `Circuit`, and proves
+//! to the `Verifier` what the circuit outputs on it. The garbler inputs of the
+//! circuit are public inputs, known to both. The protocol runs as follows:
+//!
+//! 1. The verifier garbles the circuit with a `PrivacyFreeGarbler` from a random
+//!    seed, and sends it along with the wirelabels of the public inputs.
+//! 2. The prover obtains a wirelabel for each bit of the witness by oblivious
+//!    transfer, evaluates the circuit with a `PrivacyFreeEvaluator`, and commits to
+//!    the output wirelabels.
+//! 3. The verifier reveals the seed. The prover regarbles the circuit from it, and
+//!    aborts with `EvaluatorError::CheatingDetected` if it differs from what was
+//!    sent, or if the wirelabels it received are wrong.
+//! 4. The prover opens its commitment. The verifier aborts with
+//!    `GarblerError::CheatingDetected` unless the output wirelabels are valid, which
+//!    the prover cannot forge for outputs the witness does not produce.
+//!
+//! Both parties learn the outputs, so proving knowledge of a `w` such that
+//! `f(w) = y` amounts to checking that the verified output is `y`. Security relies
+//! on the `OtSender` and `OtReceiver` implementations being malicious-secure.
+
+use crate::circuit::{Circuit, Gate};
+use crate::error::{EvaluatorError, FancyError, GarblerError};
+use crate::fancy::HasModulus;
+use crate::garble::{PrivacyFreeEvaluator, PrivacyFreeGarbler};
+use crate::ot::{OtReceiver, OtSender};
+use crate::twopac::input_bits;
+use crate::wire::Wire;
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::commitment::{Commitment, ShaCommitment};
+use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
+
+/// The verifier of zero-knowledge proofs about circuits.
+pub struct Verifier<C, RNG, OT> {
+    channel: C,
+    ot: OT,
+    rng: RNG,
+}
+
+/// The prover of zero-knowledge proofs about circuits.
+pub struct Prover<C, RNG, OT> {
+    channel: C,
+    ot: OT,
+    rng: RNG,
+}
+
+/// A privacy-free garbling of a circuit derived from a seed, so that the prover can
+/// regenerate it once the seed is revealed.
+struct Garbling {
+    /// The garbled circuit.
+    gc: Vec<u8>,
+    /// The wirelabels of the public inputs.
+    public: Vec<Wire>,
+    /// The pair of wirelabels for each bit of each witness input. The labels for the
+    /// bits of an input, weighted by their significance, sum to the label of the
+    /// input itself.
+    witness: Vec<(Wire, Wire)>,
+    /// The garbler, which decodes the output wirelabels.
+    gb: PrivacyFreeGarbler<Channel<std::io::Empty, Vec<u8>>, AesRng>,
+}
+
+/// Garble `c` privacy-free from `seed`, on the public inputs `public`.
+fn garble(c: &mut Circuit, seed: Block, public: &[u16]) -> Result<Garbling, GarblerError> {
+    let channel = Channel::new(std::io::empty(), Vec::new());
+    let mut gb = PrivacyFreeGarbler::from_seed(channel.clone(), seed);
+    let moduli = c
+        .garbler_input_refs
+        .iter()
+        .map(|r| r.modulus())
+        .collect::<Vec<u16>>();
+    let (public_zeros, public) = gb.encode_many_wires(public, &moduli)?;
+    let mut witness = Vec::new();
+    let mut witness_zeros = Vec::with_capacity(c.evaluator_input_refs.len());
+    for r in c.evaluator_input_refs.iter() {
+        let q = r.modulus();
+        let delta = gb.delta(q);
+        let mut zero = Wire::zero(q);
+        for i in 0..input_bits(q) {
+            let (w, _) = gb.encode_wire(0, q);
+            zero.plus_eq(&w.cmul(1 << i));
+            let one = w.plus(&delta);
+            witness.push((w, one));
+        }
+        witness_zeros.push(zero);
+    }
+    let outputs = c.eval(&mut gb, &public_zeros, &witness_zeros)?;
+    c.process_outputs(&outputs, &mut gb)?;
+    let gc = std::mem::take(&mut *channel.writer().borrow_mut());
+    Ok(Garbling {
+        gc,
+        public,
+        witness,
+        gb,
+    })
+}
+
+/// Read a privacy-free garbling of `c` from `channel`, refusing garblings larger than
+/// the circuit can produce: a multiplication has a ciphertext for each nonzero value
+/// of its smaller modulus, a projection at most one for each nonzero input, and the
+/// other gates and outputs are free.
+fn read_garbling<C: AbstractChannel>(
+    channel: &mut C,
+    c: &Circuit,
+) -> Result<Vec<u8>, EvaluatorError> {
+    let (operands, _) = c.operand_gates()?;
+    let nblocks = c
+        .gates
+        .iter()
+        .zip(operands.iter())
+        .map(|(gate, xs)| match gate {
+            Gate::Mul { .. } => xs.iter().map(|&x| c.gate_moduli[x]).min().unwrap() as usize - 1,
+            Gate::Proj { .. } => c.gate_moduli[xs[0]] as usize - 1,
+            _ => 0,
+        })
+        .sum::<usize>();
+    let n = channel.read_usize()?;
+    if n > 16 * nblocks {
+        return Err(EvaluatorError::CheatingDetected(format!(
+            "garbled circuit of {} bytes is larger than the {} the circuit needs",
+            n,
+            16 * nblocks
+        )));
+    }
+    let mut gc = vec![0; n];
+    channel.read_bytes(&mut gc)?;
+    Ok(gc)
+}
+
+/// Commit to the output wirelabels `outputs` with the randomness `opening`.
+fn commit(opening: [u8; 32], outputs: &[Wire]) -> [u8; 32] {
+    let mut commitment = ShaCommitment::new(opening);
+    for w in outputs.iter() {
+        commitment.input(w.as_block().as_ref());
+    }
+    commitment.finish()
+}
+
+impl<C, RNG, OT> Verifier<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtSender,
+{
+    /// Create a new verifier, running the OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG) -> Result<Self, GarblerError> {
+        let ot = OT::init(&mut channel, &mut rng)?;
+        Ok(Verifier { channel, ot, rng })
+    }
+
+    /// Verify a proof about the outputs of `c` on the public inputs `public` and the
+    /// prover's witness, returning the outputs.
+    pub fn verify(&mut self, c: &mut Circuit, public: &[u16]) -> Result<Vec<u16>, GarblerError> {
+        if public.len() != c.num_garbler_inputs() {
+            return Err(GarblerError::FancyError(FancyError::InvalidArgNum {
+                got: public.len(),
+                needed: c.num_garbler_inputs(),
+            }));
+        }
+        let seed = self.rng.gen::<Block>();
+        let garbling = garble(c, seed, public)?;
+        self.channel.write_usize(garbling.gc.len())?;
+        self.channel.write_bytes(&garbling.gc)?;
+        for w in garbling.public.iter() {
+            self.channel.write_block(&w.as_block())?;
+        }
+        self.channel.flush()?;
+        let labels = garbling
+            .witness
+            .iter()
+            .map(|(zero, one)| (zero.as_block(), one.as_block()))
+            .collect::<Vec<(Block, Block)>>();
+        self.ot.send(&mut self.channel, &labels, &mut self.rng)?;
+
+        let mut commitment = [0; 32];
+        self.channel.read_bytes(&mut commitment)?;
+        self.channel.write_block(&seed)?;
+        self.channel.flush()?;
+
+        let mut opening = [0; 32];
+        self.channel.read_bytes(&mut opening)?;
+        let outputs = c
+            .output_refs
+            .iter()
+            .map(|r| Ok(Wire::from_block(self.channel.read_block()?, r.modulus())))
+            .collect::<Result<Vec<Wire>, GarblerError>>()?;
+        if !ShaCommitment::check(&commitment, &commit(opening, &outputs)) {
+            return Err(GarblerError::CheatingDetected(
+                "the output wirelabels do not match the commitment".to_string(),
+            ));
+        }
+        garbling.gb.decode_output(&outputs).map_err(|_| {
+            GarblerError::CheatingDetected("the output wirelabels are invalid".to_string())
+        })
+    }
+}
+
+impl<C, RNG, OT> Prover<C, RNG, OT>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+    OT: OtReceiver,
+{
+    /// Create a new prover, running the OT setup over `channel`.
+    pub fn new(mut channel: C, mut rng: RNG) -> Result<Self, EvaluatorError> {
+        let ot = OT::init(&mut channel, &mut rng)?;
+        Ok(Prover { channel, ot, rng })
+    }
+
+    /// Prove the outputs of `c` on the public inputs `public` and the witness
+    /// `witness`, returning the outputs.
+    pub fn prove(
+        &mut self,
+        c: &mut Circuit,
+        public: &[u16],
+        witness: &[u16],
+    ) -> Result<Vec<u16>, EvaluatorError> {
+        if public.len() != c.num_garbler_inputs() {
+            return Err(EvaluatorError::NotEnoughGarblerInputs);
+        }
+        if witness.len() != c.num_evaluator_inputs() {
+            return Err(EvaluatorError::NotEnoughEvaluatorInputs);
+        }
+        let gc = read_garbling(&mut self.channel, c)?;
+        let public_labels = c
+            .garbler_input_refs
+            .iter()
+            .map(|r| Ok(Wire::from_block(self.channel.read_block()?, r.modulus())))
+            .collect::<Result<Vec<Wire>, EvaluatorError>>()?;
+
+        let moduli = c
+            .evaluator_input_refs
+            .iter()
+            .map(|r| r.modulus())
+            .collect::<Vec<u16>>();
+        let choices = witness
+            .iter()
+            .zip(moduli.iter())
+            .flat_map(|(x, q)| (0..input_bits(*q)).map(move |i| x & (1 << i) != 0))
+            .collect::<Vec<bool>>();
+        let labels = self
+            .ot
+            .receive(&mut self.channel, &choices, &mut self.rng)?;
+        let mut bits = labels.iter();
+        let witness_labels = moduli
+            .iter()
+            .map(|q| {
+                let mut wire = Wire::zero(*q);
+                for k in 0..input_bits(*q) {
+                    let label = bits.next().expect("one label per witness bit");
+                    wire.plus_eq(&Wire::from_block(*label, *q).cmul(1 << k));
+                }
+                wire
+            })
+            .collect::<Vec<Wire>>();
+
+        let mut ev = PrivacyFreeEvaluator::new(Channel::new(&gc[..], std::io::sink()));
+        let outputs = c.eval(&mut ev, &public_labels, &witness_labels)?;
+        c.process_outputs(&outputs, &mut ev)?;
+        let opening = self.rng.gen::<[u8; 32]>();
+        self.channel
+            .write_bytes(&commit(opening, ev.output_wires()))?;
+        self.channel.flush()?;
+
+        let seed = self.channel.read_block()?;
+        let expected = garble(c, seed, public)
+            .map_err(|e| EvaluatorError::CommunicationError(e.to_string()))?;
+        if expected.gc != gc {
+            return Err(EvaluatorError::CheatingDetected(
+                "the garbled circuit does not match its seed".to_string(),
+            ));
+        }
+        if expected.public != public_labels {
+            return Err(EvaluatorError::CheatingDetected(
+                "the public input wirelabels are invalid".to_string(),
+            ));
+        }
+        for ((zero, one), (b, label)) in expected
+            .witness
+            .iter()
+            .zip(choices.iter().zip(labels.iter()))
+        {
+            if (if *b { one } else { zero }).as_block() != *label {
+                return Err(EvaluatorError::CheatingDetected(
+                    "the witness input wirelabels are invalid".to_string(),
+                ));
+            }
+        }
+
+        self.channel.write_bytes(&opening)?;
+        for w in ev.output_wires().iter() {
+            self.channel.write_block(&w.as_block())?;
+        }
+        self.channel.flush()?;
+        Ok(ev.decode_output())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitBuilder;
+    use crate::ot::{DummyReceiver, DummySender};
+    use crate::util::RngExt;
+    use crate::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy};
+    use itertools::Itertools;
+    use rand::thread_rng;
+    use std::io::{BufReader, BufWriter};
+    use std::os::unix::net::UnixStream;
+
+    type MyChannel = Channel<BufReader<UnixStream>, BufWriter<UnixStream>>;
+
+    fn channel(stream: UnixStream) -> MyChannel {
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let writer = BufWriter::new(stream);
+        Channel::new(reader, writer)
+    }
+
+    // Whether `x * y = z` for CRT inputs `x` and `y` and public `z`, and `x + y`.
+    fn factors(q: u128) -> Circuit {
+        let mut b = CircuitBuilder::new();
+        let z = b.crt_garbler_input(q);
+        let x = b.crt_evaluator_input(q);
+        let y = b.crt_evaluator_input(q);
+        let xy = b.crt_mul(&x, &y).unwrap();
+        let eq = b.eq_bundles(&xy, &z).unwrap();
+        b.output(&eq).unwrap();
+        let s = b.crt_add(&x, &y).unwrap();
+        b.output_bundle(&s).unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn honest() {
+        let mut rng = thread_rng();
+        let q = crate::util::modulus_with_nprimes(3);
+        let mut c = factors(q);
+        for _ in 0..4 {
+            let x = rng.gen_u128() % 1000;
+            let y = rng.gen_u128() % 1000;
+            let z = x * y + (rng.gen_u16() % 2) as u128;
+            let public = crate::util::crt_factor(z, q);
+            let witness = [crate::util::crt_factor(x, q), crate::util::crt_factor(y, q)].concat();
+            let should_be = c.eval_plain(&public, &witness).unwrap();
+            assert_eq!(should_be[0], (z == x * y) as u16);
+
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let mut c_ = c.clone();
+            let public_ = public.clone();
+            let handle = std::thread::spawn(move || {
+                let mut v =
+                    Verifier::<_, _, DummySender>::new(channel(sender), thread_rng()).unwrap();
+                v.verify(&mut c_, &public_).unwrap()
+            });
+            let mut p =
+                Prover::<_, _, DummyReceiver>::new(channel(receiver), thread_rng()).unwrap();
+            assert_eq!(p.prove(&mut c, &public, &witness).unwrap(), should_be);
+            assert_eq!(handle.join().unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn binary() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_evaluator_input(16);
+        let y = b.bin_evaluator_input(16);
+        let z = b.bin_multiplication_lower_half(&x, &y).unwrap();
+        b.output_bundle(&z).unwrap();
+        let mut c = b.finish();
+        let witness = (0..32).map(|_| rng.gen_u16() % 2).collect_vec();
+        let should_be = c.eval_plain(&[], &witness).unwrap();
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let mut c_ = c.clone();
+        let handle = std::thread::spawn(move || {
+            let mut v = Verifier::<_, _, DummySender>::new(channel(sender), thread_rng()).unwrap();
+            v.verify(&mut c_, &[]).unwrap()
+        });
+        let mut p = Prover::<_, _, DummyReceiver>::new(channel(receiver), thread_rng()).unwrap();
+        assert_eq!(p.prove(&mut c, &[], &witness).unwrap(), should_be);
+        assert_eq!(handle.join().unwrap(), should_be);
+    }
+
+    #[test]
+    fn cheating_prover() {
+        // a prover without a witness which claims that the output is one
+        let mut b = CircuitBuilder::new();
+        let x = b.evaluator_input(2);
+        let y = b.evaluator_input(2);
+        let z = b.and(&x, &y).unwrap();
+        let z = b.and(&z, &x).unwrap();
+        b.output(&z).unwrap();
+        let mut c = b.finish();
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let mut c_ = c.clone();
+        let handle = std::thread::spawn(move || {
+            let mut v = Verifier::<_, _, DummySender>::new(channel(sender), thread_rng()).unwrap();
+            v.verify(&mut c_, &[])
+        });
+        let mut rng = thread_rng();
+        let mut channel = channel(receiver);
+        let mut ot = DummyReceiver::init(&mut channel, &mut rng).unwrap();
+        let gc = read_garbling(&mut channel, &c).unwrap();
+        let labels = ot.receive(&mut channel, &[true, false], &mut rng).unwrap();
+        let mut ev = PrivacyFreeEvaluator::new(Channel::new(&gc[..], std::io::sink()));
+        let inputs = labels.iter().map(|l| Wire::from_block(*l, 2)).collect_vec();
+        let outputs = c.eval(&mut ev, &[], &inputs).unwrap();
+        c.process_outputs(&outputs, &mut ev).unwrap();
+        assert_eq!(ev.decode_output(), vec![0]);
+        // flip the color of the output wirelabel to claim the output is one
+        let forged = vec![ev.output_wires()[0].with_color(1)];
+        let opening = [7; 32];
+        channel.write_bytes(&commit(opening, &forged)).unwrap();
+        channel.flush().unwrap();
+        channel.read_block().unwrap();
+        channel.write_bytes(&opening).unwrap();
+        channel.write_block(&forged[0].as_block()).unwrap();
+        channel.flush().unwrap();
+        match handle.join().unwrap() {
+            Err(GarblerError::CheatingDetected(_)) => (),
+            r => panic!("expected CheatingDetected, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn wrong_number_of_inputs() {
+        let mut c = factors(crate::util::modulus_with_nprimes(2));
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            Verifier::<_, _, DummySender>::new(channel(sender), thread_rng()).unwrap();
+        });
+        let mut p = Prover::<_, _, DummyReceiver>::new(channel(receiver), thread_rng()).unwrap();
+        match p.prove(&mut c, &[0, 0], &[0]) {
+            Err(EvaluatorError::NotEnoughEvaluatorInputs) => (),
+            r => panic!("expected NotEnoughEvaluatorInputs, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn oversized_garbling() {
+        let mut c = factors(crate::util::modulus_with_nprimes(2));
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            let mut channel = channel(sender);
+            DummySender::init(&mut channel, &mut thread_rng()).unwrap();
+            channel.write_usize(usize::MAX).unwrap();
+            channel.flush().unwrap();
+        });
+        let mut p = Prover::<_, _, DummyReceiver>::new(channel(receiver), thread_rng()).unwrap();
+        match p.prove(&mut c, &[0, 0], &[0; 4]) {
+            Err(EvaluatorError::CheatingDetected(_)) => (),
+            r => panic!("expected CheatingDetected, got {:?}", r),
+        }
+    }
+}